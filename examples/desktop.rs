@@ -0,0 +1,41 @@
+//! Runs a few hundred frames against the desktop runtime (SteamVR, Monado...) with the debug
+//! grid on, then asks the session to exit and tears everything down.
+//!
+//! `cargo run --example desktop`, with the OpenXR loader in the library path.
+
+use openxr_test::{App, Config, ControlFlow};
+
+const FRAME_COUNT: u32 = 300;
+
+fn main() {
+    env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .init();
+
+    let config = Config {
+        debug_grid: true,
+        ..Config::default()
+    };
+    let mut app = App::new(config).expect("Failed to initialize OpenXR");
+
+    let mut frames = 0;
+    app.run(|frame| {
+        frames += 1;
+        if frames < FRAME_COUNT {
+            return Ok(ControlFlow::Continue);
+        }
+
+        if frames == FRAME_COUNT {
+            log::info!(
+                "Rendered {} frames, display time {:?}",
+                frames,
+                frame.display_time
+            );
+        }
+        // The loop keeps going until the runtime has wound the session down
+        Ok(ControlFlow::Exit)
+    })
+    .expect("Frame loop failed");
+
+    app.destroy().expect("Failed to tear down OpenXR");
+}
@@ -0,0 +1,112 @@
+//! Thumbstick-driven teleport against the desktop runtime: the left thumbstick moves a target on
+//! the floor of the stage space, and the A button (or the right trigger) teleports there.
+//!
+//! Nothing gets drawn besides the debug grid, the target and the teleports are logged instead.
+//! An actual app would apply `world_offset` to its content, or recreate its reference space
+//! with it as the `pose_in_reference_space`, so the user ends up standing on the target.
+//!
+//! `cargo run --example teleport`, with the OpenXR loader in the library path. The app stops
+//! when the session does, from the runtime's UI.
+
+use openxr_sys::{ActionType, Path, Vector2f};
+use openxr_test::{App, Config, ControlFlow};
+
+/// How fast the target moves with the thumbstick fully pushed, in meters per second
+const TARGET_SPEED: f32 = 2.0;
+/// Thumbstick values below this are ignored, most sticks not quite going back to 0
+const DEAD_ZONE: f32 = 0.15;
+
+fn main() {
+    env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .init();
+
+    let config = Config {
+        debug_grid: true,
+        ..Config::default()
+    };
+    let mut app = App::new(config).expect("Failed to initialize OpenXR");
+
+    let session = app.session_mut();
+    let locomotion = session
+        .create_action_set("locomotion", "Locomotion", 0)
+        .expect("Failed to create the action set");
+    let aim = locomotion
+        .create_action("aim", "Aim teleport", ActionType::VECTOR2F_INPUT, &[])
+        .expect("Failed to create the aim action");
+    let teleport = locomotion
+        .create_action("teleport", "Teleport", ActionType::BOOLEAN_INPUT, &[])
+        .expect("Failed to create the teleport action");
+
+    session
+        .suggest_bindings(
+            "/interaction_profiles/oculus/touch_controller",
+            &[
+                (&aim, "/user/hand/left/input/thumbstick"),
+                (&teleport, "/user/hand/right/input/a/click"),
+            ],
+        )
+        .expect("Failed to suggest the Touch bindings");
+    session
+        .suggest_bindings(
+            "/interaction_profiles/valve/index_controller",
+            &[
+                (&aim, "/user/hand/left/input/thumbstick"),
+                (&teleport, "/user/hand/right/input/a/click"),
+            ],
+        )
+        .expect("Failed to suggest the Index bindings");
+    session
+        .suggest_bindings(
+            "/interaction_profiles/microsoft/motion_controller",
+            &[
+                (&aim, "/user/hand/left/input/thumbstick"),
+                (&teleport, "/user/hand/right/input/trigger/value"),
+            ],
+        )
+        .expect("Failed to suggest the Mixed Reality bindings");
+    session
+        .attach_action_sets(&[&locomotion])
+        .expect("Failed to attach the action set");
+
+    // Target on the stage floor, relative to where the user currently stands
+    let mut target = Vector2f { x: 0.0, y: 0.0 };
+    // Translation to apply to the world so the user stands where they teleported, on the XZ plane
+    let mut world_offset = Vector2f { x: 0.0, y: 0.0 };
+
+    app.run(|frame| {
+        let aim = aim.state_vector2(frame.session, Path::NULL)?;
+        let teleport = teleport.state_boolean(frame.session, Path::NULL)?;
+
+        let stick = aim.current_state;
+        if aim.is_active && stick.x.hypot(stick.y) > DEAD_ZONE {
+            let step = TARGET_SPEED * frame.display_period.as_secs_f32();
+            // Pushing the stick forward moves the target away, towards -Z
+            target.x += stick.x * step;
+            target.y -= stick.y * step;
+            log::info!("Teleport target: ({:.2}, {:.2})", target.x, target.y);
+        }
+
+        // Only on the press itself, not for as long as the button is held
+        if teleport.changed_since_last_sync && teleport.current_state {
+            world_offset.x -= target.x;
+            world_offset.y -= target.y;
+            log::info!(
+                "Teleporting by ({:.2}, {:.2}), world offset now ({:.2}, {:.2})",
+                target.x,
+                target.y,
+                world_offset.x,
+                world_offset.y
+            );
+            target = Vector2f { x: 0.0, y: 0.0 };
+        }
+
+        Ok(ControlFlow::Continue)
+    })
+    .expect("Frame loop failed");
+
+    locomotion
+        .destroy()
+        .expect("Failed to destroy the action set");
+    app.destroy().expect("Failed to tear down OpenXR");
+}
@@ -0,0 +1,566 @@
+use std::sync::Arc;
+
+use log::{info, warn};
+use openxr_sys::{ActionType, SpaceLocationFlags};
+
+use crate::{
+    error::{Error, XrResultExt},
+    session::Session,
+    time::XrTime,
+    XrInstanceFp,
+};
+
+/// Group of actions, created with `Session::create_action_set`. It has to be attached to the
+/// session with `Session::attach_action_sets` before its actions report anything.
+pub struct ActionSet {
+    pub(crate) handle: openxr_sys::ActionSet,
+    fp: Arc<XrInstanceFp>,
+}
+
+/// An input or output of the application, bound to the devices by the runtime.
+///
+/// Actions are destroyed along with their set, so they must not be used after it.
+pub struct Action {
+    pub(crate) handle: openxr_sys::Action,
+    action_type: ActionType,
+    fp: Arc<XrInstanceFp>,
+}
+
+/// Interaction profiles of the common controllers, all of which have both hand poses.
+pub const STANDARD_INTERACTION_PROFILES: [&str; 5] = [
+    "/interaction_profiles/khr/simple_controller",
+    "/interaction_profiles/oculus/touch_controller",
+    "/interaction_profiles/valve/index_controller",
+    "/interaction_profiles/htc/vive_controller",
+    "/interaction_profiles/microsoft/motion_controller",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hand {
+    Left,
+    Right,
+}
+
+impl Hand {
+    /// Top level user path of the hand, to use as a subaction path.
+    pub fn path(self) -> &'static str {
+        match self {
+            Hand::Left => "/user/hand/left",
+            Hand::Right => "/user/hand/right",
+        }
+    }
+}
+
+/// Top level user paths devices get bound under, see `Session::connected_devices`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TopLevelPath {
+    LeftHand,
+    RightHand,
+    Head,
+}
+
+impl TopLevelPath {
+    pub const ALL: [TopLevelPath; 3] = [
+        TopLevelPath::LeftHand,
+        TopLevelPath::RightHand,
+        TopLevelPath::Head,
+    ];
+
+    pub fn path(self) -> &'static str {
+        match self {
+            TopLevelPath::LeftHand => Hand::Left.path(),
+            TopLevelPath::RightHand => Hand::Right.path(),
+            TopLevelPath::Head => "/user/head",
+        }
+    }
+}
+
+/// Which of the two poses of a controller an action follows. They're easily mixed up, and
+/// differ by several centimeters and degrees on most controllers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HandPose {
+    /// `/input/grip/pose`: where the hand holding the controller is, the origin at the center
+    /// of the palm and -Z along the closed fist. What hand and controller models follow.
+    Grip,
+    /// `/input/aim/pose`: the ray the controller points along, -Z forward from its tip. What
+    /// UI pointers and laser selection follow.
+    Aim,
+}
+
+impl HandPose {
+    /// Input path of the pose for `hand`, such as `/user/hand/left/input/grip/pose`.
+    pub fn binding(self, hand: Hand) -> &'static str {
+        match (self, hand) {
+            (HandPose::Grip, Hand::Left) => "/user/hand/left/input/grip/pose",
+            (HandPose::Grip, Hand::Right) => "/user/hand/right/input/grip/pose",
+            (HandPose::Aim, Hand::Left) => "/user/hand/left/input/aim/pose",
+            (HandPose::Aim, Hand::Right) => "/user/hand/right/input/aim/pose",
+        }
+    }
+}
+
+/// Pose action following `HandPose` of both hands, created with
+/// `ActionSet::create_hand_pose_action`, along with an action space per hand.
+///
+/// Dropped or destroyed before its set, as the spaces need the action.
+pub struct HandPoseAction {
+    action: Action,
+    pose: HandPose,
+    /// Spaces of the left and right hands
+    spaces: [openxr_sys::Space; 2],
+}
+
+/// State of an action at the last `xrSyncActions`.
+#[derive(Clone, Copy, Debug)]
+pub struct ActionState<T> {
+    pub current_state: T,
+    /// Whether `current_state` differs from the previous sync
+    pub changed_since_last_sync: bool,
+    /// When the input last changed, if `changed_since_last_sync`
+    pub last_change_time: XrTime,
+    /// Whether the action is bound to an input that's currently available, `current_state`
+    /// being zero otherwise
+    pub is_active: bool,
+}
+
+impl ActionSet {
+    pub(crate) fn new(
+        fp: &Arc<XrInstanceFp>,
+        instance: openxr_sys::Instance,
+        name: &str,
+        localized_name: &str,
+        priority: u32,
+    ) -> Result<Self, Error> {
+        let mut create_info = openxr_sys::ActionSetCreateInfo {
+            ty: openxr_sys::ActionSetCreateInfo::TYPE,
+            next: std::ptr::null(),
+            action_set_name: [0; openxr_sys::MAX_ACTION_SET_NAME_SIZE],
+            localized_action_set_name: [0; openxr_sys::MAX_LOCALIZED_ACTION_SET_NAME_SIZE],
+            priority,
+        };
+        crate::write_name(&mut create_info.action_set_name, name)?;
+        crate::write_name(&mut create_info.localized_action_set_name, localized_name)?;
+
+        info!("xrCreateActionSet()");
+        let mut handle = openxr_sys::ActionSet::NULL;
+        let result = unsafe { (fp.create_action_set)(instance, &create_info, &mut handle) };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        Ok(ActionSet {
+            handle,
+            fp: Arc::clone(fp),
+        })
+    }
+
+    /// Creates an action in the set. `name` must be lowercase, and unique within the set.
+    ///
+    /// `subaction_paths` are the top level user paths (`/user/hand/left`...) the action's state
+    /// can be queried for separately, the set having to be attached to use them. Names too long
+    /// for OpenXR's fields fail with `Error::InvalidName`.
+    pub fn create_action(
+        &self,
+        name: &str,
+        localized_name: &str,
+        action_type: ActionType,
+        subaction_paths: &[openxr_sys::Path],
+    ) -> Result<Action, Error> {
+        let mut create_info = openxr_sys::ActionCreateInfo {
+            ty: openxr_sys::ActionCreateInfo::TYPE,
+            next: std::ptr::null(),
+            action_name: [0; openxr_sys::MAX_ACTION_NAME_SIZE],
+            action_type,
+            count_subaction_paths: subaction_paths.len() as u32,
+            subaction_paths: subaction_paths.as_ptr(),
+            localized_action_name: [0; openxr_sys::MAX_LOCALIZED_ACTION_NAME_SIZE],
+        };
+        crate::write_name(&mut create_info.action_name, name)?;
+        crate::write_name(&mut create_info.localized_action_name, localized_name)?;
+
+        info!("xrCreateAction()");
+        let mut handle = openxr_sys::Action::NULL;
+        let result = unsafe { (self.fp.create_action)(self.handle, &create_info, &mut handle) };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        Ok(Action {
+            handle,
+            action_type,
+            fp: Arc::clone(&self.fp),
+        })
+    }
+
+    /// Creates an action following `pose` of each hand, named after it (`grip_pose` or
+    /// `aim_pose`), with `/user/hand/left` and `/user/hand/right` as subaction paths.
+    ///
+    /// Its bindings still have to be suggested, see `HandPoseAction::bindings`.
+    pub fn create_hand_pose_action(
+        &self,
+        session: &Session,
+        pose: HandPose,
+    ) -> Result<HandPoseAction, Error> {
+        let subaction_paths = [
+            session.string_to_path(Hand::Left.path())?,
+            session.string_to_path(Hand::Right.path())?,
+        ];
+        let (name, localized_name) = match pose {
+            HandPose::Grip => ("grip_pose", "Grip pose"),
+            HandPose::Aim => ("aim_pose", "Aim pose"),
+        };
+        let action = self.create_action(
+            name,
+            localized_name,
+            ActionType::POSE_INPUT,
+            &subaction_paths,
+        )?;
+
+        // Dropped on failure, destroying the spaces already created
+        let mut hand_pose = HandPoseAction {
+            action,
+            pose,
+            spaces: [openxr_sys::Space::NULL; 2],
+        };
+        for (index, &subaction_path) in subaction_paths.iter().enumerate() {
+            let space_info = openxr_sys::ActionSpaceCreateInfo {
+                ty: openxr_sys::ActionSpaceCreateInfo::TYPE,
+                next: std::ptr::null(),
+                action: hand_pose.action.handle,
+                subaction_path,
+                pose_in_action_space: crate::identity_pose(),
+            };
+
+            info!("xrCreateActionSpace()");
+            let result = unsafe {
+                (self.fp.create_action_space)(
+                    session.handle,
+                    &space_info,
+                    &mut hand_pose.spaces[index],
+                )
+            };
+            if !result.is_success() {
+                return Err(Error::Xr(result));
+            }
+        }
+
+        Ok(hand_pose)
+    }
+
+    /// Destroys the set and its actions, reporting the failure that dropping it would only log.
+    pub fn destroy(mut self) -> Result<(), Error> {
+        let handle = std::mem::replace(&mut self.handle, openxr_sys::ActionSet::NULL);
+
+        info!("xrDestroyActionSet()");
+        let result = unsafe { (self.fp.destroy_action_set)(handle) };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for ActionSet {
+    fn drop(&mut self) {
+        if self.handle == openxr_sys::ActionSet::NULL {
+            return;
+        }
+
+        info!("xrDestroyActionSet()");
+        let result = unsafe { (self.fp.destroy_action_set)(self.handle) };
+        if !result.is_success() {
+            warn!(
+                "Failed to destroy action set {:?}: {:?}",
+                self.handle, result
+            );
+        }
+    }
+}
+
+impl Action {
+    pub fn action_type(&self) -> ActionType {
+        self.action_type
+    }
+
+    /// Value of a `BOOLEAN_INPUT` action, such as a button press. Bound to an analog input, the
+    /// runtime applies its own threshold. See `state_float` for `subaction_path`.
+    pub fn state_boolean(
+        &self,
+        session: &Session,
+        subaction_path: openxr_sys::Path,
+    ) -> Result<ActionState<bool>, Error> {
+        let mut state = openxr_sys::ActionStateBoolean::out(std::ptr::null_mut());
+        let result = unsafe {
+            (self.fp.get_action_state_boolean)(
+                session.handle,
+                &self.state_get_info(subaction_path),
+                state.as_mut_ptr(),
+            )
+        };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        let state = unsafe { state.assume_init() };
+        Ok(ActionState {
+            current_state: state.current_state.into(),
+            changed_since_last_sync: state.changed_since_last_sync.into(),
+            last_change_time: state.last_change_time.into(),
+            is_active: state.is_active.into(),
+        })
+    }
+
+    /// Value of a `FLOAT_INPUT` action, such as a trigger pull, from 0 to 1 for most inputs.
+    /// With a `subaction_path` given at creation, only the inputs under it are considered,
+    /// `Path::NULL` meaning all of them.
+    pub fn state_float(
+        &self,
+        session: &Session,
+        subaction_path: openxr_sys::Path,
+    ) -> Result<ActionState<f32>, Error> {
+        let mut state = openxr_sys::ActionStateFloat::out(std::ptr::null_mut());
+        let result = unsafe {
+            (self.fp.get_action_state_float)(
+                session.handle,
+                &self.state_get_info(subaction_path),
+                state.as_mut_ptr(),
+            )
+        };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        let state = unsafe { state.assume_init() };
+        Ok(ActionState {
+            current_state: state.current_state,
+            changed_since_last_sync: state.changed_since_last_sync.into(),
+            last_change_time: state.last_change_time.into(),
+            is_active: state.is_active.into(),
+        })
+    }
+
+    /// Value of a `VECTOR2F_INPUT` action, such as a thumbstick, each axis going from -1 to 1.
+    /// See `state_float` for `subaction_path`.
+    pub fn state_vector2(
+        &self,
+        session: &Session,
+        subaction_path: openxr_sys::Path,
+    ) -> Result<ActionState<openxr_sys::Vector2f>, Error> {
+        let mut state = openxr_sys::ActionStateVector2f::out(std::ptr::null_mut());
+        let result = unsafe {
+            (self.fp.get_action_state_vector2f)(
+                session.handle,
+                &self.state_get_info(subaction_path),
+                state.as_mut_ptr(),
+            )
+        };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        let state = unsafe { state.assume_init() };
+        Ok(ActionState {
+            current_state: state.current_state,
+            changed_since_last_sync: state.changed_since_last_sync.into(),
+            last_change_time: state.last_change_time.into(),
+            is_active: state.is_active.into(),
+        })
+    }
+
+    /// Human-readable names of the physical inputs the action is currently bound to, such as
+    /// "Left Hand Oculus Touch Controller Trigger", in the system's language, for a settings or
+    /// rebinding UI. Each name has the hand, the device and the input, in the order the runtime
+    /// sees fit.
+    ///
+    /// The bindings only exist once the action set is attached, and may change whenever the
+    /// runtime switches interaction profiles. Empty before it picked one.
+    pub fn localized_source_names(&self, session: &Session) -> Result<Vec<String>, Error> {
+        let enumerate_info = openxr_sys::BoundSourcesForActionEnumerateInfo {
+            ty: openxr_sys::BoundSourcesForActionEnumerateInfo::TYPE,
+            next: std::ptr::null(),
+            action: self.handle,
+        };
+
+        info!("xrEnumerateBoundSourcesForAction()");
+        let sources = crate::enumerate(
+            self.fp.max_enumerate_count,
+            openxr_sys::Path::NULL,
+            |capacity, count, buffer| unsafe {
+                (self.fp.enumerate_bound_sources_for_action)(
+                    session.handle,
+                    &enumerate_info,
+                    capacity,
+                    count,
+                    buffer,
+                )
+            },
+        )?;
+
+        sources
+            .into_iter()
+            .map(|source| {
+                let get_info = openxr_sys::InputSourceLocalizedNameGetInfo {
+                    ty: openxr_sys::InputSourceLocalizedNameGetInfo::TYPE,
+                    next: std::ptr::null(),
+                    source_path: source,
+                    which_components: openxr_sys::InputSourceLocalizedNameFlags::USER_PATH
+                        | openxr_sys::InputSourceLocalizedNameFlags::INTERACTION_PROFILE
+                        | openxr_sys::InputSourceLocalizedNameFlags::COMPONENT,
+                };
+
+                info!("xrGetInputSourceLocalizedName()");
+                let buffer = crate::enumerate(
+                    self.fp.max_enumerate_count,
+                    0,
+                    |capacity, count, buffer| unsafe {
+                        (self.fp.get_input_source_localized_name)(
+                            session.handle,
+                            &get_info,
+                            capacity,
+                            count,
+                            buffer,
+                        )
+                    },
+                )?;
+
+                // The count includes the terminating nul, which isn't always the last character
+                // written if the runtime pads the buffer
+                let name: Vec<u8> = buffer
+                    .iter()
+                    .take_while(|&&c| c != 0)
+                    .map(|&c| c as u8)
+                    .collect();
+                Ok(String::from_utf8_lossy(&name).into_owned())
+            })
+            .collect()
+    }
+
+    fn state_get_info(&self, subaction_path: openxr_sys::Path) -> openxr_sys::ActionStateGetInfo {
+        openxr_sys::ActionStateGetInfo {
+            ty: openxr_sys::ActionStateGetInfo::TYPE,
+            next: std::ptr::null(),
+            action: self.handle,
+            subaction_path,
+        }
+    }
+}
+
+impl HandPoseAction {
+    /// The underlying pose action.
+    pub fn action(&self) -> &Action {
+        &self.action
+    }
+
+    pub fn pose(&self) -> HandPose {
+        self.pose
+    }
+
+    /// Space following the pose of `hand`, to locate other spaces against.
+    pub fn space(&self, hand: Hand) -> openxr_sys::Space {
+        match hand {
+            Hand::Left => self.spaces[0],
+            Hand::Right => self.spaces[1],
+        }
+    }
+
+    /// Bindings of the pose of both hands, which are the same in every one of the
+    /// `STANDARD_INTERACTION_PROFILES`. Suggestions replacing the previous ones of the profile,
+    /// these are to be suggested along with the other actions bound in it:
+    ///
+    /// ```ignore
+    /// let mut bindings = vec![(&select, "/user/hand/right/input/select/click")];
+    /// bindings.extend(&grip.bindings());
+    /// bindings.extend(&aim.bindings());
+    /// session.suggest_bindings("/interaction_profiles/khr/simple_controller", &bindings)?;
+    /// ```
+    pub fn bindings(&self) -> [(&Action, &'static str); 2] {
+        [
+            (&self.action, self.pose.binding(Hand::Left)),
+            (&self.action, self.pose.binding(Hand::Right)),
+        ]
+    }
+
+    /// Pose of `hand` in `base_space` at `time`, `None` when the controller isn't tracked or
+    /// the action isn't bound. Actions must have been synced for the current frame.
+    pub fn locate(
+        &self,
+        session: &Session,
+        hand: Hand,
+        base_space: openxr_sys::Space,
+        time: XrTime,
+    ) -> Result<Option<openxr_sys::Posef>, Error> {
+        let state_info = openxr_sys::ActionStateGetInfo {
+            ty: openxr_sys::ActionStateGetInfo::TYPE,
+            next: std::ptr::null(),
+            action: self.action.handle,
+            subaction_path: session.string_to_path(hand.path())?,
+        };
+        let mut state = openxr_sys::ActionStatePose::out(std::ptr::null_mut());
+
+        let fp = &self.action.fp;
+        let result =
+            unsafe { (fp.get_action_state_pose)(session.handle, &state_info, state.as_mut_ptr()) };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        let is_active: bool = unsafe { state.assume_init() }.is_active.into();
+        if !is_active {
+            return Ok(None);
+        }
+
+        let mut location = openxr_sys::SpaceLocation::out(std::ptr::null_mut());
+        let result = unsafe {
+            (fp.locate_space)(
+                self.space(hand),
+                base_space,
+                time.into(),
+                location.as_mut_ptr(),
+            )
+        };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        let location = unsafe { location.assume_init() };
+        let valid = SpaceLocationFlags::ORIENTATION_VALID | SpaceLocationFlags::POSITION_VALID;
+        if !location.location_flags.contains(valid) {
+            return Ok(None);
+        }
+
+        Ok(Some(location.pose))
+    }
+
+    /// Destroys the spaces, reporting the failure that dropping them would only log. The action
+    /// goes away with its set.
+    pub fn destroy(mut self) -> Result<(), Error> {
+        for space in &mut self.spaces {
+            let space = std::mem::replace(space, openxr_sys::Space::NULL);
+
+            info!("xrDestroySpace()");
+            let result = unsafe { (self.action.fp.destroy_space)(space) };
+            if !result.is_success() {
+                return Err(Error::Xr(result));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for HandPoseAction {
+    fn drop(&mut self) {
+        for &space in &self.spaces {
+            if space == openxr_sys::Space::NULL {
+                continue;
+            }
+
+            info!("xrDestroySpace()");
+            let result = unsafe { (self.action.fp.destroy_space)(space) };
+            if !result.is_success() {
+                warn!("Failed to destroy action space {:?}: {:?}", space, result);
+            }
+        }
+    }
+}
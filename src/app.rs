@@ -0,0 +1,630 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use log::{debug, info, warn};
+
+use crate::{
+    error::Error,
+    frame::FrameContext,
+    frame_stats::{FrameStats, FrameTiming},
+    layers::{self, CompositionLayer, FrameLayers},
+    session::{FrameGuard, Session, SessionEvent},
+    swapchain, thread_priority, time,
+    time::XrTime,
+    Config, Context,
+};
+
+#[cfg(feature = "capture")]
+use crate::capture::{CaptureSlot, ImageData};
+#[cfg(feature = "mirror-window")]
+use crate::mirror;
+
+pub struct App {
+    context: Context,
+    stop_sender: Sender<()>,
+    stop_receiver: Receiver<()>,
+    /// Set between `pause_rendering` and `resume_rendering`
+    rendering_paused: Arc<AtomicBool>,
+}
+
+/// What the render callback wants the frame loop to do next.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ControlFlow {
+    Continue,
+    /// Asks the session to exit, like `StopHandle::stop`
+    Exit,
+}
+
+/// Lets any thread, the render callback included, ask `App::run` to stop or to pause rendering.
+#[derive(Clone)]
+pub struct StopHandle {
+    stop_sender: Sender<()>,
+    rendering_paused: Arc<AtomicBool>,
+    #[cfg(feature = "capture")]
+    capture_slot: Arc<CaptureSlot>,
+}
+
+impl StopHandle {
+    /// Requests the session to exit, `App::run` returning once the runtime has wound it down.
+    pub fn stop(&self) {
+        // The receiver only goes away with the app, at which point there's nothing left to stop
+        let _ = self.stop_sender.send(());
+    }
+
+    /// See `App::pause_rendering`.
+    pub fn pause_rendering(&self) {
+        self.rendering_paused.store(true, Ordering::Relaxed);
+    }
+
+    /// See `App::resume_rendering`.
+    pub fn resume_rendering(&self) {
+        self.rendering_paused.store(false, Ordering::Relaxed);
+    }
+
+    /// See `App::capture_frame`.
+    #[cfg(feature = "capture")]
+    pub fn capture_frame(&self) -> Option<ImageData> {
+        self.capture_slot.take_and_request()
+    }
+}
+
+impl App {
+    pub fn new(config: Config) -> Result<Self, Error> {
+        let (stop_sender, stop_receiver) = mpsc::channel();
+
+        Ok(App {
+            context: Context::new(config)?,
+            stop_sender,
+            stop_receiver,
+            rendering_paused: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    pub fn stop_handle(&self) -> StopHandle {
+        StopHandle {
+            stop_sender: self.stop_sender.clone(),
+            rendering_paused: Arc::clone(&self.rendering_paused),
+            #[cfg(feature = "capture")]
+            capture_slot: Arc::clone(&self.context.capture_slot),
+        }
+    }
+
+    /// Stops calling the render callback, from the next frame on, while keeping the session
+    /// running: events are still polled, and frames still submitted, without layers, so the
+    /// runtime keeps its cadence and shows its own content, such as a system dialog, meanwhile.
+    /// The events received in between are handed to the callback once rendering resumes.
+    ///
+    /// Unlike a stop, which goes through xrRequestExitSession and ends the session for good, this
+    /// is invisible to the runtime. Use `StopHandle::pause_rendering` while `run` is going on.
+    pub fn pause_rendering(&self) {
+        self.rendering_paused.store(true, Ordering::Relaxed);
+    }
+
+    /// Calls the render callback again, from the next frame on, after `pause_rendering`.
+    pub fn resume_rendering(&self) {
+        self.rendering_paused.store(false, Ordering::Relaxed);
+    }
+
+    /// Returns the left eye of the frame captured since the previous call, if any, and has the
+    /// next rendered frame captured. The first call only asks for a capture, so polling this,
+    /// for instance from a `StopHandle` while `run` is going on, gives one image per poll, a
+    /// frame late.
+    ///
+    /// The image is copied to host memory while the swapchain image is still acquired, after
+    /// the frame's commands and before it's handed back to the runtime, and that copy is waited
+    /// for, which costs some frame time. What gets captured is the view's sub-image, as
+    /// submitted. Nothing is captured from multisampled or protected swapchains, or formats other
+    /// than 8-bit RGBA and BGRA.
+    #[cfg(feature = "capture")]
+    pub fn capture_frame(&self) -> Option<ImageData> {
+        self.context.capture_slot.take_and_request()
+    }
+
+    /// The session, to set up the actions before `run`.
+    pub fn session_mut(&mut self) -> &mut Session {
+        &mut self.context.session
+    }
+
+    /// Runs the frame loop until the runtime asks the session to exit, or a stop is requested
+    /// through a `StopHandle` or by `render` returning `ControlFlow::Exit`. `render` is called
+    /// once per frame the runtime wants rendered.
+    ///
+    /// A stop request calls xrRequestExitSession, and the loop keeps going, frames included,
+    /// until the runtime has taken the session through STOPPING to EXITING, which is also how a
+    /// runtime-initiated exit ends the loop. Requests made after the first one are ignored.
+    ///
+    /// With `Config::render_thread`, the loop runs on a dedicated thread and `render` is called
+    /// from there, hence the `Send` bound. This call still only returns once the loop is over.
+    ///
+    /// The swapchains get recreated when the session restarts, and when `render` returns an
+    /// error for which `Error::is_out_of_date` is true, instead of stopping the loop.
+    pub fn run<F>(&mut self, mut render: F) -> Result<(), Error>
+    where
+        F: FnMut(&mut FrameContext) -> Result<ControlFlow, Error> + Send,
+    {
+        let context = &mut self.context;
+        let stop_receiver = &mut self.stop_receiver;
+        let rendering_paused = &*self.rendering_paused;
+
+        if !context.config.render_thread {
+            return run_loop(context, stop_receiver, rendering_paused, &mut render);
+        }
+
+        #[cfg(target_os = "android")]
+        register_android_thread(
+            &context.session,
+            openxr_sys::AndroidThreadTypeKHR::APPLICATION_MAIN,
+        );
+
+        std::thread::scope(|scope| {
+            let render_thread = std::thread::Builder::new()
+                .name("xr-render".to_string())
+                .spawn_scoped(scope, move || {
+                    run_loop(context, stop_receiver, rendering_paused, &mut render)
+                })
+                .expect("Failed to spawn the render thread");
+
+            render_thread
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+        })
+    }
+
+    /// Timings of the last frames, see `Session::frame_stats`.
+    pub fn frame_stats(&self) -> &FrameStats {
+        self.context.session.frame_stats()
+    }
+
+    /// How long each step of the initialization took.
+    #[cfg(feature = "timing")]
+    pub fn init_timings(&self) -> &crate::timing::InitTimings {
+        self.context.init_timings()
+    }
+
+    /// Tears down the underlying context, see `Context::destroy`.
+    pub fn destroy(self) -> Result<(), Error> {
+        self.context.destroy()
+    }
+}
+
+fn run_loop<F>(
+    context: &mut Context,
+    stop_receiver: &mut Receiver<()>,
+    rendering_paused: &AtomicBool,
+    render: &mut F,
+) -> Result<(), Error>
+where
+    F: FnMut(&mut FrameContext) -> Result<ControlFlow, Error>,
+{
+    // Whether the app wants to stop, and whether the runtime has been told about it
+    let mut stop_wanted = false;
+    let mut exit_requested = false;
+    // Events waiting for the next frame to be handed to `render`
+    let mut events = Vec::new();
+    // Last rendered frame, kept to be resubmitted with `Config::submit_rate_divisor`
+    let mut last_frame = None;
+
+    // This is the thread submitting the frames, whether it's a dedicated one or not
+    #[cfg(target_os = "android")]
+    register_android_thread(
+        &context.session,
+        openxr_sys::AndroidThreadTypeKHR::RENDERER_MAIN,
+    );
+
+    if context.config.render_thread_priority {
+        match thread_priority::raise_current_thread_priority() {
+            Ok(()) => info!("Raised the priority of the frame loop thread"),
+            Err(error) => warn!(
+                "Failed to raise the priority of the frame loop thread: {}",
+                error
+            ),
+        }
+    }
+
+    // Attaching is what makes the eye gaze action usable, when the app didn't do it itself
+    if !context.session.action_sets_attached() {
+        context.session.attach_action_sets(&[])?;
+    }
+
+    // The event loop stays on this thread, as the window has to be created on the thread
+    // handling its events
+    #[cfg(feature = "mirror-window")]
+    let mut event_loop = if context.config.mirror_window {
+        let event_loop = mirror::create_event_loop();
+        context.open_mirror(mirror::create_window(&event_loop))?;
+        Some(event_loop)
+    } else {
+        None
+    };
+
+    loop {
+        #[cfg(feature = "mirror-window")]
+        if let Some(event_loop) = &mut event_loop {
+            if mirror::pump_events(event_loop) {
+                context.close_mirror()?;
+            }
+        }
+
+        events.extend(context.session.poll_events()?);
+
+        if context.session.is_exiting() {
+            return Ok(());
+        }
+
+        stop_wanted |= stop_receiver.try_recv().is_ok();
+        if stop_wanted && !exit_requested {
+            if !context.session.is_running() {
+                return Ok(());
+            }
+            // The runtime then takes the session through STOPPING and EXITING
+            context.session.request_exit()?;
+            exit_requested = true;
+        }
+
+        if !context.session.is_running() {
+            // Only once it has been running, the swapchains being needed right away otherwise
+            if context.config.suspend_when_stopped && context.session.begun {
+                context.suspend()?;
+            }
+            // Nothing to do until the runtime tells us the session is ready
+            std::thread::sleep(Duration::from_millis(100));
+            continue;
+        }
+
+        if context.is_suspended() {
+            // The swapchains are created anew, so there's no need to recreate them on a restart
+            context.resume()?;
+            context.session.restarted = false;
+        }
+
+        if context.session.restarted {
+            info!("Session restarted, recreating the swapchains");
+            context.recreate_swapchains()?;
+            context.session.restarted = false;
+        }
+
+        let paused = rendering_paused.load(Ordering::Relaxed);
+        match frame(context, render, &mut events, &mut last_frame, paused) {
+            // The frame guard already ended the frame without layers, and its images went back
+            Err(error) if error.is_out_of_date() => {
+                warn!("Render targets out of date, recreating the swapchains");
+                context.recreate_swapchains()?;
+            }
+            result => stop_wanted |= result? == ControlFlow::Exit,
+        }
+    }
+}
+
+/// Hints the runtime about the calling thread when it supports XR_KHR_android_thread_settings. A
+/// failure only costs some scheduling, so it's logged rather than returned.
+#[cfg(target_os = "android")]
+fn register_android_thread(session: &Session, thread_type: openxr_sys::AndroidThreadTypeKHR) {
+    match session.set_android_thread(thread_type) {
+        Ok(()) | Err(Error::MissingExtension(_)) => {}
+        Err(error) => warn!("Failed to register the {:?} thread: {}", thread_type, error),
+    }
+}
+
+/// What a rendered frame submitted, to submit it again.
+struct SubmittedFrame {
+    views: Vec<openxr_sys::View>,
+    sub_images: Vec<openxr_sys::SwapchainSubImage>,
+    depth_sub_images: Option<Vec<openxr_sys::SwapchainSubImage>>,
+    projection_layer_flags: openxr_sys::CompositionLayerFlags,
+    layers: Vec<Box<dyn CompositionLayer>>,
+    /// How many times it's been submitted again
+    resubmissions: u32,
+}
+
+fn frame<F>(
+    context: &mut Context,
+    render: &mut F,
+    events: &mut Vec<SessionEvent>,
+    last_frame: &mut Option<SubmittedFrame>,
+    rendering_paused: bool,
+) -> Result<ControlFlow, Error>
+where
+    F: FnMut(&mut FrameContext) -> Result<ControlFlow, Error>,
+{
+    let wait_start = Instant::now();
+    let frame_state = context.session.wait_frame()?;
+    let wait_time = wait_start.elapsed();
+    let work_start = Instant::now();
+    let display_time = XrTime::from(frame_state.predicted_display_time);
+
+    // Before anything gets located in the space, the frame kept for resubmission being in the
+    // old one
+    if context.session.take_recenter_request() {
+        context.recenter(display_time)?;
+        *last_frame = None;
+    }
+
+    let session = &context.session;
+    // From here on, returning early or unwinding from a panic in `render` still ends the frame
+    let frame_guard = session.begin_guarded_frame(display_time)?;
+
+    let display_period = time::to_duration(frame_state.predicted_display_period);
+    // A paused frame is submitted like one the runtime doesn't want rendered
+    let should_render = bool::from(frame_state.should_render) && !rendering_paused;
+    if !should_render {
+        frame_guard.end(session, FrameLayers::new())?;
+        context.session.frame_stats.record(FrameTiming {
+            cpu_time: work_start.elapsed(),
+            wait_time,
+            gpu_time: None,
+            gpu_view_times: Vec::new(),
+        });
+        return Ok(ControlFlow::Continue);
+    }
+
+    // The frame is resubmitted with the poses late-latched, located for this frame's display
+    // time, the parts of them that aren't valid staying the previous ones. The swapchains have
+    // to be the same, the frame being rendered anew after they're recreated.
+    if let Some(last_frame) = last_frame {
+        let swapchains_current = last_frame.sub_images.iter().all(|sub_image| {
+            session
+                .swapchains
+                .iter()
+                .any(|swapchain| swapchain.handle == sub_image.swapchain)
+        });
+        if swapchains_current && last_frame.resubmissions + 1 < context.config.submit_rate_divisor {
+            last_frame.resubmissions += 1;
+            let (view_state_flags, views) = session.locate_views_at(
+                context.space,
+                display_time,
+                session.view_configuration_type(),
+            )?;
+            for (submitted, view) in last_frame.views.iter_mut().zip(&views) {
+                if view_state_flags.contains(openxr_sys::ViewStateFlags::ORIENTATION_VALID) {
+                    submitted.pose.orientation = view.pose.orientation;
+                }
+                if view_state_flags.contains(openxr_sys::ViewStateFlags::POSITION_VALID) {
+                    submitted.pose.position = view.pose.position;
+                }
+            }
+            end_frame(context, frame_guard, last_frame)?;
+            context.session.frame_stats.record(FrameTiming {
+                cpu_time: work_start.elapsed(),
+                wait_time,
+                gpu_time: None,
+                gpu_view_times: Vec::new(),
+            });
+            return Ok(ControlFlow::Continue);
+        }
+    }
+
+    let (view_state_flags, views) = session.locate_views_at(
+        context.space,
+        display_time,
+        session.view_configuration_type(),
+    )?;
+    // Input belongs to whatever has the focus, such as a system menu, while merely visible
+    if session.is_focused() {
+        session.sync_actions()?;
+    }
+
+    let layout = context.swapchain_layout();
+    let render_passes = context
+        .config
+        .render_order
+        .passes(layout, views.len() as u32);
+    let view_order: Vec<u32> = render_passes.iter().flatten().copied().collect();
+
+    // Swapchains in the order their first view gets rendered
+    let mut swapchain_order: Vec<usize> = Vec::with_capacity(context.session.swapchains.len());
+    for &view in &view_order {
+        let swapchain_index = layout.swapchain_index(view);
+        if !swapchain_order.contains(&swapchain_index) {
+            swapchain_order.push(swapchain_index);
+        }
+    }
+
+    // Whether anything gets drawn in them or not, the images have to go through an
+    // acquire/release cycle before their swapchain can be submitted. They're all acquired
+    // before anything is recorded, see `Config::render_order`.
+    let mut image_indices = vec![0; context.session.swapchains.len()];
+    for (acquired, &swapchain_index) in swapchain_order.iter().enumerate() {
+        match context.session.swapchains[swapchain_index].acquire_image() {
+            Ok(index) => image_indices[swapchain_index] = index,
+            // The frame still gets ended, without layers, so the frame timing stays on track. The
+            // images acquired from the previous swapchains go back first.
+            Err(Error::ImageUnavailable(result)) => {
+                debug!(
+                    "Skipping the frame, swapchain image unavailable: {:?}",
+                    result
+                );
+                for &swapchain_index in &swapchain_order[..acquired] {
+                    context.session.swapchains[swapchain_index].release_image()?;
+                }
+                frame_guard.end(session, FrameLayers::new())?;
+                context.session.frame_stats.record(FrameTiming {
+                    cpu_time: work_start.elapsed(),
+                    wait_time,
+                    gpu_time: None,
+                    gpu_view_times: Vec::new(),
+                });
+                return Ok(ControlFlow::Continue);
+            }
+            Err(error) => return Err(error),
+        }
+    }
+    // Where each view gets rendered, and what the compositor gets to upscale
+    let sub_images: Vec<_> = (0..views.len() as u32)
+        .map(|view| {
+            let swapchain = &context.session.swapchains[layout.swapchain_index(view)];
+            swapchain::scale_sub_image(swapchain.sub_image(layout, view), context.render_scale)
+        })
+        .collect();
+    // On errors from here on, the images acquired go back before the guard ends the frame
+    let gpu_view_times =
+        match context.draw_debug_grid(display_time, &sub_images, &image_indices, &view_order) {
+            Ok(gpu_view_times) => gpu_view_times,
+            Err(error) => return Err(release_after(context, &swapchain_order, error)),
+        };
+
+    // Lets the passthrough layer show through where the app renders a transparent color
+    let projection_layer_flags = if session.passthrough_layer().is_some() {
+        openxr_sys::CompositionLayerFlags::BLEND_TEXTURE_SOURCE_ALPHA
+    } else {
+        openxr_sys::CompositionLayerFlags::EMPTY
+    };
+
+    // Blocks while the GPU is still busy with the frame that last used the slot
+    let (frame_slot, command_buffer) = match context.session.recorder().begin() {
+        Ok(begun) => begun,
+        Err(error) => return Err(release_after(context, &swapchain_order, error)),
+    };
+    let session = &context.session;
+
+    let mut frame = FrameContext::new(
+        display_time,
+        display_period,
+        views,
+        view_state_flags,
+        layout,
+        context.shader_output,
+        render_passes,
+        sub_images,
+        context.render_scale,
+        command_buffer,
+        frame_slot,
+        session,
+        std::mem::take(events),
+        gpu_view_times,
+        (context.config.near_plane, context.config.far_plane),
+        context.config.reverse_z,
+        context.view_space,
+        &context.enabled_extensions,
+        projection_layer_flags,
+    );
+    let rendered = render(&mut frame);
+    let FrameContext {
+        views,
+        sub_images,
+        next_render_scale,
+        layers,
+        projection_layer_flags,
+        gpu_time,
+        gpu_view_times,
+        depth_sub_images,
+        ..
+    } = frame;
+    context.render_scale = next_render_scale;
+
+    // Whatever `render` returned, the recording has to be submitted before the images are
+    // released
+    let submitted = context.session.recorder().submit();
+    let control_flow = match rendered.and_then(|control_flow| submitted.map(|()| control_flow)) {
+        Ok(control_flow) => control_flow,
+        Err(error) => return Err(release_after(context, &swapchain_order, error)),
+    };
+
+    #[cfg(feature = "mirror-window")]
+    if let Err(error) = context.draw_mirror(&image_indices, &sub_images[0]) {
+        return Err(release_after(context, &swapchain_order, error));
+    }
+    #[cfg(feature = "capture")]
+    if let Err(error) = context.capture_view(&image_indices, &sub_images[0]) {
+        return Err(release_after(context, &swapchain_order, error));
+    }
+
+    context.session.release_images(&swapchain_order)?;
+
+    let submitted = SubmittedFrame {
+        views,
+        sub_images,
+        depth_sub_images,
+        projection_layer_flags,
+        layers,
+        resubmissions: 0,
+    };
+    end_frame(context, frame_guard, &submitted)?;
+    if context.config.submit_rate_divisor > 1 {
+        *last_frame = Some(submitted);
+    }
+
+    context.session.frame_stats.record(FrameTiming {
+        cpu_time: work_start.elapsed(),
+        wait_time,
+        gpu_time,
+        gpu_view_times,
+    });
+    Ok(control_flow)
+}
+
+/// Releases the images acquired from the swapchains at `swapchain_indices` when the frame fails
+/// with `error`, which is handed back, a failure to release them only being logged. The frame
+/// guard then ends the frame without layers, as `Session::render_and_submit` does.
+fn release_after(context: &Context, swapchain_indices: &[usize], error: Error) -> Error {
+    if let Err(release_error) = context.session.release_images(swapchain_indices) {
+        warn!("Failed to release the swapchain images: {}", release_error);
+    }
+    error
+}
+
+/// Ends the frame with the projection layer of `submitted`, over the passthrough layer if any,
+/// and its other layers on top.
+fn end_frame(
+    context: &Context,
+    frame_guard: FrameGuard,
+    submitted: &SubmittedFrame,
+) -> Result<(), Error> {
+    let session = &context.session;
+
+    // Chained to the projection views, so kept alive until the frame is submitted
+    let depth_infos: Option<Vec<_>> = submitted.depth_sub_images.as_ref().map(|sub_images| {
+        sub_images
+            .iter()
+            .map(|&sub_image| {
+                layers::depth_info(
+                    sub_image,
+                    context.config.near_plane,
+                    context.config.far_plane,
+                    context.config.reverse_z,
+                )
+            })
+            .collect()
+    });
+    let projection_views: Vec<_> = submitted
+        .views
+        .iter()
+        .enumerate()
+        .map(|(index, view)| openxr_sys::CompositionLayerProjectionView {
+            ty: openxr_sys::CompositionLayerProjectionView::TYPE,
+            next: depth_infos
+                .as_ref()
+                .map_or(std::ptr::null(), |depth_infos| {
+                    &depth_infos[index] as *const _ as *const std::ffi::c_void
+                }),
+            pose: view.pose,
+            fov: view.fov,
+            sub_image: submitted.sub_images[index],
+        })
+        .collect();
+
+    let projection_layer = openxr_sys::CompositionLayerProjection {
+        ty: openxr_sys::CompositionLayerProjection::TYPE,
+        next: std::ptr::null(),
+        layer_flags: submitted.projection_layer_flags,
+        space: context.space,
+        view_count: projection_views.len() as u32,
+        views: projection_views.as_ptr(),
+    };
+
+    let mut frame_layers = FrameLayers::new();
+    if let Some(passthrough_layer) = session.passthrough_layer() {
+        frame_layers.push(passthrough_layer);
+    }
+    frame_layers.push(&projection_layer);
+    for layer in &submitted.layers {
+        frame_layers.push(layer.as_ref());
+    }
+
+    frame_guard.end(session, frame_layers)
+}
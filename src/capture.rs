@@ -0,0 +1,345 @@
+//! Copies of the rendered left eye to CPU memory, for screenshots or streaming, see
+//! `App::capture_frame`.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex,
+};
+
+use ash::{
+    version::{DeviceV1_0, InstanceV1_0},
+    vk,
+};
+use log::warn;
+
+use crate::{error::Error, swapchain::Swapchain};
+
+/// Pixels of a captured view, as tightly packed 8-bit RGBA rows, top one first.
+#[derive(Clone, Debug)]
+pub struct ImageData {
+    pub width: u32,
+    pub height: u32,
+    /// `width * height * 4` bytes, encoded like the swapchain: sRGB with the default formats,
+    /// which is what image files expect
+    pub rgba: Vec<u8>,
+}
+
+/// Capture requests and results, shared by the context, the app and its `StopHandle`s.
+#[derive(Default)]
+pub(crate) struct CaptureSlot {
+    requested: AtomicBool,
+    image: Mutex<Option<ImageData>>,
+}
+
+impl CaptureSlot {
+    /// Takes the image captured since the previous call, if any, and asks for the next frame to
+    /// be captured.
+    pub(crate) fn take_and_request(&self) -> Option<ImageData> {
+        let image = self.image.lock().unwrap().take();
+        self.requested.store(true, Ordering::Relaxed);
+        image
+    }
+
+    /// Whether a capture was asked for, the request being cleared.
+    pub(crate) fn take_request(&self) -> bool {
+        self.requested.swap(false, Ordering::Relaxed)
+    }
+
+    pub(crate) fn store(&self, image: ImageData) {
+        *self.image.lock().unwrap() = Some(image);
+    }
+}
+
+/// Where the red, green, blue and alpha bytes are in a pixel of `format`, for the formats that
+/// can be captured.
+fn channel_offsets(format: vk::Format) -> Option<[usize; 4]> {
+    match format {
+        vk::Format::R8G8B8A8_SRGB | vk::Format::R8G8B8A8_UNORM => Some([0, 1, 2, 3]),
+        vk::Format::B8G8R8A8_SRGB | vk::Format::B8G8R8A8_UNORM => Some([2, 1, 0, 3]),
+        _ => None,
+    }
+}
+
+/// Whether the images of `swapchain` can be copied by `FrameCapture::capture`, with a warning
+/// when they can't.
+pub(crate) fn can_capture(swapchain: &Swapchain) -> bool {
+    if swapchain.sample_count != 1 {
+        warn!(
+            "The eye swapchains have {} samples, they can't be captured",
+            swapchain.sample_count
+        );
+        return false;
+    }
+    if channel_offsets(swapchain.format).is_none() {
+        warn!(
+            "Swapchain format {:?} isn't 8-bit RGBA or BGRA, it can't be captured",
+            swapchain.format
+        );
+        return false;
+    }
+    true
+}
+
+/// Buffer the captures are copied to, along with the commands doing so.
+pub(crate) struct FrameCapture {
+    command_pool: vk::CommandPool,
+    command_buffer: vk::CommandBuffer,
+    /// Signaled once the copy is done
+    fence: vk::Fence,
+    /// Host visible, grown as needed, null until the first capture
+    buffer: vk::Buffer,
+    memory: vk::DeviceMemory,
+    capacity: vk::DeviceSize,
+}
+
+impl FrameCapture {
+    pub(crate) fn new(device: &ash::Device, queue_family_index: u32) -> Result<Self, Error> {
+        let command_pool_info = vk::CommandPoolCreateInfo {
+            flags: vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
+            queue_family_index,
+            ..Default::default()
+        };
+        let command_pool = unsafe { device.create_command_pool(&command_pool_info, None)? };
+
+        let command_buffer_info = vk::CommandBufferAllocateInfo {
+            command_pool,
+            level: vk::CommandBufferLevel::PRIMARY,
+            command_buffer_count: 1,
+            ..Default::default()
+        };
+        let command_buffer = unsafe { device.allocate_command_buffers(&command_buffer_info)? }[0];
+        let fence = unsafe { device.create_fence(&vk::FenceCreateInfo::default(), None)? };
+
+        Ok(FrameCapture {
+            command_pool,
+            command_buffer,
+            fence,
+            buffer: vk::Buffer::null(),
+            memory: vk::DeviceMemory::null(),
+            capacity: 0,
+        })
+    }
+
+    /// Copies `sub_image` of the image at `image_index` of `source` and reads it back, waiting
+    /// for the copy to be done. The image must be acquired, pass `can_capture`, and be in the
+    /// color attachment layout, which it's left in. The runtime owns it again once released, so
+    /// this has to happen before.
+    pub(crate) fn capture(
+        &mut self,
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        device: &ash::Device,
+        queue: vk::Queue,
+        source: &Swapchain,
+        sub_image: &openxr_sys::SwapchainSubImage,
+        image_index: u32,
+    ) -> Result<ImageData, Error> {
+        let rect = sub_image.image_rect;
+        let (width, height) = (rect.extent.width as u32, rect.extent.height as u32);
+        let size = vk::DeviceSize::from(width) * vk::DeviceSize::from(height) * 4;
+        if size > self.capacity {
+            self.grow(instance, physical_device, device, size)?;
+        }
+
+        let source_image = source.images[image_index as usize];
+        let image_barrier =
+            |old_layout, new_layout, src_access_mask, dst_access_mask| vk::ImageMemoryBarrier {
+                src_access_mask,
+                dst_access_mask,
+                old_layout,
+                new_layout,
+                src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                image: source_image,
+                subresource_range: vk::ImageSubresourceRange {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    base_mip_level: 0,
+                    level_count: 1,
+                    base_array_layer: sub_image.image_array_index,
+                    layer_count: 1,
+                },
+                ..Default::default()
+            };
+        // Rows are tightly packed, a zero row length meaning the image's width
+        let region = vk::BufferImageCopy {
+            buffer_offset: 0,
+            buffer_row_length: 0,
+            buffer_image_height: 0,
+            image_subresource: vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: 0,
+                base_array_layer: sub_image.image_array_index,
+                layer_count: 1,
+            },
+            image_offset: vk::Offset3D {
+                x: rect.offset.x,
+                y: rect.offset.y,
+                z: 0,
+            },
+            image_extent: vk::Extent3D {
+                width,
+                height,
+                depth: 1,
+            },
+        };
+        // Makes the copy visible to the reads through the mapping
+        let buffer_barrier = vk::BufferMemoryBarrier {
+            src_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+            dst_access_mask: vk::AccessFlags::HOST_READ,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            buffer: self.buffer,
+            offset: 0,
+            size,
+            ..Default::default()
+        };
+
+        let begin_info = vk::CommandBufferBeginInfo {
+            flags: vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+            ..Default::default()
+        };
+
+        unsafe {
+            let command_buffer = self.command_buffer;
+            device.begin_command_buffer(command_buffer, &begin_info)?;
+
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[image_barrier(
+                    vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                    vk::AccessFlags::TRANSFER_READ,
+                )],
+            );
+            device.cmd_copy_image_to_buffer(
+                command_buffer,
+                source_image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                self.buffer,
+                &[region],
+            );
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::HOST,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[buffer_barrier],
+                &[image_barrier(
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                    vk::AccessFlags::TRANSFER_READ,
+                    vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                )],
+            );
+
+            device.end_command_buffer(command_buffer)?;
+
+            let submit_info = vk::SubmitInfo {
+                command_buffer_count: 1,
+                p_command_buffers: &command_buffer,
+                ..Default::default()
+            };
+            device.queue_submit(queue, &[submit_info], self.fence)?;
+            device.wait_for_fences(&[self.fence], true, u64::MAX)?;
+            device.reset_fences(&[self.fence])?;
+        }
+
+        let offsets = channel_offsets(source.format).expect("Capturing an unsupported format");
+        let rgba = unsafe {
+            let data = device.map_memory(self.memory, 0, size, vk::MemoryMapFlags::empty())?;
+            let pixels = std::slice::from_raw_parts(data as *const u8, size as usize);
+            let rgba = pixels
+                .chunks_exact(4)
+                .flat_map(|pixel| offsets.iter().map(move |&offset| pixel[offset]))
+                .collect();
+            device.unmap_memory(self.memory);
+            rgba
+        };
+
+        Ok(ImageData {
+            width,
+            height,
+            rgba,
+        })
+    }
+
+    /// Replaces the buffer with one of `size` bytes.
+    fn grow(
+        &mut self,
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        device: &ash::Device,
+        size: vk::DeviceSize,
+    ) -> Result<(), Error> {
+        self.destroy_buffer(device);
+
+        let buffer_info = vk::BufferCreateInfo {
+            size,
+            usage: vk::BufferUsageFlags::TRANSFER_DST,
+            sharing_mode: vk::SharingMode::EXCLUSIVE,
+            ..Default::default()
+        };
+        let buffer = unsafe { device.create_buffer(&buffer_info, None)? };
+        let requirements = unsafe { device.get_buffer_memory_requirements(buffer) };
+
+        // Cached memory makes reading back much faster, where there's such a type
+        let memory_properties =
+            unsafe { instance.get_physical_device_memory_properties(physical_device) };
+        let find_memory_type = |wanted: vk::MemoryPropertyFlags| {
+            (0..memory_properties.memory_type_count).find(|&index| {
+                requirements.memory_type_bits & (1 << index) != 0
+                    && memory_properties.memory_types[index as usize]
+                        .property_flags
+                        .contains(wanted)
+            })
+        };
+        let visible =
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
+        let memory_type_index = find_memory_type(visible | vk::MemoryPropertyFlags::HOST_CACHED)
+            .or_else(|| find_memory_type(visible))
+            .expect("No host visible memory type for the captures");
+
+        let allocate_info = vk::MemoryAllocateInfo {
+            allocation_size: requirements.size,
+            memory_type_index,
+            ..Default::default()
+        };
+
+        unsafe {
+            let memory = device.allocate_memory(&allocate_info, None)?;
+            device.bind_buffer_memory(buffer, memory, 0)?;
+            self.memory = memory;
+        }
+        self.buffer = buffer;
+        self.capacity = size;
+        Ok(())
+    }
+
+    fn destroy_buffer(&mut self, device: &ash::Device) {
+        unsafe {
+            if self.buffer != vk::Buffer::null() {
+                device.destroy_buffer(self.buffer, None);
+                device.free_memory(self.memory, None);
+            }
+        }
+        self.buffer = vk::Buffer::null();
+        self.memory = vk::DeviceMemory::null();
+        self.capacity = 0;
+    }
+
+    /// The device must be done with the last capture, which `capture` waits for anyway.
+    pub(crate) fn destroy(mut self, device: &ash::Device) {
+        self.destroy_buffer(device);
+        unsafe {
+            device.destroy_fence(self.fence, None);
+            device.destroy_command_pool(self.command_pool, None);
+        }
+    }
+}
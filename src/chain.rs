@@ -0,0 +1,45 @@
+use std::any::Any;
+
+/// An OpenXR structure to be linked into the `next` chain of a create info.
+///
+/// The structure is kept boxed, so its address stays the same until the `NextStruct` is dropped.
+pub struct NextStruct {
+    header: *mut openxr_sys::BaseInStructure,
+    _data: Box<dyn Any>,
+}
+
+// OpenXR structures aren't `Send` because of their `next` pointer, moving them across threads
+// being fine is part of the contract of `new`
+unsafe impl Send for NextStruct {}
+
+impl NextStruct {
+    /// Boxes `value` to be chained later.
+    ///
+    /// # Safety
+    ///
+    /// `value` must be an OpenXR structure, starting with its `ty` and `next` fields, and be
+    /// allowed in the chain it's added to, with the extension defining it enabled. Its `next`
+    /// field is overwritten when it gets linked. Any pointer it holds must stay valid as long as
+    /// the `NextStruct` is alive, including when it's moved to another thread.
+    pub unsafe fn new<T: 'static>(value: T) -> Self {
+        let mut data = Box::new(value);
+        let header = &mut *data as *mut T as *mut openxr_sys::BaseInStructure;
+
+        NextStruct {
+            header,
+            _data: data,
+        }
+    }
+}
+
+/// Links `structs` together in order, the last one pointing to `tail`, and returns the head of
+/// the chain. `tail` is returned as is when `structs` is empty.
+pub(crate) fn link(
+    structs: &mut [NextStruct],
+    tail: *const openxr_sys::BaseInStructure,
+) -> *const openxr_sys::BaseInStructure {
+    structs.iter_mut().rev().fold(tail, |next, next_struct| {
+        unsafe { (*next_struct.header).next = next };
+        next_struct.header
+    })
+}
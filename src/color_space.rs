@@ -0,0 +1,57 @@
+//! XR_FB_color_space, which openxr-sys 0.8 predates, hence the definitions below taken from the
+//! extension's spec.
+
+use openxr_sys::Result as XrResult;
+
+pub(crate) const EXTENSION: &str = "XR_FB_color_space";
+
+/// Color space of the submitted images, which the compositor converts from to the display's.
+/// Runtimes without XR_FB_color_space assume Rec. 709, while Quest runtimes with it default to
+/// the Rift CV1 space, so content mastered for sRGB monitors looks oversaturated until `Rec709`
+/// is set.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorSpace {
+    /// No conversion, the images being shown as the display's native colors
+    Unmanaged,
+    Rec2020,
+    /// Same primaries as sRGB
+    Rec709,
+    RiftCv1,
+    RiftS,
+    Quest,
+    P3,
+    AdobeRgb,
+}
+
+impl ColorSpace {
+    pub(crate) fn to_raw(self) -> i32 {
+        match self {
+            ColorSpace::Unmanaged => 0,
+            ColorSpace::Rec2020 => 1,
+            ColorSpace::Rec709 => 2,
+            ColorSpace::RiftCv1 => 3,
+            ColorSpace::RiftS => 4,
+            ColorSpace::Quest => 5,
+            ColorSpace::P3 => 6,
+            ColorSpace::AdobeRgb => 7,
+        }
+    }
+
+    pub(crate) fn from_raw(raw: i32) -> Option<Self> {
+        Some(match raw {
+            0 => ColorSpace::Unmanaged,
+            1 => ColorSpace::Rec2020,
+            2 => ColorSpace::Rec709,
+            3 => ColorSpace::RiftCv1,
+            4 => ColorSpace::RiftS,
+            5 => ColorSpace::Quest,
+            6 => ColorSpace::P3,
+            7 => ColorSpace::AdobeRgb,
+            _ => return None,
+        })
+    }
+}
+
+pub(crate) type EnumerateColorSpacesFB =
+    unsafe extern "system" fn(openxr_sys::Session, u32, *mut u32, *mut i32) -> XrResult;
+pub(crate) type SetColorSpaceFB = unsafe extern "system" fn(openxr_sys::Session, i32) -> XrResult;
@@ -0,0 +1,325 @@
+use std::{ops::RangeInclusive, path::PathBuf, time::Duration};
+
+use openxr_sys::{FormFactor, SwapchainUsageFlags};
+
+use ash::vk;
+
+use crate::{
+    chain::NextStruct,
+    color_space::ColorSpace,
+    foveation::FoveationLevel,
+    render_target,
+    swapchain::{self, SwapchainLayout},
+};
+
+/// Accepted values of `Config::resolution_scale`.
+pub const RESOLUTION_SCALE_RANGE: RangeInclusive<f32> = 0.3..=2.0;
+
+/// Default `Config::max_enumerate_count`.
+pub(crate) const DEFAULT_MAX_ENUMERATE_COUNT: u32 = 1 << 16;
+
+/// Settings used while bringing up the OpenXR and Vulkan state.
+pub struct Config {
+    /// Manifest of the runtime to load, instead of the system's active runtime. It's handed to
+    /// the loader through `XR_RUNTIME_JSON`, overriding that variable if it's already set, for
+    /// this process only. With `None`, `XR_RUNTIME_JSON` is used if set, then the active runtime.
+    /// Ignored on Android, where the loader doesn't support overrides.
+    pub runtime_manifest: Option<PathBuf>,
+    /// Kind of device to get the system for, `HANDHELD_DISPLAY` being the choice for phone-based
+    /// AR. Initialization fails with `Error::FormFactorUnavailable` if the runtime can't provide it.
+    pub form_factor: FormFactor,
+    /// How long to keep retrying, with an exponential backoff, while the runtime reports the form
+    /// factor as unavailable. This is for runtimes that start before the headset is ready.
+    /// With `None`, initialization fails on the first attempt.
+    pub wait_for_system: Option<Duration>,
+    /// OpenXR extensions to enable on top of the ones the context needs, for instance the ones
+    /// defining the structures in `instance_create_next`. Initialization fails with
+    /// `Error::MissingExtension` if one of them isn't available.
+    pub instance_extensions: Vec<&'static str>,
+    /// Structures chained, in order, to `XrInstanceCreateInfo` after the Android one. They're kept
+    /// alive as long as the context. See `NextStruct::new` for what they must be.
+    pub instance_create_next: Vec<NextStruct>,
+    /// Usage flags the XR swapchains are created with.
+    /// The matching Vulkan image usage is derived from these, and the combination is checked
+    /// against what the chosen format supports before creating the swapchains.
+    pub swapchain_usage: SwapchainUsageFlags,
+    /// Formats of the color swapchains, by order of preference, the first one the runtime
+    /// supports being used. The runtime's own preference is used if it supports none of them.
+    ///
+    /// The default prefers the 8-bit sRGB formats. With those, the hardware encodes the linear
+    /// colors written by the shaders, while with linear formats such as the HDR-friendly
+    /// `A2B10G10R10_UNORM_PACK32` or `R16G16B16A16_SFLOAT`, the shaders' output is stored as is
+    /// and the runtime treats it as linear.
+    pub preferred_color_formats: Vec<vk::Format>,
+    /// Has the shaders encode their output to sRGB themselves when the format picked is 8-bit
+    /// UNORM, `Context::shader_output` then being `ShaderOutput::Srgb`. The runtime treating such
+    /// images as linear, this is only right for runtimes that display them as sRGB anyway, where
+    /// linear output looks too dark. Off by default, the debug grid follows it.
+    pub manual_srgb_encode: bool,
+    /// Depth formats to pick `Context::depth_format` from, the first one the device can use as a
+    /// depth attachment. The default goes from `D32_SFLOAT` down to `D16_UNORM`, which every
+    /// device supports. Initialization fails with `Error::NoDepthFormat` if none is supported.
+    pub preferred_depth_formats: Vec<vk::Format>,
+    /// Factor applied to the runtime's recommended swapchain size, to trade quality for
+    /// performance below 1 or to supersample above it. The result is clamped to the maximum
+    /// size the runtime supports. Initialization fails with `Error::InvalidResolutionScale`
+    /// outside of `RESOLUTION_SCALE_RANGE`.
+    pub resolution_scale: f32,
+    /// How many frames the CPU can get ahead of the GPU by, each one having its own
+    /// `FrameContext::command_buffer` and a fence. `App::run` blocks before rendering a frame
+    /// until the GPU is done with the one that used the same command buffer, that many frames
+    /// ago. More frames in flight smooth out GPU spikes at the cost of latency, 2 being the
+    /// usual compromise. Initialization fails with `Error::NoFramesInFlight` if it's 0.
+    ///
+    /// Runtimes usually give swapchains 3 images, and an image can't be acquired again before
+    /// being released, which bounds how far ahead frames can get whatever this is set to.
+    pub max_frames_in_flight: usize,
+    /// How to lay out the views in the swapchains. With `None`, one swapchain with a layer per
+    /// view is used when the device allows it, a single swapchain with the views side by side
+    /// otherwise, and separate swapchains if neither works or the views differ in size. The
+    /// choice is available through `Context::swapchain_layout`. `Array` and `SideBySide` given
+    /// here need views of the same size, and `SideBySide` the view configuration's view count.
+    pub swapchain_layout: Option<SwapchainLayout>,
+    /// Order the views are rendered in, and whether they share a pass, given to the render
+    /// callback as `FrameContext::render_passes`.
+    ///
+    /// Whatever the order, `App::run` acquires the images of every swapchain before calling the
+    /// render callback, submits the frame's commands once, and only then releases them all. This
+    /// is the recommended pattern: releasing an image hands it to the compositor, so it can't
+    /// happen before the commands rendering it are submitted, and acquiring the next swapchain
+    /// between two passes would stall the GPU on the compositor for no gain.
+    pub render_order: RenderOrder,
+    /// Highest sample count the rendering pipeline can handle. The swapchains use the runtime
+    /// recommendation, lowered to this and to what the device supports.
+    pub max_msaa: Option<u32>,
+    /// When xrCreateSwapchain fails with the format and sample count picked, which some
+    /// runtimes do for combinations they advertise, retries single-sampled, then with the other
+    /// formats the runtime supports, the rest of `preferred_color_formats` first. Each downgrade
+    /// gets logged. Without it, the first failure is fatal. On by default.
+    pub swapchain_fallbacks: bool,
+    /// Vulkan device extensions to enable on top of the ones the context needs. Initialization
+    /// fails with `Error::MissingDeviceExtensions` if the device lacks one of them.
+    pub device_extensions: Vec<&'static str>,
+    /// Vulkan device features to enable, such as `sampler_anisotropy`, none by default. The
+    /// context needs none of its own, and with XR_KHR_vulkan_enable the runtime only asks for
+    /// extensions, so these are exactly the ones enabled; asking for one the runtime happens to
+    /// use as well does no harm. Initialization fails with `Error::UnsupportedDeviceFeatures`,
+    /// naming them, if the device lacks any.
+    pub required_vk_features: vk::PhysicalDeviceFeatures,
+    /// Enables the Khronos validation layer and a debug extension (debug utils, or debug report
+    /// as a fallback) when they're available. Nothing debug related is requested otherwise.
+    pub enable_validation: bool,
+    /// Enables XR_EXT_eye_gaze_interaction so the gaze can be queried through
+    /// `Session::eye_gaze_pose`. Initialization fails if the runtime or the system doesn't
+    /// support it.
+    pub eye_gaze: bool,
+    /// Enables XR_FB_passthrough and shows the surroundings behind the projection layer, where
+    /// its alpha is below 1. The frames use the `ALPHA_BLEND` blend mode when the system supports
+    /// it, `OPAQUE` otherwise, the passthrough layer doing the blending on Quest headsets.
+    /// Initialization fails if the runtime or the system doesn't support it.
+    pub passthrough: bool,
+    /// Runs the session as an overlay through XR_EXTX_overlay, its layers being composited over
+    /// the main application's instead of replacing them. Initialization fails with
+    /// `Error::MissingExtension` if the runtime doesn't support it.
+    pub overlay: bool,
+    /// Where the layers of this overlay session go among the other overlays', higher values
+    /// being composited on top of lower ones, and all of them over the main application. 0 with
+    /// `None`, which leaves overlays sharing the same value in whatever order the runtime picks.
+    /// Only meaningful with `overlay`, initialization failing with
+    /// `Error::OverlayPlacementWithoutOverlay` otherwise.
+    pub overlay_placement: Option<u32>,
+    /// Renders the four views of XR_VARJO_quad_views, a high resolution focus view within each
+    /// eye's context view, when the runtime and the system support them. `FrameContext::views`
+    /// and the swapchains then come as left and right context views followed by left and right
+    /// focus views. Stereo is used otherwise.
+    pub quad_views: bool,
+    /// Color space the images are rendered in, set through XR_FB_color_space so the compositor
+    /// converts them properly. Nothing changes if the runtime lacks the extension or doesn't
+    /// support that color space, besides a warning. See `Session::set_color_space`.
+    pub color_space: Option<ColorSpace>,
+    /// Enables fixed foveation at this level through XR_FB_foveation, its configuration and
+    /// Vulkan extensions and XR_FB_swapchain_update_state, the swapchains being created with the
+    /// fragment density map flag it needs. The level can then be changed with
+    /// `Session::set_foveation_level`. Initialization fails with `Error::MissingExtension` if the
+    /// runtime lacks one of the extensions.
+    pub foveation: Option<FoveationLevel>,
+    /// Enables XR_MSFT_controller_model when available, to render the controllers with the
+    /// runtime's models, see `Session::controller_model_key`.
+    pub controller_models: bool,
+    /// Fails the initialization with `Error::UnavailableFeatures`, before the instance gets
+    /// created, if the runtime lacks the extension of one of the features above that otherwise
+    /// fall back with a warning, such as `quad_views`, `color_space` or `controller_models`.
+    /// Either way, `Context::unavailable_features` lists what this run goes without.
+    pub require_features: bool,
+    /// Runs the frame loop of `App::run` on a dedicated thread instead of the calling one.
+    /// The whole context, session included, then lives on that thread for the duration of the
+    /// loop, so every frame and Vulkan call happens there. The calling thread just waits for the
+    /// loop to end, and `App::stop_handle` can be used from anywhere to end it.
+    pub render_thread: bool,
+    /// Raises the OS priority of the thread running the frame loop of `App::run`, so it's less
+    /// likely to miss frames when the CPU is busy. It's best effort: on Linux, raising the
+    /// priority needs `CAP_SYS_NICE` or a high enough `RLIMIT_NICE`, and a failure only gets
+    /// logged.
+    pub render_thread_priority: bool,
+    /// Draws a floor grid and the XYZ axes at the origin of the stage space (or the local space
+    /// without a stage) before each `render` call, to check tracking and orientation while
+    /// bringing up an app. The swapchain images get cleared, and `COLOR_ATTACHMENT` is added to
+    /// `swapchain_usage`.
+    pub debug_grid: bool,
+    /// Draws a head-locked "L" or "R" and a crosshair at the center of each view, over the debug
+    /// grid, which this turns on. A letter showing up in the wrong eye means the views are
+    /// swapped somewhere along the way, and crosshairs that don't fuse that the views are off.
+    pub eye_test_pattern: bool,
+    /// Distance in meters of the near clipping plane used by the `FrameContext` matrices.
+    pub near_plane: f32,
+    /// Distance in meters of the far clipping plane used by the `FrameContext` matrices. It can
+    /// be `f32::INFINITY`, which works best with `reverse_z`.
+    pub far_plane: f32,
+    /// Has the `FrameContext` projections map the near plane to depth 1 and the far plane to 0,
+    /// which spreads the float precision much more evenly. The depth buffer must then be cleared
+    /// to 0 and tested with `GREATER`. The depth submitted with `FrameContext::set_depth` is
+    /// described accordingly.
+    pub reverse_z: bool,
+    /// Has `App::run` only render one frame out of this many, the frames in between submitting
+    /// the last rendered one again, for GPU-bound scenes. The projection views of the
+    /// resubmitted frames get late-latched poses, the views being located again for their
+    /// display time just before they're submitted. Animations and moving objects judder, and
+    /// how well it goes depends on the runtime, some of which have their own half-rate mode
+    /// doing better. The layers added along with the rendered frame get resubmitted too,
+    /// so their swapchains must outlive the frames in between. 1, the default, renders every
+    /// frame, as does 0.
+    pub submit_rate_divisor: u32,
+    /// Logs a summary of `Session::frame_stats` this often while `App::run` submits frames.
+    pub frame_stats_log_interval: Option<Duration>,
+    /// Makes `App::run` call `Context::suspend` when the runtime stops the session, which is what
+    /// happens when the Android activity gets paused, and `Context::resume` when it's ready to
+    /// run again. The session isn't suspended before it first runs.
+    pub suspend_when_stopped: bool,
+    /// Highest item count accepted from the runtime's two-call enumerations, such as the
+    /// extensions or the swapchain images, above which they fail with
+    /// `Error::EnumerationTooLarge` rather than attempting a huge allocation. The default is far
+    /// above what real runtimes report. It only applies to the context created with this
+    /// configuration.
+    pub max_enumerate_count: u32,
+    /// Queues to create on the device besides the graphics one.
+    pub queue_config: QueueConfig,
+    /// Renders to protected swapchains, whose content can't be read back, for DRM'd media.
+    /// This creates the Vulkan instance and device with version 1.1, the `protectedMemory`
+    /// feature, and protected-capable queues in the graphics family, the one the runtime gets.
+    /// Falls back to unprotected rendering, with a warning, when the loader, the device or the
+    /// runtime doesn't support it. See `Context::protected_content` for the outcome.
+    ///
+    /// The constraints are substantial. The frames' command buffers are protected, and a protected
+    /// command buffer can't write to unprotected memory, run queries or use sparse resources,
+    /// so every attachment, storage buffer and image the render callback writes to must be created
+    /// with the `PROTECTED` flag, from memory of a protected type, and uploads must happen in
+    /// separate unprotected submissions. Nothing can copy protected images out, so the debug grid
+    /// and the mirror window are left out. GPU timestamps aren't available in the frames either.
+    pub protected_content: bool,
+    /// Opens a desktop window showing the left eye, for spectators, when `App::run` starts.
+    /// The eye image is blitted to it before being released, so `TRANSFER_SRC` is added to
+    /// `swapchain_usage`, and nothing gets mirrored with multisampled swapchains. Closing the
+    /// window only stops the mirroring.
+    #[cfg(feature = "mirror-window")]
+    pub mirror_window: bool,
+}
+
+/// Priorities of the device queues, an extra queue being created for each `Some`.
+///
+/// Extra queues come from a dedicated family when the device has one, and from the graphics
+/// family otherwise. When that family has no queue left, the extra queue is the same as the
+/// graphics one and submissions to them must be synchronized. The runtime is only told about the
+/// graphics queue, through the OpenXR graphics binding, and may submit to it as well during
+/// `xrEndFrame`, so that's the queue to render the swapchain images with.
+#[derive(Clone, Copy, Debug)]
+pub struct QueueConfig {
+    pub graphics_priority: f32,
+    /// Queue for async compute, from a family without graphics if there's one
+    pub compute_priority: Option<f32>,
+    /// Queue for uploads, from a transfer-only family if there's one
+    pub transfer_priority: Option<f32>,
+}
+
+/// How the views are split into render passes, and in which order, see `Config::render_order`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RenderOrder {
+    /// One pass per view, in the order of the view configuration: left eye first.
+    LeftFirst,
+    /// One pass per view, in the reverse order of the view configuration: right eye first.
+    RightFirst,
+    /// A single pass over every view, for renderers drawing all the layers of a
+    /// `SwapchainLayout::Array` swapchain at once with multiview. With the other layouts, the
+    /// views can't share a framebuffer and this falls back to `LeftFirst`.
+    SinglePass,
+}
+
+impl RenderOrder {
+    /// Views rendered by each pass, in the order to render them, for `view_count` views laid out
+    /// as `layout`.
+    pub fn passes(self, layout: SwapchainLayout, view_count: u32) -> Vec<Vec<u32>> {
+        match (self, layout) {
+            (RenderOrder::SinglePass, SwapchainLayout::Array) => vec![(0..view_count).collect()],
+            (RenderOrder::RightFirst, _) => (0..view_count).rev().map(|view| vec![view]).collect(),
+            _ => (0..view_count).map(|view| vec![view]).collect(),
+        }
+    }
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        QueueConfig {
+            graphics_priority: 1.0,
+            compute_priority: None,
+            transfer_priority: None,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            runtime_manifest: None,
+            form_factor: FormFactor::HEAD_MOUNTED_DISPLAY,
+            wait_for_system: None,
+            instance_extensions: Vec::new(),
+            instance_create_next: Vec::new(),
+            swapchain_usage: SwapchainUsageFlags::COLOR_ATTACHMENT | SwapchainUsageFlags::SAMPLED,
+            preferred_color_formats: swapchain::PREFERRED_FORMATS.to_vec(),
+            manual_srgb_encode: false,
+            preferred_depth_formats: render_target::DEPTH_FORMATS.to_vec(),
+            resolution_scale: 1.0,
+            max_frames_in_flight: 2,
+            swapchain_layout: None,
+            render_order: RenderOrder::LeftFirst,
+            max_msaa: None,
+            swapchain_fallbacks: true,
+            device_extensions: Vec::new(),
+            required_vk_features: vk::PhysicalDeviceFeatures::default(),
+            enable_validation: cfg!(debug_assertions),
+            eye_gaze: false,
+            passthrough: false,
+            overlay: false,
+            overlay_placement: None,
+            quad_views: false,
+            color_space: None,
+            foveation: None,
+            controller_models: false,
+            require_features: false,
+            render_thread: false,
+            render_thread_priority: false,
+            debug_grid: false,
+            eye_test_pattern: false,
+            near_plane: 0.05,
+            far_plane: 100.0,
+            reverse_z: false,
+            submit_rate_divisor: 1,
+            frame_stats_log_interval: None,
+            suspend_when_stopped: false,
+            max_enumerate_count: DEFAULT_MAX_ENUMERATE_COUNT,
+            queue_config: QueueConfig::default(),
+            protected_content: false,
+            #[cfg(feature = "mirror-window")]
+            mirror_window: false,
+        }
+    }
+}
@@ -0,0 +1,2228 @@
+use std::{
+    ffi::{c_void, CStr, CString},
+    marker::PhantomData,
+    mem::transmute,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use ash::{
+    extensions::ext::DebugUtils,
+    version::{DeviceV1_0, EntryV1_0, InstanceV1_0},
+    vk::Handle,
+};
+use log::{debug, info, warn};
+use openxr_sys::Result as XrResult;
+
+use crate::{
+    available_vk_device_extensions, available_vk_instance_extensions, chain, color_space, config,
+    create_logical_device, debug,
+    debug_grid::DebugGrid,
+    enumerate,
+    error::{Error, XrResultExt},
+    eye_gaze,
+    feature::{self, UnavailableFeature},
+    foveation,
+    frame_stats::FrameStats,
+    in_flight::InFlightFrames,
+    layers, math, missing_extensions, passthrough, render_target,
+    session::{self, FrameRecorder, Session},
+    spatial_anchor, split_extension_list, supports_protected_memory,
+    swapchain::{self, ShaderOutput, Swapchain, SwapchainLayout},
+    time::{self, XrTime},
+    to_veccstr, vk_name_eq, Config, XrEntry, XrEntryFp, XrInstanceFp,
+};
+
+#[cfg(feature = "capture")]
+use crate::capture::{self, CaptureSlot, FrameCapture};
+#[cfg(feature = "mirror-window")]
+use crate::mirror::{self, MirrorWindow};
+#[cfg(feature = "timing")]
+use crate::timing::InitTimings;
+
+/// First delay between two xrGetSystem attempts when `Config::wait_for_system` is set, doubled
+/// after each attempt.
+const SYSTEM_RETRY_INITIAL_DELAY: Duration = Duration::from_millis(100);
+
+const ANDROID_CREATE_INSTANCE_EXTENSION: &str = "XR_KHR_android_create_instance";
+
+/// Vulkan debug extensions, by order of preference, requested when validation is enabled.
+#[cfg(not(any(target_os = "macos", target_os = "ios")))]
+const DEBUG_EXTENSIONS: [&str; 2] = ["VK_EXT_debug_utils", "VK_EXT_debug_report"];
+/// MoltenVK fails instance creation with VK_EXT_debug_report, even when it lists it.
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+const DEBUG_EXTENSIONS: [&str; 1] = ["VK_EXT_debug_utils"];
+
+/// Vulkan device extensions enabled when the device supports them, the external memory handle
+/// type depending on the platform. Missing ones are only logged, the runtime listing what it
+/// actually needs.
+#[cfg(target_os = "windows")]
+const DEVICE_EXTENSIONS: [&str; 3] = [
+    "VK_KHR_swapchain",
+    "VK_KHR_external_memory",
+    "VK_KHR_external_memory_win32",
+];
+/// MoltenVK exports no fd or Win32 memory handles, and wants its portability subset enabled.
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+const DEVICE_EXTENSIONS: [&str; 3] = [
+    "VK_KHR_swapchain",
+    "VK_KHR_external_memory",
+    "VK_KHR_portability_subset",
+];
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "ios")))]
+const DEVICE_EXTENSIONS: [&str; 3] = [
+    "VK_KHR_swapchain",
+    "VK_KHR_external_memory",
+    "VK_KHR_external_memory_fd",
+];
+
+const VALIDATION_LAYER: &str = "VK_LAYER_KHRONOS_validation";
+
+/// Enabled with `Config::quad_views`, when available.
+pub(crate) const QUAD_VIEWS_EXTENSION: &str = "XR_VARJO_quad_views";
+
+/// Enabled with `Config::overlay`.
+const OVERLAY_EXTENSION: &str = "XR_EXTX_overlay";
+
+/// Enabled when available, the Vulkan instance and device then being created by the runtime,
+/// which adds the extensions it needs to them.
+const VULKAN_ENABLE2_EXTENSION: &str = "XR_KHR_vulkan_enable2";
+
+/// Environment variable the OpenXR loader takes the runtime manifest from, when set
+const RUNTIME_JSON_VARIABLE: &str = "XR_RUNTIME_JSON";
+
+/// Extensions enabled on the instance when the runtime supports them.
+const OPTIONAL_EXTENSIONS: [&str; 9] = [
+    VULKAN_ENABLE2_EXTENSION,
+    "XR_KHR_composition_layer_cylinder",
+    "XR_KHR_composition_layer_equirect2",
+    layers::DEPTH_EXTENSION,
+    "XR_KHR_visibility_mask",
+    "XR_KHR_android_thread_settings",
+    "XR_EXT_performance_settings",
+    spatial_anchor::EXTENSION,
+    #[cfg(not(windows))]
+    time::TIMESPEC_EXTENSION,
+    #[cfg(windows)]
+    time::PERFORMANCE_COUNTER_EXTENSION,
+];
+
+/// XR_KHR_vulkan_enable2 functions, used together in place of the XR_KHR_vulkan_enable ones.
+#[derive(Clone, Copy)]
+struct VulkanEnable2 {
+    get_graphics_requirements: openxr_sys::pfn::GetVulkanGraphicsRequirements2KHR,
+    create_instance: openxr_sys::pfn::CreateVulkanInstanceKHR,
+    get_graphics_device: openxr_sys::pfn::GetVulkanGraphicsDevice2KHR,
+    create_device: openxr_sys::pfn::CreateVulkanDeviceKHR,
+}
+
+impl VulkanEnable2 {
+    /// None unless the extension is enabled, in which case they're all loaded
+    fn new(fp: &XrInstanceFp) -> Option<Self> {
+        Some(VulkanEnable2 {
+            get_graphics_requirements: fp.get_vulkan_graphics_requirements2_KHR?,
+            create_instance: fp.create_vulkan_instance_KHR?,
+            get_graphics_device: fp.get_vulkan_graphics_device2_KHR?,
+            create_device: fp.create_vulkan_device_KHR?,
+        })
+    }
+}
+
+/// A queue of the device, along with the family it belongs to.
+#[derive(Clone, Copy, Debug)]
+pub struct DeviceQueue {
+    pub family_index: u32,
+    pub queue: ash::vk::Queue,
+}
+
+/// Raw handles of the Vulkan objects the context created, see `Context::vulkan_handles`.
+///
+/// They're borrowed from the context, which destroys them in `Context::destroy`: the lifetime
+/// keeps the struct from outliving it, but nothing stops copies of the handles themselves from
+/// doing so, and using them afterwards is undefined behavior.
+#[derive(Clone, Copy, Debug)]
+pub struct VulkanHandles<'a> {
+    pub instance: ash::vk::Instance,
+    pub physical_device: ash::vk::PhysicalDevice,
+    pub device: ash::vk::Device,
+    /// The queue the runtime was given, along with its family
+    pub graphics_queue: DeviceQueue,
+    pub compute_queue: Option<DeviceQueue>,
+    pub transfer_queue: Option<DeviceQueue>,
+    _context: PhantomData<&'a Context>,
+}
+
+/// Fails to compile when `T` isn't `Send`.
+fn _assert_send<T: Send>() {}
+
+// `App::run` hands the context over to its render thread
+fn _assert_context_send() {
+    _assert_send::<Context>();
+}
+
+/// Owns the OpenXR and Vulkan objects created during initialization.
+pub struct Context {
+    pub(crate) config: Config,
+    // Keeps the OpenXR loader loaded for as long as the instance lives
+    _entry: XrEntry,
+    instance: openxr_sys::Instance,
+    pub(crate) fp: Arc<XrInstanceFp>,
+    pub(crate) enabled_extensions: Vec<CString>,
+    unavailable_features: Vec<UnavailableFeature>,
+    system_id: openxr_sys::SystemId,
+    // Same as `_entry`, for the Vulkan loader, also used to create the mirror window's surface
+    #[cfg_attr(not(feature = "mirror-window"), allow(dead_code))]
+    vk_entry: ash::Entry,
+    vk_instance: ash::Instance,
+    physical_device: ash::vk::PhysicalDevice,
+    pub(crate) device: ash::Device,
+    queue: DeviceQueue,
+    compute_queue: Option<DeviceQueue>,
+    transfer_queue: Option<DeviceQueue>,
+    /// Only loaded when validation is enabled and VK_EXT_debug_utils got picked
+    debug_utils: Option<DebugUtils>,
+    pub(crate) session: Session,
+    /// Reference space the views and layers are expressed in
+    pub(crate) space: openxr_sys::Space,
+    /// VIEW reference space, for head-locked layers
+    pub(crate) view_space: openxr_sys::Space,
+    /// Pose of `space` in the runtime's LOCAL space, moved by `recenter`
+    space_pose: openxr_sys::Posef,
+    /// First of `Config::preferred_depth_formats` the device supports
+    depth_format: ash::vk::Format,
+    /// Follows the swapchain format
+    pub(crate) shader_output: ShaderOutput,
+    /// Last set through `FrameContext::set_render_scale`, applied to the frames' sub-images
+    pub(crate) render_scale: f32,
+    /// `Config::protected_content`, unless the loader, the device or the runtime can't do it
+    protected_content: bool,
+    /// Only created with `Config::debug_grid`, along with the swapchains it draws to
+    pub(crate) debug_grid: Option<DebugGrid>,
+    /// Only opened with `Config::mirror_window`, by `App::run`
+    #[cfg(feature = "mirror-window")]
+    mirror: Option<MirrorWindow>,
+    /// Captures asked for through `App::capture_frame`, and the last one taken
+    #[cfg(feature = "capture")]
+    pub(crate) capture_slot: Arc<CaptureSlot>,
+    /// Created along with the first capture
+    #[cfg(feature = "capture")]
+    capture: Option<FrameCapture>,
+    #[cfg(feature = "timing")]
+    init_timings: InitTimings,
+}
+
+impl Context {
+    pub fn new(mut config: Config) -> Result<Self, Error> {
+        let application_name = "test";
+        let application_version = 1;
+        let engine_name: Option<&str> = None;
+        let engine_version: Option<u32> = None;
+
+        #[cfg(feature = "timing")]
+        let mut timings = InitTimings::default();
+
+        if !config::RESOLUTION_SCALE_RANGE.contains(&config.resolution_scale) {
+            return Err(Error::InvalidResolutionScale(config.resolution_scale));
+        }
+        if config.max_frames_in_flight == 0 {
+            return Err(Error::NoFramesInFlight);
+        }
+        if let (false, Some(placement)) = (config.overlay, config.overlay_placement) {
+            return Err(Error::OverlayPlacementWithoutOverlay(placement));
+        }
+
+        config.debug_grid |= config.eye_test_pattern;
+        if config.debug_grid {
+            config.swapchain_usage |= openxr_sys::SwapchainUsageFlags::COLOR_ATTACHMENT;
+        }
+        #[cfg(feature = "mirror-window")]
+        if config.mirror_window {
+            config.swapchain_usage |= openxr_sys::SwapchainUsageFlags::TRANSFER_SRC;
+        }
+        // A capture can be asked for at any time
+        #[cfg(feature = "capture")]
+        {
+            config.swapchain_usage |= openxr_sys::SwapchainUsageFlags::TRANSFER_SRC;
+        }
+
+        // The loader reads the variable when it gets to picking the runtime, at the first call
+        #[cfg(not(target_os = "android"))]
+        if let Some(manifest) = &config.runtime_manifest {
+            std::env::set_var(RUNTIME_JSON_VARIABLE, manifest);
+        }
+        #[cfg(target_os = "android")]
+        if config.runtime_manifest.is_some() {
+            warn!("Config::runtime_manifest is ignored on Android");
+        }
+        match std::env::var_os(RUNTIME_JSON_VARIABLE) {
+            Some(manifest) if !cfg!(target_os = "android") => {
+                info!("OpenXR runtime manifest: {:?}", manifest)
+            }
+            _ => info!("OpenXR runtime: the system's active one"),
+        }
+
+        let entry = XrEntry::load().unwrap();
+
+        // Only Android runtimes need to be handed the VM and activity
+        #[cfg(not(target_os = "android"))]
+        let (vm, activity, loader_initialized) = (
+            std::ptr::null_mut::<c_void>(),
+            std::ptr::null_mut::<c_void>(),
+            false,
+        );
+
+        #[cfg(target_os = "android")]
+        let (vm, activity, loader_initialized) = {
+            info!("xrInitializeLoaderKHR()");
+            let initialize_loader_KHR: Option<openxr_sys::pfn::InitializeLoaderKHR> = unsafe {
+                transmute(
+                    entry
+                        .fp
+                        .get_proc_addr(openxr_sys::Instance::NULL, "xrInitializeLoaderKHR"),
+                )
+            };
+
+            let native_activity = ndk_glue::native_activity();
+            let vm = native_activity.vm();
+            let activity = native_activity.activity();
+
+            // https://www.khronos.org/registry/OpenXR/specs/1.0/man/html/XrLoaderInitInfoAndroidKHR.html
+            let info = Box::into_raw(Box::new(openxr_sys::LoaderInitInfoAndroidKHR {
+                ty: openxr_sys::LoaderInitInfoAndroidKHR::TYPE,
+                next: std::ptr::null(),
+                application_vm: vm as *mut c_void,
+                application_context: activity as *mut c_void,
+            })) as *const openxr_sys::LoaderInitInfoBaseHeaderKHR;
+
+            let loader_initialized = match initialize_loader_KHR {
+                Some(initialize_loader_KHR) => {
+                    let call_result = unsafe { initialize_loader_KHR(info) };
+
+                    if !call_result.is_success() {
+                        panic!("Failed initialize_loader_KHR");
+                    }
+
+                    true
+                }
+                None => {
+                    warn!("xrInitializeLoaderKHR isn't available");
+                    false
+                }
+            };
+
+            (vm, activity, loader_initialized)
+        };
+
+        let application_info = {
+            // Prevents application names from being larger than the container in ApplicationInfo
+            assert!(
+                application_name.len() <= openxr_sys::MAX_APPLICATION_NAME_SIZE,
+                "OpenXR application names must be {} bytes or less",
+                openxr_sys::MAX_APPLICATION_NAME_SIZE
+            );
+
+            // Prevents application names from being empty
+            assert!(
+                application_name.len() > 0,
+                "OpenXR application names must be greater than 0 bytes"
+            );
+
+            let mut app_info = openxr_sys::ApplicationInfo {
+                application_name: [0; openxr_sys::MAX_APPLICATION_NAME_SIZE],
+                engine_name: [0; openxr_sys::MAX_ENGINE_NAME_SIZE],
+                application_version,
+                engine_version: engine_version.map_or(0, |v| v),
+                api_version: openxr_sys::CURRENT_API_VERSION,
+            };
+
+            for (app_char, slot) in application_name
+                .bytes()
+                .zip(app_info.application_name.iter_mut())
+            {
+                *slot = app_char as _;
+            }
+
+            app_info.application_name[application_name.len()] = 0;
+
+            // Its safe to not do anything if `engine_name` is `None` because the
+            // buffer is already initialized to 0
+            if let Some(name) = engine_name {
+                for (engine_char, slot) in name.bytes().zip(app_info.application_name.iter_mut()) {
+                    *slot = engine_char as _;
+                }
+
+                app_info.application_name[application_name.len()] = 0;
+            }
+
+            app_info
+        };
+
+        let xr_available_extensions =
+            available_xr_extensions(&entry.fp, config.max_enumerate_count)?;
+
+        info!(
+            "OpenXR available extensions: {:#?}",
+            xr_available_extensions
+        );
+
+        let required_layers = to_veccstr(&[]);
+
+        // The loader init already hands the VM and activity to the runtime, so chaining them to the
+        // instance creation is only needed when the runtime supports it, or if nothing else did.
+        let android_create_instance = cfg!(target_os = "android")
+            && xr_available_extensions.iter().any(|available| {
+                available.as_bytes() == ANDROID_CREATE_INSTANCE_EXTENSION.as_bytes()
+            });
+
+        if cfg!(target_os = "android") && !android_create_instance {
+            if !loader_initialized {
+                return Err(Error::AndroidInitUnavailable);
+            }
+            warn!(
+                "{} isn't available, relying on the loader init only",
+                ANDROID_CREATE_INSTANCE_EXTENSION
+            );
+        }
+
+        let optional_extensions = OPTIONAL_EXTENSIONS.iter().copied().filter(|&name| {
+            xr_available_extensions
+                .iter()
+                .any(|available| available.as_bytes() == name.as_bytes())
+        });
+
+        let mut enabled_extensions = vec!["XR_KHR_vulkan_enable"];
+        if android_create_instance {
+            enabled_extensions.push(ANDROID_CREATE_INSTANCE_EXTENSION);
+        }
+        enabled_extensions.extend(optional_extensions);
+
+        // Checked before anything gets created, so the whole list shows up in one place, and
+        // nothing is left to clean up when they're required
+        let (feature_extensions, unavailable_features) =
+            feature::check(&config, &xr_available_extensions);
+        if !unavailable_features.is_empty() {
+            if config.require_features {
+                return Err(Error::UnavailableFeatures(unavailable_features));
+            }
+            for feature in &unavailable_features {
+                warn!("{}", feature);
+            }
+        }
+        enabled_extensions.extend(feature_extensions);
+
+        let mut requested_extensions = config.instance_extensions.clone();
+        if config.eye_gaze {
+            requested_extensions.push(eye_gaze::EXTENSION);
+        }
+        if config.passthrough {
+            requested_extensions.push(passthrough::EXTENSION);
+        }
+        if config.overlay {
+            requested_extensions.push(OVERLAY_EXTENSION);
+        }
+        if config.foveation.is_some() {
+            requested_extensions.extend(&foveation::EXTENSIONS);
+        }
+
+        for name in requested_extensions {
+            let available = xr_available_extensions
+                .iter()
+                .any(|available| available.as_bytes() == name.as_bytes());
+            if !available {
+                return Err(Error::MissingExtension(name));
+            }
+            if !enabled_extensions.contains(&name) {
+                enabled_extensions.push(name);
+            }
+        }
+
+        let required_extensions = to_veccstr(&enabled_extensions);
+        info!("OpenXR enabled extensions: {:?}", required_extensions.base);
+
+        // The optional extensions are dropped without a word otherwise, which is the first thing
+        // to check when a feature relying on one of them does nothing
+        let unavailable_optional: Vec<_> = OPTIONAL_EXTENSIONS
+            .iter()
+            .filter(|name| !enabled_extensions.contains(name))
+            .collect();
+        if !unavailable_optional.is_empty() {
+            info!(
+                "OpenXR optional extensions unavailable: {:?}",
+                unavailable_optional
+            );
+        }
+        let not_enabled: Vec<_> = xr_available_extensions
+            .iter()
+            .filter(|available| !required_extensions.base.contains(available))
+            .collect();
+        debug!(
+            "OpenXR extensions available but not enabled: {:?}",
+            not_enabled
+        );
+
+        // The caller's structures come after the Android one, they live in the config which is kept
+        // in the context
+        let config_chain = chain::link(&mut config.instance_create_next, std::ptr::null());
+
+        // https://www.khronos.org/registry/OpenXR/specs/1.0/html/xrspec.html#XR_KHR_android_create_instance
+        let create_info_ext = if android_create_instance {
+            Box::into_raw(Box::new(openxr_sys::InstanceCreateInfoAndroidKHR {
+                ty: openxr_sys::InstanceCreateInfoAndroidKHR::TYPE,
+                next: config_chain as *const c_void,
+                application_vm: vm as *mut c_void,
+                application_activity: activity as *mut c_void,
+            })) as *const c_void
+        } else {
+            config_chain as *const c_void
+        };
+
+        let create_info = openxr_sys::InstanceCreateInfo {
+            ty: openxr_sys::InstanceCreateInfo::TYPE,
+            next: create_info_ext,
+            create_flags: openxr_sys::InstanceCreateFlags::EMPTY,
+            application_info,
+            enabled_api_layer_count: required_layers.ptr.len() as _,
+            enabled_api_layer_names: required_layers.ptr.as_ptr(),
+            enabled_extension_count: required_extensions.ptr.len() as _,
+            enabled_extension_names: required_extensions.ptr.as_ptr(),
+        };
+
+        info!("xrCreateInstance()");
+        let instance = {
+            let mut instance_handle = openxr_sys::Instance::NULL;
+            let call_result = timed!(timings, "xrCreateInstance", unsafe {
+                (entry.fp.create_instance)(&create_info, &mut instance_handle)
+            });
+            if !call_result.is_success() {
+                panic!("Failed to create_instance");
+            }
+            instance_handle
+        };
+
+        let fp = Arc::new(XrInstanceFp::new(
+            &entry.fp,
+            instance,
+            &required_extensions.base,
+            config.max_enumerate_count,
+        )?);
+
+        let system_get_info = openxr_sys::SystemGetInfo {
+            ty: openxr_sys::SystemGetInfo::TYPE,
+            next: std::ptr::null_mut(),
+            form_factor: config.form_factor,
+        };
+
+        info!("xrGetSystem()");
+        let system_id = timed!(timings, "xrGetSystem", {
+            let get_system: openxr_sys::pfn::GetSystem =
+                unsafe { transmute(entry.fp.get_proc_addr(instance, "xrGetSystem")) };
+
+            // Desktop runtimes may not have the headset ready right away, so we can keep asking
+            // for a while with an increasing delay.
+            let deadline = config
+                .wait_for_system
+                .map(|timeout| Instant::now() + timeout);
+            let mut delay = SYSTEM_RETRY_INITIAL_DELAY;
+
+            loop {
+                let mut system_id = openxr_sys::SystemId::NULL;
+                let result = unsafe { get_system(instance, &system_get_info, &mut system_id) };
+                match result {
+                    XrResult::SUCCESS => break system_id,
+                    XrResult::ERROR_FORM_FACTOR_UNAVAILABLE => {
+                        let remaining = deadline
+                            .and_then(|deadline| deadline.checked_duration_since(Instant::now()))
+                            .filter(|remaining| *remaining > Duration::from_secs(0));
+
+                        match remaining {
+                            Some(remaining) => {
+                                let wait = delay.min(remaining);
+                                debug!(
+                                    "Form factor unavailable, retrying xrGetSystem in {:?}",
+                                    wait
+                                );
+                                std::thread::sleep(wait);
+                                delay *= 2;
+                            }
+                            None => return Err(Error::FormFactorUnavailable(config.form_factor)),
+                        }
+                    }
+                    XrResult::ERROR_FORM_FACTOR_UNSUPPORTED => {
+                        return Err(Error::FormFactorUnavailable(config.form_factor));
+                    }
+                    result => return Err(Error::Xr(result)),
+                }
+            }
+        });
+        info!("  form factor: {:?}", config.form_factor);
+
+        let view_configuration_type = if enabled_extensions.contains(&QUAD_VIEWS_EXTENSION) {
+            select_view_configuration(&fp, instance, system_id)?
+        } else {
+            openxr_sys::ViewConfigurationType::PRIMARY_STEREO
+        };
+        info!("  view configuration: {:?}", view_configuration_type);
+
+        if config.eye_gaze && !eye_gaze::is_supported(&fp, instance, system_id)? {
+            return Err(Error::EyeGazeUnsupported);
+        }
+        if config.passthrough && !passthrough::is_supported(&fp, instance, system_id)? {
+            return Err(Error::PassthroughUnsupported);
+        }
+
+        // XR_KHR_vulkan_enable2 is used from the requirements to the device creation when the
+        // runtime has it, XR_KHR_vulkan_enable otherwise
+        let vulkan_enable2 = VulkanEnable2::new(&fp);
+
+        // Both versions fill the same structure
+        let mut graphics_requirements =
+            openxr_sys::GraphicsRequirementsVulkanKHR::out(std::ptr::null_mut());
+        let get_graphics_requirements = match vulkan_enable2 {
+            Some(vulkan_enable2) => {
+                info!("xrGetVulkanGraphicsRequirements2KHR()");
+                vulkan_enable2.get_graphics_requirements
+            }
+            None => {
+                info!("xrGetVulkanGraphicsRequirementsKHR()");
+                fp.get_vulkan_graphics_requirements_KHR
+            }
+        };
+        unsafe {
+            get_graphics_requirements(instance, system_id, graphics_requirements.as_mut_ptr())
+        }
+        .into_result()?;
+
+        let graphics_requirements = unsafe { graphics_requirements.assume_init() };
+
+        info!(
+            "graphics_requirements: min={}, max={}",
+            graphics_requirements.min_api_version_supported,
+            graphics_requirements.max_api_version_supported,
+        );
+
+        let vk_entry = unsafe { ash::Entry::new().unwrap() };
+
+        // Protected memory is core in Vulkan 1.1, which the instance has to be created with.
+        // The device gets checked once known.
+        let mut protected_content = config.protected_content;
+        if protected_content {
+            let version = vk_entry.try_enumerate_instance_version()?;
+            if version.map_or(true, |version| version < ash::vk::API_VERSION_1_1) {
+                warn!("Protected content needs Vulkan 1.1, which the loader doesn't support");
+                protected_content = false;
+            }
+        }
+
+        let extensions = available_vk_instance_extensions(&vk_entry)?;
+
+        info!("vulkan extensions: {:?}", extensions);
+
+        // xrCreateVulkanInstanceKHR adds the ones the runtime needs by itself
+        let req_extensions = if vulkan_enable2.is_some() {
+            Vec::new()
+        } else {
+            info!("xrGetVulkanInstanceExtensionsKHR()");
+            required_vulkan_extensions(
+                &fp,
+                instance,
+                system_id,
+                fp.get_vulkan_instance_extensions_KHR,
+            )?
+        };
+
+        info!("vulkan ext required: {:?}", req_extensions);
+
+        info!("vkCreateInstance()");
+        let (vk_instance, debug_utils_enabled) = {
+            let app_name = CString::new("openxr-test").unwrap();
+            let engine_name = CString::new("Vulkan Engine").unwrap();
+            let app_info = ash::vk::ApplicationInfo {
+                s_type: ash::vk::StructureType::APPLICATION_INFO,
+                p_next: std::ptr::null(),
+                p_application_name: app_name.as_ptr(),
+                application_version: 1,
+                p_engine_name: engine_name.as_ptr(),
+                engine_version: 1,
+                api_version: if protected_content {
+                    ash::vk::API_VERSION_1_1
+                } else {
+                    ash::vk::API_VERSION_1_0
+                },
+            };
+
+            let debug_extension = if config.enable_validation {
+                let debug_extension = DEBUG_EXTENSIONS
+                    .iter()
+                    .copied()
+                    .find(|&name| extensions.iter().any(|ext| ext == name));
+
+                if debug_extension.is_none() {
+                    warn!("Validation enabled but no debug extension is available");
+                }
+                debug_extension
+            } else {
+                None
+            };
+
+            // The runtime can't share images with an instance lacking them, and vkCreateInstance
+            // would only fail on the first one. Only the debug and surface extensions are left
+            // out when unavailable.
+            let required: Vec<&str> = req_extensions
+                .iter()
+                .map(|name| name.to_str().unwrap())
+                .collect();
+            let missing = missing_extensions(&required, &extensions);
+            if !missing.is_empty() {
+                return Err(Error::MissingInstanceExtensions(missing));
+            }
+            let debug_candidates: &[&str] = if config.enable_validation {
+                &DEBUG_EXTENSIONS
+            } else {
+                &[]
+            };
+            info!(
+                "vulkan instance extensions requested: {:?}, debug: {:?}",
+                required, debug_candidates
+            );
+
+            let extension_names: Vec<_> = debug_extension
+                .map(|name| CString::new(name).unwrap())
+                .into_iter()
+                .chain(req_extensions.into_iter())
+                .collect();
+
+            #[cfg(feature = "mirror-window")]
+            let extension_names = if config.mirror_window {
+                mirror::with_surface_extensions(extension_names, &extensions)
+            } else {
+                extension_names
+            };
+
+            info!("vulkan instance extensions granted: {:?}", extension_names);
+
+            let layer_names = if config.enable_validation {
+                let layers = vk_entry
+                    .enumerate_instance_layer_properties()
+                    .expect("Failed to get vulkan layers");
+
+                if layers
+                    .iter()
+                    .any(|layer| vk_name_eq(&layer.layer_name, VALIDATION_LAYER))
+                {
+                    to_veccstr(&[VALIDATION_LAYER])
+                } else {
+                    warn!(
+                        "Validation enabled but {} isn't available",
+                        VALIDATION_LAYER
+                    );
+                    to_veccstr(&[])
+                }
+            } else {
+                to_veccstr(&[])
+            };
+
+            let extension_ptrs: Vec<_> = extension_names
+                .iter()
+                .map(|x| x.as_bytes_with_nul().as_ptr())
+                .collect();
+
+            let create_info = ash::vk::InstanceCreateInfo {
+                s_type: ash::vk::StructureType::INSTANCE_CREATE_INFO,
+                p_next: std::ptr::null(),
+                flags: ash::vk::InstanceCreateFlags::empty(),
+                p_application_info: &app_info,
+                pp_enabled_layer_names: layer_names.ptr.as_ptr(),
+                enabled_layer_count: layer_names.ptr.len() as u32,
+                pp_enabled_extension_names: extension_ptrs.as_ptr() as *const *const u8,
+                enabled_extension_count: extension_ptrs.len() as u32,
+            };
+
+            let vk_instance = timed!(
+                timings,
+                "vkCreateInstance",
+                match vulkan_enable2 {
+                    Some(vulkan_enable2) => create_vulkan_instance(
+                        vulkan_enable2,
+                        instance,
+                        system_id,
+                        &vk_entry,
+                        &create_info,
+                    ),
+                    None => match unsafe { vk_entry.create_instance(&create_info, None) } {
+                        Ok(vk_instance) => Ok(vk_instance),
+                        Err(ash::InstanceError::VkError(result)) => Err(Error::Vulkan(result)),
+                        Err(error) => panic!("Failed vkCreateInstance(): {}", error),
+                    },
+                }
+            );
+            let vk_instance = match vk_instance {
+                Ok(vk_instance) => vk_instance,
+                // The extensions were filtered against the available ones, but a layer going away
+                // in between, or a driver disagreeing with its own list, still ends up here
+                Err(Error::Vulkan(ash::vk::Result::ERROR_EXTENSION_NOT_PRESENT)) => {
+                    let available = available_vk_instance_extensions(&vk_entry)?;
+                    let requested: Vec<&str> = extension_names
+                        .iter()
+                        .map(|name| name.to_str().unwrap())
+                        .collect();
+                    let missing = missing_extensions(&requested, &available);
+                    if missing.is_empty() {
+                        return Err(Error::Vulkan(ash::vk::Result::ERROR_EXTENSION_NOT_PRESENT));
+                    }
+                    return Err(Error::MissingInstanceExtensions(missing));
+                }
+                Err(error) => return Err(error),
+            };
+
+            (vk_instance, debug_extension == Some(DEBUG_EXTENSIONS[0]))
+        };
+
+        let debug_utils = if debug_utils_enabled {
+            Some(DebugUtils::new(&vk_entry, &vk_instance))
+        } else {
+            None
+        };
+
+        let vk_instance_raw = vk_instance.handle().as_raw() as *const c_void;
+
+        let physical_device = {
+            let mut physical_device = std::ptr::null();
+            let result = match vulkan_enable2 {
+                Some(vulkan_enable2) => {
+                    info!("xrGetVulkanGraphicsDevice2KHR()");
+                    let get_info = openxr_sys::VulkanGraphicsDeviceGetInfoKHR {
+                        ty: openxr_sys::VulkanGraphicsDeviceGetInfoKHR::TYPE,
+                        next: std::ptr::null(),
+                        system_id,
+                        vulkan_instance: vk_instance_raw,
+                    };
+                    unsafe {
+                        (vulkan_enable2.get_graphics_device)(
+                            instance,
+                            &get_info,
+                            &mut physical_device,
+                        )
+                    }
+                }
+                None => {
+                    info!("xrGetVulkanGraphicsDeviceKHR()");
+                    unsafe {
+                        (fp.get_vulkan_graphics_device_KHR)(
+                            instance,
+                            system_id,
+                            vk_instance_raw,
+                            &mut physical_device,
+                        )
+                    }
+                }
+            };
+            result.into_result()?;
+
+            ash::vk::PhysicalDevice::from_raw(physical_device as u64)
+        };
+        info!("  physical_device: {:?}", physical_device);
+
+        if protected_content && !supports_protected_memory(&vk_instance, physical_device) {
+            warn!("The device doesn't support protected memory, rendering unprotected content");
+            protected_content = false;
+        }
+
+        // Same as the instance ones, xrCreateVulkanDeviceKHR adds them by itself
+        let req_dev_extensions = if vulkan_enable2.is_some() {
+            Vec::new()
+        } else {
+            info!("xrGetVulkanDeviceExtensionsKHR()");
+            required_vulkan_extensions(
+                &fp,
+                instance,
+                system_id,
+                fp.get_vulkan_device_extensions_KHR,
+            )?
+        };
+
+        info!("vulkan device ext required: {:?}", req_dev_extensions);
+
+        let available_dev_extensions =
+            available_vk_device_extensions(&vk_instance, physical_device)?;
+        let (default_extensions, unavailable): (Vec<&str>, Vec<&str>) = DEVICE_EXTENSIONS
+            .iter()
+            .copied()
+            .partition(|&name| available_dev_extensions.iter().any(|ext| ext == name));
+        info!(
+            "vulkan device ext requested: {:?}",
+            DEVICE_EXTENSIONS
+                .iter()
+                .chain(&config.device_extensions)
+                .collect::<Vec<_>>()
+        );
+        if !unavailable.is_empty() {
+            info!("vulkan device ext unavailable, skipped: {:?}", unavailable);
+        }
+
+        // What the runtime needs comes first, the swapchain images being shared with it. Those
+        // and the ones from the config are checked by `create_logical_device`.
+        let mut device_extensions: Vec<&str> = Vec::new();
+        let wanted_extensions = req_dev_extensions
+            .iter()
+            .map(|name| name.to_str().unwrap())
+            .chain(default_extensions)
+            .chain(config.device_extensions.iter().copied());
+        for name in wanted_extensions {
+            if !device_extensions.contains(&name) {
+                device_extensions.push(name);
+            }
+        }
+        info!("vulkan device ext granted: {:?}", device_extensions);
+
+        info!("create_logical_device()");
+        let logical_device = timed!(
+            timings,
+            "create_logical_device",
+            create_logical_device(
+                &vk_instance,
+                physical_device,
+                &config.queue_config,
+                &device_extensions,
+                &config.required_vk_features,
+                protected_content,
+                |create_info| match vulkan_enable2 {
+                    Some(vulkan_enable2) => create_vulkan_device(
+                        vulkan_enable2,
+                        instance,
+                        system_id,
+                        &vk_entry,
+                        &vk_instance,
+                        physical_device,
+                        create_info,
+                    ),
+                    None =>
+                        unsafe { vk_instance.create_device(physical_device, create_info, None) }
+                            .map_err(Error::Vulkan),
+                },
+            )?
+        );
+        let device = logical_device.device;
+        info!("  device: {:?}", device.handle());
+
+        // The main application's layers are always below, the placement only orders the
+        // overlays between them
+        let overlay_info = openxr_sys::SessionCreateInfoOverlayEXTX {
+            ty: openxr_sys::SessionCreateInfoOverlayEXTX::TYPE,
+            next: std::ptr::null(),
+            create_flags: openxr_sys::OverlaySessionCreateFlagsEXTX::EMPTY,
+            session_layers_placement: config.overlay_placement.unwrap_or(0),
+        };
+        let graphics_binding = openxr_sys::GraphicsBindingVulkanKHR {
+            ty: openxr_sys::StructureType::GRAPHICS_BINDING_VULKAN_KHR,
+            instance: vk_instance_raw,
+            physical_device: physical_device.as_raw() as *const c_void,
+            device: device.handle().as_raw() as *const c_void,
+            queue_family_index: logical_device.graphics_queue.family_index,
+            queue_index: 0,
+            next: if config.overlay {
+                &overlay_info as *const _ as *const c_void
+            } else {
+                std::ptr::null()
+            },
+        };
+        validate_graphics_binding(&graphics_binding, &vk_instance, physical_device)?;
+
+        let session_create_info = openxr_sys::SessionCreateInfo {
+            ty: openxr_sys::StructureType::SESSION_CREATE_INFO,
+            create_flags: openxr_sys::SessionCreateFlags::EMPTY,
+            system_id,
+            next: Box::into_raw(Box::new(graphics_binding)) as *const c_void,
+        };
+
+        info!("xrCreateSession()");
+        let mut session = openxr_sys::Session::NULL;
+        let result = timed!(timings, "xrCreateSession", unsafe {
+            (fp.create_session)(instance, &session_create_info, &mut session)
+        });
+
+        if !result.is_success() {
+            panic!("Failed xrCreateSession");
+        }
+
+        let reference_space_create_info = openxr_sys::ReferenceSpaceCreateInfo {
+            ty: openxr_sys::ReferenceSpaceCreateInfo::TYPE,
+            next: std::ptr::null(),
+            reference_space_type: openxr_sys::ReferenceSpaceType::LOCAL,
+            pose_in_reference_space: crate::identity_pose(),
+        };
+
+        info!("xrCreateReferenceSpace()");
+        let mut space = openxr_sys::Space::NULL;
+        let result = unsafe {
+            (fp.create_reference_space)(session, &reference_space_create_info, &mut space)
+        };
+
+        if !result.is_success() {
+            panic!("Failed xrCreateReferenceSpace");
+        }
+
+        let view_space_create_info = openxr_sys::ReferenceSpaceCreateInfo {
+            reference_space_type: openxr_sys::ReferenceSpaceType::VIEW,
+            ..reference_space_create_info
+        };
+
+        info!("xrCreateReferenceSpace()");
+        let mut view_space = openxr_sys::Space::NULL;
+        let result = unsafe {
+            (fp.create_reference_space)(session, &view_space_create_info, &mut view_space)
+        };
+
+        if !result.is_success() {
+            panic!("Failed xrCreateReferenceSpace");
+        }
+
+        let environment_blend_modes = session::enumerate_environment_blend_modes(
+            &fp,
+            instance,
+            system_id,
+            view_configuration_type,
+        )?;
+
+        let mut session = Session::new(
+            session,
+            instance,
+            Arc::clone(&fp),
+            view_configuration_type,
+            environment_blend_modes,
+        );
+        session.frame_stats = FrameStats::new(config.frame_stats_log_interval);
+        if config.eye_gaze {
+            session.create_eye_gaze(space)?;
+        }
+        if config.passthrough {
+            session.create_passthrough()?;
+        }
+        if let Some(color_space) = config.color_space {
+            if enabled_extensions.contains(&color_space::EXTENSION) {
+                let supported = session.color_spaces()?;
+                info!("  color spaces: {:?}", supported);
+                if supported.contains(&color_space) {
+                    session.set_color_space(color_space)?;
+                } else {
+                    warn!(
+                        "Color space {:?} isn't supported, keeping the runtime's",
+                        color_space
+                    );
+                }
+            }
+        }
+
+        let depth_format = render_target::select_depth_format(
+            &vk_instance,
+            physical_device,
+            &config.preferred_depth_formats,
+            ash::vk::ImageTiling::OPTIMAL,
+            ash::vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT,
+        )?;
+        info!("  depth format: {:?}", depth_format);
+
+        let mut context = Context {
+            config,
+            _entry: entry,
+            instance,
+            fp,
+            enabled_extensions: required_extensions.base,
+            unavailable_features,
+            system_id,
+            vk_entry,
+            vk_instance,
+            physical_device,
+            device,
+            queue: logical_device.graphics_queue,
+            compute_queue: logical_device.compute_queue,
+            transfer_queue: logical_device.transfer_queue,
+            debug_utils,
+            session,
+            space,
+            view_space,
+            space_pose: crate::identity_pose(),
+            depth_format,
+            shader_output: ShaderOutput::Linear,
+            render_scale: 1.0,
+            protected_content,
+            debug_grid: None,
+            #[cfg(feature = "mirror-window")]
+            mirror: None,
+            #[cfg(feature = "capture")]
+            capture_slot: Arc::default(),
+            #[cfg(feature = "capture")]
+            capture: None,
+            #[cfg(feature = "timing")]
+            init_timings: timings,
+        };
+        timed!(
+            context.init_timings,
+            "swapchain creation",
+            context.create_swapchains()
+        )?;
+        // After the swapchains, the runtime may have turned down protected ones, in which case
+        // the frames can't be protected either
+        let in_flight = InFlightFrames::new(
+            &context.device,
+            context.queue.family_index,
+            context.config.max_frames_in_flight,
+            context.protected_content,
+        )?;
+        context.session.recorder = Some(FrameRecorder::new(
+            &context.device,
+            context.queue,
+            in_flight,
+            &context.config,
+        ));
+        context.debug_grid = context.create_debug_grid()?;
+
+        context.name_objects();
+
+        #[cfg(feature = "timing")]
+        context.init_timings.log_summary();
+
+        Ok(context)
+    }
+
+    /// Creates the swapchains of the views, laid out as configured or as `select_layout` picks.
+    /// Fails with `Error::SwapchainCreationFailed` when none of the attempts, see
+    /// `Config::swapchain_fallbacks`, succeeds, and right away if the session or the instance is
+    /// lost.
+    fn create_swapchains(&mut self) -> Result<(), Error> {
+        info!("xrEnumerateViewConfigurationViews()");
+        let view_configuration_views = {
+            let view_configuration_type = self.session.view_configuration_type();
+            let empty_view = openxr_sys::ViewConfigurationView {
+                ty: openxr_sys::ViewConfigurationView::TYPE,
+                next: std::ptr::null_mut(),
+                recommended_image_rect_width: 0,
+                max_image_rect_width: 0,
+                recommended_image_rect_height: 0,
+                max_image_rect_height: 0,
+                recommended_swapchain_sample_count: 0,
+                max_swapchain_sample_count: 0,
+            };
+            enumerate(
+                self.fp.max_enumerate_count,
+                empty_view,
+                |capacity, count, buffer| unsafe {
+                    (self.fp.enumerate_view_configuration_views)(
+                        self.instance,
+                        self.system_id,
+                        view_configuration_type,
+                        capacity,
+                        count,
+                        buffer,
+                    )
+                },
+            )?
+        };
+
+        info!("xrEnumerateSwapchainFormats()");
+        let swapchain_formats = enumerate(
+            self.fp.max_enumerate_count,
+            0,
+            |capacity, count, buffer| unsafe {
+                (self.fp.enumerate_swapchain_formats)(self.session.handle, capacity, count, buffer)
+            },
+        )?;
+
+        let swapchain_format =
+            swapchain::select_format(&swapchain_formats, &self.config.preferred_color_formats)?;
+        info!("  swapchain format: {:?}", swapchain_format);
+
+        // Anything rendered to the swapchains, color or depth, has to use the same sample count
+        let limits = unsafe {
+            self.vk_instance
+                .get_physical_device_properties(self.physical_device)
+        }
+        .limits;
+        let supported_sample_counts =
+            limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts;
+
+        let view_sizes: Vec<_> = view_configuration_views
+            .iter()
+            .map(|view| swapchain::scaled_size(view, self.config.resolution_scale))
+            .collect();
+        for (view, (width, height)) in view_configuration_views.iter().zip(&view_sizes) {
+            info!(
+                "  view size: {}x{} (recommended {}x{}, scale {})",
+                width,
+                height,
+                view.recommended_image_rect_width,
+                view.recommended_image_rect_height,
+                self.config.resolution_scale
+            );
+        }
+
+        let layout = self
+            .config
+            .swapchain_layout
+            .unwrap_or_else(|| swapchain::select_layout(&view_sizes, &limits));
+        info!("  swapchain layout: {:?}", layout);
+
+        let view_sample_count = |view: &openxr_sys::ViewConfigurationView, single_sample| {
+            if single_sample {
+                return 1;
+            }
+            swapchain::clamp_sample_count(
+                view.recommended_swapchain_sample_count,
+                supported_sample_counts,
+                self.config.max_msaa,
+            )
+        };
+        let create_swapchain = |size, array_size, format, sample_count, create_flags| {
+            swapchain::create_swapchain(
+                &self.fp,
+                self.session.handle,
+                size,
+                array_size,
+                format,
+                sample_count,
+                self.config.swapchain_usage,
+                create_flags,
+                self.config.foveation.is_some(),
+            )
+        };
+
+        let view_count = view_sizes.len() as u32;
+        let create_swapchains = |format, single_sample, create_flags| -> Result<Vec<_>, Error> {
+            match layout {
+                SwapchainLayout::Separate => view_configuration_views
+                    .iter()
+                    .zip(&view_sizes)
+                    .map(|(view, &size)| {
+                        let sample_count = view_sample_count(view, single_sample);
+                        create_swapchain(size, 1, format, sample_count, create_flags)
+                    })
+                    .collect(),
+                // The views have the same size, the first one stands for all of them
+                SwapchainLayout::Array => Ok(vec![create_swapchain(
+                    view_sizes[0],
+                    view_count,
+                    format,
+                    view_sample_count(&view_configuration_views[0], single_sample),
+                    create_flags,
+                )?]),
+                SwapchainLayout::SideBySide { view_count } => {
+                    let (width, height) = view_sizes[0];
+                    Ok(vec![create_swapchain(
+                        (width * view_count, height),
+                        1,
+                        format,
+                        view_sample_count(&view_configuration_views[0], single_sample),
+                        create_flags,
+                    )?])
+                }
+            }
+        };
+
+        let mut protected = self.protected_content;
+        let mut create_attempt = |format, single_sample| {
+            if protected {
+                let flags = openxr_sys::SwapchainCreateFlags::PROTECTED_CONTENT;
+                match create_swapchains(format, single_sample, flags) {
+                    Err(Error::Xr(XrResult::ERROR_FEATURE_UNSUPPORTED)) => {
+                        warn!("The runtime doesn't support protected swapchains");
+                        protected = false;
+                    }
+                    swapchains => return swapchains,
+                }
+            }
+            create_swapchains(
+                format,
+                single_sample,
+                openxr_sys::SwapchainCreateFlags::EMPTY,
+            )
+        };
+
+        let multisampled = view_configuration_views
+            .iter()
+            .any(|view| view_sample_count(view, false) > 1);
+        let attempts = swapchain::creation_attempts(
+            swapchain_format,
+            &swapchain_formats,
+            &self.config.preferred_color_formats,
+            multisampled,
+            self.config.swapchain_fallbacks,
+        );
+
+        let mut created = None;
+        let mut failures = Vec::new();
+        for &(format, single_sample) in &attempts {
+            if !swapchain::supports_usage(
+                &self.vk_instance,
+                self.physical_device,
+                format,
+                self.config.swapchain_usage,
+            ) {
+                warn!(
+                    "Swapchain format {:?} doesn't support usage {:?}, skipping it",
+                    format, self.config.swapchain_usage
+                );
+                continue;
+            }
+
+            match create_attempt(format, single_sample) {
+                Ok(swapchains) => {
+                    created = Some((format, swapchains));
+                    break;
+                }
+                // Nothing else will work on a lost session
+                Err(error @ Error::Xr(XrResult::ERROR_SESSION_LOST))
+                | Err(error @ Error::Xr(XrResult::ERROR_INSTANCE_LOST)) => return Err(error),
+                Err(error) => {
+                    warn!(
+                        "Failed to create the swapchains in {:?}{}: {}",
+                        format,
+                        if single_sample {
+                            ", single-sampled"
+                        } else {
+                            ""
+                        },
+                        error
+                    );
+                    failures.push((format, single_sample, error));
+                }
+            }
+        }
+        let (format, swapchains) = match created {
+            Some(created) => created,
+            None => return Err(Error::SwapchainCreationFailed(failures)),
+        };
+        if format != swapchain_format || swapchains[0].sample_count == 1 && multisampled {
+            warn!(
+                "Swapchains downgraded to {:?} with {} samples",
+                format, swapchains[0].sample_count
+            );
+        }
+
+        let shader_output = swapchain::shader_output(format, self.config.manual_srgb_encode);
+        info!("  shader output: {:?}", shader_output);
+
+        self.protected_content = protected;
+        self.session.swapchain_layout = layout;
+        self.shader_output = shader_output;
+        self.session.swapchains = swapchains;
+
+        if let Some(level) = self.config.foveation {
+            let handles = self
+                .session
+                .swapchains
+                .iter()
+                .map(|swapchain| swapchain.handle)
+                .collect();
+            self.session.foveate_swapchains(handles, level)?;
+        }
+        Ok(())
+    }
+
+    /// Whether the shaders rendering to the swapchains should output linear or sRGB-encoded
+    /// colors, which depends on the format picked and on `Config::manual_srgb_encode`.
+    pub fn shader_output(&self) -> ShaderOutput {
+        self.shader_output
+    }
+
+    /// Recreates the reference space the views and layers are expressed in under the head, as
+    /// located at `time`, facing where it faces but level, see `Session::recenter`, which
+    /// `App::run` calls this for. Does nothing, with a warning, while the head isn't tracked.
+    /// `Session::eye_gaze_pose` is located relative to the new space afterwards.
+    pub fn recenter(&mut self, time: XrTime) -> Result<(), Error> {
+        let mut location = openxr_sys::SpaceLocation::out(std::ptr::null_mut());
+        let result = unsafe {
+            (self.fp.locate_space)(
+                self.view_space,
+                self.space,
+                time.into(),
+                location.as_mut_ptr(),
+            )
+        };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        let location = unsafe { location.assume_init() };
+        let valid = openxr_sys::SpaceLocationFlags::ORIENTATION_VALID
+            | openxr_sys::SpaceLocationFlags::POSITION_VALID;
+        if !location.location_flags.contains(valid) {
+            warn!("Can't recenter while the head isn't tracked");
+            return Ok(());
+        }
+
+        let head = openxr_sys::Posef {
+            orientation: math::yaw_only(location.pose.orientation),
+            position: location.pose.position,
+        };
+        let pose = math::compose_poses(&self.space_pose, &head);
+        let create_info = openxr_sys::ReferenceSpaceCreateInfo {
+            ty: openxr_sys::ReferenceSpaceCreateInfo::TYPE,
+            next: std::ptr::null(),
+            reference_space_type: openxr_sys::ReferenceSpaceType::LOCAL,
+            pose_in_reference_space: pose,
+        };
+
+        info!("xrCreateReferenceSpace()");
+        let mut space = openxr_sys::Space::NULL;
+        let result = unsafe {
+            (self.fp.create_reference_space)(self.session.handle, &create_info, &mut space)
+        };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        let old_space = std::mem::replace(&mut self.space, space);
+        self.space_pose = pose;
+        // The gaze is located relative to the space, nothing else keeps its handle
+        self.session.set_eye_gaze_base_space(space);
+
+        info!("xrDestroySpace()");
+        let result = unsafe { (self.fp.destroy_space)(old_space) };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+        Ok(())
+    }
+
+    /// The VIEW reference space, whose origin sits between the eyes and follows the head, -Z
+    /// looking ahead. Layers in it are head-locked, see `FrameContext::head_locked_quad`.
+    pub fn view_space(&self) -> openxr_sys::Space {
+        self.view_space
+    }
+
+    /// How the views are laid out in `swapchains`, to get the swapchain and the sub-image of each
+    /// view with `SwapchainLayout::swapchain_index` and `Swapchain::sub_image`.
+    pub fn swapchain_layout(&self) -> SwapchainLayout {
+        self.session.swapchain_layout
+    }
+
+    /// How long each step of the initialization took.
+    #[cfg(feature = "timing")]
+    pub fn init_timings(&self) -> &InitTimings {
+        &self.init_timings
+    }
+
+    pub fn vk_instance(&self) -> &ash::Instance {
+        &self.vk_instance
+    }
+
+    pub fn physical_device(&self) -> ash::vk::PhysicalDevice {
+        self.physical_device
+    }
+
+    pub fn device(&self) -> &ash::Device {
+        &self.device
+    }
+
+    /// Swapchains the views are rendered to, laid out as `swapchain_layout` says, to create the
+    /// `ViewRenderTarget`s from. They're recreated along with the session, after which the
+    /// targets must be too.
+    pub fn swapchains(&self) -> &[Swapchain] {
+        &self.session.swapchains
+    }
+
+    /// Converts a `CLOCK_MONOTONIC` time, as returned by `clock_gettime`, to the runtime clock,
+    /// to relate timestamps from other sources, such as sensors or audio, to the predicted
+    /// display times. Uses XR_KHR_convert_timespec_time, hence `Error::MissingExtension` when
+    /// the runtime doesn't have it. See `performance_counter_to_xr_time` on Windows.
+    #[cfg(not(windows))]
+    pub fn timespec_to_xr_time(&self, timespec: time::Timespec) -> Result<XrTime, Error> {
+        let convert = self
+            .fp
+            .convert_timespec_time_to_time_KHR
+            .ok_or(Error::MissingExtension(time::TIMESPEC_EXTENSION))?;
+
+        let mut xr_time = openxr_sys::Time::from_nanos(0);
+        let result = unsafe { convert(self.instance, &timespec, &mut xr_time) };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        Ok(xr_time.into())
+    }
+
+    /// Inverse of `timespec_to_xr_time`.
+    #[cfg(not(windows))]
+    pub fn xr_time_to_timespec(&self, xr_time: XrTime) -> Result<time::Timespec, Error> {
+        let convert = self
+            .fp
+            .convert_time_to_timespec_time_KHR
+            .ok_or(Error::MissingExtension(time::TIMESPEC_EXTENSION))?;
+
+        let mut timespec = time::Timespec::default();
+        let result = unsafe { convert(self.instance, xr_time.into(), &mut timespec) };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        Ok(timespec)
+    }
+
+    /// Converts a `QueryPerformanceCounter` value to the runtime clock, the Windows counterpart
+    /// of `timespec_to_xr_time`, with XR_KHR_win32_convert_performance_counter_time.
+    #[cfg(windows)]
+    pub fn performance_counter_to_xr_time(&self, counter: i64) -> Result<XrTime, Error> {
+        let convert = self
+            .fp
+            .convert_win32_performance_counter_to_time_KHR
+            .ok_or(Error::MissingExtension(time::PERFORMANCE_COUNTER_EXTENSION))?;
+
+        let mut xr_time = openxr_sys::Time::from_nanos(0);
+        let result = unsafe { convert(self.instance, &counter, &mut xr_time) };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        Ok(xr_time.into())
+    }
+
+    /// Inverse of `performance_counter_to_xr_time`.
+    #[cfg(windows)]
+    pub fn xr_time_to_performance_counter(&self, xr_time: XrTime) -> Result<i64, Error> {
+        let convert = self
+            .fp
+            .convert_time_to_win32_performance_counter_KHR
+            .ok_or(Error::MissingExtension(time::PERFORMANCE_COUNTER_EXTENSION))?;
+
+        let mut counter = 0;
+        let result = unsafe { convert(self.instance, xr_time.into(), &mut counter) };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        Ok(counter)
+    }
+
+    /// Features asked for in the config that this run goes without, the runtime lacking their
+    /// extension. Empty with `Config::require_features`, initialization failing instead.
+    pub fn unavailable_features(&self) -> &[UnavailableFeature] {
+        &self.unavailable_features
+    }
+
+    /// OpenXR extensions enabled on the instance: the ones the context needs, the optional ones
+    /// the runtime has, and the ones asked for by the config.
+    pub fn enabled_extensions(&self) -> &[CString] {
+        &self.enabled_extensions
+    }
+
+    /// Whether the swapchain images are protected, see `Config::protected_content`. When they are,
+    /// the frames' command buffers are protected too, and so must be whatever the render callback
+    /// writes to the images with.
+    pub fn protected_content(&self) -> bool {
+        self.protected_content
+    }
+
+    /// Depth format to create the depth attachments with, as given to `ViewRenderTarget::new`,
+    /// and the render passes using them.
+    pub fn depth_format(&self) -> ash::vk::Format {
+        self.depth_format
+    }
+
+    /// Handles to share the Vulkan objects with a renderer that doesn't go through ash, or
+    /// that's built on a different version of it. The ash wrappers are available through
+    /// `vk_instance` and `device` otherwise.
+    pub fn vulkan_handles(&self) -> VulkanHandles<'_> {
+        VulkanHandles {
+            instance: self.vk_instance.handle(),
+            physical_device: self.physical_device,
+            device: self.device.handle(),
+            graphics_queue: self.queue,
+            compute_queue: self.compute_queue,
+            transfer_queue: self.transfer_queue,
+            _context: PhantomData,
+        }
+    }
+
+    /// Queue the swapchain images are rendered with, the one the runtime knows about.
+    pub fn graphics_queue(&self) -> DeviceQueue {
+        self.queue
+    }
+
+    /// Extra queue requested through `QueueConfig::compute_priority`.
+    pub fn compute_queue(&self) -> Option<DeviceQueue> {
+        self.compute_queue
+    }
+
+    /// Extra queue requested through `QueueConfig::transfer_priority`.
+    pub fn transfer_queue(&self) -> Option<DeviceQueue> {
+        self.transfer_queue
+    }
+
+    /// Blocks until the device has finished all the work submitted to it.
+    ///
+    /// This must be called before any `destroy_*` of resources that may still be in flight,
+    /// otherwise the GPU can end up using freed objects.
+    pub fn device_wait_idle(&self) -> Result<(), Error> {
+        info!("vkDeviceWaitIdle()");
+        unsafe { self.device.device_wait_idle()? };
+        Ok(())
+    }
+
+    /// Runs a whole frame in `space`, see `Session::render_and_submit`.
+    pub fn render_and_submit<F>(
+        &mut self,
+        space: openxr_sys::Space,
+        render: &mut F,
+    ) -> Result<bool, Error>
+    where
+        F: FnMut(usize, u32, [[f32; 4]; 4], ash::vk::CommandBuffer) -> Result<(), Error>,
+    {
+        self.session.render_and_submit(space, render)
+    }
+
+    /// Destroys the swapchains and creates them again from the current view configuration.
+    ///
+    /// The images belong to the runtime, which never invalidates them on its own, so this is
+    /// needed when the session restarts (the recommended resolution may have changed), or when
+    /// the Vulkan objects built on top of the images are out of date. `App::run` takes care of
+    /// both cases.
+    ///
+    /// Fails with `Error::SwapchainCreationFailed` if the runtime turns down every format and
+    /// sample count tried, the context being left without swapchains.
+    pub fn recreate_swapchains(&mut self) -> Result<(), Error> {
+        self.device_wait_idle()?;
+        #[cfg(feature = "mirror-window")]
+        self.close_mirror()?;
+        self.destroy_debug_grid()?;
+        self.destroy_swapchains()?;
+        self.create_swapchains()?;
+        self.debug_grid = self.create_debug_grid()?;
+        self.name_objects();
+        Ok(())
+    }
+
+    /// Releases the GPU resources that can be recreated, so a backgrounded Android app holds on
+    /// to as little memory as possible.
+    ///
+    /// The swapchains and the objects built on top of them (the debug grid) are destroyed. The
+    /// OpenXR instance, session and spaces, and the Vulkan instance, device and queue, are kept.
+    /// No frame must be submitted until `resume`, which `App::run` takes care of when
+    /// `Config::suspend_when_stopped` is set.
+    pub fn suspend(&mut self) -> Result<(), Error> {
+        if self.session.suspended {
+            return Ok(());
+        }
+
+        info!("Suspending, releasing the swapchains");
+        self.device_wait_idle()?;
+        self.destroy_debug_grid()?;
+        self.destroy_swapchains()?;
+        self.session.suspended = true;
+        Ok(())
+    }
+
+    /// Creates again what `suspend` released, from the current view configuration.
+    pub fn resume(&mut self) -> Result<(), Error> {
+        if !self.session.suspended {
+            return Ok(());
+        }
+
+        info!("Resuming, creating the swapchains");
+        self.create_swapchains()?;
+        self.debug_grid = self.create_debug_grid()?;
+        self.name_objects();
+        self.session.suspended = false;
+        Ok(())
+    }
+
+    pub fn is_suspended(&self) -> bool {
+        self.session.suspended
+    }
+
+    /// Names the main Vulkan objects and the swapchain images after what they are, when debug
+    /// utils are available.
+    fn name_objects(&self) {
+        let debug_utils = match &self.debug_utils {
+            Some(debug_utils) => debug_utils,
+            None => return,
+        };
+        let device = self.device.handle();
+
+        debug::set_object_name(
+            debug_utils,
+            device,
+            self.vk_instance.handle(),
+            "xr-instance",
+        );
+        debug::set_object_name(debug_utils, device, device, "xr-device");
+        debug::set_object_name(debug_utils, device, self.queue.queue, "xr-queue");
+        if let Some(compute_queue) = self.compute_queue {
+            debug::set_object_name(debug_utils, device, compute_queue.queue, "xr-compute-queue");
+        }
+        if let Some(transfer_queue) = self.transfer_queue {
+            debug::set_object_name(
+                debug_utils,
+                device,
+                transfer_queue.queue,
+                "xr-transfer-queue",
+            );
+        }
+
+        // The views each swapchain holds, there being one per view only with `Separate`
+        for (swapchain_index, swapchain) in self.session.swapchains.iter().enumerate() {
+            let views = match self.session.swapchain_layout {
+                SwapchainLayout::Separate => format!("view{}", swapchain_index),
+                SwapchainLayout::Array => format!("views0-{}-array", swapchain.array_size - 1),
+                SwapchainLayout::SideBySide { view_count } => {
+                    format!("views0-{}-side-by-side", view_count - 1)
+                }
+            };
+            for (index, &image) in swapchain.images.iter().enumerate() {
+                let name = format!("xr-swapchain{}-img{}-{}", swapchain_index, index, views);
+                debug::set_object_name(debug_utils, device, image, &name);
+            }
+        }
+    }
+
+    fn create_debug_grid(&self) -> Result<Option<DebugGrid>, Error> {
+        if !self.config.debug_grid {
+            return Ok(None);
+        }
+        if self.protected_content {
+            warn!("The debug grid can't draw to protected swapchains, leaving it out");
+            return Ok(None);
+        }
+
+        DebugGrid::new(
+            &self.fp,
+            self.session.handle,
+            &self.vk_instance,
+            self.physical_device,
+            &self.device,
+            self.queue.family_index,
+            &self.session.swapchains,
+            self.session.swapchain_layout,
+            self.shader_output,
+            self.config.eye_test_pattern,
+        )
+        .map(Some)
+    }
+
+    fn destroy_debug_grid(&mut self) -> Result<(), Error> {
+        match self.debug_grid.take() {
+            Some(debug_grid) => debug_grid.destroy(&self.fp, &self.device),
+            None => Ok(()),
+        }
+    }
+
+    /// Renders the debug grid, if enabled, to the images acquired from the swapchains, the views
+    /// in `view_order`, each within its sub-image. Returns the GPU time of each view, when the
+    /// device supports timestamps.
+    pub(crate) fn draw_debug_grid(
+        &self,
+        display_time: XrTime,
+        sub_images: &[openxr_sys::SwapchainSubImage],
+        image_indices: &[u32],
+        view_order: &[u32],
+    ) -> Result<Vec<Duration>, Error> {
+        match &self.debug_grid {
+            Some(debug_grid) => debug_grid.draw(
+                &self.session,
+                &self.device,
+                self.queue.queue,
+                display_time,
+                sub_images,
+                image_indices,
+                view_order,
+            ),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Starts mirroring the left eye to `window`. Nothing happens with multisampled swapchains,
+    /// which can't be blitted.
+    #[cfg(feature = "mirror-window")]
+    pub(crate) fn open_mirror(&mut self, window: winit::window::Window) -> Result<(), Error> {
+        if !self.session.swapchains.is_empty() && !mirror::can_mirror(&self.session.swapchains[0]) {
+            return Ok(());
+        }
+        // Protected images can't be copied to an unprotected one, which the window's are
+        if self.protected_content {
+            warn!("Protected swapchains can't be mirrored");
+            return Ok(());
+        }
+
+        self.mirror = Some(MirrorWindow::new(
+            &self.vk_entry,
+            &self.vk_instance,
+            self.physical_device,
+            &self.device,
+            self.queue.family_index,
+            window,
+        )?);
+        Ok(())
+    }
+
+    #[cfg(feature = "mirror-window")]
+    pub(crate) fn close_mirror(&mut self) -> Result<(), Error> {
+        if let Some(mirror) = self.mirror.take() {
+            self.device_wait_idle()?;
+            mirror.destroy(&self.device);
+        }
+        Ok(())
+    }
+
+    /// Copies `sub_image` of the left eye image, acquired at `image_indices[0]`, to the mirror
+    /// window if open.
+    #[cfg(feature = "mirror-window")]
+    pub(crate) fn draw_mirror(
+        &mut self,
+        image_indices: &[u32],
+        sub_image: &openxr_sys::SwapchainSubImage,
+    ) -> Result<(), Error> {
+        match (&mut self.mirror, self.session.swapchains.first()) {
+            (Some(mirror), Some(swapchain)) => mirror.blit(
+                self.physical_device,
+                &self.device,
+                self.queue.queue,
+                swapchain,
+                sub_image,
+                image_indices[0],
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Copies `sub_image` of the left eye image, acquired at `image_indices[0]`, to
+    /// `capture_slot` if a capture was asked for. Nothing gets captured from protected, or
+    /// multisampled, swapchains, or from formats other than 8-bit RGBA and BGRA.
+    #[cfg(feature = "capture")]
+    pub(crate) fn capture_view(
+        &mut self,
+        image_indices: &[u32],
+        sub_image: &openxr_sys::SwapchainSubImage,
+    ) -> Result<(), Error> {
+        if !self.capture_slot.take_request() {
+            return Ok(());
+        }
+        let swapchain = match self.session.swapchains.first() {
+            Some(swapchain) if capture::can_capture(swapchain) => swapchain,
+            _ => return Ok(()),
+        };
+        if self.protected_content {
+            warn!("Protected swapchains can't be captured");
+            return Ok(());
+        }
+
+        if self.capture.is_none() {
+            self.capture = Some(FrameCapture::new(&self.device, self.queue.family_index)?);
+        }
+        let image = self.capture.as_mut().unwrap().capture(
+            &self.vk_instance,
+            self.physical_device,
+            &self.device,
+            self.queue.queue,
+            swapchain,
+            sub_image,
+            image_indices[0],
+        )?;
+        self.capture_slot.store(image);
+        Ok(())
+    }
+
+    fn destroy_swapchains(&mut self) -> Result<(), Error> {
+        self.session.forget_foveated_swapchains();
+        for swapchain in self.session.swapchains.drain(..) {
+            swapchain.destroy()?;
+        }
+        Ok(())
+    }
+
+    /// Tears down everything, children before their parents.
+    pub fn destroy(mut self) -> Result<(), Error> {
+        self.device_wait_idle()?;
+        self.destroy_debug_grid()?;
+        self.destroy_swapchains()?;
+        self.session.destroy_actions()?;
+        self.session.destroy_passthrough()?;
+
+        info!("xrDestroySpace()");
+        let result = unsafe { (self.fp.destroy_space)(self.view_space) };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        info!("xrDestroySpace()");
+        let result = unsafe { (self.fp.destroy_space)(self.space) };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        info!("xrDestroySession()");
+        let result = unsafe { (self.fp.destroy_session)(self.session.handle) };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        info!("xrDestroyInstance()");
+        let result = unsafe { (self.fp.destroy_instance)(self.instance) };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        if let Some(recorder) = self.session.recorder.take() {
+            recorder.destroy();
+        }
+        #[cfg(feature = "capture")]
+        if let Some(capture) = self.capture.take() {
+            capture.destroy(&self.device);
+        }
+
+        unsafe {
+            info!("vkDestroyDevice()");
+            self.device.destroy_device(None);
+            info!("vkDestroyInstance()");
+            self.vk_instance.destroy_instance(None);
+        }
+
+        Ok(())
+    }
+}
+
+/// Names of the instance extensions the runtime and the API layers provide.
+fn available_xr_extensions(
+    entry: &XrEntryFp,
+    max_enumerate_count: u32,
+) -> Result<Vec<CString>, Error> {
+    info!("xrEnumerateInstanceExtensionProperties()");
+    let empty_properties = openxr_sys::ExtensionProperties {
+        ty: openxr_sys::ExtensionProperties::TYPE,
+        next: std::ptr::null_mut(),
+        extension_name: [0; openxr_sys::MAX_EXTENSION_NAME_SIZE],
+        extension_version: 0,
+    };
+    let ext_properties = enumerate(
+        max_enumerate_count,
+        empty_properties,
+        |capacity, count, buffer| unsafe {
+            (entry.enumerate_instance_extension_properties)(
+                std::ptr::null(),
+                capacity,
+                count,
+                buffer,
+            )
+        },
+    )?;
+
+    Ok(ext_properties
+        .iter()
+        .map(|x| {
+            if !x.extension_name.contains(&0) {
+                panic!("Found invalid extension");
+            }
+            unsafe { CStr::from_ptr(x.extension_name.as_ptr()) }.to_owned()
+        })
+        .collect())
+}
+
+/// Vulkan extensions the runtime needs, from `get_extensions`, xrGetVulkanInstanceExtensionsKHR
+/// or xrGetVulkanDeviceExtensionsKHR, which share their signature.
+fn required_vulkan_extensions(
+    fp: &XrInstanceFp,
+    instance: openxr_sys::Instance,
+    system_id: openxr_sys::SystemId,
+    get_extensions: openxr_sys::pfn::GetVulkanInstanceExtensionsKHR,
+) -> Result<Vec<CString>, Error> {
+    let buffer = enumerate(
+        fp.max_enumerate_count,
+        0,
+        |capacity, count, buffer| unsafe {
+            get_extensions(instance, system_id, capacity, count, buffer)
+        },
+    )?;
+    Ok(split_extension_list(&buffer))
+}
+
+/// Creates the Vulkan instance through xrCreateVulkanInstanceKHR, the runtime adding the
+/// extensions it needs to `create_info`. Fails with `Error::Vulkan` when vkCreateInstance, which
+/// the runtime calls, does.
+fn create_vulkan_instance(
+    vulkan_enable2: VulkanEnable2,
+    instance: openxr_sys::Instance,
+    system_id: openxr_sys::SystemId,
+    vk_entry: &ash::Entry,
+    create_info: &ash::vk::InstanceCreateInfo,
+) -> Result<ash::Instance, Error> {
+    info!("xrCreateVulkanInstanceKHR()");
+    let xr_create_info = openxr_sys::VulkanInstanceCreateInfoKHR {
+        ty: openxr_sys::VulkanInstanceCreateInfoKHR::TYPE,
+        next: std::ptr::null(),
+        system_id,
+        create_flags: openxr_sys::VulkanInstanceCreateFlagsKHR::EMPTY,
+        pfn_get_instance_proc_addr: Some(unsafe {
+            transmute(vk_entry.static_fn().get_instance_proc_addr)
+        }),
+        vulkan_create_info: create_info as *const ash::vk::InstanceCreateInfo as *const _,
+        vulkan_allocator: std::ptr::null(),
+    };
+
+    let mut vk_instance = std::ptr::null();
+    let mut vk_result = ash::vk::Result::ERROR_INITIALIZATION_FAILED.as_raw();
+    unsafe {
+        (vulkan_enable2.create_instance)(
+            instance,
+            &xr_create_info,
+            &mut vk_instance,
+            &mut vk_result,
+        )
+    }
+    .into_result()?;
+    match ash::vk::Result::from_raw(vk_result) {
+        ash::vk::Result::SUCCESS => {}
+        result => return Err(Error::Vulkan(result)),
+    }
+
+    let vk_instance = ash::vk::Instance::from_raw(vk_instance as u64);
+    Ok(unsafe { ash::Instance::load(vk_entry.static_fn(), vk_instance) })
+}
+
+/// Creates the Vulkan device through xrCreateVulkanDeviceKHR, the counterpart of
+/// `create_vulkan_instance`.
+fn create_vulkan_device(
+    vulkan_enable2: VulkanEnable2,
+    instance: openxr_sys::Instance,
+    system_id: openxr_sys::SystemId,
+    vk_entry: &ash::Entry,
+    vk_instance: &ash::Instance,
+    physical_device: ash::vk::PhysicalDevice,
+    create_info: &ash::vk::DeviceCreateInfo,
+) -> Result<ash::Device, Error> {
+    info!("xrCreateVulkanDeviceKHR()");
+    let xr_create_info = openxr_sys::VulkanDeviceCreateInfoKHR {
+        ty: openxr_sys::VulkanDeviceCreateInfoKHR::TYPE,
+        next: std::ptr::null(),
+        system_id,
+        create_flags: openxr_sys::VulkanDeviceCreateFlagsKHR::EMPTY,
+        pfn_get_instance_proc_addr: Some(unsafe {
+            transmute(vk_entry.static_fn().get_instance_proc_addr)
+        }),
+        vulkan_physical_device: physical_device.as_raw() as *const c_void,
+        vulkan_create_info: create_info as *const ash::vk::DeviceCreateInfo as *const _,
+        vulkan_allocator: std::ptr::null(),
+    };
+
+    let mut device = std::ptr::null();
+    let mut vk_result = ash::vk::Result::ERROR_INITIALIZATION_FAILED.as_raw();
+    unsafe {
+        (vulkan_enable2.create_device)(instance, &xr_create_info, &mut device, &mut vk_result)
+    }
+    .into_result()?;
+    match ash::vk::Result::from_raw(vk_result) {
+        ash::vk::Result::SUCCESS => {}
+        result => return Err(Error::Vulkan(result)),
+    }
+
+    let device = ash::vk::Device::from_raw(device as u64);
+    Ok(unsafe { ash::Device::load(vk_instance.fp_v1_0(), device) })
+}
+
+/// Picks the Varjo quad views when the system supports them, stereo otherwise, the extension
+/// being available even on systems without focus displays.
+fn select_view_configuration(
+    fp: &XrInstanceFp,
+    instance: openxr_sys::Instance,
+    system_id: openxr_sys::SystemId,
+) -> Result<openxr_sys::ViewConfigurationType, Error> {
+    info!("xrEnumerateViewConfigurations()");
+    let view_configurations = enumerate(
+        fp.max_enumerate_count,
+        openxr_sys::ViewConfigurationType::PRIMARY_STEREO,
+        |capacity, count, buffer| unsafe {
+            (fp.enumerate_view_configurations)(instance, system_id, capacity, count, buffer)
+        },
+    )?;
+
+    let quad_views = openxr_sys::ViewConfigurationType::PRIMARY_QUAD_VARJO;
+    if view_configurations.contains(&quad_views) {
+        Ok(quad_views)
+    } else {
+        warn!("The system doesn't support {:?}, using stereo", quad_views);
+        Ok(openxr_sys::ViewConfigurationType::PRIMARY_STEREO)
+    }
+}
+
+/// Checks the handles given to the runtime, so a failure earlier in the Vulkan setup is reported
+/// here rather than as an opaque xrCreateSession error.
+fn validate_graphics_binding(
+    binding: &openxr_sys::GraphicsBindingVulkanKHR,
+    vk_instance: &ash::Instance,
+    physical_device: ash::vk::PhysicalDevice,
+) -> Result<(), Error> {
+    let handles = [
+        ("instance", binding.instance.is_null()),
+        ("physical_device", binding.physical_device.is_null()),
+        ("device", binding.device.is_null()),
+    ];
+    if let Some((field, _)) = handles.iter().find(|(_, is_null)| *is_null) {
+        return Err(Error::InvalidGraphicsBinding(*field));
+    }
+
+    let queue_family_count = unsafe {
+        vk_instance
+            .get_physical_device_queue_family_properties(physical_device)
+            .len()
+    };
+    if binding.queue_family_index as usize >= queue_family_count {
+        return Err(Error::InvalidGraphicsBinding("queue_family_index"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::VecDeque, os::raw::c_char};
+
+    use openxr_sys::pfn;
+
+    use super::*;
+    use crate::{config::DEFAULT_MAX_ENUMERATE_COUNT, mock, ENUMERATE_ATTEMPTS};
+
+    thread_local! {
+        /// Counts the mock runtime reports in turn, the last one staying
+        static COUNTS: RefCell<VecDeque<u32>> = RefCell::new(VecDeque::new());
+    }
+
+    fn next_count() -> u32 {
+        COUNTS.with(|counts| {
+            let mut counts = counts.borrow_mut();
+            if counts.len() > 1 {
+                counts.pop_front().unwrap()
+            } else {
+                counts[0]
+            }
+        })
+    }
+
+    unsafe extern "system" fn enumerate_extensions(
+        _layer_name: *const c_char,
+        capacity: u32,
+        count: *mut u32,
+        properties: *mut openxr_sys::ExtensionProperties,
+    ) -> XrResult {
+        let current = next_count();
+        *count = current;
+        if capacity == 0 {
+            return XrResult::SUCCESS;
+        }
+        if capacity < current {
+            return XrResult::ERROR_SIZE_INSUFFICIENT;
+        }
+        for index in 0..current as usize {
+            let name = format!("XR_TEST_extension{}", index);
+            let extension_name = &mut (*properties.add(index)).extension_name;
+            for (dst, &src) in extension_name.iter_mut().zip(name.as_bytes()) {
+                *dst = src as c_char;
+            }
+        }
+        XrResult::SUCCESS
+    }
+
+    /// Entry points of a runtime whose extension count goes through `counts`
+    fn extensions_entry(counts: &[u32]) -> XrEntryFp {
+        COUNTS.with(|reported| *reported.borrow_mut() = counts.iter().copied().collect());
+        mock::entry_fp(&[(
+            "xrEnumerateInstanceExtensionProperties",
+            mock::function(enumerate_extensions as pfn::EnumerateInstanceExtensionProperties),
+        )])
+    }
+
+    #[test]
+    fn xr_extensions_retry_when_the_count_grows() {
+        let entry = extensions_entry(&[1, 2]);
+        let extensions = available_xr_extensions(&entry, DEFAULT_MAX_ENUMERATE_COUNT).unwrap();
+        assert_eq!(
+            extensions,
+            vec![
+                CString::new("XR_TEST_extension0").unwrap(),
+                CString::new("XR_TEST_extension1").unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn xr_extensions_count_keeps_growing() {
+        let counts: Vec<u32> = (1..=ENUMERATE_ATTEMPTS + 2).collect();
+        let entry = extensions_entry(&counts);
+        assert!(matches!(
+            available_xr_extensions(&entry, DEFAULT_MAX_ENUMERATE_COUNT),
+            Err(Error::Xr(XrResult::ERROR_SIZE_INSUFFICIENT))
+        ));
+    }
+
+    #[test]
+    fn xr_extensions_over_the_cap() {
+        let entry = extensions_entry(&[3]);
+        assert!(matches!(
+            available_xr_extensions(&entry, 2),
+            Err(Error::EnumerationTooLarge(3, 2))
+        ));
+    }
+
+    const VULKAN_EXTENSIONS: &[u8] = b"VK_KHR_external_memory VK_KHR_dedicated_allocation\0";
+
+    unsafe extern "system" fn get_vulkan_extensions(
+        _instance: openxr_sys::Instance,
+        _system_id: openxr_sys::SystemId,
+        capacity: u32,
+        count: *mut u32,
+        buffer: *mut c_char,
+    ) -> XrResult {
+        let current = next_count();
+        *count = current;
+        if capacity == 0 {
+            return XrResult::SUCCESS;
+        }
+        if capacity < current {
+            return XrResult::ERROR_SIZE_INSUFFICIENT;
+        }
+        for (index, &byte) in VULKAN_EXTENSIONS.iter().enumerate() {
+            *buffer.add(index) = byte as c_char;
+        }
+        XrResult::SUCCESS
+    }
+
+    /// Instance functions of a runtime whose Vulkan extension list length goes through `counts`
+    fn vulkan_extensions_fp(counts: &[u32]) -> Arc<XrInstanceFp> {
+        COUNTS.with(|reported| *reported.borrow_mut() = counts.iter().copied().collect());
+        mock::instance_fp(
+            &[(
+                "xrGetVulkanInstanceExtensionsKHR",
+                mock::function(get_vulkan_extensions as pfn::GetVulkanInstanceExtensionsKHR),
+            )],
+            &[],
+        )
+    }
+
+    fn required_instance_extensions(fp: &XrInstanceFp) -> Result<Vec<CString>, Error> {
+        required_vulkan_extensions(
+            fp,
+            openxr_sys::Instance::from_raw(1),
+            openxr_sys::SystemId::from_raw(1),
+            fp.get_vulkan_instance_extensions_KHR,
+        )
+    }
+
+    #[test]
+    fn vulkan_extensions_retry_when_the_count_grows() {
+        let length = VULKAN_EXTENSIONS.len() as u32;
+        let fp = vulkan_extensions_fp(&[length - 10, length]);
+        assert_eq!(
+            required_instance_extensions(&fp).unwrap(),
+            vec![
+                CString::new("VK_KHR_external_memory").unwrap(),
+                CString::new("VK_KHR_dedicated_allocation").unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn vulkan_extensions_count_keeps_growing() {
+        let length = VULKAN_EXTENSIONS.len() as u32;
+        let counts: Vec<u32> = (0..ENUMERATE_ATTEMPTS + 2).map(|n| length + n).collect();
+        let fp = vulkan_extensions_fp(&counts);
+        assert!(matches!(
+            required_instance_extensions(&fp),
+            Err(Error::Xr(XrResult::ERROR_SIZE_INSUFFICIENT))
+        ));
+    }
+}
@@ -0,0 +1,174 @@
+//! XR_MSFT_controller_model: glTF models of the controllers in use, and the poses of their
+//! moving parts (buttons, triggers, thumbsticks...) to animate them with.
+
+use std::ffi::CStr;
+
+use log::info;
+use openxr_sys::ControllerModelKeyMSFT;
+
+use crate::{
+    error::{Error, XrResultExt},
+    XrInstanceFp,
+};
+
+pub(crate) const EXTENSION: &str = "XR_MSFT_controller_model";
+
+/// Model of a controller, as loaded by `Session::load_controller_model`.
+pub struct ControllerModel {
+    /// Key the model was loaded with
+    pub key: ControllerModelKeyMSFT,
+    /// Binary glTF (`.glb`), left to the application to parse
+    pub gltf: Vec<u8>,
+    /// Nodes of the glTF whose poses the runtime animates, in the order of
+    /// `Session::controller_model_node_poses`
+    pub nodes: Vec<ControllerModelNode>,
+}
+
+/// Animated node of a controller model, found in the glTF by `name` under `parent_name`.
+#[derive(Clone, Debug)]
+pub struct ControllerModelNode {
+    /// Empty when the name alone identifies the node
+    pub parent_name: String,
+    pub name: String,
+}
+
+fn missing_extension() -> Error {
+    Error::MissingExtension(EXTENSION)
+}
+
+/// Key of the model of the controller at `user_path` (`/user/hand/left`...), `None` when the
+/// runtime has no model for it, such as when no controller is connected.
+pub(crate) fn model_key(
+    fp: &XrInstanceFp,
+    session: openxr_sys::Session,
+    user_path: openxr_sys::Path,
+) -> Result<Option<ControllerModelKeyMSFT>, Error> {
+    let get_model_key = fp
+        .get_controller_model_key_MSFT
+        .ok_or_else(missing_extension)?;
+
+    let mut key_state = openxr_sys::ControllerModelKeyStateMSFT {
+        ty: openxr_sys::ControllerModelKeyStateMSFT::TYPE,
+        next: std::ptr::null_mut(),
+        model_key: ControllerModelKeyMSFT::from_raw(0),
+    };
+
+    info!("xrGetControllerModelKeyMSFT()");
+    let result = unsafe { get_model_key(session, user_path, &mut key_state) };
+    if !result.is_success() {
+        return Err(Error::Xr(result));
+    }
+
+    // XR_NULL_CONTROLLER_MODEL_KEY_MSFT
+    if key_state.model_key.into_raw() == 0 {
+        return Ok(None);
+    }
+    Ok(Some(key_state.model_key))
+}
+
+pub(crate) fn load(
+    fp: &XrInstanceFp,
+    session: openxr_sys::Session,
+    key: ControllerModelKeyMSFT,
+) -> Result<ControllerModel, Error> {
+    let (load_model, get_properties) = match (
+        fp.load_controller_model_MSFT,
+        fp.get_controller_model_properties_MSFT,
+    ) {
+        (Some(load_model), Some(get_properties)) => (load_model, get_properties),
+        _ => return Err(missing_extension()),
+    };
+
+    info!("xrLoadControllerModelMSFT()");
+    let gltf = crate::enumerate(
+        fp.max_enumerate_count,
+        0,
+        |capacity, count, buffer| unsafe { load_model(session, key, capacity, count, buffer) },
+    )?;
+
+    let empty_node = openxr_sys::ControllerModelNodePropertiesMSFT {
+        ty: openxr_sys::ControllerModelNodePropertiesMSFT::TYPE,
+        next: std::ptr::null_mut(),
+        parent_node_name: [0; openxr_sys::MAX_CONTROLLER_MODEL_NODE_NAME_SIZE_MSFT],
+        node_name: [0; openxr_sys::MAX_CONTROLLER_MODEL_NODE_NAME_SIZE_MSFT],
+    };
+    let mut properties = openxr_sys::ControllerModelPropertiesMSFT {
+        ty: openxr_sys::ControllerModelPropertiesMSFT::TYPE,
+        next: std::ptr::null_mut(),
+        node_capacity_input: 0,
+        node_count_output: 0,
+        node_properties: std::ptr::null_mut(),
+    };
+
+    info!("xrGetControllerModelPropertiesMSFT()");
+    let result = unsafe { get_properties(session, key, &mut properties) };
+    if !result.is_success() {
+        return Err(Error::Xr(result));
+    }
+
+    let mut nodes = vec![empty_node; properties.node_count_output as usize];
+    properties.node_capacity_input = nodes.len() as u32;
+    properties.node_properties = nodes.as_mut_ptr();
+
+    let result = unsafe { get_properties(session, key, &mut properties) };
+    if !result.is_success() {
+        return Err(Error::Xr(result));
+    }
+    nodes.truncate(properties.node_count_output as usize);
+
+    let name = |name: &[std::os::raw::c_char]| {
+        unsafe { CStr::from_ptr(name.as_ptr()) }
+            .to_string_lossy()
+            .into_owned()
+    };
+    let nodes = nodes
+        .iter()
+        .map(|node| ControllerModelNode {
+            parent_name: name(&node.parent_node_name),
+            name: name(&node.node_name),
+        })
+        .collect();
+
+    Ok(ControllerModel { key, gltf, nodes })
+}
+
+/// Current poses of the nodes, relative to their parent in the glTF.
+pub(crate) fn node_poses(
+    fp: &XrInstanceFp,
+    session: openxr_sys::Session,
+    key: ControllerModelKeyMSFT,
+) -> Result<Vec<openxr_sys::Posef>, Error> {
+    let get_state = fp
+        .get_controller_model_state_MSFT
+        .ok_or_else(missing_extension)?;
+
+    let empty_node = openxr_sys::ControllerModelNodeStateMSFT {
+        ty: openxr_sys::ControllerModelNodeStateMSFT::TYPE,
+        next: std::ptr::null_mut(),
+        node_pose: crate::identity_pose(),
+    };
+    let mut state = openxr_sys::ControllerModelStateMSFT {
+        ty: openxr_sys::ControllerModelStateMSFT::TYPE,
+        next: std::ptr::null_mut(),
+        node_capacity_input: 0,
+        node_count_output: 0,
+        node_states: std::ptr::null_mut(),
+    };
+
+    let result = unsafe { get_state(session, key, &mut state) };
+    if !result.is_success() {
+        return Err(Error::Xr(result));
+    }
+
+    let mut nodes = vec![empty_node; state.node_count_output as usize];
+    state.node_capacity_input = nodes.len() as u32;
+    state.node_states = nodes.as_mut_ptr();
+
+    let result = unsafe { get_state(session, key, &mut state) };
+    if !result.is_success() {
+        return Err(Error::Xr(result));
+    }
+    nodes.truncate(state.node_count_output as usize);
+
+    Ok(nodes.iter().map(|node| node.node_pose).collect())
+}
@@ -0,0 +1,27 @@
+use std::ffi::CString;
+
+use ash::{extensions::ext::DebugUtils, vk};
+use log::warn;
+
+/// Gives `object` a name that shows up in validation messages and in captures from tools like
+/// RenderDoc. Failing to name an object isn't worth stopping for, so it only gets logged.
+pub(crate) fn set_object_name<H: vk::Handle>(
+    debug_utils: &DebugUtils,
+    device: vk::Device,
+    object: H,
+    name: &str,
+) {
+    let object_name = CString::new(name).unwrap();
+    let name_info = vk::DebugUtilsObjectNameInfoEXT {
+        s_type: vk::StructureType::DEBUG_UTILS_OBJECT_NAME_INFO_EXT,
+        p_next: std::ptr::null(),
+        object_type: H::TYPE,
+        object_handle: object.as_raw(),
+        p_object_name: object_name.as_ptr(),
+    };
+
+    let result = unsafe { debug_utils.debug_utils_set_object_name(device, &name_info) };
+    if let Err(result) = result {
+        warn!("Failed to name {:?} {}: {}", H::TYPE, name, result);
+    }
+}
@@ -0,0 +1,685 @@
+use std::{io::Cursor, time::Duration};
+
+use ash::{
+    version::{DeviceV1_0, InstanceV1_0},
+    vk,
+};
+use log::{info, warn};
+
+use crate::{
+    error::{Error, XrResultExt},
+    frame_stats::GpuTimer,
+    math,
+    render_target::ViewRenderTarget,
+    session::Session,
+    swapchain::{self, ShaderOutput, Swapchain, SwapchainLayout},
+    time::XrTime,
+    XrInstanceFp,
+};
+
+/// Half the size of the floor grid, in meters
+const GRID_EXTENT: i32 = 5;
+const GRID_COLOR: [f32; 3] = [0.4, 0.4, 0.4];
+const AXIS_LENGTH: f32 = 1.0;
+const CLEAR_COLOR: [f32; 4] = [0.05, 0.05, 0.05, 1.0];
+const NEAR: f32 = 0.05;
+const FAR: f32 = 100.0;
+/// Colors of the letters of the eye test pattern, left then right
+const EYE_COLORS: [[f32; 3]; 2] = [[1.0, 0.8, 0.0], [0.0, 0.8, 1.0]];
+const CROSSHAIR_COLOR: [f32; 3] = [1.0, 1.0, 1.0];
+/// Drawing the pattern with it keeps its vertices, given in clip space, fixed in the view
+const CLIP_SPACE: math::Mat4 = [
+    1.0, 0.0, 0.0, 0.0, //
+    0.0, 1.0, 0.0, 0.0, //
+    0.0, 0.0, 1.0, 0.0, //
+    0.0, 0.0, 0.0, 1.0,
+];
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Vertex {
+    position: [f32; 3],
+    color: [f32; 3],
+}
+
+/// Draws a one meter floor grid and the X (red), Y (green) and Z (blue) axes at the origin of the
+/// stage space, to check that tracking, scale and orientation are right while bringing up an app.
+///
+/// It's meant as a bring-up aid rather than for real rendering: the frame is recorded, submitted
+/// and waited for on each call, and it clears the swapchain images.
+pub(crate) struct DebugGrid {
+    /// Stage space, or local space if the runtime has no stage
+    space: openxr_sys::Space,
+    render_pass: vk::RenderPass,
+    pipeline_layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+    vertex_buffer: vk::Buffer,
+    vertex_memory: vk::DeviceMemory,
+    vertex_count: u32,
+    /// First vertex and vertex count of the test pattern of the left and right eyes, drawn after
+    /// the grid with `Config::eye_test_pattern`
+    eye_pattern: Option<[(u32, u32); 2]>,
+    command_pool: vk::CommandPool,
+    command_buffer: vk::CommandBuffer,
+    fence: vk::Fence,
+    /// Measures each view, unless the queue doesn't support timestamps
+    timer: Option<GpuTimer>,
+    /// Target of each view
+    targets: Vec<ViewRenderTarget>,
+    layout: SwapchainLayout,
+    /// `CLEAR_COLOR` as the shader output wants it
+    clear_color: [f32; 4],
+}
+
+impl DebugGrid {
+    /// Sets everything up to render to `swapchains`, laid out as `layout`, which must all share
+    /// the same format and sample count, the colors being encoded as `shader_output` says. With
+    /// `eye_pattern`, the views also get the test pattern of their eye.
+    pub(crate) fn new(
+        fp: &XrInstanceFp,
+        session: openxr_sys::Session,
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        device: &ash::Device,
+        queue_family_index: u32,
+        swapchains: &[Swapchain],
+        layout: SwapchainLayout,
+        shader_output: ShaderOutput,
+        eye_pattern: bool,
+    ) -> Result<Self, Error> {
+        let space = create_space(fp, session)?;
+
+        let view_count = match layout {
+            SwapchainLayout::Separate => swapchains.len() as u32,
+            SwapchainLayout::Array => swapchains[0].array_size,
+            SwapchainLayout::SideBySide { view_count } => view_count,
+        };
+
+        let format = swapchains[0].format;
+        let samples = vk::SampleCountFlags::from_raw(swapchains[0].sample_count);
+
+        let render_pass = create_render_pass(device, format, samples)?;
+        let (pipeline_layout, pipeline) = create_pipeline(device, render_pass, samples)?;
+
+        let mut vertices = vertices();
+        let vertex_count = vertices.len() as u32;
+        let eye_pattern = if eye_pattern {
+            let mut ranges = [(0, 0); 2];
+            for (eye, range) in ranges.iter_mut().enumerate() {
+                let pattern = eye_pattern_vertices(eye);
+                *range = (vertices.len() as u32, pattern.len() as u32);
+                vertices.extend(pattern);
+            }
+            Some(ranges)
+        } else {
+            None
+        };
+        // The shaders pass the vertex colors through, so encoding them is enough
+        for vertex in &mut vertices {
+            vertex.color = shader_output.encode(vertex.color);
+        }
+        let [r, g, b, a] = CLEAR_COLOR;
+        let [r, g, b] = shader_output.encode([r, g, b]);
+        let (vertex_buffer, vertex_memory) =
+            create_vertex_buffer(instance, physical_device, device, &vertices)?;
+
+        let command_pool_info = vk::CommandPoolCreateInfo {
+            flags: vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
+            queue_family_index,
+            ..Default::default()
+        };
+        let command_pool = unsafe { device.create_command_pool(&command_pool_info, None)? };
+
+        let command_buffer_info = vk::CommandBufferAllocateInfo {
+            command_pool,
+            level: vk::CommandBufferLevel::PRIMARY,
+            command_buffer_count: 1,
+            ..Default::default()
+        };
+        let command_buffer = unsafe { device.allocate_command_buffers(&command_buffer_info)? }[0];
+
+        let fence = unsafe { device.create_fence(&vk::FenceCreateInfo::default(), None)? };
+
+        let timer = match GpuTimer::new(
+            instance,
+            physical_device,
+            device,
+            queue_family_index,
+            view_count,
+        ) {
+            Ok(timer) => Some(timer),
+            Err(error @ Error::TimestampsUnsupported(_)) => {
+                info!("{}, the debug grid won't be timed", error);
+                None
+            }
+            Err(error) => return Err(error),
+        };
+
+        let targets = (0..view_count)
+            .map(|view_index| {
+                let swapchain = &swapchains[layout.swapchain_index(view_index)];
+                let array_layer = match layout {
+                    SwapchainLayout::Array => view_index,
+                    _ => 0,
+                };
+                ViewRenderTarget::new(
+                    instance,
+                    physical_device,
+                    device,
+                    render_pass,
+                    swapchain,
+                    array_layer,
+                    None,
+                    swapchain.sample_count,
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(DebugGrid {
+            space,
+            render_pass,
+            pipeline_layout,
+            pipeline,
+            vertex_buffer,
+            vertex_memory,
+            vertex_count,
+            eye_pattern,
+            command_pool,
+            command_buffer,
+            fence,
+            timer,
+            targets,
+            layout,
+            clear_color: [r, g, b, a],
+        })
+    }
+
+    /// Renders the grid to the image at `image_indices[i]` of the swapchain `i`, for the swapchain
+    /// of each view, within `sub_images[view]`, one view after the other in `view_order`, and
+    /// waits for it to be done.
+    /// Returns the GPU time of each view, if it could be measured.
+    pub(crate) fn draw(
+        &self,
+        session: &Session,
+        device: &ash::Device,
+        queue: vk::Queue,
+        display_time: XrTime,
+        sub_images: &[openxr_sys::SwapchainSubImage],
+        image_indices: &[u32],
+        view_order: &[u32],
+    ) -> Result<Vec<Duration>, Error> {
+        let views = session.locate_views(self.space, display_time)?;
+
+        let begin_info = vk::CommandBufferBeginInfo {
+            flags: vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+            ..Default::default()
+        };
+
+        unsafe {
+            device.begin_command_buffer(self.command_buffer, &begin_info)?;
+            if let Some(timer) = &self.timer {
+                timer.reset(device, self.command_buffer);
+            }
+
+            for &index in view_order {
+                let (view, target) = (&views[index as usize], &self.targets[index as usize]);
+                let swapchain_index = self.layout.swapchain_index(index);
+                let image_index = image_indices[swapchain_index];
+                if let Some(timer) = &self.timer {
+                    timer.begin(device, self.command_buffer, index);
+                }
+                self.record_view(
+                    device,
+                    index,
+                    view,
+                    &sub_images[index as usize],
+                    target.for_image(image_index),
+                );
+                if let Some(timer) = &self.timer {
+                    timer.end(device, self.command_buffer, index);
+                }
+            }
+
+            device.end_command_buffer(self.command_buffer)?;
+
+            let submit_info = vk::SubmitInfo {
+                command_buffer_count: 1,
+                p_command_buffers: &self.command_buffer,
+                ..Default::default()
+            };
+            device.queue_submit(queue, &[submit_info], self.fence)?;
+            device.wait_for_fences(&[self.fence], true, u64::MAX)?;
+            device.reset_fences(&[self.fence])?;
+        }
+
+        // The fence having been waited for, the results are there
+        match &self.timer {
+            Some(timer) => Ok(timer.elapsed(device)?.unwrap_or_default()),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    unsafe fn record_view(
+        &self,
+        device: &ash::Device,
+        view_index: u32,
+        view: &openxr_sys::View,
+        sub_image: &openxr_sys::SwapchainSubImage,
+        framebuffer: vk::Framebuffer,
+    ) {
+        let rect = sub_image.image_rect;
+        let clear_value = vk::ClearValue {
+            color: vk::ClearColorValue {
+                float32: self.clear_color,
+            },
+        };
+        let render_pass_begin_info = vk::RenderPassBeginInfo {
+            render_pass: self.render_pass,
+            framebuffer,
+            render_area: vk::Rect2D {
+                offset: vk::Offset2D {
+                    x: rect.offset.x,
+                    y: rect.offset.y,
+                },
+                extent: vk::Extent2D {
+                    width: rect.extent.width as u32,
+                    height: rect.extent.height as u32,
+                },
+            },
+            clear_value_count: 1,
+            p_clear_values: &clear_value,
+            ..Default::default()
+        };
+        let view_projection = math::view_projection(&view.pose, &view.fov, NEAR, FAR, false);
+
+        let command_buffer = self.command_buffer;
+        device.cmd_begin_render_pass(
+            command_buffer,
+            &render_pass_begin_info,
+            vk::SubpassContents::INLINE,
+        );
+        swapchain::set_viewport_and_scissor(device, command_buffer, sub_image);
+        device.cmd_bind_pipeline(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            self.pipeline,
+        );
+        device.cmd_push_constants(
+            command_buffer,
+            self.pipeline_layout,
+            vk::ShaderStageFlags::VERTEX,
+            0,
+            matrix_bytes(&view_projection),
+        );
+        device.cmd_bind_vertex_buffers(command_buffer, 0, &[self.vertex_buffer], &[0]);
+        device.cmd_draw(command_buffer, self.vertex_count, 1, 0, 0);
+
+        if let Some(eye_pattern) = &self.eye_pattern {
+            // Quad views come as left, right, then the left and right focus views
+            let (first_vertex, vertex_count) = eye_pattern[view_index as usize % 2];
+            device.cmd_push_constants(
+                command_buffer,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                matrix_bytes(&CLIP_SPACE),
+            );
+            device.cmd_draw(command_buffer, vertex_count, 1, first_vertex, 0);
+        }
+
+        device.cmd_end_render_pass(command_buffer);
+    }
+
+    /// The device must be idle, or at least done with the grid.
+    pub(crate) fn destroy(self, fp: &XrInstanceFp, device: &ash::Device) -> Result<(), Error> {
+        unsafe {
+            for target in self.targets {
+                target.destroy(device);
+            }
+            if let Some(timer) = self.timer {
+                timer.destroy(device);
+            }
+            device.destroy_fence(self.fence, None);
+            device.destroy_command_pool(self.command_pool, None);
+            device.destroy_buffer(self.vertex_buffer, None);
+            device.free_memory(self.vertex_memory, None);
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+            device.destroy_render_pass(self.render_pass, None);
+        }
+
+        info!("xrDestroySpace()");
+        let result = unsafe { (fp.destroy_space)(self.space) };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        Ok(())
+    }
+}
+
+fn create_space(
+    fp: &XrInstanceFp,
+    session: openxr_sys::Session,
+) -> Result<openxr_sys::Space, Error> {
+    let mut create_info = openxr_sys::ReferenceSpaceCreateInfo {
+        ty: openxr_sys::ReferenceSpaceCreateInfo::TYPE,
+        next: std::ptr::null(),
+        reference_space_type: openxr_sys::ReferenceSpaceType::STAGE,
+        pose_in_reference_space: crate::identity_pose(),
+    };
+
+    info!("xrCreateReferenceSpace()");
+    let mut space = openxr_sys::Space::NULL;
+    let result = unsafe { (fp.create_reference_space)(session, &create_info, &mut space) };
+    if result.is_success() {
+        return Ok(space);
+    }
+
+    warn!(
+        "Failed to create a stage space ({:?}), the debug grid will be in the local space",
+        result
+    );
+    create_info.reference_space_type = openxr_sys::ReferenceSpaceType::LOCAL;
+    let result = unsafe { (fp.create_reference_space)(session, &create_info, &mut space) };
+    if !result.is_success() {
+        return Err(Error::Xr(result));
+    }
+
+    Ok(space)
+}
+
+/// Lines of the grid on the floor, followed by the axes so they're drawn over it.
+fn vertices() -> Vec<Vertex> {
+    let mut vertices = Vec::new();
+    let extent = GRID_EXTENT as f32;
+    let mut line = |from: [f32; 3], to: [f32; 3], color: [f32; 3]| {
+        vertices.push(Vertex {
+            position: from,
+            color,
+        });
+        vertices.push(Vertex {
+            position: to,
+            color,
+        });
+    };
+
+    for i in -GRID_EXTENT..=GRID_EXTENT {
+        let offset = i as f32;
+        line([offset, 0.0, -extent], [offset, 0.0, extent], GRID_COLOR);
+        line([-extent, 0.0, offset], [extent, 0.0, offset], GRID_COLOR);
+    }
+
+    let origin = [0.0, 0.0, 0.0];
+    line(origin, [AXIS_LENGTH, 0.0, 0.0], [1.0, 0.0, 0.0]);
+    line(origin, [0.0, AXIS_LENGTH, 0.0], [0.0, 1.0, 0.0]);
+    line(origin, [0.0, 0.0, AXIS_LENGTH], [0.0, 0.0, 1.0]);
+
+    vertices
+}
+
+/// Head-locked pattern telling which eye is which: a centered crosshair, and an "L" or "R"
+/// above it for `eye` 0 or 1. Positions are in clip space, Y pointing down.
+fn eye_pattern_vertices(eye: usize) -> Vec<Vertex> {
+    let mut vertices = Vec::new();
+    let mut line = |from: [f32; 2], to: [f32; 2], color: [f32; 3]| {
+        for &[x, y] in &[from, to] {
+            vertices.push(Vertex {
+                position: [x, y, 0.5],
+                color,
+            });
+        }
+    };
+
+    line([-0.05, 0.0], [0.05, 0.0], CROSSHAIR_COLOR);
+    line([0.0, -0.05], [0.0, 0.05], CROSSHAIR_COLOR);
+
+    let color = EYE_COLORS[eye];
+    let (left, right, top, middle, bottom) = (-0.1, 0.1, -0.6, -0.45, -0.3);
+    line([left, top], [left, bottom], color);
+    if eye == 0 {
+        line([left, bottom], [right, bottom], color);
+    } else {
+        line([left, top], [right, top], color);
+        line([right, top], [right, middle], color);
+        line([right, middle], [left, middle], color);
+        line([0.0, middle], [right, bottom], color);
+    }
+
+    vertices
+}
+
+/// Bytes of `matrix`, as pushed to the vertex shader.
+fn matrix_bytes(matrix: &math::Mat4) -> &[u8] {
+    unsafe {
+        std::slice::from_raw_parts(matrix.as_ptr() as *const u8, std::mem::size_of_val(matrix))
+    }
+}
+
+fn create_render_pass(
+    device: &ash::Device,
+    format: vk::Format,
+    samples: vk::SampleCountFlags,
+) -> Result<vk::RenderPass, Error> {
+    // The runtime hands color swapchain images over in the color attachment layout and expects
+    // them to be left in it. Starting from it rather than from UNDEFINED keeps the other views
+    // of a side-by-side swapchain, outside of the render area, from being discarded.
+    let attachment = vk::AttachmentDescription {
+        format,
+        samples,
+        load_op: vk::AttachmentLoadOp::CLEAR,
+        store_op: vk::AttachmentStoreOp::STORE,
+        stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+        stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+        initial_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        final_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        ..Default::default()
+    };
+    let color_reference = vk::AttachmentReference {
+        attachment: 0,
+        layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+    };
+    let subpass = vk::SubpassDescription {
+        pipeline_bind_point: vk::PipelineBindPoint::GRAPHICS,
+        color_attachment_count: 1,
+        p_color_attachments: &color_reference,
+        ..Default::default()
+    };
+    let create_info = vk::RenderPassCreateInfo {
+        attachment_count: 1,
+        p_attachments: &attachment,
+        subpass_count: 1,
+        p_subpasses: &subpass,
+        ..Default::default()
+    };
+
+    Ok(unsafe { device.create_render_pass(&create_info, None)? })
+}
+
+fn create_shader_module(device: &ash::Device, spv: &[u8]) -> Result<vk::ShaderModule, Error> {
+    let code = ash::util::read_spv(&mut Cursor::new(spv)).expect("Invalid SPIR-V");
+    let create_info = vk::ShaderModuleCreateInfo {
+        code_size: code.len() * 4,
+        p_code: code.as_ptr(),
+        ..Default::default()
+    };
+
+    Ok(unsafe { device.create_shader_module(&create_info, None)? })
+}
+
+fn create_pipeline(
+    device: &ash::Device,
+    render_pass: vk::RenderPass,
+    samples: vk::SampleCountFlags,
+) -> Result<(vk::PipelineLayout, vk::Pipeline), Error> {
+    let push_constant_range = vk::PushConstantRange {
+        stage_flags: vk::ShaderStageFlags::VERTEX,
+        offset: 0,
+        size: std::mem::size_of::<math::Mat4>() as u32,
+    };
+    let layout_info = vk::PipelineLayoutCreateInfo {
+        push_constant_range_count: 1,
+        p_push_constant_ranges: &push_constant_range,
+        ..Default::default()
+    };
+    let pipeline_layout = unsafe { device.create_pipeline_layout(&layout_info, None)? };
+
+    let vertex_module =
+        create_shader_module(device, include_bytes!("shaders/debug_grid.vert.spv"))?;
+    let fragment_module =
+        create_shader_module(device, include_bytes!("shaders/debug_grid.frag.spv"))?;
+
+    let entry_point = b"main\0";
+    let stages = [
+        vk::PipelineShaderStageCreateInfo {
+            stage: vk::ShaderStageFlags::VERTEX,
+            module: vertex_module,
+            p_name: entry_point.as_ptr() as *const _,
+            ..Default::default()
+        },
+        vk::PipelineShaderStageCreateInfo {
+            stage: vk::ShaderStageFlags::FRAGMENT,
+            module: fragment_module,
+            p_name: entry_point.as_ptr() as *const _,
+            ..Default::default()
+        },
+    ];
+
+    let binding = vk::VertexInputBindingDescription {
+        binding: 0,
+        stride: std::mem::size_of::<Vertex>() as u32,
+        input_rate: vk::VertexInputRate::VERTEX,
+    };
+    let attributes = [
+        vk::VertexInputAttributeDescription {
+            location: 0,
+            binding: 0,
+            format: vk::Format::R32G32B32_SFLOAT,
+            offset: 0,
+        },
+        vk::VertexInputAttributeDescription {
+            location: 1,
+            binding: 0,
+            format: vk::Format::R32G32B32_SFLOAT,
+            offset: std::mem::size_of::<[f32; 3]>() as u32,
+        },
+    ];
+    let vertex_input = vk::PipelineVertexInputStateCreateInfo {
+        vertex_binding_description_count: 1,
+        p_vertex_binding_descriptions: &binding,
+        vertex_attribute_description_count: attributes.len() as u32,
+        p_vertex_attribute_descriptions: attributes.as_ptr(),
+        ..Default::default()
+    };
+    let input_assembly = vk::PipelineInputAssemblyStateCreateInfo {
+        topology: vk::PrimitiveTopology::LINE_LIST,
+        ..Default::default()
+    };
+    // Viewport and scissor depend on the swapchain, they're set when recording
+    let viewport_state = vk::PipelineViewportStateCreateInfo {
+        viewport_count: 1,
+        scissor_count: 1,
+        ..Default::default()
+    };
+    let rasterization = vk::PipelineRasterizationStateCreateInfo {
+        polygon_mode: vk::PolygonMode::FILL,
+        cull_mode: vk::CullModeFlags::NONE,
+        line_width: 1.0,
+        ..Default::default()
+    };
+    let multisample = vk::PipelineMultisampleStateCreateInfo {
+        rasterization_samples: samples,
+        ..Default::default()
+    };
+    let color_blend_attachment = vk::PipelineColorBlendAttachmentState {
+        color_write_mask: vk::ColorComponentFlags::all(),
+        ..Default::default()
+    };
+    let color_blend = vk::PipelineColorBlendStateCreateInfo {
+        attachment_count: 1,
+        p_attachments: &color_blend_attachment,
+        ..Default::default()
+    };
+    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state = vk::PipelineDynamicStateCreateInfo {
+        dynamic_state_count: dynamic_states.len() as u32,
+        p_dynamic_states: dynamic_states.as_ptr(),
+        ..Default::default()
+    };
+
+    let create_info = vk::GraphicsPipelineCreateInfo {
+        stage_count: stages.len() as u32,
+        p_stages: stages.as_ptr(),
+        p_vertex_input_state: &vertex_input,
+        p_input_assembly_state: &input_assembly,
+        p_viewport_state: &viewport_state,
+        p_rasterization_state: &rasterization,
+        p_multisample_state: &multisample,
+        p_color_blend_state: &color_blend,
+        p_dynamic_state: &dynamic_state,
+        layout: pipeline_layout,
+        render_pass,
+        subpass: 0,
+        ..Default::default()
+    };
+
+    let pipelines = unsafe {
+        device.create_graphics_pipelines(vk::PipelineCache::null(), &[create_info], None)
+    };
+
+    unsafe {
+        device.destroy_shader_module(vertex_module, None);
+        device.destroy_shader_module(fragment_module, None);
+    }
+
+    let pipeline = pipelines.map_err(|(_, result)| result)?[0];
+    Ok((pipeline_layout, pipeline))
+}
+
+fn create_vertex_buffer(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+    device: &ash::Device,
+    vertices: &[Vertex],
+) -> Result<(vk::Buffer, vk::DeviceMemory), Error> {
+    let size = std::mem::size_of_val(vertices) as vk::DeviceSize;
+
+    let buffer_info = vk::BufferCreateInfo {
+        size,
+        usage: vk::BufferUsageFlags::VERTEX_BUFFER,
+        sharing_mode: vk::SharingMode::EXCLUSIVE,
+        ..Default::default()
+    };
+    let buffer = unsafe { device.create_buffer(&buffer_info, None)? };
+    let requirements = unsafe { device.get_buffer_memory_requirements(buffer) };
+
+    // The vertices never change, so host visible memory is good enough
+    let memory_properties =
+        unsafe { instance.get_physical_device_memory_properties(physical_device) };
+    let wanted = vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
+    let memory_type_index = (0..memory_properties.memory_type_count)
+        .find(|&index| {
+            requirements.memory_type_bits & (1 << index) != 0
+                && memory_properties.memory_types[index as usize]
+                    .property_flags
+                    .contains(wanted)
+        })
+        .expect("No host visible memory type for the debug grid vertices");
+
+    let allocate_info = vk::MemoryAllocateInfo {
+        allocation_size: requirements.size,
+        memory_type_index,
+        ..Default::default()
+    };
+
+    unsafe {
+        let memory = device.allocate_memory(&allocate_info, None)?;
+        device.bind_buffer_memory(buffer, memory, 0)?;
+
+        let data = device.map_memory(memory, 0, size, vk::MemoryMapFlags::empty())?;
+        std::ptr::copy_nonoverlapping(vertices.as_ptr(), data as *mut Vertex, vertices.len());
+        device.unmap_memory(memory);
+
+        Ok((buffer, memory))
+    }
+}
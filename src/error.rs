@@ -0,0 +1,275 @@
+use std::fmt;
+
+use log::warn;
+use openxr_sys::Result as XrResult;
+
+#[derive(Debug)]
+pub enum Error {
+    /// An OpenXR call returned an error code
+    Xr(XrResult),
+    /// A Vulkan call returned an error code
+    Vulkan(ash::vk::Result),
+    /// The feature used needs an extension that isn't enabled on the instance
+    MissingExtension(&'static str),
+    /// These features were asked for with `Config::require_features`, but the runtime lacks
+    /// their extensions
+    UnavailableFeatures(Vec<crate::UnavailableFeature>),
+    /// Neither xrInitializeLoaderKHR nor XR_KHR_android_create_instance are available, so the
+    /// runtime can't be given the Android VM and activity
+    AndroidInitUnavailable,
+    /// The runtime has no system of the requested form factor, or it isn't available right now
+    FormFactorUnavailable(openxr_sys::FormFactor),
+    /// Eye gaze was requested but the system doesn't support it
+    EyeGazeUnsupported,
+    /// `Config::resolution_scale` is outside of `RESOLUTION_SCALE_RANGE`
+    InvalidResolutionScale(f32),
+    /// `Config::max_frames_in_flight` is 0
+    NoFramesInFlight,
+    /// The runtime returned a swapchain without any image
+    NoSwapchainImages,
+    /// Every attempt at creating the swapchains failed: the format, whether it was
+    /// single-sampled, and why, for each. Empty when no format supports
+    /// `Config::swapchain_usage`.
+    SwapchainCreationFailed(Vec<(ash::vk::Format, bool, Error)>),
+    /// The swapchain image at this index, as filled by the runtime, has a wrong structure type or
+    /// a null handle
+    InvalidSwapchainImage(usize, &'static str),
+    /// The runtime reported this many items to enumerate, over `Config::max_enumerate_count`,
+    /// the second value
+    EnumerationTooLarge(u32, u32),
+    /// The runtime didn't provide these functions, though they're part of the core API or of an
+    /// enabled extension
+    MissingFunctions(Vec<&'static str>),
+    /// xrWaitFrame, xrBeginFrame and xrEndFrame weren't called in sequence, as described, see
+    /// `Session::wait_frame`. The call wasn't made.
+    FrameOutOfOrder(&'static str),
+    /// A swapchain image couldn't be acquired for a transient reason, see
+    /// `Swapchain::acquire_image`. `App::run` skips the frame when this happens.
+    ImageUnavailable(XrResult),
+    /// A field of the Vulkan graphics binding given to xrCreateSession is null or out of range
+    InvalidGraphicsBinding(&'static str),
+    /// The Vulkan instance doesn't support these extensions, which the runtime requires or
+    /// which vkCreateInstance failed on with `ERROR_EXTENSION_NOT_PRESENT`
+    MissingInstanceExtensions(Vec<String>),
+    /// The physical device doesn't support these Vulkan device extensions
+    MissingDeviceExtensions(Vec<String>),
+    /// The physical device doesn't support these features from `Config::required_vk_features`
+    UnsupportedDeviceFeatures(Vec<&'static str>),
+    /// Passthrough was requested but the system doesn't support it
+    PassthroughUnsupported,
+    /// `FrameContext::set_depth` was given this many sub-images rather than one per view, the
+    /// second value
+    DepthSubImageCount(usize, usize),
+    /// The action set or action name, or its localized version, is too long for the runtime or
+    /// has a nul byte
+    InvalidName(String),
+    /// The foveation level was set without `Config::foveation`, so the runtime's foveation
+    /// extensions weren't enabled or the swapchains weren't created for it, or while there are no
+    /// swapchains
+    FoveationNotEnabled,
+    /// `Config::overlay_placement` was set, to this value, without `Config::overlay`
+    OverlayPlacementWithoutOverlay(u32),
+    /// The graphics queue family can't present to the mirror window's surface
+    PresentationUnsupported,
+    /// The mirror window's surface reports no format to create its swapchain with
+    NoSurfaceFormats,
+    /// xrEnumerateSwapchainFormats returned no format to create the swapchains with
+    NoSwapchainFormats,
+    /// The queues of this family can't write timestamps, see `GpuTimer::new`
+    TimestampsUnsupported(u32),
+    /// The physical device supports none of these depth formats, see `select_depth_format`
+    NoDepthFormat(Vec<ash::vk::Format>),
+}
+
+impl Error {
+    /// Whether the error means what's being rendered to doesn't match the swapchains anymore, the
+    /// way out being to recreate them. `App::run` does so by itself when the render callback
+    /// returns such an error.
+    pub fn is_out_of_date(&self) -> bool {
+        matches!(self, Error::Vulkan(ash::vk::Result::ERROR_OUT_OF_DATE_KHR))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Xr(result) => write!(f, "OpenXR call failed: {:?}", result),
+            Error::Vulkan(result) => write!(f, "Vulkan call failed: {}", result),
+            Error::MissingExtension(name) => write!(f, "Extension {} isn't enabled", name),
+            Error::UnavailableFeatures(features) => {
+                let features: Vec<_> = features.iter().map(ToString::to_string).collect();
+                write!(f, "Required features unavailable: {}", features.join("; "))
+            }
+            Error::AndroidInitUnavailable => write!(
+                f,
+                "Neither xrInitializeLoaderKHR nor XR_KHR_android_create_instance are available"
+            ),
+            Error::FormFactorUnavailable(form_factor) => {
+                write!(f, "Form factor {:?} is unavailable", form_factor)
+            }
+            Error::EyeGazeUnsupported => write!(f, "The system doesn't support eye gaze"),
+            Error::PassthroughUnsupported => write!(f, "The system doesn't support passthrough"),
+            Error::DepthSubImageCount(count, views) => {
+                write!(f, "{} depth sub-images given for {} views", count, views)
+            }
+            Error::InvalidName(name) => write!(f, "Invalid action or action set name {:?}", name),
+            Error::FoveationNotEnabled => {
+                write!(f, "Foveation isn't enabled on the session's swapchains")
+            }
+            Error::OverlayPlacementWithoutOverlay(placement) => write!(
+                f,
+                "Overlay placement {} set for a session that isn't an overlay",
+                placement
+            ),
+            Error::InvalidResolutionScale(scale) => write!(
+                f,
+                "Resolution scale {} is outside of {:?}",
+                scale,
+                crate::config::RESOLUTION_SCALE_RANGE
+            ),
+            Error::NoFramesInFlight => write!(f, "At least one frame must be allowed in flight"),
+            Error::NoSwapchainImages => write!(f, "The swapchain has no image"),
+            Error::SwapchainCreationFailed(attempts) if attempts.is_empty() => write!(
+                f,
+                "Failed to create the swapchains, no format supports the requested usage"
+            ),
+            Error::SwapchainCreationFailed(attempts) => {
+                let attempts: Vec<_> = attempts
+                    .iter()
+                    .map(|(format, single_sample, error)| {
+                        let samples = if *single_sample {
+                            ", single-sampled"
+                        } else {
+                            ""
+                        };
+                        format!("{:?}{}: {}", format, samples, error)
+                    })
+                    .collect();
+                write!(
+                    f,
+                    "Failed to create the swapchains, tried {}",
+                    attempts.join("; ")
+                )
+            }
+            Error::InvalidSwapchainImage(index, problem) => write!(
+                f,
+                "Swapchain image {} returned by the runtime is invalid: {}",
+                index, problem
+            ),
+            Error::EnumerationTooLarge(count, max) => write!(
+                f,
+                "The runtime reported {} items to enumerate, more than the maximum of {}",
+                count, max
+            ),
+            Error::MissingFunctions(names) => {
+                write!(f, "Failed to load OpenXR functions: {}", names.join(", "))
+            }
+            Error::FrameOutOfOrder(problem) => write!(f, "Frame calls out of order: {}", problem),
+            Error::ImageUnavailable(result) => {
+                write!(f, "Swapchain image unavailable: {:?}", result)
+            }
+            Error::MissingInstanceExtensions(names) => write!(
+                f,
+                "Vulkan instance extensions unavailable: {}",
+                names.join(", ")
+            ),
+            Error::MissingDeviceExtensions(names) => write!(
+                f,
+                "Vulkan device extensions unavailable: {}",
+                names.join(", ")
+            ),
+            Error::UnsupportedDeviceFeatures(names) => write!(
+                f,
+                "Vulkan device features unsupported: {}",
+                names.join(", ")
+            ),
+            Error::InvalidGraphicsBinding(field) => {
+                write!(f, "Invalid {} in the Vulkan graphics binding", field)
+            }
+            Error::PresentationUnsupported => {
+                write!(f, "The graphics queue can't present to the mirror window")
+            }
+            Error::NoSurfaceFormats => write!(f, "The mirror window's surface has no format"),
+            Error::NoSwapchainFormats => write!(f, "The runtime supports no swapchain format"),
+            Error::TimestampsUnsupported(family_index) => write!(
+                f,
+                "Queue family {} doesn't support timestamps",
+                family_index
+            ),
+            Error::NoDepthFormat(formats) => {
+                write!(f, "None of the depth formats {:?} is supported", formats)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Adds the checks the OpenXR headers provide as macros to `openxr_sys::Result`.
+pub trait XrResultExt {
+    /// Whether the call succeeded, which is the case for every non-negative code. Qualified
+    /// successes such as `SESSION_LOSS_PENDING` or `FRAME_DISCARDED`, which the call sites
+    /// can still check for, get logged as warnings.
+    fn is_success(self) -> bool;
+
+    /// Splits the codes the way the spec does: the negative ones become `Error::Xr`, and every
+    /// other one is handed back as is, without being logged, for the call sites expecting
+    /// qualified successes such as `EVENT_UNAVAILABLE` or `TIMEOUT_EXPIRED` to tell them apart.
+    fn into_result(self) -> Result<XrResult, Error>;
+}
+
+impl XrResultExt for XrResult {
+    fn is_success(self) -> bool {
+        if self.into_raw() < 0 {
+            return false;
+        }
+        if self != XrResult::SUCCESS {
+            warn!("OpenXR call succeeded with {:?}", self);
+        }
+        true
+    }
+
+    fn into_result(self) -> Result<XrResult, Error> {
+        if self.into_raw() < 0 {
+            Err(Error::Xr(self))
+        } else {
+            Ok(self)
+        }
+    }
+}
+
+impl From<ash::vk::Result> for Error {
+    fn from(result: ash::vk::Result) -> Self {
+        Error::Vulkan(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn into_result_fails_on_negative_codes() {
+        for &code in &[
+            XrResult::ERROR_RUNTIME_FAILURE,
+            XrResult::ERROR_SESSION_LOST,
+            XrResult::ERROR_SIZE_INSUFFICIENT,
+        ] {
+            assert!(matches!(code.into_result(), Err(Error::Xr(result)) if result == code));
+            assert!(!code.is_success());
+        }
+    }
+
+    #[test]
+    fn into_result_passes_successes_through() {
+        for &code in &[
+            XrResult::SUCCESS,
+            XrResult::TIMEOUT_EXPIRED,
+            XrResult::SESSION_LOSS_PENDING,
+            XrResult::EVENT_UNAVAILABLE,
+        ] {
+            assert_eq!(code.into_result().unwrap(), code);
+            assert!(code.is_success());
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use log::info;
+use openxr_sys::SpaceLocationFlags;
+
+use crate::{
+    error::{Error, XrResultExt},
+    time::XrTime,
+    XrInstanceFp,
+};
+
+pub(crate) const EXTENSION: &str = "XR_EXT_eye_gaze_interaction";
+
+const INTERACTION_PROFILE: &str = "/interaction_profiles/ext/eye_gaze_interaction";
+const GAZE_POSE: &str = "/user/eyes_ext/input/gaze_ext/pose";
+
+/// Whether the system reports support for eye gaze interaction, which may depend on the
+/// headset even when the runtime has the extension.
+pub(crate) fn is_supported(
+    fp: &XrInstanceFp,
+    instance: openxr_sys::Instance,
+    system_id: openxr_sys::SystemId,
+) -> Result<bool, Error> {
+    let mut eye_gaze_properties = openxr_sys::SystemEyeGazeInteractionPropertiesEXT {
+        ty: openxr_sys::SystemEyeGazeInteractionPropertiesEXT::TYPE,
+        next: std::ptr::null_mut(),
+        supports_eye_gaze_interaction: false.into(),
+    };
+    let mut properties = openxr_sys::SystemProperties::out(
+        &mut eye_gaze_properties as *mut _ as *mut openxr_sys::BaseOutStructure,
+    );
+
+    info!("xrGetSystemProperties()");
+    let result =
+        unsafe { (fp.get_system_properties)(instance, system_id, properties.as_mut_ptr()) };
+    if !result.is_success() {
+        return Err(Error::Xr(result));
+    }
+
+    Ok(eye_gaze_properties.supports_eye_gaze_interaction.into())
+}
+
+/// Pose action bound to the eye gaze, along with the space it's located through.
+pub(crate) struct EyeGaze {
+    pub(crate) action_set: openxr_sys::ActionSet,
+    action: openxr_sys::Action,
+    space: openxr_sys::Space,
+    /// Space the gaze pose is expressed in
+    base_space: openxr_sys::Space,
+}
+
+impl EyeGaze {
+    /// Creates the action and suggests its binding. The action set still has to be attached to
+    /// the session before the gaze can be located.
+    pub(crate) fn new(
+        fp: &XrInstanceFp,
+        instance: openxr_sys::Instance,
+        session: openxr_sys::Session,
+        base_space: openxr_sys::Space,
+    ) -> Result<Self, Error> {
+        let mut action_set_info = openxr_sys::ActionSetCreateInfo {
+            ty: openxr_sys::ActionSetCreateInfo::TYPE,
+            next: std::ptr::null(),
+            action_set_name: [0; openxr_sys::MAX_ACTION_SET_NAME_SIZE],
+            localized_action_set_name: [0; openxr_sys::MAX_LOCALIZED_ACTION_SET_NAME_SIZE],
+            priority: 0,
+        };
+        crate::write_name(&mut action_set_info.action_set_name, "eye_gaze")?;
+        crate::write_name(&mut action_set_info.localized_action_set_name, "Eye gaze")?;
+
+        info!("xrCreateActionSet()");
+        let mut action_set = openxr_sys::ActionSet::NULL;
+        let result = unsafe { (fp.create_action_set)(instance, &action_set_info, &mut action_set) };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        let mut action_info = openxr_sys::ActionCreateInfo {
+            ty: openxr_sys::ActionCreateInfo::TYPE,
+            next: std::ptr::null(),
+            action_name: [0; openxr_sys::MAX_ACTION_NAME_SIZE],
+            action_type: openxr_sys::ActionType::POSE_INPUT,
+            count_subaction_paths: 0,
+            subaction_paths: std::ptr::null(),
+            localized_action_name: [0; openxr_sys::MAX_LOCALIZED_ACTION_NAME_SIZE],
+        };
+        crate::write_name(&mut action_info.action_name, "gaze_pose")?;
+        crate::write_name(&mut action_info.localized_action_name, "Gaze pose")?;
+
+        info!("xrCreateAction()");
+        let mut action = openxr_sys::Action::NULL;
+        let result = unsafe { (fp.create_action)(action_set, &action_info, &mut action) };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        let binding = openxr_sys::ActionSuggestedBinding {
+            action,
+            binding: crate::string_to_path(fp, instance, GAZE_POSE)?,
+        };
+        let suggested_bindings = openxr_sys::InteractionProfileSuggestedBinding {
+            ty: openxr_sys::InteractionProfileSuggestedBinding::TYPE,
+            next: std::ptr::null(),
+            interaction_profile: crate::string_to_path(fp, instance, INTERACTION_PROFILE)?,
+            count_suggested_bindings: 1,
+            suggested_bindings: &binding,
+        };
+
+        info!("xrSuggestInteractionProfileBindings()");
+        let result =
+            unsafe { (fp.suggest_interaction_profile_bindings)(instance, &suggested_bindings) };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        let space_info = openxr_sys::ActionSpaceCreateInfo {
+            ty: openxr_sys::ActionSpaceCreateInfo::TYPE,
+            next: std::ptr::null(),
+            action,
+            subaction_path: openxr_sys::Path::NULL,
+            pose_in_action_space: crate::identity_pose(),
+        };
+
+        info!("xrCreateActionSpace()");
+        let mut space = openxr_sys::Space::NULL;
+        let result = unsafe { (fp.create_action_space)(session, &space_info, &mut space) };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        Ok(EyeGaze {
+            action_set,
+            action,
+            space,
+            base_space,
+        })
+    }
+
+    /// Has the gaze located relative to `base_space` from now on, the previous base space
+    /// being about to be destroyed.
+    pub(crate) fn set_base_space(&mut self, base_space: openxr_sys::Space) {
+        self.base_space = base_space;
+    }
+
+    /// Pose of the gaze at `time`, `None` when the runtime isn't tracking the eyes.
+    /// Actions must have been synced for the current frame.
+    pub(crate) fn locate(
+        &self,
+        fp: &XrInstanceFp,
+        session: openxr_sys::Session,
+        time: XrTime,
+    ) -> Result<Option<openxr_sys::Posef>, Error> {
+        let state_info = openxr_sys::ActionStateGetInfo {
+            ty: openxr_sys::ActionStateGetInfo::TYPE,
+            next: std::ptr::null(),
+            action: self.action,
+            subaction_path: openxr_sys::Path::NULL,
+        };
+        let mut state = openxr_sys::ActionStatePose::out(std::ptr::null_mut());
+
+        let result =
+            unsafe { (fp.get_action_state_pose)(session, &state_info, state.as_mut_ptr()) };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        let is_active: bool = unsafe { state.assume_init() }.is_active.into();
+        if !is_active {
+            return Ok(None);
+        }
+
+        let mut location = openxr_sys::SpaceLocation::out(std::ptr::null_mut());
+        let result = unsafe {
+            (fp.locate_space)(
+                self.space,
+                self.base_space,
+                time.into(),
+                location.as_mut_ptr(),
+            )
+        };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        let location = unsafe { location.assume_init() };
+        let valid = SpaceLocationFlags::ORIENTATION_VALID | SpaceLocationFlags::POSITION_VALID;
+        if !location.location_flags.contains(valid) {
+            return Ok(None);
+        }
+
+        Ok(Some(location.pose))
+    }
+
+    /// Destroys the action space and action set, the action going away with its set.
+    pub(crate) fn destroy(self, fp: &XrInstanceFp) -> Result<(), Error> {
+        info!("xrDestroySpace()");
+        let result = unsafe { (fp.destroy_space)(self.space) };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        info!("xrDestroyActionSet()");
+        let result = unsafe { (fp.destroy_action_set)(self.action_set) };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        Ok(())
+    }
+}
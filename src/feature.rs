@@ -0,0 +1,82 @@
+use std::{ffi::CString, fmt};
+
+use crate::{color_space, context::QUAD_VIEWS_EXTENSION, controller_model, Config};
+
+/// A feature asked for in the `Config` that the runtime can't provide this run, its extension
+/// being unavailable. Initialization carries on without it, see `Context::unavailable_features`,
+/// unless `Config::require_features` is set.
+///
+/// The features that can't do without their extension, such as `Config::eye_gaze` or
+/// `Config::passthrough`, fail the initialization with `Error::MissingExtension` instead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnavailableFeature {
+    /// `Config::quad_views`, stereo being used instead
+    QuadViews,
+    /// `Config::color_space`, the runtime's color space being kept
+    ColorSpace,
+    /// `Config::controller_models`, there being no runtime models to load
+    ControllerModels,
+}
+
+impl UnavailableFeature {
+    /// The OpenXR extension the feature needs.
+    pub fn extension(self) -> &'static str {
+        match self {
+            UnavailableFeature::QuadViews => QUAD_VIEWS_EXTENSION,
+            UnavailableFeature::ColorSpace => color_space::EXTENSION,
+            UnavailableFeature::ControllerModels => controller_model::EXTENSION,
+        }
+    }
+
+    /// What happens instead, for the logs.
+    fn fallback(self) -> &'static str {
+        match self {
+            UnavailableFeature::QuadViews => "using stereo",
+            UnavailableFeature::ColorSpace => "keeping the runtime's color space",
+            UnavailableFeature::ControllerModels => "there won't be controller models",
+        }
+    }
+}
+
+impl fmt::Display for UnavailableFeature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} isn't available, {}",
+            self.extension(),
+            self.fallback()
+        )
+    }
+}
+
+/// Splits the optional features `config` asks for between the available ones, returning the
+/// extensions to enable for them, and the unavailable ones.
+pub(crate) fn check(
+    config: &Config,
+    available_extensions: &[CString],
+) -> (Vec<&'static str>, Vec<UnavailableFeature>) {
+    let requested = [
+        (UnavailableFeature::QuadViews, config.quad_views),
+        (UnavailableFeature::ColorSpace, config.color_space.is_some()),
+        (
+            UnavailableFeature::ControllerModels,
+            config.controller_models,
+        ),
+    ];
+
+    let mut extensions = Vec::new();
+    let mut unavailable = Vec::new();
+    for &(feature, _) in requested.iter().filter(|(_, requested)| *requested) {
+        let extension = feature.extension();
+        let available = available_extensions
+            .iter()
+            .any(|available| available.as_bytes() == extension.as_bytes());
+        if available {
+            extensions.push(extension);
+        } else {
+            unavailable.push(feature);
+        }
+    }
+
+    (extensions, unavailable)
+}
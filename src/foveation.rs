@@ -0,0 +1,140 @@
+//! Fixed foveation through XR_FB_foveation, XR_FB_foveation_configuration and
+//! XR_FB_swapchain_update_state, which openxr-sys 0.8 predates, hence the definitions below taken
+//! from the extensions' spec.
+
+use std::os::raw::c_void;
+
+use openxr_sys::{Result as XrResult, StructureType};
+
+pub(crate) const EXTENSION: &str = "XR_FB_foveation";
+/// Extensions `Config::foveation` enables, XR_FB_foveation_vulkan being the one allowing the
+/// fragment density map flag on Vulkan swapchains
+pub(crate) const EXTENSIONS: [&str; 4] = [
+    EXTENSION,
+    "XR_FB_foveation_configuration",
+    "XR_FB_swapchain_update_state",
+    "XR_FB_foveation_vulkan",
+];
+
+// Raw `StructureType` values, `from_raw` not being a const fn
+const TYPE_FOVEATION_PROFILE_CREATE_INFO_FB: i32 = 1000114000;
+const TYPE_SWAPCHAIN_CREATE_INFO_FOVEATION_FB: i32 = 1000114001;
+const TYPE_SWAPCHAIN_STATE_FOVEATION_FB: i32 = 1000114002;
+const TYPE_FOVEATION_LEVEL_PROFILE_CREATE_INFO_FB: i32 = 1000115000;
+
+/// XR_SWAPCHAIN_CREATE_FOVEATION_FRAGMENT_DENSITY_MAP_BIT_FB, the swapchain getting a fragment
+/// density map along with each image
+const CREATE_FRAGMENT_DENSITY_MAP: u64 = 0x2;
+/// XR_FOVEATION_DYNAMIC_DISABLED_FB, the level staying the one set
+const DYNAMIC_DISABLED: i32 = 0;
+
+pub(crate) type FoveationProfileFB = u64;
+
+/// How much the resolution drops towards the edges of the views, see
+/// `Session::set_foveation_level`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FoveationLevel {
+    None,
+    Low,
+    Medium,
+    High,
+}
+
+impl FoveationLevel {
+    /// Raw `XrFoveationLevelFB`
+    fn as_raw(self) -> i32 {
+        match self {
+            FoveationLevel::None => 0,
+            FoveationLevel::Low => 1,
+            FoveationLevel::Medium => 2,
+            FoveationLevel::High => 3,
+        }
+    }
+}
+
+#[repr(C)]
+pub(crate) struct SwapchainCreateInfoFoveationFB {
+    ty: StructureType,
+    next: *mut c_void,
+    flags: u64,
+}
+
+impl SwapchainCreateInfoFoveationFB {
+    /// To chain to `SwapchainCreateInfo`, for the foveation level to be set on the swapchain
+    pub(crate) fn new() -> Self {
+        SwapchainCreateInfoFoveationFB {
+            ty: StructureType::from_raw(TYPE_SWAPCHAIN_CREATE_INFO_FOVEATION_FB),
+            next: std::ptr::null_mut(),
+            flags: CREATE_FRAGMENT_DENSITY_MAP,
+        }
+    }
+}
+
+#[repr(C)]
+pub(crate) struct FoveationProfileCreateInfoFB {
+    ty: StructureType,
+    pub(crate) next: *mut c_void,
+}
+
+#[repr(C)]
+pub(crate) struct FoveationLevelProfileCreateInfoFB {
+    ty: StructureType,
+    next: *mut c_void,
+    pub(crate) level: i32,
+    vertical_offset: f32,
+    dynamic: i32,
+}
+
+#[repr(C)]
+pub(crate) struct SwapchainStateFoveationFB {
+    ty: StructureType,
+    next: *mut c_void,
+    flags: u64,
+    pub(crate) profile: FoveationProfileFB,
+}
+
+impl FoveationLevelProfileCreateInfoFB {
+    /// Centered profile of `level`
+    pub(crate) fn new(level: FoveationLevel) -> Self {
+        FoveationLevelProfileCreateInfoFB {
+            ty: StructureType::from_raw(TYPE_FOVEATION_LEVEL_PROFILE_CREATE_INFO_FB),
+            next: std::ptr::null_mut(),
+            level: level.as_raw(),
+            vertical_offset: 0.0,
+            dynamic: DYNAMIC_DISABLED,
+        }
+    }
+}
+
+impl FoveationProfileCreateInfoFB {
+    /// Profile described by `level_info`, which must outlive it
+    pub(crate) fn new(level_info: &mut FoveationLevelProfileCreateInfoFB) -> Self {
+        FoveationProfileCreateInfoFB {
+            ty: StructureType::from_raw(TYPE_FOVEATION_PROFILE_CREATE_INFO_FB),
+            next: level_info as *mut _ as *mut c_void,
+        }
+    }
+}
+
+impl SwapchainStateFoveationFB {
+    pub(crate) fn new(profile: FoveationProfileFB) -> Self {
+        SwapchainStateFoveationFB {
+            ty: StructureType::from_raw(TYPE_SWAPCHAIN_STATE_FOVEATION_FB),
+            next: std::ptr::null_mut(),
+            flags: 0,
+            profile,
+        }
+    }
+}
+
+pub(crate) type CreateFoveationProfileFB = unsafe extern "system" fn(
+    openxr_sys::Session,
+    *const FoveationProfileCreateInfoFB,
+    *mut FoveationProfileFB,
+) -> XrResult;
+pub(crate) type DestroyFoveationProfileFB =
+    unsafe extern "system" fn(FoveationProfileFB) -> XrResult;
+/// Takes an `XrSwapchainStateBaseHeaderFB`, `SwapchainStateFoveationFB` being the only state
+/// passed here
+pub(crate) type UpdateSwapchainFB =
+    unsafe extern "system" fn(openxr_sys::Swapchain, *const SwapchainStateFoveationFB) -> XrResult;
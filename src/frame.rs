@@ -0,0 +1,308 @@
+use std::{ffi::CString, ops::RangeInclusive, time::Duration};
+
+use openxr_sys::CompositionLayerFlags;
+
+use crate::{
+    error::Error,
+    layers::{self, CompositionLayer, CylinderLayer, EquirectLayer, QuadLayer},
+    math,
+    session::{Session, SessionEvent},
+    swapchain::{ShaderOutput, Swapchain, SwapchainLayout},
+    time::XrTime,
+};
+
+/// Layer flags that are only valid with an extension enabled.
+/// XR_COMPOSITION_LAYER_INVERTED_ALPHA_BIT_EXT isn't defined by openxr-sys 0.8, hence the raw value.
+const EXTENSION_LAYER_FLAGS: [(u64, &str); 1] = [(0x8, "XR_EXT_composition_layer_inverted_alpha")];
+
+/// Bounds `FrameContext::set_render_scale` clamps to. Below a quarter, the upscaled image is
+/// too blurry to be worth the time saved.
+pub const RENDER_SCALE_RANGE: RangeInclusive<f32> = 0.25..=1.0;
+
+/// What the render callback gets to know about, and add to, the frame being built.
+pub struct FrameContext<'a> {
+    /// Time at which the frame is predicted to be displayed
+    pub display_time: XrTime,
+    /// Predicted time between two displayed frames, `display_time + display_period` being a
+    /// good estimate of when the next frame will be displayed
+    pub display_period: Duration,
+    /// Located views, in the order of the view configuration
+    pub views: Vec<openxr_sys::View>,
+    /// Which parts of the view poses are valid, and which come from actual tracking
+    pub view_state_flags: openxr_sys::ViewStateFlags,
+    /// Where each view goes in the swapchains, see `Context::swapchain_layout`
+    pub swapchain_layout: SwapchainLayout,
+    /// What the shaders should output, see `Context::shader_output`
+    pub shader_output: ShaderOutput,
+    /// Indices into `views` of the views to render in each pass, passes in the order to record
+    /// them, following `Config::render_order`
+    pub render_passes: Vec<Vec<u32>>,
+    /// Where to render each view, see `sub_image`
+    pub(crate) sub_images: Vec<openxr_sys::SwapchainSubImage>,
+    render_scale: f32,
+    /// Set by `set_render_scale`, for the next frame
+    pub(crate) next_render_scale: f32,
+    /// Command buffer of this frame, in the recording state, for the commands rendering to the
+    /// swapchain images. `App::run` submits it to the graphics queue once the callback returns,
+    /// before releasing the images.
+    pub command_buffer: ash::vk::CommandBuffer,
+    /// Which of the `Config::max_frames_in_flight` slots this frame uses, to index the app's own
+    /// per-frame resources, which the GPU is done with by the time the slot comes back
+    pub frame_slot: usize,
+    /// The session, to read the state of the actions synced for this frame
+    pub session: &'a Session,
+    /// Events received since the previous `render` call, oldest first
+    pub events: Vec<SessionEvent>,
+    /// GPU time to record in `Session::frame_stats` for this frame, left to the render callback
+    /// to measure. With a `GpuTimer`, that's usually the previous frame's, read without waiting.
+    pub gpu_time: Option<Duration>,
+    /// GPU time of each view, recorded along with `gpu_time`. With `Config::debug_grid`, it
+    /// comes filled with the time the grid took to draw each view.
+    pub gpu_view_times: Vec<Duration>,
+    /// Near and far planes from the config
+    clip_planes: (f32, f32),
+    /// `Config::reverse_z`
+    reverse_z: bool,
+    /// Where the depth of each view is, set by `set_depth`
+    pub(crate) depth_sub_images: Option<Vec<openxr_sys::SwapchainSubImage>>,
+    /// See `Context::view_space`
+    view_space: openxr_sys::Space,
+    enabled_extensions: &'a [CString],
+    /// Layers to submit over the projection layer, bottom one first
+    pub(crate) layers: Vec<Box<dyn CompositionLayer>>,
+    pub(crate) projection_layer_flags: CompositionLayerFlags,
+}
+
+impl<'a> FrameContext<'a> {
+    pub(crate) fn new(
+        display_time: XrTime,
+        display_period: Duration,
+        views: Vec<openxr_sys::View>,
+        view_state_flags: openxr_sys::ViewStateFlags,
+        swapchain_layout: SwapchainLayout,
+        shader_output: ShaderOutput,
+        render_passes: Vec<Vec<u32>>,
+        sub_images: Vec<openxr_sys::SwapchainSubImage>,
+        render_scale: f32,
+        command_buffer: ash::vk::CommandBuffer,
+        frame_slot: usize,
+        session: &'a Session,
+        events: Vec<SessionEvent>,
+        gpu_view_times: Vec<Duration>,
+        clip_planes: (f32, f32),
+        reverse_z: bool,
+        view_space: openxr_sys::Space,
+        enabled_extensions: &'a [CString],
+        projection_layer_flags: CompositionLayerFlags,
+    ) -> Self {
+        FrameContext {
+            display_time,
+            display_period,
+            views,
+            view_state_flags,
+            swapchain_layout,
+            shader_output,
+            render_passes,
+            sub_images,
+            render_scale,
+            next_render_scale: render_scale,
+            command_buffer,
+            frame_slot,
+            session,
+            events,
+            gpu_time: None,
+            gpu_view_times,
+            clip_planes,
+            reverse_z,
+            depth_sub_images: None,
+            view_space,
+            enabled_extensions,
+            layers: Vec::new(),
+            projection_layer_flags,
+        }
+    }
+
+    /// Region of the swapchain image the view at `eye` must be rendered to this frame, the one
+    /// submitted in the projection layer, to give to `set_viewport_and_scissor` and use as the
+    /// render area. It's the view's whole part of the swapchain at a render scale of 1, and
+    /// shrinks from the top left corner with it, the compositor upscaling what's submitted.
+    pub fn sub_image(&self, eye: usize) -> openxr_sys::SwapchainSubImage {
+        self.sub_images[eye]
+    }
+
+    /// Scale of the sub-images this frame, relative to the swapchain size.
+    pub fn render_scale(&self) -> f32 {
+        self.render_scale
+    }
+
+    /// Sets the render scale from the next frame on, clamped to `RENDER_SCALE_RANGE`, to render
+    /// fewer pixels under load, for instance based on `Session::frame_stats`. This frame keeps
+    /// its sub-images, which may already be in use by the commands recorded so far.
+    ///
+    /// Unlike `Config::resolution_scale`, this doesn't recreate the swapchains: only the
+    /// sub-image region of each view gets rendered and submitted, the rest of the images being
+    /// left as is, so the swapchains must be sized for the largest scale wanted.
+    pub fn set_render_scale(&mut self, scale: f32) {
+        self.next_render_scale = scale
+            .max(*RENDER_SCALE_RANGE.start())
+            .min(*RENDER_SCALE_RANGE.end());
+    }
+
+    /// Pose of the view at `eye` in the reference space of the projection layer.
+    pub fn pose(&self, eye: usize) -> openxr_sys::Posef {
+        self.views[eye].pose
+    }
+
+    /// Field of view of the view at `eye`.
+    pub fn fov(&self, eye: usize) -> openxr_sys::Fovf {
+        self.views[eye].fov
+    }
+
+    /// Transform from the reference space to the space of the view at `eye`.
+    ///
+    /// Like the other matrices here, it's returned as an array of columns and follows the OpenXR
+    /// conventions: right handed, X to the right, Y up and the view looking down -Z.
+    pub fn view(&self, eye: usize) -> [[f32; 4]; 4] {
+        math::to_columns(&math::inverse_pose(&self.views[eye].pose))
+    }
+
+    /// Projection of the view at `eye` to Vulkan clip space, with Y pointing down and depth
+    /// going from 0 at `Config::near_plane` to 1 at `Config::far_plane`, the other way around
+    /// with `Config::reverse_z`.
+    pub fn projection(&self, eye: usize) -> [[f32; 4]; 4] {
+        let (near, far) = self.clip_planes;
+        let fov = &self.views[eye].fov;
+        math::to_columns(&math::projection(fov, near, far, self.reverse_z))
+    }
+
+    /// `projection(eye) * view(eye)`, taking a point in the reference space of the projection
+    /// layer to clip space.
+    pub fn view_projection(&self, eye: usize) -> [[f32; 4]; 4] {
+        let (near, far) = self.clip_planes;
+        let view = &self.views[eye];
+        math::to_columns(&math::view_projection(
+            &view.pose,
+            &view.fov,
+            near,
+            far,
+            self.reverse_z,
+        ))
+    }
+
+    /// Submits the depth of each view along with its color, at `sub_images[eye]` of depth
+    /// swapchains, through XR_KHR_composition_layer_depth, which lets the compositor reproject
+    /// more accurately. The depth must have been rendered with `projection`: the near and far
+    /// planes, infinite or not, and `Config::reverse_z` are described to the runtime to match.
+    /// Fails if the extension isn't enabled on the instance, or without exactly one sub-image per
+    /// view.
+    pub fn set_depth(
+        &mut self,
+        sub_images: Vec<openxr_sys::SwapchainSubImage>,
+    ) -> Result<(), Error> {
+        if !crate::is_extension_enabled(self.enabled_extensions, layers::DEPTH_EXTENSION) {
+            return Err(Error::MissingExtension(layers::DEPTH_EXTENSION));
+        }
+
+        if sub_images.len() != self.views.len() {
+            return Err(Error::DepthSubImageCount(
+                sub_images.len(),
+                self.views.len(),
+            ));
+        }
+        self.depth_sub_images = Some(sub_images);
+        Ok(())
+    }
+
+    /// Flags the projection layer will be submitted with. They're empty by default, or
+    /// `BLEND_TEXTURE_SOURCE_ALPHA` with `Config::passthrough` so the surroundings show through
+    /// transparent pixels.
+    pub fn projection_layer_flags(&self) -> CompositionLayerFlags {
+        self.projection_layer_flags
+    }
+
+    /// Replaces the flags of the projection layer for this frame, for instance to add
+    /// `CORRECT_CHROMATIC_ABERRATION`.
+    /// Fails if one of them belongs to an extension that isn't enabled on the instance.
+    pub fn set_projection_layer_flags(
+        &mut self,
+        flags: CompositionLayerFlags,
+    ) -> Result<(), Error> {
+        for &(flag, extension) in &EXTENSION_LAYER_FLAGS {
+            let is_set = flags.into_raw() & flag != 0;
+            if is_set && !crate::is_extension_enabled(self.enabled_extensions, extension) {
+                return Err(Error::MissingExtension(extension));
+            }
+        }
+
+        self.projection_layer_flags = flags;
+        Ok(())
+    }
+
+    /// The VIEW reference space, which follows the head, see `Context::view_space`.
+    pub fn view_space(&self) -> openxr_sys::Space {
+        self.view_space
+    }
+
+    /// Quad in the VIEW space covering the field of view of every view, for content locked to
+    /// the head such as fades to black, letterboxing, comfort vignettes or loading screens, to
+    /// add with `add_quad_layer`.
+    ///
+    /// The projection layer is world-locked: the compositor reprojects it to where the head is
+    /// by the time it's displayed, so the scene stays put. A layer in the VIEW space is instead
+    /// kept fixed in front of the eyes whatever the head does. It's placed `distance` meters
+    /// ahead, which matters for the stereo disparity, and sized with some margin since the
+    /// eyes are a few centimeters off the space's origin.
+    pub fn head_locked_quad(&self, swapchain: &Swapchain, distance: f32) -> QuadLayer {
+        // Largest tangents of the half-angles on each side, over the views
+        let (mut horizontal, mut vertical) = (0.0f32, 0.0f32);
+        for view in &self.views {
+            let fov = view.fov;
+            horizontal = horizontal
+                .max(fov.angle_left.abs().tan())
+                .max(fov.angle_right.abs().tan());
+            vertical = vertical
+                .max(fov.angle_up.abs().tan())
+                .max(fov.angle_down.abs().tan());
+        }
+        let margin = 1.1;
+        let mut pose = crate::identity_pose();
+        pose.position.z = -distance;
+
+        QuadLayer::new(self.view_space, swapchain)
+            .size(
+                2.0 * distance * horizontal * margin,
+                2.0 * distance * vertical * margin,
+            )
+            .pose(pose)
+    }
+
+    /// Adds a quad layer to submit on top of the projection layer and of the layers added before
+    /// it. Its swapchain must stay alive until the frame is submitted.
+    pub fn add_quad_layer(&mut self, layer: QuadLayer) {
+        self.layers.push(Box::new(layer));
+    }
+
+    /// Adds a cylinder layer to submit on top of the projection layer and of the layers added
+    /// before it.
+    /// Fails if XR_KHR_composition_layer_cylinder isn't enabled on the instance.
+    pub fn add_cylinder_layer(&mut self, layer: CylinderLayer) -> Result<(), Error> {
+        if !crate::is_extension_enabled(self.enabled_extensions, CylinderLayer::EXTENSION) {
+            return Err(Error::MissingExtension(CylinderLayer::EXTENSION));
+        }
+
+        self.layers.push(Box::new(layer));
+        Ok(())
+    }
+
+    /// Adds an equirect layer to submit on top of the projection layer and of the layers added
+    /// before it. Its swapchain must stay alive until the frame is submitted.
+    /// Fails if XR_KHR_composition_layer_equirect2 isn't enabled on the instance.
+    pub fn add_equirect_layer(&mut self, layer: EquirectLayer) -> Result<(), Error> {
+        if !crate::is_extension_enabled(self.enabled_extensions, EquirectLayer::EXTENSION) {
+            return Err(Error::MissingExtension(EquirectLayer::EXTENSION));
+        }
+
+        self.layers.push(Box::new(layer));
+        Ok(())
+    }
+}
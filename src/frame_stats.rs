@@ -0,0 +1,303 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use ash::{
+    version::{DeviceV1_0, InstanceV1_0},
+    vk,
+};
+use log::info;
+
+use crate::error::Error;
+
+/// Number of frames the statistics are computed over, a few seconds at the usual refresh rates.
+const WINDOW: usize = 300;
+
+/// How long a frame took, measured by `App::run`.
+#[derive(Clone, Debug, Default)]
+pub struct FrameTiming {
+    /// From xrWaitFrame returning to xrEndFrame returning, the time the frame kept the CPU busy
+    pub cpu_time: Duration,
+    /// Time spent blocked in xrWaitFrame, the runtime throttling the frame loop
+    pub wait_time: Duration,
+    /// Set by the render callback through `FrameContext::gpu_time`, see `GpuTimer`
+    pub gpu_time: Option<Duration>,
+    /// GPU time of each view, from `FrameContext::gpu_view_times`, empty when not measured
+    pub gpu_view_times: Vec<Duration>,
+}
+
+/// Distribution of one of the timings over the last frames.
+#[derive(Clone, Copy, Debug)]
+pub struct TimingSummary {
+    pub average: Duration,
+    pub median: Duration,
+    pub percentile_95: Duration,
+    pub percentile_99: Duration,
+    pub max: Duration,
+}
+
+impl TimingSummary {
+    fn new(mut durations: Vec<Duration>) -> Option<Self> {
+        if durations.is_empty() {
+            return None;
+        }
+        durations.sort_unstable();
+
+        let percentile = |p: usize| durations[(durations.len() - 1) * p / 100];
+        Some(TimingSummary {
+            average: durations.iter().sum::<Duration>() / durations.len() as u32,
+            median: percentile(50),
+            percentile_95: percentile(95),
+            percentile_99: percentile(99),
+            max: durations[durations.len() - 1],
+        })
+    }
+}
+
+/// Timings of the last frames, to tell apart a slow app (high CPU or GPU times) from a runtime
+/// holding the frames back (high wait times), and to spot the spikes causing judder.
+#[derive(Clone, Debug)]
+pub struct FrameStats {
+    timings: VecDeque<FrameTiming>,
+    frame_count: u64,
+    /// Set from `Config::frame_stats_log_interval`
+    log_interval: Option<Duration>,
+    last_log: Instant,
+}
+
+impl FrameStats {
+    pub(crate) fn new(log_interval: Option<Duration>) -> Self {
+        FrameStats {
+            timings: VecDeque::with_capacity(WINDOW),
+            frame_count: 0,
+            log_interval,
+            last_log: Instant::now(),
+        }
+    }
+
+    pub(crate) fn record(&mut self, timing: FrameTiming) {
+        if self.timings.len() == WINDOW {
+            self.timings.pop_front();
+        }
+        self.timings.push_back(timing);
+        self.frame_count += 1;
+
+        if let Some(log_interval) = self.log_interval {
+            if self.last_log.elapsed() >= log_interval {
+                self.log_summary();
+                self.last_log = Instant::now();
+            }
+        }
+    }
+
+    /// Number of frames recorded since the session started, the statistics only covering the
+    /// last ones.
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// Timings of the last frame.
+    pub fn last(&self) -> Option<&FrameTiming> {
+        self.timings.back()
+    }
+
+    pub fn cpu_time(&self) -> Option<TimingSummary> {
+        TimingSummary::new(self.timings.iter().map(|timing| timing.cpu_time).collect())
+    }
+
+    pub fn wait_time(&self) -> Option<TimingSummary> {
+        TimingSummary::new(self.timings.iter().map(|timing| timing.wait_time).collect())
+    }
+
+    /// Only covers the frames the render callback measured.
+    pub fn gpu_time(&self) -> Option<TimingSummary> {
+        TimingSummary::new(
+            self.timings
+                .iter()
+                .filter_map(|timing| timing.gpu_time)
+                .collect(),
+        )
+    }
+
+    /// Only covers the frames for which the view at `view_index` was measured.
+    pub fn gpu_view_time(&self, view_index: usize) -> Option<TimingSummary> {
+        TimingSummary::new(
+            self.timings
+                .iter()
+                .filter_map(|timing| timing.gpu_view_times.get(view_index).copied())
+                .collect(),
+        )
+    }
+
+    pub(crate) fn log_summary(&self) {
+        info!("Frame timings over the last {} frames:", self.timings.len());
+        let view_count = self
+            .timings
+            .iter()
+            .map(|timing| timing.gpu_view_times.len())
+            .max()
+            .unwrap_or(0);
+        let timings = vec![
+            ("cpu".to_string(), self.cpu_time()),
+            ("xrWaitFrame".to_string(), self.wait_time()),
+            ("gpu".to_string(), self.gpu_time()),
+        ]
+        .into_iter()
+        .chain(
+            (0..view_count).map(|index| (format!("gpu view {}", index), self.gpu_view_time(index))),
+        );
+        for (name, summary) in timings {
+            if let Some(summary) = summary {
+                info!(
+                    "  {:<12} avg {:>7.3} ms, median {:>7.3} ms, p95 {:>7.3} ms, p99 {:>7.3} ms, max {:>7.3} ms",
+                    name,
+                    to_ms(summary.average),
+                    to_ms(summary.median),
+                    to_ms(summary.percentile_95),
+                    to_ms(summary.percentile_99),
+                    to_ms(summary.max),
+                );
+            }
+        }
+    }
+}
+
+fn to_ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Measures GPU times with timestamp queries, as a number of intervals each delimited by a
+/// `begin` and an `end` call, for instance one per view. The results are available once the
+/// command buffer has executed.
+///
+/// The render callback typically writes the timestamps around its rendering, and reads the
+/// previous frame's results before that, to set `FrameContext::gpu_time` and
+/// `FrameContext::gpu_view_times` without waiting.
+pub struct GpuTimer {
+    query_pool: vk::QueryPool,
+    interval_count: u32,
+    /// Nanoseconds per timestamp tick
+    timestamp_period: f32,
+    /// Bits of the timestamps that are meaningful, the others being undefined
+    timestamp_mask: u64,
+}
+
+impl GpuTimer {
+    /// Creates a timer for `interval_count` intervals, measured on queues of
+    /// `queue_family_index`. Fails with `Error::TimestampsUnsupported` if that family can't
+    /// write timestamps.
+    pub fn new(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        device: &ash::Device,
+        queue_family_index: u32,
+        interval_count: u32,
+    ) -> Result<Self, Error> {
+        let valid_bits =
+            unsafe { instance.get_physical_device_queue_family_properties(physical_device) }
+                [queue_family_index as usize]
+                .timestamp_valid_bits;
+        if valid_bits == 0 {
+            return Err(Error::TimestampsUnsupported(queue_family_index));
+        }
+
+        let limits = unsafe { instance.get_physical_device_properties(physical_device) }.limits;
+
+        let create_info = vk::QueryPoolCreateInfo {
+            query_type: vk::QueryType::TIMESTAMP,
+            query_count: interval_count * 2,
+            ..Default::default()
+        };
+        let query_pool = unsafe { device.create_query_pool(&create_info, None)? };
+
+        Ok(GpuTimer {
+            query_pool,
+            interval_count,
+            timestamp_period: limits.timestamp_period,
+            timestamp_mask: u64::MAX >> (64 - valid_bits.min(64)),
+        })
+    }
+
+    /// Resets the queries before a new measurement.
+    ///
+    /// # Safety
+    ///
+    /// `command_buffer` must be recording, outside of a render pass, and the previous
+    /// measurement must be done executing.
+    pub unsafe fn reset(&self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        device.cmd_reset_query_pool(command_buffer, self.query_pool, 0, self.interval_count * 2);
+    }
+
+    /// Records the start timestamp of `interval`, once all the previous commands have started.
+    ///
+    /// # Safety
+    ///
+    /// `command_buffer` must be recording, after a `reset` call.
+    pub unsafe fn begin(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        interval: u32,
+    ) {
+        device.cmd_write_timestamp(
+            command_buffer,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            self.query_pool,
+            interval * 2,
+        );
+    }
+
+    /// Records the end timestamp of `interval`, once all the previous commands have completed.
+    ///
+    /// # Safety
+    ///
+    /// `command_buffer` must be recording, after the `begin` call of `interval`.
+    pub unsafe fn end(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        interval: u32,
+    ) {
+        device.cmd_write_timestamp(
+            command_buffer,
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            self.query_pool,
+            interval * 2 + 1,
+        );
+    }
+
+    /// Duration of each interval, or `None` while the command buffer hasn't finished executing.
+    pub fn elapsed(&self, device: &ash::Device) -> Result<Option<Vec<Duration>>, Error> {
+        let mut timestamps = vec![0u64; self.interval_count as usize * 2];
+        let result = unsafe {
+            device.get_query_pool_results(
+                self.query_pool,
+                0,
+                self.interval_count * 2,
+                &mut timestamps,
+                vk::QueryResultFlags::TYPE_64,
+            )
+        };
+
+        match result {
+            Ok(()) => Ok(Some(
+                timestamps
+                    .chunks_exact(2)
+                    .map(|interval| {
+                        let ticks = interval[1].wrapping_sub(interval[0]) & self.timestamp_mask;
+                        let nanos = ticks as f64 * self.timestamp_period as f64;
+                        Duration::from_nanos(nanos as u64)
+                    })
+                    .collect(),
+            )),
+            Err(vk::Result::NOT_READY) => Ok(None),
+            Err(result) => Err(Error::Vulkan(result)),
+        }
+    }
+
+    /// The device must be done with the command buffers using the timer.
+    pub fn destroy(self, device: &ash::Device) {
+        unsafe { device.destroy_query_pool(self.query_pool, None) };
+    }
+}
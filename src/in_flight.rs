@@ -0,0 +1,135 @@
+use ash::{version::DeviceV1_0, vk};
+
+use crate::error::Error;
+
+/// What one frame records and submits its commands with.
+struct FrameSlot {
+    command_pool: vk::CommandPool,
+    command_buffer: vk::CommandBuffer,
+    /// Signaled once the frame's submission has executed
+    fence: vk::Fence,
+}
+
+/// Ring of `Config::max_frames_in_flight` slots, each frame taking the next one after waiting for
+/// the GPU to be done with its previous use.
+pub(crate) struct InFlightFrames {
+    slots: Vec<FrameSlot>,
+    current: usize,
+    /// The command buffers are protected and submitted as such, see `Config::protected_content`
+    protected: bool,
+}
+
+impl InFlightFrames {
+    pub(crate) fn new(
+        device: &ash::Device,
+        queue_family_index: u32,
+        count: usize,
+        protected: bool,
+    ) -> Result<Self, Error> {
+        let mut pool_flags = vk::CommandPoolCreateFlags::TRANSIENT;
+        if protected {
+            pool_flags |= vk::CommandPoolCreateFlags::PROTECTED;
+        }
+
+        let slots = (0..count)
+            .map(|_| {
+                let command_pool_info = vk::CommandPoolCreateInfo {
+                    flags: pool_flags,
+                    queue_family_index,
+                    ..Default::default()
+                };
+                let command_pool = unsafe { device.create_command_pool(&command_pool_info, None)? };
+
+                let command_buffer_info = vk::CommandBufferAllocateInfo {
+                    command_pool,
+                    level: vk::CommandBufferLevel::PRIMARY,
+                    command_buffer_count: 1,
+                    ..Default::default()
+                };
+                let command_buffer =
+                    unsafe { device.allocate_command_buffers(&command_buffer_info)? }[0];
+
+                // Signaled, as if a previous frame was done with the slot
+                let fence_info = vk::FenceCreateInfo {
+                    flags: vk::FenceCreateFlags::SIGNALED,
+                    ..Default::default()
+                };
+                let fence = unsafe { device.create_fence(&fence_info, None)? };
+
+                Ok(FrameSlot {
+                    command_pool,
+                    command_buffer,
+                    fence,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Ok(InFlightFrames {
+            slots,
+            current: 0,
+            protected,
+        })
+    }
+
+    /// Waits until the GPU is done with the next slot, then starts recording its command buffer.
+    /// Returns the index of the slot along with the command buffer.
+    pub(crate) fn begin(
+        &mut self,
+        device: &ash::Device,
+    ) -> Result<(usize, vk::CommandBuffer), Error> {
+        self.current = (self.current + 1) % self.slots.len();
+        let slot = &self.slots[self.current];
+
+        let begin_info = vk::CommandBufferBeginInfo {
+            flags: vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+            ..Default::default()
+        };
+
+        unsafe {
+            // The fence only gets reset when submitting, so a frame dropped after this doesn't
+            // leave the slot waiting forever
+            device.wait_for_fences(&[slot.fence], true, u64::MAX)?;
+            device.reset_command_pool(slot.command_pool, vk::CommandPoolResetFlags::empty())?;
+            device.begin_command_buffer(slot.command_buffer, &begin_info)?;
+        }
+
+        Ok((self.current, slot.command_buffer))
+    }
+
+    /// Submits the command buffer recorded since `begin` to `queue`.
+    pub(crate) fn submit(&self, device: &ash::Device, queue: vk::Queue) -> Result<(), Error> {
+        let slot = &self.slots[self.current];
+        let protected_submit_info = vk::ProtectedSubmitInfo {
+            protected_submit: vk::TRUE,
+            ..Default::default()
+        };
+        let submit_info = vk::SubmitInfo {
+            p_next: if self.protected {
+                &protected_submit_info as *const _ as *const std::ffi::c_void
+            } else {
+                std::ptr::null()
+            },
+            command_buffer_count: 1,
+            p_command_buffers: &slot.command_buffer,
+            ..Default::default()
+        };
+
+        unsafe {
+            device.end_command_buffer(slot.command_buffer)?;
+            device.reset_fences(&[slot.fence])?;
+            device.queue_submit(queue, &[submit_info], slot.fence)?;
+        }
+
+        Ok(())
+    }
+
+    /// The device must be idle, or at least done with every slot.
+    pub(crate) fn destroy(self, device: &ash::Device) {
+        unsafe {
+            for slot in self.slots {
+                device.destroy_fence(slot.fence, None);
+                device.destroy_command_pool(slot.command_pool, None);
+            }
+        }
+    }
+}
@@ -0,0 +1,194 @@
+//! Ready-made binding sets for the common interaction profiles, so that apps get working inputs
+//! without spelling out every path.
+
+use std::{cell::RefCell, collections::HashMap, sync::Arc};
+
+use openxr_sys::ActionType;
+
+use crate::{
+    action::{Action, ActionSet, Hand, HandPose, HandPoseAction},
+    error::Error,
+    session::Session,
+    XrInstanceFp,
+};
+
+/// Converts path strings to atoms, asking the runtime only once for each of them. Atoms are
+/// valid for the whole instance, so the cache can be shared between sessions.
+pub struct PathCache {
+    fp: Arc<XrInstanceFp>,
+    instance: openxr_sys::Instance,
+    paths: RefCell<HashMap<String, openxr_sys::Path>>,
+}
+
+impl PathCache {
+    pub fn new(session: &Session) -> Self {
+        PathCache {
+            fp: Arc::clone(&session.fp),
+            instance: session.instance,
+            paths: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Atom of `path`, such as `/user/hand/left/input/select/click`.
+    pub fn get(&self, path: &str) -> Result<openxr_sys::Path, Error> {
+        if let Some(&atom) = self.paths.borrow().get(path) {
+            return Ok(atom);
+        }
+
+        let atom = crate::string_to_path(&self.fp, self.instance, path)?;
+        self.paths.borrow_mut().insert(path.to_string(), atom);
+        Ok(atom)
+    }
+}
+
+/// Input or output of a profile, found under both hands.
+enum ProfileInput {
+    Action {
+        name: &'static str,
+        localized_name: &'static str,
+        action_type: ActionType,
+        /// Bindings of the left and right hands
+        bindings: [openxr_sys::Path; 2],
+    },
+    Pose {
+        pose: HandPose,
+        bindings: [openxr_sys::Path; 2],
+    },
+}
+
+/// Inputs of an interaction profile, each turned into an action by `create_actions`:
+///
+/// ```ignore
+/// let profile = InteractionProfile::simple_controller(&PathCache::new(session))?;
+/// let controller = profile.create_actions(session, &action_set)?;
+/// ```
+pub struct InteractionProfile {
+    path: openxr_sys::Path,
+    inputs: Vec<ProfileInput>,
+}
+
+impl InteractionProfile {
+    /// `/interaction_profiles/khr/simple_controller`, which every runtime can map its
+    /// controllers to: `select` and `menu` boolean actions, the grip and aim poses, and a
+    /// `haptic` vibration output.
+    pub fn simple_controller(paths: &PathCache) -> Result<Self, Error> {
+        let hand_bindings = |subpath: &str| -> Result<[openxr_sys::Path; 2], Error> {
+            Ok([
+                paths.get(&format!("{}{}", Hand::Left.path(), subpath))?,
+                paths.get(&format!("{}{}", Hand::Right.path(), subpath))?,
+            ])
+        };
+        let pose = |pose: HandPose| -> Result<ProfileInput, Error> {
+            Ok(ProfileInput::Pose {
+                pose,
+                bindings: [
+                    paths.get(pose.binding(Hand::Left))?,
+                    paths.get(pose.binding(Hand::Right))?,
+                ],
+            })
+        };
+
+        Ok(InteractionProfile {
+            path: paths.get("/interaction_profiles/khr/simple_controller")?,
+            inputs: vec![
+                ProfileInput::Action {
+                    name: "select",
+                    localized_name: "Select",
+                    action_type: ActionType::BOOLEAN_INPUT,
+                    bindings: hand_bindings("/input/select/click")?,
+                },
+                ProfileInput::Action {
+                    name: "menu",
+                    localized_name: "Menu",
+                    action_type: ActionType::BOOLEAN_INPUT,
+                    bindings: hand_bindings("/input/menu/click")?,
+                },
+                pose(HandPose::Grip)?,
+                pose(HandPose::Aim)?,
+                ProfileInput::Action {
+                    name: "haptic",
+                    localized_name: "Haptic",
+                    action_type: ActionType::VIBRATION_OUTPUT,
+                    bindings: hand_bindings("/output/haptic")?,
+                },
+            ],
+        })
+    }
+
+    /// Creates an action in `action_set` for each input of the profile, with both hands as
+    /// subaction paths, and suggests their bindings. This replaces the previous suggestions for
+    /// the profile, and must be done before the set is attached.
+    pub fn create_actions(
+        &self,
+        session: &Session,
+        action_set: &ActionSet,
+    ) -> Result<ProfileActions, Error> {
+        let subaction_paths = [
+            session.string_to_path(Hand::Left.path())?,
+            session.string_to_path(Hand::Right.path())?,
+        ];
+
+        let mut actions = Vec::new();
+        let mut poses = Vec::new();
+        let mut suggested_bindings = Vec::new();
+        for input in &self.inputs {
+            match *input {
+                ProfileInput::Action {
+                    name,
+                    localized_name,
+                    action_type,
+                    bindings,
+                } => {
+                    let action = action_set.create_action(
+                        name,
+                        localized_name,
+                        action_type,
+                        &subaction_paths,
+                    )?;
+                    suggested_bindings.extend(bindings.iter().map(|&binding| {
+                        openxr_sys::ActionSuggestedBinding {
+                            action: action.handle,
+                            binding,
+                        }
+                    }));
+                    actions.push((name, action));
+                }
+                ProfileInput::Pose { pose, bindings } => {
+                    let hand_pose = action_set.create_hand_pose_action(session, pose)?;
+                    suggested_bindings.extend(bindings.iter().map(|&binding| {
+                        openxr_sys::ActionSuggestedBinding {
+                            action: hand_pose.action().handle,
+                            binding,
+                        }
+                    }));
+                    poses.push(hand_pose);
+                }
+            }
+        }
+
+        session.suggest_binding_paths(self.path, &suggested_bindings)?;
+
+        Ok(ProfileActions { actions, poses })
+    }
+}
+
+/// Actions created by `InteractionProfile::create_actions`, destroyed along with their set.
+pub struct ProfileActions {
+    actions: Vec<(&'static str, Action)>,
+    poses: Vec<HandPoseAction>,
+}
+
+impl ProfileActions {
+    /// Action of the input named `name`, as listed by the profile's constructor.
+    pub fn get(&self, name: &str) -> Option<&Action> {
+        self.actions
+            .iter()
+            .find(|(action_name, _)| *action_name == name)
+            .map(|(_, action)| action)
+    }
+
+    /// Action and spaces of `pose`, if the profile has it.
+    pub fn pose(&self, pose: HandPose) -> Option<&HandPoseAction> {
+        self.poses.iter().find(|hand_pose| hand_pose.pose() == pose)
+    }
+}
@@ -0,0 +1,273 @@
+//! Conversions of the OpenXR poses and fields of view to math library types, enabled by the
+//! `glam` and `nalgebra` features.
+//!
+//! The matrices are the same as the `FrameContext` ones, computed the same way, and follow the
+//! same conventions: right handed, X to the right, Y up, views looking down -Z, and projections
+//! to Vulkan clip space. `far` may be infinite, and `reverse_z` is meant to be
+//! `Config::reverse_z`.
+
+/// Conversions to `glam` types.
+#[cfg(feature = "glam")]
+pub mod glam {
+    use ::glam::{Mat4, Quat, Vec3};
+    use openxr_sys::{Fovf, Posef};
+
+    use crate::math;
+
+    /// Transform from the space `pose` describes to the space it's expressed in.
+    pub fn pose(pose: &Posef) -> Mat4 {
+        let openxr_sys::Quaternionf { x, y, z, w } = pose.orientation;
+        let position = pose.position;
+        Mat4::from_rotation_translation(
+            Quat::from_xyzw(x, y, z, w),
+            Vec3::new(position.x, position.y, position.z),
+        )
+    }
+
+    /// View matrix of a view located at `pose`, see `FrameContext::view`.
+    pub fn view(pose: &Posef) -> Mat4 {
+        Mat4::from_cols_array(&math::inverse_pose(pose))
+    }
+
+    /// Projection matching `fov`, see `FrameContext::projection`.
+    pub fn projection(fov: &Fovf, near: f32, far: f32, reverse_z: bool) -> Mat4 {
+        Mat4::from_cols_array(&math::projection(fov, near, far, reverse_z))
+    }
+
+    /// `projection(fov, near, far, reverse_z) * view(pose)`, see
+    /// `FrameContext::view_projection`.
+    pub fn view_projection(pose: &Posef, fov: &Fovf, near: f32, far: f32, reverse_z: bool) -> Mat4 {
+        Mat4::from_cols_array(&math::view_projection(pose, fov, near, far, reverse_z))
+    }
+}
+
+/// Conversions to `nalgebra` types.
+#[cfg(feature = "nalgebra")]
+pub mod nalgebra {
+    use ::nalgebra::{Isometry3, Matrix4, Quaternion, Translation3, UnitQuaternion};
+    use openxr_sys::{Fovf, Posef};
+
+    use crate::math;
+
+    /// Transform from the space `pose` describes to the space it's expressed in, its inverse
+    /// being the view transform for a view pose.
+    pub fn pose(pose: &Posef) -> Isometry3<f32> {
+        let openxr_sys::Quaternionf { x, y, z, w } = pose.orientation;
+        let position = pose.position;
+        Isometry3::from_parts(
+            Translation3::new(position.x, position.y, position.z),
+            // OpenXR quaternions are unit ones already, this only guards against drift
+            UnitQuaternion::from_quaternion(Quaternion::new(w, x, y, z)),
+        )
+    }
+
+    /// View matrix of a view located at `pose`, see `FrameContext::view`.
+    pub fn view(pose: &Posef) -> Matrix4<f32> {
+        Matrix4::from_column_slice(&math::inverse_pose(pose))
+    }
+
+    /// Projection matching `fov`, see `FrameContext::projection`.
+    pub fn projection(fov: &Fovf, near: f32, far: f32, reverse_z: bool) -> Matrix4<f32> {
+        Matrix4::from_column_slice(&math::projection(fov, near, far, reverse_z))
+    }
+
+    /// `projection(fov, near, far, reverse_z) * view(pose)`, see
+    /// `FrameContext::view_projection`.
+    pub fn view_projection(
+        pose: &Posef,
+        fov: &Fovf,
+        near: f32,
+        far: f32,
+        reverse_z: bool,
+    ) -> Matrix4<f32> {
+        Matrix4::from_column_slice(&math::view_projection(pose, fov, near, far, reverse_z))
+    }
+}
+
+#[cfg(all(test, any(feature = "glam", feature = "nalgebra")))]
+mod tests {
+    use openxr_sys::{Fovf, Posef, Quaternionf, Vector3f};
+
+    const NEAR: f32 = 0.05;
+    /// Distance standing for an infinite far plane, where the depth is within 1e-6 of its limit
+    const FAR_AWAY: f32 = 1.0e5;
+
+    fn pose() -> Posef {
+        // Turned 45 degrees to the left, standing a bit off the origin
+        let half_angle = std::f32::consts::FRAC_PI_8;
+        Posef {
+            orientation: Quaternionf {
+                x: 0.0,
+                y: half_angle.sin(),
+                z: 0.0,
+                w: half_angle.cos(),
+            },
+            position: Vector3f {
+                x: 0.3,
+                y: 1.6,
+                z: -0.5,
+            },
+        }
+    }
+
+    /// Where the point a meter in front of `pose()` ends up, worked out by hand: looking down -Z
+    /// turned 45 degrees to the left means looking towards -X and -Z.
+    fn point_in_front() -> [f32; 3] {
+        let offset = std::f32::consts::FRAC_1_SQRT_2;
+        [0.3 - offset, 1.6, -0.5 - offset]
+    }
+
+    fn fov() -> Fovf {
+        // Asymmetric, as the eyes' are
+        Fovf {
+            angle_left: -0.9,
+            angle_right: 0.7,
+            angle_up: 0.8,
+            angle_down: -0.85,
+        }
+    }
+
+    /// Points of the view space on the corners of the frustum, along with where they land in
+    /// normalized device coordinates: top left on the near plane, bottom right on the far one,
+    /// with Vulkan's Y pointing down.
+    fn frustum_corners(far: f32, reverse_z: bool) -> [([f32; 3], [f32; 3]); 2] {
+        let fov = fov();
+        let far = if far.is_infinite() { FAR_AWAY } else { far };
+        let (near_depth, far_depth) = if reverse_z { (1.0, 0.0) } else { (0.0, 1.0) };
+        [
+            (
+                [
+                    NEAR * fov.angle_left.tan(),
+                    NEAR * fov.angle_up.tan(),
+                    -NEAR,
+                ],
+                [-1.0, -1.0, near_depth],
+            ),
+            (
+                [
+                    far * fov.angle_right.tan(),
+                    far * fov.angle_down.tan(),
+                    -far,
+                ],
+                [1.0, 1.0, far_depth],
+            ),
+        ]
+    }
+
+    fn assert_close(actual: &[f32], expected: &[f32]) {
+        assert_eq!(actual.len(), expected.len());
+        for (index, (actual, expected)) in actual.iter().zip(expected).enumerate() {
+            assert!(
+                (actual - expected).abs() < 1e-4,
+                "{:?} != {:?} at {}",
+                actual,
+                expected,
+                index
+            );
+        }
+    }
+
+    #[cfg(feature = "glam")]
+    mod glam {
+        use ::glam::{Mat4, Vec3};
+
+        use super::{assert_close, fov, frustum_corners, point_in_front, NEAR};
+        use crate::interop::glam;
+
+        fn project(matrix: Mat4, point: [f32; 3]) -> [f32; 3] {
+            let clip = matrix * Vec3::from(point).extend(1.0);
+            (clip.truncate() / clip.w).into()
+        }
+
+        #[test]
+        fn pose() {
+            let pose = glam::pose(&super::pose());
+            let origin: [f32; 3] = pose.transform_point3(Vec3::new(0.0, 0.0, 0.0)).into();
+            assert_close(&origin, &[0.3, 1.6, -0.5]);
+            let in_front: [f32; 3] = pose.transform_point3(Vec3::new(0.0, 0.0, -1.0)).into();
+            assert_close(&in_front, &point_in_front());
+        }
+
+        #[test]
+        fn view_inverts_the_pose() {
+            assert_close(
+                &glam::view(&super::pose()).to_cols_array(),
+                &glam::pose(&super::pose()).inverse().to_cols_array(),
+            );
+        }
+
+        #[test]
+        fn projections() {
+            for &far in &[100.0, f32::INFINITY] {
+                for &reverse_z in &[false, true] {
+                    let projection = glam::projection(&fov(), NEAR, far, reverse_z);
+                    for (point, expected) in frustum_corners(far, reverse_z).iter() {
+                        assert_close(&project(projection, *point), expected);
+                    }
+
+                    assert_close(
+                        &glam::view_projection(&super::pose(), &fov(), NEAR, far, reverse_z)
+                            .to_cols_array(),
+                        &(projection * glam::pose(&super::pose()).inverse()).to_cols_array(),
+                    );
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "nalgebra")]
+    mod nalgebra {
+        use ::nalgebra::{Matrix4, Point3, Vector4};
+
+        use super::{assert_close, fov, frustum_corners, point_in_front, NEAR};
+        use crate::interop::nalgebra;
+
+        fn project(matrix: Matrix4<f32>, [x, y, z]: [f32; 3]) -> [f32; 3] {
+            let clip = matrix * Vector4::new(x, y, z, 1.0);
+            [clip.x / clip.w, clip.y / clip.w, clip.z / clip.w]
+        }
+
+        #[test]
+        fn pose() {
+            let pose = nalgebra::pose(&super::pose());
+            assert_close(
+                (pose * Point3::origin()).coords.as_slice(),
+                &[0.3, 1.6, -0.5],
+            );
+            assert_close(
+                (pose * Point3::new(0.0, 0.0, -1.0)).coords.as_slice(),
+                &point_in_front(),
+            );
+        }
+
+        #[test]
+        fn view_inverts_the_pose() {
+            assert_close(
+                nalgebra::view(&super::pose()).as_slice(),
+                nalgebra::pose(&super::pose())
+                    .inverse()
+                    .to_homogeneous()
+                    .as_slice(),
+            );
+        }
+
+        #[test]
+        fn projections() {
+            for &far in &[100.0, f32::INFINITY] {
+                for &reverse_z in &[false, true] {
+                    let projection = nalgebra::projection(&fov(), NEAR, far, reverse_z);
+                    for (point, expected) in frustum_corners(far, reverse_z).iter() {
+                        assert_close(&project(projection, *point), expected);
+                    }
+
+                    let view = nalgebra::pose(&super::pose()).inverse().to_homogeneous();
+                    assert_close(
+                        nalgebra::view_projection(&super::pose(), &fov(), NEAR, far, reverse_z)
+                            .as_slice(),
+                        (projection * view).as_slice(),
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,375 @@
+use std::marker::PhantomData;
+
+use openxr_sys::{CompositionLayerBaseHeader, EyeVisibility, Posef};
+
+use crate::swapchain::Swapchain;
+
+/// A composition layer structure that can be handed to xrEndFrame.
+///
+/// # Safety
+///
+/// `as_base_header` must point to an OpenXR composition layer structure, starting with the
+/// `ty`, `next`, `layer_flags` and `space` fields, valid as long as `self` is borrowed.
+pub unsafe trait CompositionLayer {
+    fn as_base_header(&self) -> *const CompositionLayerBaseHeader;
+}
+
+unsafe impl CompositionLayer for openxr_sys::CompositionLayerProjection {
+    fn as_base_header(&self) -> *const CompositionLayerBaseHeader {
+        self as *const _ as *const CompositionLayerBaseHeader
+    }
+}
+
+unsafe impl CompositionLayer for openxr_sys::CompositionLayerQuad {
+    fn as_base_header(&self) -> *const CompositionLayerBaseHeader {
+        self as *const _ as *const CompositionLayerBaseHeader
+    }
+}
+
+pub(crate) const DEPTH_EXTENSION: &str = "XR_KHR_composition_layer_depth";
+
+/// Depth info of a projection view for XR_KHR_composition_layer_depth, describing the depth
+/// written with `math::projection`: 0 at `near` and 1 at `far`, swapped with `reverse_z`, which
+/// the extension expresses as a near distance beyond the far one. `far` may be infinite.
+pub(crate) fn depth_info(
+    sub_image: openxr_sys::SwapchainSubImage,
+    near: f32,
+    far: f32,
+    reverse_z: bool,
+) -> openxr_sys::CompositionLayerDepthInfoKHR {
+    // Distances of the min_depth and max_depth values
+    let (near_z, far_z) = if reverse_z { (far, near) } else { (near, far) };
+    openxr_sys::CompositionLayerDepthInfoKHR {
+        ty: openxr_sys::CompositionLayerDepthInfoKHR::TYPE,
+        next: std::ptr::null(),
+        sub_image,
+        min_depth: 0.0,
+        max_depth: 1.0,
+        near_z,
+        far_z,
+    }
+}
+
+/// Ordered list of the layers to submit with a frame.
+///
+/// The compositor blends the layers back to front: the first one pushed ends up at the bottom
+/// and each following one is drawn over it, according to its blending flags. The layers are only
+/// borrowed, so they can't go away before the frame is submitted. Note that the projection views
+/// and swapchains referenced by the layers aren't tracked and must also still be alive then.
+pub struct FrameLayers<'a> {
+    headers: Vec<*const CompositionLayerBaseHeader>,
+    _layers: PhantomData<&'a dyn CompositionLayer>,
+}
+
+impl<'a> FrameLayers<'a> {
+    pub fn new() -> Self {
+        FrameLayers {
+            headers: Vec::new(),
+            _layers: PhantomData,
+        }
+    }
+
+    /// Adds `layer` on top of the ones pushed so far.
+    pub fn push(&mut self, layer: &'a dyn CompositionLayer) -> &mut Self {
+        self.headers.push(layer.as_base_header());
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.headers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty()
+    }
+
+    pub(crate) fn headers(&self) -> &[*const CompositionLayerBaseHeader] {
+        &self.headers
+    }
+}
+
+impl Default for FrameLayers<'_> {
+    fn default() -> Self {
+        FrameLayers::new()
+    }
+}
+
+/// Builder for a flat rectangular layer, part of the core API.
+///
+/// Created in a world space, such as the reference space of the projection layer, the quad stays
+/// put as the user moves around. Created in the VIEW reference space, see
+/// `FrameContext::head_locked_quad`, it follows the head instead.
+#[derive(Clone, Copy)]
+pub struct QuadLayer {
+    layer: openxr_sys::CompositionLayerQuad,
+}
+
+unsafe impl CompositionLayer for QuadLayer {
+    fn as_base_header(&self) -> *const CompositionLayerBaseHeader {
+        &self.layer as *const _ as *const CompositionLayerBaseHeader
+    }
+}
+
+impl QuadLayer {
+    /// Creates a layer showing the whole `swapchain` on a quad one meter wide, its height
+    /// following the swapchain's aspect ratio, centered on the origin of `space` and facing +Z.
+    pub fn new(space: openxr_sys::Space, swapchain: &Swapchain) -> Self {
+        QuadLayer {
+            layer: openxr_sys::CompositionLayerQuad {
+                ty: openxr_sys::CompositionLayerQuad::TYPE,
+                next: std::ptr::null(),
+                layer_flags: openxr_sys::CompositionLayerFlags::EMPTY,
+                space,
+                eye_visibility: EyeVisibility::BOTH,
+                sub_image: swapchain.full_sub_image(),
+                pose: crate::identity_pose(),
+                size: openxr_sys::Extent2Df {
+                    width: 1.0,
+                    height: swapchain.height as f32 / swapchain.width as f32,
+                },
+            },
+        }
+    }
+
+    /// Width and height of the quad, in meters.
+    pub fn size(mut self, width: f32, height: f32) -> Self {
+        self.layer.size = openxr_sys::Extent2Df { width, height };
+        self
+    }
+
+    /// Position and orientation of the quad center in the layer space.
+    pub fn pose(mut self, pose: Posef) -> Self {
+        self.layer.pose = pose;
+        self
+    }
+
+    /// `BLEND_TEXTURE_SOURCE_ALPHA` lets what's below show through transparent pixels, for fades
+    /// or vignettes.
+    pub fn layer_flags(mut self, layer_flags: openxr_sys::CompositionLayerFlags) -> Self {
+        self.layer.layer_flags = layer_flags;
+        self
+    }
+
+    pub fn eye_visibility(mut self, eye_visibility: EyeVisibility) -> Self {
+        self.layer.eye_visibility = eye_visibility;
+        self
+    }
+
+    /// Part of the swapchain to show on the quad.
+    pub fn sub_image(mut self, sub_image: openxr_sys::SwapchainSubImage) -> Self {
+        self.layer.sub_image = sub_image;
+        self
+    }
+}
+
+/// Builder for a curved layer, submitted through XR_KHR_composition_layer_cylinder.
+///
+/// Cylinder layers are a better fit than flat quads for large UI surfaces, since the content
+/// stays at the same distance from the viewer and the edges get less distorted.
+#[derive(Clone, Copy)]
+pub struct CylinderLayer {
+    layer: openxr_sys::CompositionLayerCylinderKHR,
+}
+
+unsafe impl CompositionLayer for CylinderLayer {
+    fn as_base_header(&self) -> *const CompositionLayerBaseHeader {
+        &self.layer as *const _ as *const CompositionLayerBaseHeader
+    }
+}
+
+impl CylinderLayer {
+    pub const EXTENSION: &'static str = "XR_KHR_composition_layer_cylinder";
+
+    /// Creates a layer showing the whole `swapchain` on a cylinder centered on the origin of
+    /// `space`, with a one meter radius and a quarter turn of central angle.
+    pub fn new(space: openxr_sys::Space, swapchain: &Swapchain) -> Self {
+        CylinderLayer {
+            layer: openxr_sys::CompositionLayerCylinderKHR {
+                ty: openxr_sys::CompositionLayerCylinderKHR::TYPE,
+                next: std::ptr::null(),
+                layer_flags: openxr_sys::CompositionLayerFlags::EMPTY,
+                space,
+                eye_visibility: EyeVisibility::BOTH,
+                sub_image: swapchain.full_sub_image(),
+                pose: crate::identity_pose(),
+                radius: 1.0,
+                central_angle: std::f32::consts::FRAC_PI_2,
+                aspect_ratio: swapchain.width as f32 / swapchain.height as f32,
+            },
+        }
+    }
+
+    /// Radius of the cylinder, in meters.
+    pub fn radius(mut self, radius: f32) -> Self {
+        self.layer.radius = radius;
+        self
+    }
+
+    /// Angle covered by the layer around the cylinder axis, in radians.
+    pub fn central_angle(mut self, central_angle: f32) -> Self {
+        self.layer.central_angle = central_angle;
+        self
+    }
+
+    /// Width over height of the visible part of the cylinder.
+    /// The height is derived from the radius, central angle and this ratio.
+    pub fn aspect_ratio(mut self, aspect_ratio: f32) -> Self {
+        self.layer.aspect_ratio = aspect_ratio;
+        self
+    }
+
+    /// Position and orientation of the cylinder center in the layer space.
+    pub fn pose(mut self, pose: Posef) -> Self {
+        self.layer.pose = pose;
+        self
+    }
+
+    /// `BLEND_TEXTURE_SOURCE_ALPHA` lets what's below show through transparent pixels, around
+    /// the panels of a UI for instance.
+    pub fn layer_flags(mut self, layer_flags: openxr_sys::CompositionLayerFlags) -> Self {
+        self.layer.layer_flags = layer_flags;
+        self
+    }
+
+    pub fn eye_visibility(mut self, eye_visibility: EyeVisibility) -> Self {
+        self.layer.eye_visibility = eye_visibility;
+        self
+    }
+
+    /// Part of the swapchain to show on the cylinder, the aspect ratio not following it.
+    pub fn sub_image(mut self, sub_image: openxr_sys::SwapchainSubImage) -> Self {
+        self.layer.sub_image = sub_image;
+        self
+    }
+}
+
+/// Builder for a layer showing an equirectangular image on a sphere, submitted through
+/// XR_KHR_composition_layer_equirect2.
+///
+/// This is the way to play 360 or 180 degrees videos, the compositor doing the projection
+/// instead of the application rendering a textured sphere.
+#[derive(Clone, Copy)]
+pub struct EquirectLayer {
+    layer: openxr_sys::CompositionLayerEquirect2KHR,
+}
+
+unsafe impl CompositionLayer for EquirectLayer {
+    fn as_base_header(&self) -> *const CompositionLayerBaseHeader {
+        &self.layer as *const _ as *const CompositionLayerBaseHeader
+    }
+}
+
+impl EquirectLayer {
+    pub const EXTENSION: &'static str = "XR_KHR_composition_layer_equirect2";
+
+    /// Creates a layer mapping the whole `swapchain` on a full sphere of infinite radius centered
+    /// on the origin of `space`.
+    pub fn new(space: openxr_sys::Space, swapchain: &Swapchain) -> Self {
+        EquirectLayer {
+            layer: openxr_sys::CompositionLayerEquirect2KHR {
+                ty: openxr_sys::CompositionLayerEquirect2KHR::TYPE,
+                next: std::ptr::null(),
+                layer_flags: openxr_sys::CompositionLayerFlags::EMPTY,
+                space,
+                eye_visibility: EyeVisibility::BOTH,
+                sub_image: swapchain.full_sub_image(),
+                pose: crate::identity_pose(),
+                radius: std::f32::INFINITY,
+                central_horizontal_angle: 2.0 * std::f32::consts::PI,
+                upper_vertical_angle: std::f32::consts::FRAC_PI_2,
+                lower_vertical_angle: -std::f32::consts::FRAC_PI_2,
+            },
+        }
+    }
+
+    /// Radius of the sphere, in meters. Zero or infinity give a sphere at infinity.
+    pub fn radius(mut self, radius: f32) -> Self {
+        self.layer.radius = radius;
+        self
+    }
+
+    /// Horizontal angle covered by the image, in radians, `PI` for a 180 degrees video.
+    pub fn central_horizontal_angle(mut self, central_horizontal_angle: f32) -> Self {
+        self.layer.central_horizontal_angle = central_horizontal_angle;
+        self
+    }
+
+    /// Angles of the top and bottom edges of the image relative to the horizon, in radians.
+    pub fn vertical_angles(mut self, upper_vertical_angle: f32, lower_vertical_angle: f32) -> Self {
+        self.layer.upper_vertical_angle = upper_vertical_angle;
+        self.layer.lower_vertical_angle = lower_vertical_angle;
+        self
+    }
+
+    /// Position and orientation of the sphere center in the layer space.
+    pub fn pose(mut self, pose: Posef) -> Self {
+        self.layer.pose = pose;
+        self
+    }
+
+    /// Which eyes see the layer, `LEFT` and `RIGHT` allowing stereo videos with one layer per
+    /// eye.
+    pub fn eye_visibility(mut self, eye_visibility: EyeVisibility) -> Self {
+        self.layer.eye_visibility = eye_visibility;
+        self
+    }
+
+    /// Part of the swapchain holding the image, for instance one half of a stereo video frame.
+    pub fn sub_image(mut self, sub_image: openxr_sys::SwapchainSubImage) -> Self {
+        self.layer.sub_image = sub_image;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math;
+
+    /// Depth `projection` gives to a point `distance` ahead of the view.
+    fn depth_at(projection: &math::Mat4, distance: f32) -> f32 {
+        // z / w, w being the distance, which tends to -projection[10] as it goes to infinity
+        if distance.is_infinite() {
+            return -projection[10];
+        }
+        (-projection[10] * distance + projection[14]) / distance
+    }
+
+    /// Checks that `depth_info` describes the depth `math::projection` writes.
+    fn assert_consistent(near: f32, far: f32, reverse_z: bool) {
+        let fov = openxr_sys::Fovf {
+            angle_left: -0.8,
+            angle_right: 0.8,
+            angle_up: 0.8,
+            angle_down: -0.8,
+        };
+        let projection = math::projection(&fov, near, far, reverse_z);
+        let sub_image: openxr_sys::SwapchainSubImage = unsafe { std::mem::zeroed() };
+        let info = depth_info(sub_image, near, far, reverse_z);
+
+        assert!((depth_at(&projection, info.near_z) - info.min_depth).abs() < 1e-6);
+        assert!((depth_at(&projection, info.far_z) - info.max_depth).abs() < 1e-6);
+    }
+
+    #[test]
+    fn forward_depth_info() {
+        let info = depth_info(unsafe { std::mem::zeroed() }, 0.1, 100.0, false);
+        assert_eq!((info.near_z, info.far_z), (0.1, 100.0));
+        assert_eq!((info.min_depth, info.max_depth), (0.0, 1.0));
+        assert_consistent(0.1, 100.0, false);
+    }
+
+    #[test]
+    fn reverse_infinite_depth_info() {
+        let info = depth_info(unsafe { std::mem::zeroed() }, 0.1, f32::INFINITY, true);
+        assert_eq!((info.near_z, info.far_z), (f32::INFINITY, 0.1));
+        assert_eq!((info.min_depth, info.max_depth), (0.0, 1.0));
+        assert_consistent(0.1, f32::INFINITY, true);
+    }
+
+    #[test]
+    fn other_depth_infos() {
+        assert_consistent(0.1, f32::INFINITY, false);
+        assert_consistent(0.1, 100.0, true);
+    }
+}
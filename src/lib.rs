@@ -1,16 +1,90 @@
 #![allow(non_snake_case)]
 
-use std::{
-    ffi::{c_void, CString},
-    mem::transmute,
-};
+/// Evaluates `$body` and, with the `timing` feature, records how long it took into `$timings`.
+macro_rules! timed {
+    ($timings:expr, $step:expr, $body:expr) => {{
+        #[cfg(feature = "timing")]
+        let start = std::time::Instant::now();
+        let value = $body;
+        #[cfg(feature = "timing")]
+        $timings.record($step, start.elapsed());
+        value
+    }};
+}
 
-use ash::{
-    version::{DeviceV1_0, EntryV1_0, InstanceV1_0},
-    vk::Handle,
+mod action;
+mod app;
+#[cfg(feature = "capture")]
+mod capture;
+mod chain;
+mod color_space;
+mod config;
+mod context;
+mod controller_model;
+mod debug;
+mod debug_grid;
+mod error;
+mod eye_gaze;
+mod feature;
+mod foveation;
+mod frame;
+mod frame_stats;
+mod in_flight;
+mod interaction_profile;
+#[cfg(any(feature = "glam", feature = "nalgebra"))]
+pub mod interop;
+mod layers;
+mod math;
+#[cfg(feature = "mirror-window")]
+mod mirror;
+#[cfg(test)]
+mod mock;
+mod passthrough;
+mod render_target;
+mod session;
+mod spatial_anchor;
+mod swapchain;
+mod thread_priority;
+mod time;
+#[cfg(feature = "timing")]
+mod timing;
+
+pub use action::{
+    Action, ActionSet, ActionState, Hand, HandPose, HandPoseAction, TopLevelPath,
+    STANDARD_INTERACTION_PROFILES,
+};
+pub use app::{App, ControlFlow, StopHandle};
+#[cfg(feature = "capture")]
+pub use capture::ImageData;
+pub use chain::NextStruct;
+pub use color_space::ColorSpace;
+pub use config::{Config, QueueConfig, RenderOrder, RESOLUTION_SCALE_RANGE};
+pub use context::{Context, DeviceQueue, VulkanHandles};
+pub use controller_model::{ControllerModel, ControllerModelNode};
+pub use error::{Error, XrResultExt};
+pub use feature::UnavailableFeature;
+pub use foveation::FoveationLevel;
+pub use frame::{FrameContext, RENDER_SCALE_RANGE};
+pub use frame_stats::{FrameStats, FrameTiming, GpuTimer, TimingSummary};
+pub use interaction_profile::{InteractionProfile, PathCache, ProfileActions};
+pub use layers::{CompositionLayer, CylinderLayer, EquirectLayer, FrameLayers, QuadLayer};
+pub use render_target::{select_depth_format, ViewRenderTarget};
+pub use session::{Session, SessionEvent, TrackingCapabilities, VisibilityMask};
+pub use spatial_anchor::SpatialAnchor;
+pub use swapchain::{
+    is_srgb, set_viewport_and_scissor, ImageWait, ShaderOutput, Swapchain, SwapchainLayout,
 };
+#[cfg(not(windows))]
+pub use time::Timespec;
+pub use time::XrTime;
+#[cfg(feature = "timing")]
+pub use timing::InitTimings;
+
+use std::{cell::RefCell, ffi::CString, mem::transmute};
+
+use ash::version::{DeviceV1_0, DeviceV1_1, EntryV1_0, InstanceV1_0, InstanceV1_1};
 use libloading::Library;
-use log::{error, info};
+use log::{debug, error, info, warn};
 use openxr_sys::Result as XrResult;
 
 #[cfg_attr(target_os = "android", ndk_glue::main(backtrace = "full"))]
@@ -19,364 +93,219 @@ pub fn android_main() {
         .filter_level(log::LevelFilter::max())
         .try_init();
 
-    let application_name = "test";
-    let application_version = 1;
-    let engine_name: Option<&str> = None;
-    let engine_version: Option<u32> = None;
-
-    let entry = XrEntry::load().unwrap();
-
-    info!("xrInitializeLoaderKHR()");
-    let (vm, activity) = {
-        let initialize_loader_KHR: openxr_sys::pfn::InitializeLoaderKHR = unsafe {
-            transmute(
-                entry
-                    .fp
-                    .get_proc_addr(openxr_sys::Instance::NULL, "xrInitializeLoaderKHR"),
-            )
-        };
-
-        let native_activity = ndk_glue::native_activity();
-        let vm = native_activity.vm();
-        let activity = native_activity.activity();
-
-        // https://www.khronos.org/registry/OpenXR/specs/1.0/man/html/XrLoaderInitInfoAndroidKHR.html
-        let info = Box::into_raw(Box::new(openxr_sys::LoaderInitInfoAndroidKHR {
-            ty: openxr_sys::LoaderInitInfoAndroidKHR::TYPE,
-            next: std::ptr::null(),
-            application_vm: vm as *mut c_void,
-            application_context: activity as *mut c_void,
-        })) as *const openxr_sys::LoaderInitInfoBaseHeaderKHR;
-
-        let call_result = unsafe { initialize_loader_KHR(info) };
-
-        if call_result != XrResult::SUCCESS {
-            panic!("Failed initialize_loader_KHR");
-        }
-
-        (vm, activity)
-    };
-
-    let application_info = {
-        // Prevents application names from being larger than the container in ApplicationInfo
-        assert!(
-            application_name.len() <= openxr_sys::MAX_APPLICATION_NAME_SIZE,
-            "OpenXR application names must be {} bytes or less",
-            openxr_sys::MAX_APPLICATION_NAME_SIZE
-        );
-
-        // Prevents application names from being empty
-        assert!(
-            application_name.len() > 0,
-            "OpenXR application names must be greater than 0 bytes"
-        );
-
-        let mut app_info = openxr_sys::ApplicationInfo {
-            application_name: [0; openxr_sys::MAX_APPLICATION_NAME_SIZE],
-            engine_name: [0; openxr_sys::MAX_ENGINE_NAME_SIZE],
-            application_version,
-            engine_version: engine_version.map_or(0, |v| v),
-            api_version: openxr_sys::CURRENT_API_VERSION,
-        };
-
-        for (app_char, slot) in application_name
-            .bytes()
-            .zip(app_info.application_name.iter_mut())
-        {
-            *slot = app_char as _;
-        }
-
-        app_info.application_name[application_name.len()] = 0;
-
-        // Its safe to not do anything if `engine_name` is `None` because the
-        // buffer is already initialized to 0
-        if let Some(name) = engine_name {
-            for (engine_char, slot) in name.bytes().zip(app_info.application_name.iter_mut()) {
-                *slot = engine_char as _;
-            }
-
-            app_info.application_name[application_name.len()] = 0;
-        }
-
-        app_info
-    };
-
-    info!("xrEnumerateInstanceExtensionProperties()");
-    let xr_available_extensions = unsafe {
-        let mut count = 0;
-        (entry.fp.enumerate_instance_extension_properties)(
-            std::ptr::null(),
-            0,
-            &mut count,
-            std::ptr::null_mut(),
-        );
-        let mut ext_properties = Vec::with_capacity(count as usize);
-        let result = (entry.fp.enumerate_instance_extension_properties)(
-            std::ptr::null(),
-            ext_properties.len() as u32,
-            &mut count,
-            ext_properties.as_mut_ptr(),
-        );
-        if result != XrResult::SUCCESS {
-            panic!("Failed xrEnumerateInstanceExtensionProperties")
-        }
-        ext_properties.set_len((count - 1) as usize);
-        ext_properties
-            .iter()
-            .map(|x| {
-                let pos = x.extension_name.iter().position(|&c| c == 0);
-                match pos {
-                    Some(idx) => {
-                        std::ffi::CStr::from_bytes_with_nul_unchecked(&x.extension_name[..idx + 1])
-                            .to_owned()
-                    }
-                    None => panic!("Found invalid extension"),
-                }
-            })
-            .collect::<Vec<_>>()
-    };
-
-    info!(
-        "OpenXR available extensions: {:#?}",
-        xr_available_extensions
-    );
-
-    let required_layers = to_veccstr(&[]);
-
-    let required_extensions =
-        to_veccstr(&["XR_KHR_vulkan_enable", "XR_KHR_android_create_instance"]);
-
-    // https://www.khronos.org/registry/OpenXR/specs/1.0/html/xrspec.html#XR_KHR_android_create_instance
-    let create_info_ext = Box::into_raw(Box::new(openxr_sys::InstanceCreateInfoAndroidKHR {
-        ty: openxr_sys::InstanceCreateInfoAndroidKHR::TYPE,
-        next: std::ptr::null(),
-        application_vm: vm as *mut c_void,
-        application_activity: activity as *mut c_void,
-    })) as *const c_void;
-
-    let create_info = openxr_sys::InstanceCreateInfo {
-        ty: openxr_sys::InstanceCreateInfo::TYPE,
-        next: create_info_ext,
-        create_flags: openxr_sys::InstanceCreateFlags::EMPTY,
-        application_info,
-        enabled_api_layer_count: required_layers.ptr.len() as _,
-        enabled_api_layer_names: required_layers.ptr.as_ptr(),
-        enabled_extension_count: required_extensions.ptr.len() as _,
-        enabled_extension_names: required_extensions.ptr.as_ptr(),
-    };
-
-    info!("xrCreateInstance()");
-    let instance = {
-        let mut instance_handle = openxr_sys::Instance::NULL;
-        let call_result = unsafe { (entry.fp.create_instance)(&create_info, &mut instance_handle) };
-        if call_result != XrResult::SUCCESS {
-            panic!("Failed to create_instance");
-        }
-        instance_handle
-    };
-
-    let fp = XrInstanceFp::new(&entry.fp, instance);
-
-    let system_get_info = openxr_sys::SystemGetInfo {
-        ty: openxr_sys::SystemGetInfo::TYPE,
-        next: std::ptr::null_mut(),
-        form_factor: openxr_sys::FormFactor::HEAD_MOUNTED_DISPLAY,
-    };
-
-    info!("xrGetSystem()");
-    let system_id = {
-        let mut system_id = openxr_sys::SystemId::NULL;
-        let get_system: openxr_sys::pfn::GetSystem =
-            unsafe { transmute(entry.fp.get_proc_addr(instance, "xrGetSystem")) };
-        let result = unsafe { get_system(instance, &system_get_info, &mut system_id) };
-        if result != XrResult::SUCCESS {
-            panic!("Failed xrGetSystem");
+    let mut app = App::new(Config::default()).unwrap();
+
+    let session = app.session_mut();
+    let controls = session
+        .create_action_set("controls", "Controls", 0)
+        .unwrap();
+    let trigger = controls
+        .create_action(
+            "trigger",
+            "Trigger",
+            openxr_sys::ActionType::FLOAT_INPUT,
+            &[],
+        )
+        .unwrap();
+    let thumbstick = controls
+        .create_action(
+            "thumbstick",
+            "Thumbstick",
+            openxr_sys::ActionType::VECTOR2F_INPUT,
+            &[],
+        )
+        .unwrap();
+    session
+        .suggest_bindings(
+            "/interaction_profiles/oculus/touch_controller",
+            &[
+                (&trigger, "/user/hand/right/input/trigger/value"),
+                (&thumbstick, "/user/hand/right/input/thumbstick"),
+            ],
+        )
+        .unwrap();
+    session.attach_action_sets(&[&controls]).unwrap();
+
+    app.run(|frame| {
+        let trigger = trigger.state_float(frame.session, openxr_sys::Path::NULL)?;
+        let thumbstick = thumbstick.state_vector2(frame.session, openxr_sys::Path::NULL)?;
+        if trigger.changed_since_last_sync || thumbstick.changed_since_last_sync {
+            info!(
+                "trigger: {}, thumbstick: {:?}",
+                trigger.current_state, thumbstick.current_state
+            );
         }
-        system_id
-    };
+        Ok(ControlFlow::Continue)
+    })
+    .unwrap();
 
-    info!("xrGetVulkanGraphicsRequirementsKHR()");
-    let mut graphics_requirements =
-        openxr_sys::GraphicsRequirementsVulkanKHR::out(std::ptr::null_mut());
-    let result = unsafe {
-        (fp.get_vulkan_graphics_requirements_KHR)(
-            instance,
-            system_id,
-            graphics_requirements.as_mut_ptr(),
-        )
-    };
+    controls.destroy().unwrap();
+    app.destroy().unwrap();
+}
 
-    if result != XrResult::SUCCESS {
-        panic!("Failed xrGetVulkanGraphicsRequirementsKHR");
+fn identity_pose() -> openxr_sys::Posef {
+    openxr_sys::Posef {
+        orientation: openxr_sys::Quaternionf {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+            w: 1.0,
+        },
+        position: openxr_sys::Vector3f {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        },
     }
+}
 
-    let graphics_requirements = unsafe { graphics_requirements.assume_init() };
-
-    info!(
-        "graphics_requirements: min={}, max={}",
-        graphics_requirements.min_api_version_supported,
-        graphics_requirements.max_api_version_supported,
-    );
-
-    let vk_entry = unsafe { ash::Entry::new().unwrap() };
-
-    let extensions = vk_entry
-        .enumerate_instance_extension_properties()
-        .expect("Failed to get vulkan extensions");
-
-    info!("vulkan extensions: {:#?}", extensions);
-
-    info!("xrGetVulkanInstanceExtensionsKHR()");
-    let req_extensions = {
-        let mut count: u32 = 0;
-        let count_ptr: *mut u32 = &mut count;
-        let mut buffer = [0; 256];
-        let result = unsafe {
-            (fp.get_vulkan_instance_extensions_KHR)(
-                instance,
-                system_id,
-                256,
-                count_ptr,
-                buffer.as_mut_ptr(),
-            )
-        };
-
-        if result != XrResult::SUCCESS {
-            panic!("Failed xrGetVulkanInstanceExtensionsKHR");
-        }
-
-        let req_extensions = &std::str::from_utf8(&buffer).unwrap()[..(count - 1) as usize];
-        req_extensions
-            .split_ascii_whitespace()
-            .map(|x| CString::new(x).unwrap())
-            .collect::<Vec<_>>()
-    };
+/// Compares a name from a Vulkan properties struct (extension, layer...) with `name`.
+fn vk_name_eq(raw_name: &[std::os::raw::c_char], name: &str) -> bool {
+    let raw_name = unsafe { std::ffi::CStr::from_ptr(raw_name.as_ptr()) };
+    raw_name.to_bytes() == name.as_bytes()
+}
 
-    info!("vulkan ext required: {:?}", req_extensions);
-
-    info!("vkCreateInstance()");
-    let vk_instance = {
-        let app_name = CString::new("openxr-test").unwrap();
-        let engine_name = CString::new("Vulkan Engine").unwrap();
-        let app_info = ash::vk::ApplicationInfo {
-            s_type: ash::vk::StructureType::APPLICATION_INFO,
-            p_next: std::ptr::null(),
-            p_application_name: app_name.as_ptr(),
-            application_version: 1,
-            p_engine_name: engine_name.as_ptr(),
-            engine_version: 1,
-            api_version: ash::vk::API_VERSION_1_0,
-        };
+/// Decodes a name from a Vulkan properties struct, stopping at the nul terminator, or at the end
+/// of the array should a driver fill it entirely.
+fn vk_name(raw_name: &[std::os::raw::c_char]) -> String {
+    let bytes: Vec<u8> = raw_name
+        .iter()
+        .take_while(|&&c| c != 0)
+        .map(|&c| c as u8)
+        .collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
 
-        let extension_names = vec![CString::new("VK_EXT_debug_report").unwrap()];
+/// Names of the Vulkan instance extensions the loader, the drivers and the implicit layers
+/// provide.
+pub fn available_vk_instance_extensions(entry: &ash::Entry) -> Result<Vec<String>, Error> {
+    let properties = entry.enumerate_instance_extension_properties()?;
+    Ok(properties
+        .iter()
+        .map(|properties| vk_name(&properties.extension_name))
+        .collect())
+}
 
-        let extension_names: Vec<_> = extension_names
-            .into_iter()
-            .chain(req_extensions.into_iter())
-            .collect();
+/// Names of the Vulkan device extensions `physical_device` supports.
+pub fn available_vk_device_extensions(
+    instance: &ash::Instance,
+    physical_device: ash::vk::PhysicalDevice,
+) -> Result<Vec<String>, Error> {
+    let properties = unsafe { instance.enumerate_device_extension_properties(physical_device)? };
+    Ok(properties
+        .iter()
+        .map(|properties| vk_name(&properties.extension_name))
+        .collect())
+}
 
-        let extension_names: Vec<_> = extension_names
-            .iter()
-            .map(|x| x.as_bytes_with_nul().as_ptr())
-            .collect();
-
-        let create_info = ash::vk::InstanceCreateInfo {
-            s_type: ash::vk::StructureType::INSTANCE_CREATE_INFO,
-            p_next: std::ptr::null(),
-            flags: ash::vk::InstanceCreateFlags::empty(),
-            p_application_info: &app_info,
-            pp_enabled_layer_names: std::ptr::null(),
-            enabled_layer_count: 0,
-            pp_enabled_extension_names: extension_names.as_ptr() as *const *const u8,
-            enabled_extension_count: extension_names.len() as u32,
-        };
+/// The names of `requested` missing from the `available` extensions.
+fn missing_extensions(requested: &[&str], available: &[String]) -> Vec<String> {
+    requested
+        .iter()
+        .filter(|&&name| !available.iter().any(|available| available == name))
+        .map(|name| name.to_string())
+        .collect()
+}
 
-        unsafe {
-            vk_entry
-                .create_instance(&create_info, None)
-                .expect("Failed vkCreateInstance()")
-        }
-    };
+/// Copies `name` into a fixed-size name field of an OpenXR struct, nul-terminating it. Fails if
+/// it doesn't fit along with the terminator, or has a nul byte of its own.
+fn write_name(buffer: &mut [std::os::raw::c_char], name: &str) -> Result<(), Error> {
+    if name.len() >= buffer.len() || name.contains('\0') {
+        return Err(Error::InvalidName(name.to_string()));
+    }
+    for (dst, &src) in buffer.iter_mut().zip(name.as_bytes()) {
+        *dst = src as std::os::raw::c_char;
+    }
+    buffer[name.len()] = 0;
+    Ok(())
+}
 
-    let vk_instance_raw = vk_instance.handle().as_raw() as *const c_void;
-
-    info!("xrGetVulkanGraphicsDeviceKHR()");
-    let physical_device = {
-        let mut physical_device = std::mem::MaybeUninit::new(std::ptr::null());
-        // TODO: Error handling
-        let result = unsafe {
-            (fp.get_vulkan_graphics_device_KHR)(
-                instance,
-                system_id,
-                vk_instance_raw,
-                physical_device.as_mut_ptr(),
-            )
-        };
+/// How many times `enumerate` tries to fill its buffer while the count keeps growing.
+const ENUMERATE_ATTEMPTS: u32 = 4;
 
-        if result != XrResult::SUCCESS {
-            panic!("Failed xrGetVulkanGraphicsDeviceKHR");
-        }
+/// Checks a count reported by the runtime against `max_count`.
+fn check_enumerate_count(count: u32, max_count: u32) -> Result<(), Error> {
+    if count > max_count {
+        return Err(Error::EnumerationTooLarge(count, max_count));
+    }
+    Ok(())
+}
 
-        let physical_device = unsafe { physical_device.assume_init() };
-        ash::vk::PhysicalDevice::from_raw(physical_device as u64)
-    };
-    info!("  physical_device: {:?}", physical_device);
-
-    info!("xrGetVulkanDeviceExtensionsKHR()");
-    let req_dev_extensions = {
-        let mut count: u32 = 0;
-        let mut buffer = [0; 256];
-        let result = unsafe {
-            (fp.get_vulkan_device_extensions_KHR)(
-                instance,
-                system_id,
-                256,
-                &mut count,
-                buffer.as_mut_ptr(),
-            )
-        };
+/// Runs an OpenXR two-call enumeration, `f` being called with the capacity, count and buffer
+/// arguments. The first call gets the count, the second one fills a buffer of that many copies of
+/// `empty`, which is how the structures the runtime fills get their `ty` set.
+///
+/// The count may grow in between, the runtime then failing with `ERROR_SIZE_INSUFFICIENT` and
+/// reporting the new count, which the buffer is grown to before trying again. That error is
+/// returned if the count still grows after `ENUMERATE_ATTEMPTS` tries.
+///
+/// Counts above `max_count`, `Config::max_enumerate_count` as kept by `XrInstanceFp`, which only
+/// a misbehaving runtime would report, fail with `Error::EnumerationTooLarge` instead of being
+/// allocated for.
+fn enumerate<T, F>(max_count: u32, empty: T, mut f: F) -> Result<Vec<T>, Error>
+where
+    T: Clone,
+    F: FnMut(u32, *mut u32, *mut T) -> XrResult,
+{
+    let mut count = 0;
+    let result = f(0, &mut count, std::ptr::null_mut());
+    if !result.is_success() {
+        return Err(Error::Xr(result));
+    }
 
-        if result != XrResult::SUCCESS {
-            panic!("Failed xrGetVulkanDeviceExtensionsKHR");
+    for _ in 0..ENUMERATE_ATTEMPTS {
+        check_enumerate_count(count, max_count)?;
+        let mut items = vec![empty.clone(); count as usize];
+        let result = f(items.len() as u32, &mut count, items.as_mut_ptr());
+        match result.into_result() {
+            Ok(_) => {
+                items.truncate(count as usize);
+                return Ok(items);
+            }
+            Err(Error::Xr(XrResult::ERROR_SIZE_INSUFFICIENT)) => {
+                debug!("Enumeration count grew to {}, retrying", count);
+            }
+            Err(error) => return Err(error),
         }
+    }
 
-        let req_dev_extensions = &std::str::from_utf8(&buffer).unwrap()[..(count - 1) as usize];
-        req_dev_extensions
-            .split_ascii_whitespace()
-            .map(|x| CString::new(x).unwrap())
-            .collect::<Vec<_>>()
-    };
-
-    info!("vulkan device ext required: {:?}", req_dev_extensions);
-
-    info!("create_logical_device()");
-    let (device, _queue) = create_logical_device(&vk_instance, physical_device);
-    info!("  device: {:?}", device.handle());
+    Err(Error::Xr(XrResult::ERROR_SIZE_INSUFFICIENT))
+}
 
-    let graphics_binding = openxr_sys::GraphicsBindingVulkanKHR {
-        ty: openxr_sys::StructureType::GRAPHICS_BINDING_VULKAN_KHR,
-        instance: vk_instance_raw,
-        physical_device: physical_device.as_raw() as *const c_void,
-        device: device.handle().as_raw() as *const c_void,
-        queue_family_index: 0,
-        queue_index: 0,
-        next: std::ptr::null_mut(),
-    };
+/// Splits the space separated list of Vulkan extensions returned by
+/// xrGetVulkanInstanceExtensionsKHR and xrGetVulkanDeviceExtensionsKHR.
+fn split_extension_list(buffer: &[std::os::raw::c_char]) -> Vec<CString> {
+    let list: Vec<u8> = buffer
+        .iter()
+        .take_while(|&&c| c != 0)
+        .map(|&c| c as u8)
+        .collect();
+    std::str::from_utf8(&list)
+        .unwrap()
+        .split_ascii_whitespace()
+        .map(|name| CString::new(name).unwrap())
+        .collect()
+}
 
-    let session_create_info = openxr_sys::SessionCreateInfo {
-        ty: openxr_sys::StructureType::SESSION_CREATE_INFO,
-        create_flags: openxr_sys::SessionCreateFlags::EMPTY,
-        system_id,
-        next: Box::into_raw(Box::new(graphics_binding)) as *const c_void,
-    };
+fn string_to_path(
+    fp: &XrInstanceFp,
+    instance: openxr_sys::Instance,
+    path: &str,
+) -> Result<openxr_sys::Path, Error> {
+    let path_string = CString::new(path).unwrap();
+    let mut path = openxr_sys::Path::NULL;
+    let result = unsafe { (fp.string_to_path)(instance, path_string.as_ptr(), &mut path) };
+    if !result.is_success() {
+        return Err(Error::Xr(result));
+    }
 
-    info!("xrCreateSession()");
-    let mut session = openxr_sys::Session::NULL;
-    let result = unsafe { (fp.create_session)(instance, &session_create_info, &mut session) };
+    Ok(path)
+}
 
-    if result != XrResult::SUCCESS {
-        panic!("Failed xrCreateSession");
-    }
+fn is_extension_enabled(enabled_extensions: &[CString], name: &str) -> bool {
+    enabled_extensions
+        .iter()
+        .any(|extension| extension.as_bytes() == name.as_bytes())
 }
 
 struct VecCStr {
@@ -406,86 +335,317 @@ fn find_queue_family(
 
     let mut queue_family_indices = QueueFamilyIndices {
         graphics_family: None,
+        compute_family: None,
+        transfer_family: None,
     };
 
-    let mut index = 0;
-    for queue_family in queue_families.iter() {
-        if queue_family.queue_count > 0
-            && queue_family
-                .queue_flags
-                .contains(ash::vk::QueueFlags::GRAPHICS)
-        {
-            queue_family_indices.graphics_family = Some(index);
+    for (index, queue_family) in queue_families.iter().enumerate() {
+        if queue_family.queue_count == 0 {
+            continue;
         }
 
-        if queue_family_indices.is_complete() {
-            break;
-        }
+        let flags = queue_family.queue_flags;
+        let graphics = flags.contains(ash::vk::QueueFlags::GRAPHICS);
+        let compute = flags.contains(ash::vk::QueueFlags::COMPUTE);
+        let transfer = flags.contains(ash::vk::QueueFlags::TRANSFER);
+        let index = Some(index as u32);
 
-        index += 1;
+        if graphics && queue_family_indices.graphics_family.is_none() {
+            queue_family_indices.graphics_family = index;
+        }
+        if compute && !graphics && queue_family_indices.compute_family.is_none() {
+            queue_family_indices.compute_family = index;
+        }
+        if transfer && !graphics && !compute && queue_family_indices.transfer_family.is_none() {
+            queue_family_indices.transfer_family = index;
+        }
     }
 
     queue_family_indices
 }
 
+/// Names of the `VkPhysicalDeviceFeatures` members, in declaration order.
+const DEVICE_FEATURE_NAMES: [&str; 55] = [
+    "robustBufferAccess",
+    "fullDrawIndexUint32",
+    "imageCubeArray",
+    "independentBlend",
+    "geometryShader",
+    "tessellationShader",
+    "sampleRateShading",
+    "dualSrcBlend",
+    "logicOp",
+    "multiDrawIndirect",
+    "drawIndirectFirstInstance",
+    "depthClamp",
+    "depthBiasClamp",
+    "fillModeNonSolid",
+    "depthBounds",
+    "wideLines",
+    "largePoints",
+    "alphaToOne",
+    "multiViewport",
+    "samplerAnisotropy",
+    "textureCompressionETC2",
+    "textureCompressionASTC_LDR",
+    "textureCompressionBC",
+    "occlusionQueryPrecise",
+    "pipelineStatisticsQuery",
+    "vertexPipelineStoresAndAtomics",
+    "fragmentStoresAndAtomics",
+    "shaderTessellationAndGeometryPointSize",
+    "shaderImageGatherExtended",
+    "shaderStorageImageExtendedFormats",
+    "shaderStorageImageMultisample",
+    "shaderStorageImageReadWithoutFormat",
+    "shaderStorageImageWriteWithoutFormat",
+    "shaderUniformBufferArrayDynamicIndexing",
+    "shaderSampledImageArrayDynamicIndexing",
+    "shaderStorageBufferArrayDynamicIndexing",
+    "shaderStorageImageArrayDynamicIndexing",
+    "shaderClipDistance",
+    "shaderCullDistance",
+    "shaderFloat64",
+    "shaderInt64",
+    "shaderInt16",
+    "shaderResourceResidency",
+    "shaderResourceMinLod",
+    "sparseBinding",
+    "sparseResidencyBuffer",
+    "sparseResidencyImage2D",
+    "sparseResidencyImage3D",
+    "sparseResidency2Samples",
+    "sparseResidency4Samples",
+    "sparseResidency8Samples",
+    "sparseResidency16Samples",
+    "sparseResidencyAliased",
+    "variableMultisampleRate",
+    "inheritedQueries",
+];
+
+/// The names of the features set in `requested` that `supported` lacks.
+fn missing_features(
+    requested: &ash::vk::PhysicalDeviceFeatures,
+    supported: &ash::vk::PhysicalDeviceFeatures,
+) -> Vec<&'static str> {
+    // The struct is nothing but VkBool32 members, in the same order as the names
+    let as_array = |features: &ash::vk::PhysicalDeviceFeatures| unsafe {
+        transmute::<_, [ash::vk::Bool32; 55]>(*features)
+    };
+    let (requested, supported) = (as_array(requested), as_array(supported));
+    DEVICE_FEATURE_NAMES
+        .iter()
+        .zip(requested.iter().zip(&supported))
+        .filter(|(_, (&requested, &supported))| {
+            requested != ash::vk::FALSE && supported == ash::vk::FALSE
+        })
+        .map(|(&name, _)| name)
+        .collect()
+}
+
+/// Whether the device can run protected submissions on its graphics family, see
+/// `Config::protected_content`. That takes Vulkan 1.1 on the device side as well as on the
+/// instance, which is the caller's to check, along with the `protectedMemory` feature.
+fn supports_protected_memory(
+    instance: &ash::Instance,
+    physical_device: ash::vk::PhysicalDevice,
+) -> bool {
+    let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+    if properties.api_version < ash::vk::API_VERSION_1_1 {
+        return false;
+    }
+
+    let mut protected_memory = ash::vk::PhysicalDeviceProtectedMemoryFeatures::default();
+    let mut features = ash::vk::PhysicalDeviceFeatures2 {
+        p_next: &mut protected_memory as *mut _ as *mut std::ffi::c_void,
+        ..Default::default()
+    };
+    unsafe { instance.get_physical_device_features2(physical_device, &mut features) };
+    if protected_memory.protected_memory != ash::vk::TRUE {
+        return false;
+    }
+
+    let queue_families =
+        unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
+    find_queue_family(instance, physical_device)
+        .graphics_family
+        .map_or(false, |index| {
+            queue_families[index as usize]
+                .queue_flags
+                .contains(ash::vk::QueueFlags::PROTECTED)
+        })
+}
+
+/// With `protected`, the queues of the graphics family are created protected-capable and the
+/// `protectedMemory` feature is enabled, see `supports_protected_memory`. `features` are checked
+/// against what the device supports before being enabled. `create_device` makes the actual call,
+/// either vkCreateDevice or xrCreateVulkanDeviceKHR.
 fn create_logical_device(
     instance: &ash::Instance,
     physical_device: ash::vk::PhysicalDevice,
-) -> (ash::Device, ash::vk::Queue) {
+    queue_config: &QueueConfig,
+    extensions: &[&str],
+    features: &ash::vk::PhysicalDeviceFeatures,
+    protected: bool,
+    create_device: impl FnOnce(&ash::vk::DeviceCreateInfo) -> Result<ash::Device, Error>,
+) -> Result<LogicalDevice, Error> {
+    let available_extensions = available_vk_device_extensions(instance, physical_device)?;
+    let missing = missing_extensions(extensions, &available_extensions);
+    if !missing.is_empty() {
+        return Err(Error::MissingDeviceExtensions(missing));
+    }
+
+    let supported_features = unsafe { instance.get_physical_device_features(physical_device) };
+    let missing = missing_features(features, &supported_features);
+    if !missing.is_empty() {
+        return Err(Error::UnsupportedDeviceFeatures(missing));
+    }
+
     let indices = find_queue_family(instance, physical_device);
+    let queue_families =
+        unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
+    let graphics_family = indices.graphics_family.unwrap();
+
+    // Priorities of the queues created in each family, the graphics queue coming first
+    let mut family_priorities = vec![(graphics_family, vec![queue_config.graphics_priority])];
+
+    // Returns the family and index of the queue to get, sharing the last queue of the family when
+    // it has no more
+    let mut request_queue = |family_index: u32, priority: f32| {
+        let position = family_priorities
+            .iter()
+            .position(|(index, _)| *index == family_index);
+        let priorities = match position {
+            Some(position) => &mut family_priorities[position].1,
+            None => {
+                family_priorities.push((family_index, Vec::new()));
+                &mut family_priorities.last_mut().unwrap().1
+            }
+        };
 
-    let queue_priorities = [1.0_f32];
-    let queue_create_info = ash::vk::DeviceQueueCreateInfo {
-        s_type: ash::vk::StructureType::DEVICE_QUEUE_CREATE_INFO,
-        p_next: std::ptr::null(),
-        flags: ash::vk::DeviceQueueCreateFlags::empty(),
-        queue_family_index: indices.graphics_family.unwrap(),
-        p_queue_priorities: queue_priorities.as_ptr(),
-        queue_count: queue_priorities.len() as u32,
+        if priorities.len() < queue_families[family_index as usize].queue_count as usize {
+            priorities.push(priority);
+        } else {
+            warn!(
+                "Queue family {} has no queue left, sharing its last one",
+                family_index
+            );
+        }
+        (family_index, priorities.len() as u32 - 1)
     };
 
-    let physical_device_features = ash::vk::PhysicalDeviceFeatures {
-        ..Default::default() // default just enable no feature.
+    let compute_queue = queue_config
+        .compute_priority
+        .map(|priority| request_queue(indices.compute_family.unwrap_or(graphics_family), priority));
+    let transfer_queue = queue_config.transfer_priority.map(|priority| {
+        request_queue(indices.transfer_family.unwrap_or(graphics_family), priority)
+    });
+
+    // Protected-capable queues still take unprotected submissions, so the graphics family's ones
+    // can all be
+    let queue_flags = |family_index: u32| {
+        if protected && family_index == graphics_family {
+            ash::vk::DeviceQueueCreateFlags::PROTECTED
+        } else {
+            ash::vk::DeviceQueueCreateFlags::empty()
+        }
     };
 
-    let extensions = to_veccstr(&[
-        "VK_KHR_swapchain",
-        "VK_KHR_external_memory",
-        "VK_KHR_external_memory_fd",
-    ]);
+    let queue_create_infos: Vec<_> = family_priorities
+        .iter()
+        .map(
+            |(queue_family_index, priorities)| ash::vk::DeviceQueueCreateInfo {
+                s_type: ash::vk::StructureType::DEVICE_QUEUE_CREATE_INFO,
+                p_next: std::ptr::null(),
+                flags: queue_flags(*queue_family_index),
+                queue_family_index: *queue_family_index,
+                p_queue_priorities: priorities.as_ptr(),
+                queue_count: priorities.len() as u32,
+            },
+        )
+        .collect();
+
+    let requested_extensions = extensions;
+    let extensions = to_veccstr(extensions);
+
+    let mut protected_memory = ash::vk::PhysicalDeviceProtectedMemoryFeatures {
+        protected_memory: ash::vk::TRUE,
+        ..Default::default()
+    };
 
     let device_create_info = ash::vk::DeviceCreateInfo {
         s_type: ash::vk::StructureType::DEVICE_CREATE_INFO,
-        p_next: std::ptr::null(),
+        p_next: if protected {
+            &mut protected_memory as *mut _ as *const std::ffi::c_void
+        } else {
+            std::ptr::null()
+        },
         flags: ash::vk::DeviceCreateFlags::empty(),
-        queue_create_info_count: 1,
-        p_queue_create_infos: &queue_create_info,
+        queue_create_info_count: queue_create_infos.len() as u32,
+        p_queue_create_infos: queue_create_infos.as_ptr(),
         enabled_layer_count: 0,
         pp_enabled_layer_names: std::ptr::null(),
         enabled_extension_count: extensions.ptr.len() as u32,
         pp_enabled_extension_names: extensions.ptr.as_ptr(),
-        p_enabled_features: &physical_device_features,
+        p_enabled_features: features,
     };
 
-    let device: ash::Device = unsafe {
-        instance
-            .create_device(physical_device, &device_create_info, None)
-            .expect("Failed to create logical Device!")
+    let device: ash::Device = match create_device(&device_create_info) {
+        Ok(device) => device,
+        // Checked above, but the driver may disagree, layers may come and go. Telling which
+        // extension is missing beats the bare error code.
+        Err(Error::Vulkan(ash::vk::Result::ERROR_EXTENSION_NOT_PRESENT)) => {
+            let available_extensions = available_vk_device_extensions(instance, physical_device)?;
+            let missing = missing_extensions(requested_extensions, &available_extensions);
+            if missing.is_empty() {
+                return Err(Error::Vulkan(ash::vk::Result::ERROR_EXTENSION_NOT_PRESENT));
+            }
+            return Err(Error::MissingDeviceExtensions(missing));
+        }
+        Err(error) => return Err(error),
     };
 
-    let graphics_queue = unsafe { device.get_device_queue(indices.graphics_family.unwrap(), 0) };
+    // Queues created with flags can only be retrieved along with those flags
+    let get_queue = |(family_index, queue_index)| {
+        let flags = queue_flags(family_index);
+        let queue = if flags.is_empty() {
+            unsafe { device.get_device_queue(family_index, queue_index) }
+        } else {
+            let queue_info = ash::vk::DeviceQueueInfo2 {
+                flags,
+                queue_family_index: family_index,
+                queue_index,
+                ..Default::default()
+            };
+            unsafe { device.get_device_queue2(&queue_info) }
+        };
+        DeviceQueue {
+            family_index,
+            queue,
+        }
+    };
 
-    (device, graphics_queue)
+    Ok(LogicalDevice {
+        graphics_queue: get_queue((graphics_family, 0)),
+        compute_queue: compute_queue.map(get_queue),
+        transfer_queue: transfer_queue.map(get_queue),
+        device,
+    })
 }
 
 struct QueueFamilyIndices {
     graphics_family: Option<u32>,
+    /// Family with compute but without graphics, for async compute
+    compute_family: Option<u32>,
+    /// Family with transfer only, usually backed by a DMA engine
+    transfer_family: Option<u32>,
 }
 
-impl QueueFamilyIndices {
-    pub fn is_complete(&self) -> bool {
-        self.graphics_family.is_some()
-    }
+struct LogicalDevice {
+    device: ash::Device,
+    graphics_queue: DeviceQueue,
+    compute_queue: Option<DeviceQueue>,
+    transfer_queue: Option<DeviceQueue>,
 }
 
 struct XrEntry {
@@ -547,32 +707,411 @@ impl XrEntryFp {
     }
 }
 
+/// Instance function pointers, shared through an `Arc` by the objects calling them, which each keep
+/// it along with their handle.
 struct XrInstanceFp {
     get_vulkan_graphics_requirements_KHR: openxr_sys::pfn::GetVulkanGraphicsRequirementsKHR,
+    get_vulkan_graphics_requirements2_KHR:
+        Option<openxr_sys::pfn::GetVulkanGraphicsRequirements2KHR>,
+    create_vulkan_instance_KHR: Option<openxr_sys::pfn::CreateVulkanInstanceKHR>,
+    get_vulkan_graphics_device2_KHR: Option<openxr_sys::pfn::GetVulkanGraphicsDevice2KHR>,
+    create_vulkan_device_KHR: Option<openxr_sys::pfn::CreateVulkanDeviceKHR>,
     get_vulkan_graphics_device_KHR: openxr_sys::pfn::GetVulkanGraphicsDeviceKHR,
     get_vulkan_instance_extensions_KHR: openxr_sys::pfn::GetVulkanInstanceExtensionsKHR,
     get_vulkan_device_extensions_KHR: openxr_sys::pfn::GetVulkanDeviceExtensionsKHR,
     create_session: openxr_sys::pfn::CreateSession,
+    enumerate_view_configurations: openxr_sys::pfn::EnumerateViewConfigurations,
+    enumerate_view_configuration_views: openxr_sys::pfn::EnumerateViewConfigurationViews,
+    enumerate_swapchain_formats: openxr_sys::pfn::EnumerateSwapchainFormats,
+    enumerate_swapchain_images: openxr_sys::pfn::EnumerateSwapchainImages,
+    create_swapchain: openxr_sys::pfn::CreateSwapchain,
+    destroy_swapchain: openxr_sys::pfn::DestroySwapchain,
+    destroy_session: openxr_sys::pfn::DestroySession,
+    destroy_instance: openxr_sys::pfn::DestroyInstance,
+    create_reference_space: openxr_sys::pfn::CreateReferenceSpace,
+    destroy_space: openxr_sys::pfn::DestroySpace,
+    get_reference_space_bounds_rect: openxr_sys::pfn::GetReferenceSpaceBoundsRect,
+    begin_session: openxr_sys::pfn::BeginSession,
+    end_session: openxr_sys::pfn::EndSession,
+    request_exit_session: openxr_sys::pfn::RequestExitSession,
+    poll_event: openxr_sys::pfn::PollEvent,
+    wait_frame: openxr_sys::pfn::WaitFrame,
+    begin_frame: openxr_sys::pfn::BeginFrame,
+    end_frame: openxr_sys::pfn::EndFrame,
+    locate_views: openxr_sys::pfn::LocateViews,
+    acquire_swapchain_image: openxr_sys::pfn::AcquireSwapchainImage,
+    wait_swapchain_image: openxr_sys::pfn::WaitSwapchainImage,
+    release_swapchain_image: openxr_sys::pfn::ReleaseSwapchainImage,
+    get_visibility_mask_KHR: Option<openxr_sys::pfn::GetVisibilityMaskKHR>,
+    set_android_application_thread_KHR: Option<openxr_sys::pfn::SetAndroidApplicationThreadKHR>,
+    perf_settings_set_performance_level_EXT:
+        Option<openxr_sys::pfn::PerfSettingsSetPerformanceLevelEXT>,
+    create_passthrough_FB: Option<passthrough::CreatePassthroughFB>,
+    destroy_passthrough_FB: Option<passthrough::DestroyPassthroughFB>,
+    create_passthrough_layer_FB: Option<passthrough::CreatePassthroughLayerFB>,
+    destroy_passthrough_layer_FB: Option<passthrough::DestroyPassthroughLayerFB>,
+    enumerate_color_spaces_FB: Option<color_space::EnumerateColorSpacesFB>,
+    set_color_space_FB: Option<color_space::SetColorSpaceFB>,
+    get_controller_model_key_MSFT: Option<openxr_sys::pfn::GetControllerModelKeyMSFT>,
+    load_controller_model_MSFT: Option<openxr_sys::pfn::LoadControllerModelMSFT>,
+    get_controller_model_properties_MSFT: Option<openxr_sys::pfn::GetControllerModelPropertiesMSFT>,
+    get_controller_model_state_MSFT: Option<openxr_sys::pfn::GetControllerModelStateMSFT>,
+    create_spatial_anchor_MSFT: Option<openxr_sys::pfn::CreateSpatialAnchorMSFT>,
+    create_spatial_anchor_space_MSFT: Option<openxr_sys::pfn::CreateSpatialAnchorSpaceMSFT>,
+    destroy_spatial_anchor_MSFT: Option<openxr_sys::pfn::DestroySpatialAnchorMSFT>,
+    create_foveation_profile_FB: Option<foveation::CreateFoveationProfileFB>,
+    destroy_foveation_profile_FB: Option<foveation::DestroyFoveationProfileFB>,
+    update_swapchain_FB: Option<foveation::UpdateSwapchainFB>,
+    #[cfg(not(windows))]
+    convert_timespec_time_to_time_KHR: Option<time::ConvertTimespecTimeToTimeKHR>,
+    #[cfg(not(windows))]
+    convert_time_to_timespec_time_KHR: Option<time::ConvertTimeToTimespecTimeKHR>,
+    #[cfg(windows)]
+    convert_win32_performance_counter_to_time_KHR:
+        Option<time::ConvertWin32PerformanceCounterToTimeKHR>,
+    #[cfg(windows)]
+    convert_time_to_win32_performance_counter_KHR:
+        Option<time::ConvertTimeToWin32PerformanceCounterKHR>,
+    get_system_properties: openxr_sys::pfn::GetSystemProperties,
+    string_to_path: openxr_sys::pfn::StringToPath,
+    create_action_set: openxr_sys::pfn::CreateActionSet,
+    destroy_action_set: openxr_sys::pfn::DestroyActionSet,
+    create_action: openxr_sys::pfn::CreateAction,
+    suggest_interaction_profile_bindings: openxr_sys::pfn::SuggestInteractionProfileBindings,
+    attach_session_action_sets: openxr_sys::pfn::AttachSessionActionSets,
+    get_current_interaction_profile: openxr_sys::pfn::GetCurrentInteractionProfile,
+    sync_actions: openxr_sys::pfn::SyncActions,
+    get_action_state_pose: openxr_sys::pfn::GetActionStatePose,
+    get_action_state_boolean: openxr_sys::pfn::GetActionStateBoolean,
+    get_action_state_float: openxr_sys::pfn::GetActionStateFloat,
+    get_action_state_vector2f: openxr_sys::pfn::GetActionStateVector2f,
+    enumerate_bound_sources_for_action: openxr_sys::pfn::EnumerateBoundSourcesForAction,
+    get_input_source_localized_name: openxr_sys::pfn::GetInputSourceLocalizedName,
+    create_action_space: openxr_sys::pfn::CreateActionSpace,
+    locate_space: openxr_sys::pfn::LocateSpace,
+    enumerate_environment_blend_modes: openxr_sys::pfn::EnumerateEnvironmentBlendModes,
+    /// `Config::max_enumerate_count`, passed to `enumerate` along with these functions
+    max_enumerate_count: u32,
 }
 
 impl XrInstanceFp {
-    fn new(fp: &XrEntryFp, instance: openxr_sys::Instance) -> Self {
-        unsafe {
+    /// Loads the instance functions, failing with the names of the ones the runtime didn't
+    /// provide rather than keeping null function pointers around.
+    fn new(
+        fp: &XrEntryFp,
+        instance: openxr_sys::Instance,
+        extensions: &[CString],
+        max_enumerate_count: u32,
+    ) -> Result<Self, Error> {
+        let missing = RefCell::new(Vec::new());
+
+        let load = |name: &'static str| {
+            unsafe { fp.get_proc_addr(instance, name) }.unwrap_or_else(|| {
+                missing.borrow_mut().push(name);
+                unavailable_function as openxr_sys::pfn::VoidFunction
+            })
+        };
+
+        // Extension functions are only loaded when their extension is enabled
+        let load_ext = |extension: &str, name: &'static str| {
+            if !is_extension_enabled(extensions, extension) {
+                return None;
+            }
+
+            let function = unsafe { fp.get_proc_addr(instance, name) };
+            if function.is_none() {
+                missing.borrow_mut().push(name);
+            }
+            function
+        };
+
+        let instance_fp = unsafe {
             XrInstanceFp {
-                get_vulkan_graphics_requirements_KHR: transmute(
-                    fp.get_proc_addr(instance, "xrGetVulkanGraphicsRequirementsKHR"),
-                ),
-                get_vulkan_graphics_device_KHR: transmute(
-                    fp.get_proc_addr(instance, "xrGetVulkanGraphicsDeviceKHR"),
-                ),
-                get_vulkan_instance_extensions_KHR: transmute(
-                    fp.get_proc_addr(instance, "xrGetVulkanInstanceExtensionsKHR"),
-                ),
-                get_vulkan_device_extensions_KHR: transmute(
-                    fp.get_proc_addr(instance, "xrGetVulkanDeviceExtensionsKHR"),
-                ),
-                create_session: transmute(fp.get_proc_addr(instance, "xrCreateSession")),
+                get_vulkan_graphics_requirements_KHR: transmute(load(
+                    "xrGetVulkanGraphicsRequirementsKHR",
+                )),
+                get_vulkan_graphics_device_KHR: transmute(load("xrGetVulkanGraphicsDeviceKHR")),
+                get_vulkan_instance_extensions_KHR: transmute(load(
+                    "xrGetVulkanInstanceExtensionsKHR",
+                )),
+                get_vulkan_device_extensions_KHR: transmute(load("xrGetVulkanDeviceExtensionsKHR")),
+                create_session: transmute(load("xrCreateSession")),
+                enumerate_view_configurations: transmute(load("xrEnumerateViewConfigurations")),
+                enumerate_view_configuration_views: transmute(load(
+                    "xrEnumerateViewConfigurationViews",
+                )),
+                enumerate_swapchain_formats: transmute(load("xrEnumerateSwapchainFormats")),
+                enumerate_swapchain_images: transmute(load("xrEnumerateSwapchainImages")),
+                create_swapchain: transmute(load("xrCreateSwapchain")),
+                destroy_swapchain: transmute(load("xrDestroySwapchain")),
+                destroy_session: transmute(load("xrDestroySession")),
+                destroy_instance: transmute(load("xrDestroyInstance")),
+                create_reference_space: transmute(load("xrCreateReferenceSpace")),
+                destroy_space: transmute(load("xrDestroySpace")),
+                get_reference_space_bounds_rect: transmute(load("xrGetReferenceSpaceBoundsRect")),
+                begin_session: transmute(load("xrBeginSession")),
+                end_session: transmute(load("xrEndSession")),
+                request_exit_session: transmute(load("xrRequestExitSession")),
+                poll_event: transmute(load("xrPollEvent")),
+                wait_frame: transmute(load("xrWaitFrame")),
+                begin_frame: transmute(load("xrBeginFrame")),
+                end_frame: transmute(load("xrEndFrame")),
+                locate_views: transmute(load("xrLocateViews")),
+                acquire_swapchain_image: transmute(load("xrAcquireSwapchainImage")),
+                wait_swapchain_image: transmute(load("xrWaitSwapchainImage")),
+                release_swapchain_image: transmute(load("xrReleaseSwapchainImage")),
+                get_vulkan_graphics_requirements2_KHR: transmute(load_ext(
+                    "XR_KHR_vulkan_enable2",
+                    "xrGetVulkanGraphicsRequirements2KHR",
+                )),
+                create_vulkan_instance_KHR: transmute(load_ext(
+                    "XR_KHR_vulkan_enable2",
+                    "xrCreateVulkanInstanceKHR",
+                )),
+                get_vulkan_graphics_device2_KHR: transmute(load_ext(
+                    "XR_KHR_vulkan_enable2",
+                    "xrGetVulkanGraphicsDevice2KHR",
+                )),
+                create_vulkan_device_KHR: transmute(load_ext(
+                    "XR_KHR_vulkan_enable2",
+                    "xrCreateVulkanDeviceKHR",
+                )),
+                set_android_application_thread_KHR: transmute(load_ext(
+                    "XR_KHR_android_thread_settings",
+                    "xrSetAndroidApplicationThreadKHR",
+                )),
+                perf_settings_set_performance_level_EXT: transmute(load_ext(
+                    "XR_EXT_performance_settings",
+                    "xrPerfSettingsSetPerformanceLevelEXT",
+                )),
+                create_passthrough_FB: transmute(load_ext(
+                    passthrough::EXTENSION,
+                    "xrCreatePassthroughFB",
+                )),
+                destroy_passthrough_FB: transmute(load_ext(
+                    passthrough::EXTENSION,
+                    "xrDestroyPassthroughFB",
+                )),
+                create_passthrough_layer_FB: transmute(load_ext(
+                    passthrough::EXTENSION,
+                    "xrCreatePassthroughLayerFB",
+                )),
+                destroy_passthrough_layer_FB: transmute(load_ext(
+                    passthrough::EXTENSION,
+                    "xrDestroyPassthroughLayerFB",
+                )),
+                enumerate_color_spaces_FB: transmute(load_ext(
+                    color_space::EXTENSION,
+                    "xrEnumerateColorSpacesFB",
+                )),
+                set_color_space_FB: transmute(load_ext(
+                    color_space::EXTENSION,
+                    "xrSetColorSpaceFB",
+                )),
+                get_controller_model_key_MSFT: transmute(load_ext(
+                    controller_model::EXTENSION,
+                    "xrGetControllerModelKeyMSFT",
+                )),
+                load_controller_model_MSFT: transmute(load_ext(
+                    controller_model::EXTENSION,
+                    "xrLoadControllerModelMSFT",
+                )),
+                get_controller_model_properties_MSFT: transmute(load_ext(
+                    controller_model::EXTENSION,
+                    "xrGetControllerModelPropertiesMSFT",
+                )),
+                get_controller_model_state_MSFT: transmute(load_ext(
+                    controller_model::EXTENSION,
+                    "xrGetControllerModelStateMSFT",
+                )),
+                create_spatial_anchor_MSFT: transmute(load_ext(
+                    spatial_anchor::EXTENSION,
+                    "xrCreateSpatialAnchorMSFT",
+                )),
+                create_spatial_anchor_space_MSFT: transmute(load_ext(
+                    spatial_anchor::EXTENSION,
+                    "xrCreateSpatialAnchorSpaceMSFT",
+                )),
+                destroy_spatial_anchor_MSFT: transmute(load_ext(
+                    spatial_anchor::EXTENSION,
+                    "xrDestroySpatialAnchorMSFT",
+                )),
+                create_foveation_profile_FB: transmute(load_ext(
+                    foveation::EXTENSION,
+                    "xrCreateFoveationProfileFB",
+                )),
+                destroy_foveation_profile_FB: transmute(load_ext(
+                    foveation::EXTENSION,
+                    "xrDestroyFoveationProfileFB",
+                )),
+                update_swapchain_FB: transmute(load_ext(
+                    foveation::EXTENSIONS[2],
+                    "xrUpdateSwapchainFB",
+                )),
+                #[cfg(not(windows))]
+                convert_timespec_time_to_time_KHR: transmute(load_ext(
+                    time::TIMESPEC_EXTENSION,
+                    "xrConvertTimespecTimeToTimeKHR",
+                )),
+                #[cfg(not(windows))]
+                convert_time_to_timespec_time_KHR: transmute(load_ext(
+                    time::TIMESPEC_EXTENSION,
+                    "xrConvertTimeToTimespecTimeKHR",
+                )),
+                #[cfg(windows)]
+                convert_win32_performance_counter_to_time_KHR: transmute(load_ext(
+                    time::PERFORMANCE_COUNTER_EXTENSION,
+                    "xrConvertWin32PerformanceCounterToTimeKHR",
+                )),
+                #[cfg(windows)]
+                convert_time_to_win32_performance_counter_KHR: transmute(load_ext(
+                    time::PERFORMANCE_COUNTER_EXTENSION,
+                    "xrConvertTimeToWin32PerformanceCounterKHR",
+                )),
+                get_visibility_mask_KHR: transmute(load_ext(
+                    "XR_KHR_visibility_mask",
+                    "xrGetVisibilityMaskKHR",
+                )),
+                get_system_properties: transmute(load("xrGetSystemProperties")),
+                string_to_path: transmute(load("xrStringToPath")),
+                create_action_set: transmute(load("xrCreateActionSet")),
+                destroy_action_set: transmute(load("xrDestroyActionSet")),
+                create_action: transmute(load("xrCreateAction")),
+                suggest_interaction_profile_bindings: transmute(load(
+                    "xrSuggestInteractionProfileBindings",
+                )),
+                attach_session_action_sets: transmute(load("xrAttachSessionActionSets")),
+                get_current_interaction_profile: transmute(load("xrGetCurrentInteractionProfile")),
+                sync_actions: transmute(load("xrSyncActions")),
+                get_action_state_pose: transmute(load("xrGetActionStatePose")),
+                get_action_state_boolean: transmute(load("xrGetActionStateBoolean")),
+                get_action_state_float: transmute(load("xrGetActionStateFloat")),
+                get_action_state_vector2f: transmute(load("xrGetActionStateVector2f")),
+                enumerate_bound_sources_for_action: transmute(load(
+                    "xrEnumerateBoundSourcesForAction",
+                )),
+                get_input_source_localized_name: transmute(load("xrGetInputSourceLocalizedName")),
+                create_action_space: transmute(load("xrCreateActionSpace")),
+                locate_space: transmute(load("xrLocateSpace")),
+                enumerate_environment_blend_modes: transmute(load(
+                    "xrEnumerateEnvironmentBlendModes",
+                )),
+                max_enumerate_count,
             }
+        };
+
+        let missing = missing.into_inner();
+        if !missing.is_empty() {
+            return Err(Error::MissingFunctions(missing));
         }
+
+        info!("Loaded the OpenXR instance functions");
+        Ok(instance_fp)
+    }
+}
+
+/// Stands in for the functions that failed to load, `XrInstanceFp::new` failing in that case.
+unsafe extern "system" fn unavailable_function() {}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use super::*;
+
+    const MAX: u32 = config::DEFAULT_MAX_ENUMERATE_COUNT;
+
+    /// Enumeration closure of a runtime holding `items`, which reports `counts` in turn as the
+    /// number of items, the last one being the actual count, before filling the buffer.
+    /// Returns the number of calls made to the closure along with it.
+    fn growing_list(
+        items: Vec<u32>,
+        mut counts: Vec<u32>,
+    ) -> (
+        Rc<Cell<u32>>,
+        impl FnMut(u32, *mut u32, *mut u32) -> XrResult,
+    ) {
+        let calls = Rc::new(Cell::new(0));
+        let calls_made = Rc::clone(&calls);
+        counts.reverse();
+        let f = move |capacity: u32, count: *mut u32, buffer: *mut u32| {
+            calls_made.set(calls_made.get() + 1);
+            let current = if counts.len() > 1 {
+                counts.pop().unwrap()
+            } else {
+                counts[0]
+            };
+            unsafe { *count = current };
+            if capacity == 0 {
+                return XrResult::SUCCESS;
+            }
+            if capacity < current {
+                return XrResult::ERROR_SIZE_INSUFFICIENT;
+            }
+            for (index, &item) in items.iter().take(current as usize).enumerate() {
+                unsafe { *buffer.add(index) = item };
+            }
+            XrResult::SUCCESS
+        };
+        (calls, f)
+    }
+
+    #[test]
+    fn enumerate_retries_when_the_count_grows() {
+        let (calls, f) = growing_list(vec![1, 2, 3], vec![2, 3]);
+        assert_eq!(enumerate(MAX, 0, f).unwrap(), vec![1, 2, 3]);
+        // The count, a buffer too small, then the right one
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[test]
+    fn enumerate_gives_up_when_the_count_keeps_growing() {
+        let counts: Vec<u32> = (1..=ENUMERATE_ATTEMPTS + 2).collect();
+        let items = counts.clone();
+        let (calls, f) = growing_list(items, counts);
+        assert!(matches!(
+            enumerate(MAX, 0, f),
+            Err(Error::Xr(XrResult::ERROR_SIZE_INSUFFICIENT))
+        ));
+        assert_eq!(calls.get(), 1 + ENUMERATE_ATTEMPTS);
+    }
+
+    #[test]
+    fn enumerate_rejects_huge_counts() {
+        let (calls, f) = growing_list(Vec::new(), vec![MAX + 1]);
+        assert!(matches!(
+            enumerate(MAX, 0, f),
+            Err(Error::EnumerationTooLarge(count, max)) if count == MAX + 1 && max == MAX
+        ));
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn enumerate_applies_the_given_cap() {
+        // The cap is the one passed in, nothing being shared between calls
+        let (_, f) = growing_list(vec![1, 2, 3], vec![3]);
+        assert!(matches!(
+            enumerate(2, 0, f),
+            Err(Error::EnumerationTooLarge(3, 2))
+        ));
+        let (_, f) = growing_list(vec![1, 2, 3], vec![3]);
+        assert_eq!(enumerate(3, 0, f).unwrap(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn write_name_checks_the_name() {
+        let mut buffer = [1; 4];
+        write_name(&mut buffer, "abc").unwrap();
+        assert_eq!(buffer, [b'a' as _, b'b' as _, b'c' as _, 0]);
+
+        // No room left for the terminator
+        assert!(matches!(
+            write_name(&mut buffer, "abcd"),
+            Err(Error::InvalidName(name)) if name == "abcd"
+        ));
+        assert!(matches!(
+            write_name(&mut buffer, "a\0b"),
+            Err(Error::InvalidName(_))
+        ));
     }
 }
@@ -0,0 +1,151 @@
+//! Small matrix helpers, matrices being column-major `[f32; 16]` as expected by shaders.
+
+use openxr_sys::{Fovf, Posef};
+
+pub(crate) type Mat4 = [f32; 16];
+
+/// Projection matching `fov` for Vulkan clip space: Y pointing down and depth going from 0 at
+/// `near` to 1 at `far`, or from 1 to 0 with `reverse_z`. `far` may be infinite.
+pub(crate) fn projection(fov: &Fovf, near: f32, far: f32, reverse_z: bool) -> Mat4 {
+    let tan_left = fov.angle_left.tan();
+    let tan_right = fov.angle_right.tan();
+    let tan_down = fov.angle_down.tan();
+    let tan_up = fov.angle_up.tan();
+
+    let tan_width = tan_right - tan_left;
+    // Flipped compared to OpenGL since Vulkan's Y axis points down
+    let tan_height = tan_down - tan_up;
+
+    let mut matrix = [0.0; 16];
+    matrix[0] = 2.0 / tan_width;
+    matrix[5] = 2.0 / tan_height;
+    matrix[8] = (tan_right + tan_left) / tan_width;
+    matrix[9] = (tan_up + tan_down) / tan_height;
+    matrix[11] = -1.0;
+    // The infinite cases are the limits of the finite ones, which would give NaN
+    let (depth_scale, depth_offset) = match (reverse_z, far.is_infinite()) {
+        (false, false) => (-far / (far - near), -(far * near) / (far - near)),
+        (false, true) => (-1.0, -near),
+        (true, false) => (near / (far - near), (far * near) / (far - near)),
+        (true, true) => (0.0, near),
+    };
+    matrix[10] = depth_scale;
+    matrix[14] = depth_offset;
+    matrix
+}
+
+/// Transform from the space `pose` is expressed in to the space it describes, which for a view
+/// pose is the view matrix.
+pub(crate) fn inverse_pose(pose: &Posef) -> Mat4 {
+    let openxr_sys::Quaternionf { x, y, z, w } = pose.orientation;
+
+    // Rotation of the pose, row by row
+    let rotation = [
+        [
+            1.0 - 2.0 * (y * y + z * z),
+            2.0 * (x * y - w * z),
+            2.0 * (x * z + w * y),
+        ],
+        [
+            2.0 * (x * y + w * z),
+            1.0 - 2.0 * (x * x + z * z),
+            2.0 * (y * z - w * x),
+        ],
+        [
+            2.0 * (x * z - w * y),
+            2.0 * (y * z + w * x),
+            1.0 - 2.0 * (x * x + y * y),
+        ],
+    ];
+    let position = [pose.position.x, pose.position.y, pose.position.z];
+
+    // The inverse rotation is the transpose, and the translation gets rotated back
+    let mut matrix = [0.0; 16];
+    for row in 0..3 {
+        for column in 0..3 {
+            matrix[column * 4 + row] = rotation[column][row];
+        }
+        matrix[12 + row] = -(0..3).map(|i| rotation[i][row] * position[i]).sum::<f32>();
+    }
+    matrix[15] = 1.0;
+    matrix
+}
+
+pub(crate) fn multiply(a: &Mat4, b: &Mat4) -> Mat4 {
+    let mut matrix = [0.0; 16];
+    for column in 0..4 {
+        for row in 0..4 {
+            matrix[column * 4 + row] = (0..4).map(|i| a[i * 4 + row] * b[column * 4 + i]).sum();
+        }
+    }
+    matrix
+}
+
+/// Splits `matrix` into its columns, the layout `glam::Mat4::from_cols_array_2d` and
+/// `nalgebra::Matrix4::from` expect.
+pub(crate) fn to_columns(matrix: &Mat4) -> [[f32; 4]; 4] {
+    let mut columns = [[0.0; 4]; 4];
+    for (column, values) in columns.iter_mut().enumerate() {
+        values.copy_from_slice(&matrix[column * 4..column * 4 + 4]);
+    }
+    columns
+}
+
+/// `b`, expressed relative to `a`, expressed in the space `a` is.
+pub(crate) fn compose_poses(a: &Posef, b: &Posef) -> Posef {
+    let (qa, qb) = (a.orientation, b.orientation);
+    let orientation = openxr_sys::Quaternionf {
+        x: qa.w * qb.x + qa.x * qb.w + qa.y * qb.z - qa.z * qb.y,
+        y: qa.w * qb.y - qa.x * qb.z + qa.y * qb.w + qa.z * qb.x,
+        z: qa.w * qb.z + qa.x * qb.y - qa.y * qb.x + qa.z * qb.w,
+        w: qa.w * qb.w - qa.x * qb.x - qa.y * qb.y - qa.z * qb.z,
+    };
+
+    // The rotation matrix of `a` applied to the position of `b`, as `inverse_pose` builds it
+    let openxr_sys::Quaternionf { x, y, z, w } = qa;
+    let p = b.position;
+    let position = openxr_sys::Vector3f {
+        x: a.position.x
+            + (1.0 - 2.0 * (y * y + z * z)) * p.x
+            + 2.0 * (x * y - w * z) * p.y
+            + 2.0 * (x * z + w * y) * p.z,
+        y: a.position.y
+            + 2.0 * (x * y + w * z) * p.x
+            + (1.0 - 2.0 * (x * x + z * z)) * p.y
+            + 2.0 * (y * z - w * x) * p.z,
+        z: a.position.z
+            + 2.0 * (x * z - w * y) * p.x
+            + 2.0 * (y * z + w * x) * p.y
+            + (1.0 - 2.0 * (x * x + y * y)) * p.z,
+    };
+
+    Posef {
+        orientation,
+        position,
+    }
+}
+
+/// The rotation of `orientation` around the Y axis, without its pitch and roll, so that a space
+/// oriented with it stays level.
+pub(crate) fn yaw_only(orientation: openxr_sys::Quaternionf) -> openxr_sys::Quaternionf {
+    let openxr_sys::Quaternionf { x, y, z, w } = orientation;
+    let yaw = (2.0 * (w * y + x * z)).atan2(1.0 - 2.0 * (x * x + y * y));
+    let half = yaw / 2.0;
+    openxr_sys::Quaternionf {
+        x: 0.0,
+        y: half.sin(),
+        z: 0.0,
+        w: half.cos(),
+    }
+}
+
+/// Combined view and projection matrix of a view located with `pose` and `fov`.
+pub(crate) fn view_projection(
+    pose: &Posef,
+    fov: &Fovf,
+    near: f32,
+    far: f32,
+    reverse_z: bool,
+) -> Mat4 {
+    multiply(&projection(fov, near, far, reverse_z), &inverse_pose(pose))
+}
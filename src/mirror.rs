@@ -0,0 +1,460 @@
+use std::ffi::CString;
+
+use ash::{
+    extensions::khr,
+    version::{DeviceV1_0, InstanceV1_0},
+    vk,
+};
+use log::{info, warn};
+use winit::{
+    dpi::LogicalSize,
+    event::{Event, WindowEvent},
+    event_loop::{ControlFlow, EventLoop},
+    platform::run_return::EventLoopExtRunReturn,
+    window::{Window, WindowBuilder},
+};
+
+use crate::{error::Error, swapchain::Swapchain};
+
+/// Instance extensions a surface may need, depending on the platform and windowing system. The
+/// available ones all get enabled, since which one the window ends up using isn't known when
+/// the instance is created.
+pub(crate) const SURFACE_EXTENSIONS: [&str; 6] = [
+    "VK_KHR_surface",
+    "VK_KHR_win32_surface",
+    "VK_KHR_xlib_surface",
+    "VK_KHR_xcb_surface",
+    "VK_KHR_wayland_surface",
+    "VK_EXT_metal_surface",
+];
+
+/// Adds the available `SURFACE_EXTENSIONS` to the instance `extensions`.
+pub(crate) fn with_surface_extensions(
+    mut extensions: Vec<CString>,
+    available: &[String],
+) -> Vec<CString> {
+    for &name in &SURFACE_EXTENSIONS {
+        let is_available = available.iter().any(|ext| ext == name);
+        let name = CString::new(name).unwrap();
+        if is_available && !extensions.contains(&name) {
+            extensions.push(name);
+        }
+    }
+    extensions
+}
+
+/// Creates the event loop of the mirror window on the calling thread, which doesn't have to be
+/// the main one where the platform allows it.
+pub(crate) fn create_event_loop() -> EventLoop<()> {
+    #[cfg(target_os = "linux")]
+    return <EventLoop<()> as winit::platform::unix::EventLoopExtUnix>::new_any_thread();
+    #[cfg(target_os = "windows")]
+    return <EventLoop<()> as winit::platform::windows::EventLoopExtWindows>::new_any_thread();
+    #[cfg(not(any(target_os = "linux", target_os = "windows")))]
+    return EventLoop::new();
+}
+
+pub(crate) fn create_window(event_loop: &EventLoop<()>) -> Window {
+    WindowBuilder::new()
+        .with_title("XR mirror")
+        .with_inner_size(LogicalSize::new(960, 540))
+        .build(event_loop)
+        .expect("Failed to create the mirror window")
+}
+
+/// Handles the pending window events without blocking. Returns whether the window was asked to
+/// close.
+pub(crate) fn pump_events(event_loop: &mut EventLoop<()>) -> bool {
+    let mut close_requested = false;
+    event_loop.run_return(|event, _, control_flow| {
+        *control_flow = ControlFlow::Poll;
+        match event {
+            Event::WindowEvent {
+                event: WindowEvent::CloseRequested,
+                ..
+            } => close_requested = true,
+            Event::MainEventsCleared => *control_flow = ControlFlow::Exit,
+            _ => {}
+        }
+    });
+    close_requested
+}
+
+/// Desktop window showing what one eye sees, for spectators. The eye image is blitted to the
+/// window's swapchain, stretched to its size, every frame.
+pub(crate) struct MirrorWindow {
+    window: Window,
+    surface_loader: khr::Surface,
+    surface: vk::SurfaceKHR,
+    swapchain_loader: khr::Swapchain,
+    swapchain: vk::SwapchainKHR,
+    images: Vec<vk::Image>,
+    extent: vk::Extent2D,
+    command_pool: vk::CommandPool,
+    command_buffer: vk::CommandBuffer,
+    image_available: vk::Semaphore,
+    blit_done: vk::Semaphore,
+    fence: vk::Fence,
+}
+
+impl MirrorWindow {
+    /// Fails with `Error::PresentationUnsupported` if the graphics queue family can't present to
+    /// the window.
+    pub(crate) fn new(
+        entry: &ash::Entry,
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        device: &ash::Device,
+        queue_family_index: u32,
+        window: Window,
+    ) -> Result<Self, Error> {
+        let surface_loader = khr::Surface::new(entry, instance);
+        let surface = unsafe { ash_window::create_surface(entry, instance, &window, None)? };
+
+        let supported = unsafe {
+            surface_loader.get_physical_device_surface_support(
+                physical_device,
+                queue_family_index,
+                surface,
+            )?
+        };
+        if !supported {
+            unsafe { surface_loader.destroy_surface(surface, None) };
+            return Err(Error::PresentationUnsupported);
+        }
+
+        let command_pool_info = vk::CommandPoolCreateInfo {
+            flags: vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
+            queue_family_index,
+            ..Default::default()
+        };
+        let command_pool = unsafe { device.create_command_pool(&command_pool_info, None)? };
+
+        let command_buffer_info = vk::CommandBufferAllocateInfo {
+            command_pool,
+            level: vk::CommandBufferLevel::PRIMARY,
+            command_buffer_count: 1,
+            ..Default::default()
+        };
+        let command_buffer = unsafe { device.allocate_command_buffers(&command_buffer_info)? }[0];
+
+        let semaphore_info = vk::SemaphoreCreateInfo::default();
+        let image_available = unsafe { device.create_semaphore(&semaphore_info, None)? };
+        let blit_done = unsafe { device.create_semaphore(&semaphore_info, None)? };
+        let fence = unsafe { device.create_fence(&vk::FenceCreateInfo::default(), None)? };
+
+        let mut mirror = MirrorWindow {
+            window,
+            surface_loader,
+            surface,
+            swapchain_loader: khr::Swapchain::new(instance, device),
+            swapchain: vk::SwapchainKHR::null(),
+            images: Vec::new(),
+            extent: vk::Extent2D::default(),
+            command_pool,
+            command_buffer,
+            image_available,
+            blit_done,
+            fence,
+        };
+        mirror.create_swapchain(physical_device)?;
+
+        Ok(mirror)
+    }
+
+    /// Creates the window swapchain, replacing the current one if any.
+    fn create_swapchain(&mut self, physical_device: vk::PhysicalDevice) -> Result<(), Error> {
+        let (capabilities, formats) = unsafe {
+            (
+                self.surface_loader
+                    .get_physical_device_surface_capabilities(physical_device, self.surface)?,
+                self.surface_loader
+                    .get_physical_device_surface_formats(physical_device, self.surface)?,
+            )
+        };
+
+        // The eye images are sRGB, blitting to an sRGB target keeps the colors as they are
+        let format = formats
+            .iter()
+            .copied()
+            .find(|format| format.format == vk::Format::B8G8R8A8_SRGB)
+            .or_else(|| formats.first().copied())
+            .ok_or(Error::NoSurfaceFormats)?;
+
+        // A current extent of u32::MAX means the swapchain decides the window size
+        let extent = if capabilities.current_extent.width != u32::MAX {
+            capabilities.current_extent
+        } else {
+            let size = self.window.inner_size();
+            vk::Extent2D {
+                width: size.width,
+                height: size.height,
+            }
+        };
+
+        let image_count = match capabilities.max_image_count {
+            0 => capabilities.min_image_count + 1,
+            max => (capabilities.min_image_count + 1).min(max),
+        };
+
+        let old_swapchain = self.swapchain;
+        let create_info = vk::SwapchainCreateInfoKHR {
+            surface: self.surface,
+            min_image_count: image_count,
+            image_format: format.format,
+            image_color_space: format.color_space,
+            image_extent: extent,
+            image_array_layers: 1,
+            image_usage: vk::ImageUsageFlags::TRANSFER_DST,
+            image_sharing_mode: vk::SharingMode::EXCLUSIVE,
+            pre_transform: capabilities.current_transform,
+            composite_alpha: vk::CompositeAlphaFlagsKHR::OPAQUE,
+            present_mode: vk::PresentModeKHR::FIFO,
+            clipped: vk::TRUE,
+            old_swapchain,
+            ..Default::default()
+        };
+
+        info!("vkCreateSwapchainKHR({}x{})", extent.width, extent.height);
+        self.swapchain = unsafe { self.swapchain_loader.create_swapchain(&create_info, None)? };
+        self.images = unsafe { self.swapchain_loader.get_swapchain_images(self.swapchain)? };
+        self.extent = extent;
+
+        if old_swapchain != vk::SwapchainKHR::null() {
+            unsafe { self.swapchain_loader.destroy_swapchain(old_swapchain, None) };
+        }
+
+        Ok(())
+    }
+
+    /// Copies `sub_image` of the image at `image_index` of `source` to the window and presents it,
+    /// waiting for the copy to be done. The image must be acquired, single sampled, and in the
+    /// color attachment layout, which it's left in.
+    pub(crate) fn blit(
+        &mut self,
+        physical_device: vk::PhysicalDevice,
+        device: &ash::Device,
+        queue: vk::Queue,
+        source: &Swapchain,
+        sub_image: &openxr_sys::SwapchainSubImage,
+        image_index: u32,
+    ) -> Result<(), Error> {
+        let size = self.window.inner_size();
+        if size.width == 0 || size.height == 0 {
+            // Minimized, there's nothing to present to
+            return Ok(());
+        }
+
+        let acquired = unsafe {
+            self.swapchain_loader.acquire_next_image(
+                self.swapchain,
+                u64::MAX,
+                self.image_available,
+                vk::Fence::null(),
+            )
+        };
+        // A suboptimal image is still acquired, with `image_available` to be signaled, so it's
+        // presented before the swapchain gets recreated
+        let (target_index, suboptimal) = match acquired {
+            Ok(acquired) => acquired,
+            // The window was resized, this frame is skipped
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                unsafe { device.device_wait_idle()? };
+                return self.create_swapchain(physical_device);
+            }
+            Err(result) => return Err(Error::Vulkan(result)),
+        };
+
+        let source_image = source.images[image_index as usize];
+        let target_image = self.images[target_index as usize];
+        let color_range = |base_array_layer| vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer,
+            layer_count: 1,
+        };
+        let barrier = |image, layer, old_layout, new_layout, src_access_mask, dst_access_mask| {
+            vk::ImageMemoryBarrier {
+                src_access_mask,
+                dst_access_mask,
+                old_layout,
+                new_layout,
+                src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                image,
+                subresource_range: color_range(layer),
+                ..Default::default()
+            }
+        };
+
+        let layers = |base_array_layer| vk::ImageSubresourceLayers {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_level: 0,
+            base_array_layer,
+            layer_count: 1,
+        };
+        let corner = |width: u32, height: u32| vk::Offset3D {
+            x: width as i32,
+            y: height as i32,
+            z: 1,
+        };
+        let rect = sub_image.image_rect;
+        let region = vk::ImageBlit {
+            src_subresource: layers(sub_image.image_array_index),
+            src_offsets: [
+                vk::Offset3D {
+                    x: rect.offset.x,
+                    y: rect.offset.y,
+                    z: 0,
+                },
+                vk::Offset3D {
+                    x: rect.offset.x + rect.extent.width,
+                    y: rect.offset.y + rect.extent.height,
+                    z: 1,
+                },
+            ],
+            dst_subresource: layers(0),
+            dst_offsets: [
+                vk::Offset3D::default(),
+                corner(self.extent.width, self.extent.height),
+            ],
+        };
+
+        let begin_info = vk::CommandBufferBeginInfo {
+            flags: vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+            ..Default::default()
+        };
+
+        unsafe {
+            let command_buffer = self.command_buffer;
+            device.begin_command_buffer(command_buffer, &begin_info)?;
+
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[
+                    barrier(
+                        source_image,
+                        sub_image.image_array_index,
+                        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                        vk::AccessFlags::TRANSFER_READ,
+                    ),
+                    barrier(
+                        target_image,
+                        0,
+                        vk::ImageLayout::UNDEFINED,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        vk::AccessFlags::empty(),
+                        vk::AccessFlags::TRANSFER_WRITE,
+                    ),
+                ],
+            );
+            device.cmd_blit_image(
+                command_buffer,
+                source_image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                target_image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[region],
+                vk::Filter::LINEAR,
+            );
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                    | vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[
+                    barrier(
+                        source_image,
+                        sub_image.image_array_index,
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                        vk::AccessFlags::TRANSFER_READ,
+                        vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                    ),
+                    barrier(
+                        target_image,
+                        0,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        vk::ImageLayout::PRESENT_SRC_KHR,
+                        vk::AccessFlags::TRANSFER_WRITE,
+                        vk::AccessFlags::empty(),
+                    ),
+                ],
+            );
+
+            device.end_command_buffer(command_buffer)?;
+
+            let wait_stage = vk::PipelineStageFlags::TRANSFER;
+            let submit_info = vk::SubmitInfo {
+                wait_semaphore_count: 1,
+                p_wait_semaphores: &self.image_available,
+                p_wait_dst_stage_mask: &wait_stage,
+                command_buffer_count: 1,
+                p_command_buffers: &command_buffer,
+                signal_semaphore_count: 1,
+                p_signal_semaphores: &self.blit_done,
+                ..Default::default()
+            };
+            device.queue_submit(queue, &[submit_info], self.fence)?;
+
+            let present_info = vk::PresentInfoKHR {
+                wait_semaphore_count: 1,
+                p_wait_semaphores: &self.blit_done,
+                swapchain_count: 1,
+                p_swapchains: &self.swapchain,
+                p_image_indices: &target_index,
+                ..Default::default()
+            };
+            let presented = self.swapchain_loader.queue_present(queue, &present_info);
+
+            // The XR image is released right after, so the copy has to be over by then
+            device.wait_for_fences(&[self.fence], true, u64::MAX)?;
+            device.reset_fences(&[self.fence])?;
+
+            match presented {
+                Ok(false) if !suboptimal => Ok(()),
+                Ok(_) | Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                    device.device_wait_idle()?;
+                    self.create_swapchain(physical_device)
+                }
+                Err(result) => Err(Error::Vulkan(result)),
+            }
+        }
+    }
+
+    /// The device must be idle, or at least done with the mirror.
+    pub(crate) fn destroy(self, device: &ash::Device) {
+        unsafe {
+            device.destroy_fence(self.fence, None);
+            device.destroy_semaphore(self.blit_done, None);
+            device.destroy_semaphore(self.image_available, None);
+            device.destroy_command_pool(self.command_pool, None);
+            self.swapchain_loader
+                .destroy_swapchain(self.swapchain, None);
+            self.surface_loader.destroy_surface(self.surface, None);
+        }
+    }
+}
+
+/// Whether eye images of `swapchain` can be blitted to the mirror window, warning if not.
+pub(crate) fn can_mirror(swapchain: &Swapchain) -> bool {
+    if swapchain.sample_count != 1 {
+        warn!(
+            "The eye swapchains have {} samples, they can't be mirrored",
+            swapchain.sample_count
+        );
+        return false;
+    }
+    true
+}
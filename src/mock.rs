@@ -0,0 +1,102 @@
+//! Stand-in for the OpenXR loader in the unit tests: the runtime only has the functions a test
+//! provides, the other ones doing nothing.
+
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString},
+    mem::transmute,
+    os::raw::c_char,
+    sync::Arc,
+};
+
+use openxr_sys::{
+    pfn::VoidFunction, EnvironmentBlendMode, Result as XrResult, ViewConfigurationType,
+};
+
+use crate::{session::Session, unavailable_function, XrEntryFp, XrInstanceFp};
+
+thread_local! {
+    /// Functions handed out by `get_instance_proc_addr`, by name. Each test runs on its own
+    /// thread, so they don't see each other's.
+    static FUNCTIONS: RefCell<Vec<(&'static str, VoidFunction)>> = RefCell::new(Vec::new());
+}
+
+/// `function`, a mock cast to its `openxr_sys::pfn` type, as loaded by `XrInstanceFp::new`.
+pub(crate) fn function<F: Copy>(function: F) -> VoidFunction {
+    assert_eq!(
+        std::mem::size_of::<F>(),
+        std::mem::size_of::<VoidFunction>(),
+        "Not a function pointer"
+    );
+    unsafe { std::mem::transmute_copy(&function) }
+}
+
+/// Loader functions of a runtime made of `functions`.
+pub(crate) fn entry_fp(functions: &[(&'static str, VoidFunction)]) -> XrEntryFp {
+    FUNCTIONS.with(|table| *table.borrow_mut() = functions.to_vec());
+
+    let load = |name| find(name).unwrap_or(unavailable_function);
+    unsafe {
+        XrEntryFp {
+            get_instance_proc_addr,
+            create_instance: transmute(load("xrCreateInstance")),
+            enumerate_instance_extension_properties: transmute(load(
+                "xrEnumerateInstanceExtensionProperties",
+            )),
+            enumerate_api_layer_properties: transmute(load("xrEnumerateApiLayerProperties")),
+        }
+    }
+}
+
+/// Instance functions loaded from a runtime made of `functions`, with `extensions` enabled.
+pub(crate) fn instance_fp(
+    functions: &[(&'static str, VoidFunction)],
+    extensions: &[&str],
+) -> Arc<XrInstanceFp> {
+    let entry = entry_fp(functions);
+    let extensions: Vec<_> = extensions
+        .iter()
+        .map(|&name| CString::new(name).unwrap())
+        .collect();
+
+    Arc::new(
+        XrInstanceFp::new(
+            &entry,
+            openxr_sys::Instance::NULL,
+            &extensions,
+            crate::config::DEFAULT_MAX_ENUMERATE_COUNT,
+        )
+        .unwrap(),
+    )
+}
+
+/// Session of a stereo system, calling into `fp`.
+pub(crate) fn session(fp: Arc<XrInstanceFp>) -> Session {
+    Session::new(
+        openxr_sys::Session::from_raw(1),
+        openxr_sys::Instance::from_raw(1),
+        fp,
+        ViewConfigurationType::PRIMARY_STEREO,
+        vec![EnvironmentBlendMode::OPAQUE],
+    )
+}
+
+unsafe extern "system" fn get_instance_proc_addr(
+    _instance: openxr_sys::Instance,
+    name: *const c_char,
+    function: *mut Option<VoidFunction>,
+) -> XrResult {
+    let name = CStr::from_ptr(name).to_str().unwrap();
+    *function = Some(find(name).unwrap_or(unavailable_function));
+    XrResult::SUCCESS
+}
+
+fn find(name: &str) -> Option<VoidFunction> {
+    FUNCTIONS.with(|table| {
+        table
+            .borrow()
+            .iter()
+            .find(|&&(table_name, _)| table_name == name)
+            .map(|&(_, function)| function)
+    })
+}
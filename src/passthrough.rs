@@ -0,0 +1,205 @@
+//! XR_FB_passthrough, which openxr-sys 0.8 predates, hence the definitions below taken from the
+//! extension's spec.
+
+use std::os::raw::c_void;
+
+use log::{info, warn};
+use openxr_sys::{
+    CompositionLayerBaseHeader, CompositionLayerFlags, Result as XrResult, StructureType,
+};
+
+use crate::{
+    error::{Error, XrResultExt},
+    layers::CompositionLayer,
+    XrInstanceFp,
+};
+
+pub(crate) const EXTENSION: &str = "XR_FB_passthrough";
+
+// Raw `StructureType` values, `from_raw` not being a const fn
+const TYPE_SYSTEM_PASSTHROUGH_PROPERTIES_FB: i32 = 1000118000;
+const TYPE_PASSTHROUGH_CREATE_INFO_FB: i32 = 1000118001;
+const TYPE_PASSTHROUGH_LAYER_CREATE_INFO_FB: i32 = 1000118002;
+const TYPE_COMPOSITION_LAYER_PASSTHROUGH_FB: i32 = 1000118003;
+
+/// XR_PASSTHROUGH_IS_RUNNING_AT_CREATION_BIT_FB, sparing the start and resume calls
+const IS_RUNNING_AT_CREATION: u64 = 0x1;
+/// XR_PASSTHROUGH_LAYER_PURPOSE_RECONSTRUCTION_FB, the full view of the surroundings
+const PURPOSE_RECONSTRUCTION: i32 = 0;
+
+pub(crate) type PassthroughFB = u64;
+pub(crate) type PassthroughLayerFB = u64;
+
+#[repr(C)]
+struct SystemPassthroughPropertiesFB {
+    ty: StructureType,
+    next: *const c_void,
+    supports_passthrough: openxr_sys::Bool32,
+}
+
+#[repr(C)]
+pub(crate) struct PassthroughCreateInfoFB {
+    ty: StructureType,
+    next: *const c_void,
+    flags: u64,
+}
+
+#[repr(C)]
+pub(crate) struct PassthroughLayerCreateInfoFB {
+    ty: StructureType,
+    next: *const c_void,
+    passthrough: PassthroughFB,
+    flags: u64,
+    purpose: i32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CompositionLayerPassthroughFB {
+    ty: StructureType,
+    next: *const c_void,
+    flags: CompositionLayerFlags,
+    space: openxr_sys::Space,
+    layer_handle: PassthroughLayerFB,
+}
+
+pub(crate) type CreatePassthroughFB = unsafe extern "system" fn(
+    openxr_sys::Session,
+    *const PassthroughCreateInfoFB,
+    *mut PassthroughFB,
+) -> XrResult;
+pub(crate) type DestroyPassthroughFB = unsafe extern "system" fn(PassthroughFB) -> XrResult;
+pub(crate) type CreatePassthroughLayerFB = unsafe extern "system" fn(
+    openxr_sys::Session,
+    *const PassthroughLayerCreateInfoFB,
+    *mut PassthroughLayerFB,
+) -> XrResult;
+pub(crate) type DestroyPassthroughLayerFB =
+    unsafe extern "system" fn(PassthroughLayerFB) -> XrResult;
+
+/// Whether the system has cameras to show the surroundings through, the extension being
+/// available on headsets without them too.
+pub(crate) fn is_supported(
+    fp: &XrInstanceFp,
+    instance: openxr_sys::Instance,
+    system_id: openxr_sys::SystemId,
+) -> Result<bool, Error> {
+    let mut passthrough_properties = SystemPassthroughPropertiesFB {
+        ty: StructureType::from_raw(TYPE_SYSTEM_PASSTHROUGH_PROPERTIES_FB),
+        next: std::ptr::null(),
+        supports_passthrough: false.into(),
+    };
+    let mut properties = openxr_sys::SystemProperties::out(
+        &mut passthrough_properties as *mut _ as *mut openxr_sys::BaseOutStructure,
+    );
+
+    info!("xrGetSystemProperties()");
+    let result =
+        unsafe { (fp.get_system_properties)(instance, system_id, properties.as_mut_ptr()) };
+    if !result.is_success() {
+        return Err(Error::Xr(result));
+    }
+
+    Ok(passthrough_properties.supports_passthrough.into())
+}
+
+/// Running passthrough feature, along with the layer compositing the surroundings.
+pub(crate) struct Passthrough {
+    passthrough: PassthroughFB,
+    layer: CompositionLayerPassthroughFB,
+}
+
+// The layer isn't `Send` because of its `next` pointer, which stays null, and the handles it
+// holds can be used from any thread
+unsafe impl Send for Passthrough {}
+
+impl Passthrough {
+    pub(crate) fn new(fp: &XrInstanceFp, session: openxr_sys::Session) -> Result<Self, Error> {
+        let (create_passthrough, create_passthrough_layer) =
+            match (fp.create_passthrough_FB, fp.create_passthrough_layer_FB) {
+                (Some(create), Some(create_layer)) => (create, create_layer),
+                _ => return Err(Error::MissingExtension(EXTENSION)),
+            };
+
+        let create_info = PassthroughCreateInfoFB {
+            ty: StructureType::from_raw(TYPE_PASSTHROUGH_CREATE_INFO_FB),
+            next: std::ptr::null(),
+            flags: IS_RUNNING_AT_CREATION,
+        };
+
+        info!("xrCreatePassthroughFB()");
+        let mut passthrough = 0;
+        let result = unsafe { create_passthrough(session, &create_info, &mut passthrough) };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        let layer_info = PassthroughLayerCreateInfoFB {
+            ty: StructureType::from_raw(TYPE_PASSTHROUGH_LAYER_CREATE_INFO_FB),
+            next: std::ptr::null(),
+            passthrough,
+            flags: IS_RUNNING_AT_CREATION,
+            purpose: PURPOSE_RECONSTRUCTION,
+        };
+
+        info!("xrCreatePassthroughLayerFB()");
+        let mut layer_handle = 0;
+        let result = unsafe { create_passthrough_layer(session, &layer_info, &mut layer_handle) };
+        if !result.is_success() {
+            destroy_passthrough(fp, passthrough);
+            return Err(Error::Xr(result));
+        }
+
+        Ok(Passthrough {
+            passthrough,
+            layer: CompositionLayerPassthroughFB {
+                ty: StructureType::from_raw(TYPE_COMPOSITION_LAYER_PASSTHROUGH_FB),
+                next: std::ptr::null(),
+                flags: CompositionLayerFlags::BLEND_TEXTURE_SOURCE_ALPHA,
+                space: openxr_sys::Space::NULL,
+                layer_handle,
+            },
+        })
+    }
+
+    /// The layer to submit below the others.
+    pub(crate) fn layer(&self) -> &dyn CompositionLayer {
+        &self.layer
+    }
+
+    pub(crate) fn destroy(self, fp: &XrInstanceFp) -> Result<(), Error> {
+        // Both are loaded, or the passthrough couldn't have been created
+        let destroy_layer = fp.destroy_passthrough_layer_FB.unwrap();
+
+        info!("xrDestroyPassthroughLayerFB()");
+        let result = unsafe { destroy_layer(self.layer.layer_handle) };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        info!("xrDestroyPassthroughFB()");
+        let result = unsafe { (fp.destroy_passthrough_FB.unwrap())(self.passthrough) };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        Ok(())
+    }
+}
+
+/// Cleanup after a failed creation, which only gets logged since the original error matters more.
+fn destroy_passthrough(fp: &XrInstanceFp, passthrough: PassthroughFB) {
+    if let Some(destroy) = fp.destroy_passthrough_FB {
+        info!("xrDestroyPassthroughFB()");
+        let result = unsafe { destroy(passthrough) };
+        if !result.is_success() {
+            warn!("Failed to destroy passthrough: {:?}", result);
+        }
+    }
+}
+
+unsafe impl CompositionLayer for CompositionLayerPassthroughFB {
+    fn as_base_header(&self) -> *const CompositionLayerBaseHeader {
+        self as *const _ as *const CompositionLayerBaseHeader
+    }
+}
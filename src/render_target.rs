@@ -0,0 +1,303 @@
+use ash::{
+    version::{DeviceV1_0, InstanceV1_0},
+    vk,
+};
+
+use crate::{error::Error, swapchain::Swapchain};
+
+/// Depth formats to try, in order of preference, the default of `Config::preferred_depth_formats`.
+/// Vulkan guarantees `D16_UNORM` can be a depth attachment, so the list always ends with it.
+pub(crate) const DEPTH_FORMATS: [vk::Format; 4] = [
+    vk::Format::D32_SFLOAT,
+    vk::Format::D24_UNORM_S8_UINT,
+    vk::Format::X8_D24_UNORM_PACK32,
+    vk::Format::D16_UNORM,
+];
+
+/// Returns the first of `candidates` the physical device supports with `features` at `tiling`,
+/// or `Error::NoDepthFormat` if it supports none of them.
+pub fn select_depth_format(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+    candidates: &[vk::Format],
+    tiling: vk::ImageTiling,
+    features: vk::FormatFeatureFlags,
+) -> Result<vk::Format, Error> {
+    candidates
+        .iter()
+        .copied()
+        .find(|&format| {
+            let properties =
+                unsafe { instance.get_physical_device_format_properties(physical_device, format) };
+            let supported = match tiling {
+                vk::ImageTiling::LINEAR => properties.linear_tiling_features,
+                _ => properties.optimal_tiling_features,
+            };
+            supported.contains(features)
+        })
+        .ok_or_else(|| Error::NoDepthFormat(candidates.to_vec()))
+}
+
+/// Aspects of the depth `format`, which includes the stencil for the combined formats.
+fn depth_aspect_mask(format: vk::Format) -> vk::ImageAspectFlags {
+    match format {
+        vk::Format::D16_UNORM_S8_UINT
+        | vk::Format::D24_UNORM_S8_UINT
+        | vk::Format::D32_SFLOAT_S8_UINT => {
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        }
+        _ => vk::ImageAspectFlags::DEPTH,
+    }
+}
+
+/// Image owned by a render target, along with its memory and view.
+struct Attachment {
+    image: vk::Image,
+    memory: vk::DeviceMemory,
+    view: vk::ImageView,
+}
+
+/// Everything needed to render one view to its swapchain: a view and a framebuffer per swapchain
+/// image, plus the depth and multisampled color images shared by all of them.
+///
+/// The framebuffer attachments come in this order, which the render pass must follow:
+///
+/// 1. color: the swapchain image, or the multisampled image when `samples` is higher than the
+///    swapchain's sample count
+/// 2. depth, when a depth format is given
+/// 3. resolve: the swapchain image, only with the multisampled color image
+pub struct ViewRenderTarget {
+    extent: vk::Extent2D,
+    samples: vk::SampleCountFlags,
+    image_views: Vec<vk::ImageView>,
+    depth: Option<Attachment>,
+    msaa_color: Option<Attachment>,
+    framebuffers: Vec<vk::Framebuffer>,
+}
+
+impl ViewRenderTarget {
+    /// Creates the attachments and framebuffers to render to the images of `swapchain` with
+    /// `render_pass`, at `samples` samples per pixel. `array_layer` is the layer of the images
+    /// the view is rendered to, which is only non-zero with `SwapchainLayout::Array`.
+    pub fn new(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        device: &ash::Device,
+        render_pass: vk::RenderPass,
+        swapchain: &Swapchain,
+        array_layer: u32,
+        depth_format: Option<vk::Format>,
+        samples: u32,
+    ) -> Result<Self, Error> {
+        let extent = vk::Extent2D {
+            width: swapchain.width,
+            height: swapchain.height,
+        };
+        let sample_flags = vk::SampleCountFlags::from_raw(samples);
+        let memory_properties =
+            unsafe { instance.get_physical_device_memory_properties(physical_device) };
+
+        let image_views = swapchain
+            .images
+            .iter()
+            .map(|&image| {
+                create_image_view(
+                    device,
+                    image,
+                    swapchain.format,
+                    vk::ImageAspectFlags::COLOR,
+                    array_layer,
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let depth = depth_format
+            .map(|format| {
+                create_attachment(
+                    device,
+                    &memory_properties,
+                    extent,
+                    format,
+                    sample_flags,
+                    vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+                    depth_aspect_mask(format),
+                )
+            })
+            .transpose()?;
+
+        // Only needed when the swapchain images can't be rendered to directly
+        let msaa_color = if samples > swapchain.sample_count {
+            Some(create_attachment(
+                device,
+                &memory_properties,
+                extent,
+                swapchain.format,
+                sample_flags,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+                vk::ImageAspectFlags::COLOR,
+            )?)
+        } else {
+            None
+        };
+
+        let framebuffers = image_views
+            .iter()
+            .map(|&image_view| {
+                let attachments: Vec<_> = match &msaa_color {
+                    Some(msaa_color) => std::iter::once(msaa_color.view)
+                        .chain(depth.as_ref().map(|depth| depth.view))
+                        .chain(std::iter::once(image_view))
+                        .collect(),
+                    None => std::iter::once(image_view)
+                        .chain(depth.as_ref().map(|depth| depth.view))
+                        .collect(),
+                };
+
+                let framebuffer_info = vk::FramebufferCreateInfo {
+                    render_pass,
+                    attachment_count: attachments.len() as u32,
+                    p_attachments: attachments.as_ptr(),
+                    width: extent.width,
+                    height: extent.height,
+                    layers: 1,
+                    ..Default::default()
+                };
+                unsafe { device.create_framebuffer(&framebuffer_info, None) }.map_err(Error::from)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(ViewRenderTarget {
+            extent,
+            samples: sample_flags,
+            image_views,
+            depth,
+            msaa_color,
+            framebuffers,
+        })
+    }
+
+    /// Framebuffer rendering to the swapchain image at `image_index`, as returned by
+    /// `Swapchain::acquire_image`.
+    pub fn for_image(&self, image_index: u32) -> vk::Framebuffer {
+        self.framebuffers[image_index as usize]
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        self.extent
+    }
+
+    pub fn samples(&self) -> vk::SampleCountFlags {
+        self.samples
+    }
+
+    /// Whether the color is rendered to a multisampled image, to be resolved to the swapchain
+    /// image.
+    pub fn resolves(&self) -> bool {
+        self.msaa_color.is_some()
+    }
+
+    /// The device must be done with the target.
+    pub fn destroy(self, device: &ash::Device) {
+        unsafe {
+            for &framebuffer in &self.framebuffers {
+                device.destroy_framebuffer(framebuffer, None);
+            }
+            for attachment in self.depth.iter().chain(&self.msaa_color) {
+                device.destroy_image_view(attachment.view, None);
+                device.destroy_image(attachment.image, None);
+                device.free_memory(attachment.memory, None);
+            }
+            for &image_view in &self.image_views {
+                device.destroy_image_view(image_view, None);
+            }
+        }
+    }
+}
+
+fn create_image_view(
+    device: &ash::Device,
+    image: vk::Image,
+    format: vk::Format,
+    aspect_mask: vk::ImageAspectFlags,
+    array_layer: u32,
+) -> Result<vk::ImageView, Error> {
+    let view_info = vk::ImageViewCreateInfo {
+        image,
+        view_type: vk::ImageViewType::TYPE_2D,
+        format,
+        subresource_range: vk::ImageSubresourceRange {
+            aspect_mask,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: array_layer,
+            layer_count: 1,
+        },
+        ..Default::default()
+    };
+    Ok(unsafe { device.create_image_view(&view_info, None)? })
+}
+
+fn create_attachment(
+    device: &ash::Device,
+    memory_properties: &vk::PhysicalDeviceMemoryProperties,
+    extent: vk::Extent2D,
+    format: vk::Format,
+    samples: vk::SampleCountFlags,
+    usage: vk::ImageUsageFlags,
+    aspect_mask: vk::ImageAspectFlags,
+) -> Result<Attachment, Error> {
+    let image_info = vk::ImageCreateInfo {
+        image_type: vk::ImageType::TYPE_2D,
+        format,
+        extent: vk::Extent3D {
+            width: extent.width,
+            height: extent.height,
+            depth: 1,
+        },
+        mip_levels: 1,
+        array_layers: 1,
+        samples,
+        tiling: vk::ImageTiling::OPTIMAL,
+        usage,
+        sharing_mode: vk::SharingMode::EXCLUSIVE,
+        initial_layout: vk::ImageLayout::UNDEFINED,
+        ..Default::default()
+    };
+    let image = unsafe { device.create_image(&image_info, None)? };
+    let requirements = unsafe { device.get_image_memory_requirements(image) };
+
+    // Lazily allocated memory saves the bandwidth of transient attachments on tilers, like the
+    // Quest's GPU
+    let memory_type_index = [
+        vk::MemoryPropertyFlags::DEVICE_LOCAL | vk::MemoryPropertyFlags::LAZILY_ALLOCATED,
+        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+    ]
+    .iter()
+    .find_map(|&wanted| {
+        (0..memory_properties.memory_type_count).find(|&index| {
+            requirements.memory_type_bits & (1 << index) != 0
+                && memory_properties.memory_types[index as usize]
+                    .property_flags
+                    .contains(wanted)
+        })
+    })
+    .expect("No device local memory type for the render target");
+
+    let allocate_info = vk::MemoryAllocateInfo {
+        allocation_size: requirements.size,
+        memory_type_index,
+        ..Default::default()
+    };
+
+    unsafe {
+        let memory = device.allocate_memory(&allocate_info, None)?;
+        device.bind_image_memory(image, memory, 0)?;
+        let view = create_image_view(device, image, format, aspect_mask, 0)?;
+
+        Ok(Attachment {
+            image,
+            memory,
+            view,
+        })
+    }
+}
@@ -0,0 +1,1968 @@
+use std::{
+    cell::Cell,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use log::{debug, info, warn};
+use openxr_sys::{EnvironmentBlendMode, Result as XrResult, SessionState, ViewConfigurationType};
+
+use crate::{
+    action::{Action, ActionSet, Hand, TopLevelPath},
+    color_space::{self, ColorSpace},
+    config::RenderOrder,
+    controller_model::{self, ControllerModel},
+    error::{Error, XrResultExt},
+    eye_gaze,
+    eye_gaze::EyeGaze,
+    foveation::{
+        FoveationLevel, FoveationLevelProfileCreateInfoFB, FoveationProfileCreateInfoFB,
+        SwapchainStateFoveationFB,
+    },
+    frame_stats::{FrameStats, FrameTiming},
+    in_flight::InFlightFrames,
+    layers::{CompositionLayer, FrameLayers},
+    math,
+    passthrough::Passthrough,
+    spatial_anchor::SpatialAnchor,
+    swapchain::{Swapchain, SwapchainLayout},
+    time::XrTime,
+    XrInstanceFp,
+};
+
+const VISIBILITY_MASK_EXTENSION: &str = "XR_KHR_visibility_mask";
+#[cfg(target_os = "android")]
+const ANDROID_THREAD_SETTINGS_EXTENSION: &str = "XR_KHR_android_thread_settings";
+const PERFORMANCE_SETTINGS_EXTENSION: &str = "XR_EXT_performance_settings";
+
+/// Where the session stands in the xrWaitFrame, xrBeginFrame, xrEndFrame sequence, shared with
+/// the `FrameGuard`s so abandoned frames count as ended.
+///
+/// Each xrBeginFrame has to follow its own xrWaitFrame, a second xrBeginFrame discarding the
+/// frame begun before, and each xrEndFrame its own xrBeginFrame. The next frame may be waited
+/// for while one is begun, but not twice in a row, which blocks until a frame gets begun.
+#[derive(Default)]
+struct FrameSequence {
+    /// xrWaitFrame succeeded, its xrBeginFrame not having been called yet
+    waited: AtomicBool,
+    /// xrBeginFrame succeeded, its xrEndFrame not having been called yet
+    begun: AtomicBool,
+}
+
+/// Frame begun by `Session::begin_guarded_frame`, ended with no layers when dropped before `end`,
+/// as happens when rendering fails or panics.
+///
+/// Every xrBeginFrame must be followed by an xrEndFrame: a frame left hanging makes the next
+/// xrBeginFrame discard it, and throws off the timing the runtime paces xrWaitFrame with.
+pub(crate) struct FrameGuard {
+    fp: Arc<XrInstanceFp>,
+    session: openxr_sys::Session,
+    display_time: XrTime,
+    environment_blend_mode: EnvironmentBlendMode,
+    ended: bool,
+    sequence: Arc<FrameSequence>,
+}
+
+impl FrameGuard {
+    /// Submits `layers`, see `Session::end_frame`. The frame counts as ended even if it fails.
+    pub(crate) fn end(mut self, session: &Session, layers: FrameLayers) -> Result<(), Error> {
+        self.ended = true;
+        session.end_frame(self.display_time, layers)
+    }
+}
+
+impl Drop for FrameGuard {
+    fn drop(&mut self) {
+        if self.ended {
+            return;
+        }
+
+        warn!("Frame abandoned after xrBeginFrame, ending it without layers");
+        self.sequence.begun.store(false, Ordering::Relaxed);
+        let end_info = openxr_sys::FrameEndInfo {
+            ty: openxr_sys::FrameEndInfo::TYPE,
+            next: std::ptr::null(),
+            display_time: self.display_time.into(),
+            environment_blend_mode: self.environment_blend_mode,
+            layer_count: 0,
+            layers: std::ptr::null(),
+        };
+        let result = unsafe { (self.fp.end_frame)(self.session, &end_info) };
+        if !result.is_success() {
+            warn!("Failed to end the abandoned frame: {:?}", result);
+        }
+    }
+}
+
+/// Mesh of the area covered by a view, as given by XR_KHR_visibility_mask.
+/// `indices` describe triangles, or a line loop for `VisibilityMaskTypeKHR::LINE_LOOP`.
+pub struct VisibilityMask {
+    pub vertices: Vec<openxr_sys::Vector2f>,
+    pub indices: Vec<u32>,
+}
+
+/// Event from the runtime the application may have to react to, the session state changes being
+/// handled by `Session::poll_events` itself.
+#[derive(Clone, Copy, Debug)]
+pub enum SessionEvent {
+    /// A reference space is about to change its origin, typically because the user recentered
+    /// the view or reset the boundary. Spaces of that type keep their handle, but their origin
+    /// moves at `change_time`, so content anchored to them jumps unless it's moved along.
+    ///
+    /// `pose_in_previous_space` is the new origin expressed in the old one: transforming the
+    /// poses of world-locked content by its inverse keeps it in place from the user's point of
+    /// view. It's only known when `pose_valid` is true.
+    ReferenceSpaceChangePending {
+        reference_space_type: openxr_sys::ReferenceSpaceType,
+        change_time: XrTime,
+        pose_valid: bool,
+        pose_in_previous_space: openxr_sys::Posef,
+    },
+    /// The performance of a domain crossed a threshold, from XR_EXT_performance_settings. Going
+    /// to `IMPAIRED` means the runtime is already degrading the experience, for instance by
+    /// throttling, and the app should lower its workload or its `Session::set_performance_level`.
+    PerformanceChanged {
+        domain: openxr_sys::PerfSettingsDomainEXT,
+        sub_domain: openxr_sys::PerfSettingsSubDomainEXT,
+        from_level: openxr_sys::PerfSettingsNotificationLevelEXT,
+        to_level: openxr_sys::PerfSettingsNotificationLevelEXT,
+    },
+}
+
+/// What the tracking of the headset currently provides, see `Session::tracking_capabilities`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TrackingCapabilities {
+    /// Whether the orientation of the views comes from actual tracking
+    pub orientation: bool,
+    /// Whether the position of the views comes from actual tracking. Without it, the position
+    /// is either not valid at all or made up by the runtime, for instance with a neck model on
+    /// 3DOF devices.
+    pub position: bool,
+}
+
+impl TrackingCapabilities {
+    fn from_flags(flags: openxr_sys::ViewStateFlags) -> Self {
+        TrackingCapabilities {
+            orientation: flags.contains(openxr_sys::ViewStateFlags::ORIENTATION_TRACKED),
+            position: flags.contains(openxr_sys::ViewStateFlags::POSITION_TRACKED),
+        }
+    }
+}
+
+/// Returns the blend modes the system supports for `view_configuration_type`, in the runtime's
+/// order of preference.
+pub(crate) fn enumerate_environment_blend_modes(
+    fp: &XrInstanceFp,
+    instance: openxr_sys::Instance,
+    system_id: openxr_sys::SystemId,
+    view_configuration_type: ViewConfigurationType,
+) -> Result<Vec<EnvironmentBlendMode>, Error> {
+    info!("xrEnumerateEnvironmentBlendModes()");
+    let blend_modes = crate::enumerate(
+        fp.max_enumerate_count,
+        EnvironmentBlendMode::OPAQUE,
+        |capacity, count, buffer| unsafe {
+            (fp.enumerate_environment_blend_modes)(
+                instance,
+                system_id,
+                view_configuration_type,
+                capacity,
+                count,
+                buffer,
+            )
+        },
+    )?;
+    info!("  blend modes: {:?}", blend_modes);
+    Ok(blend_modes)
+}
+
+/// The Vulkan side of `Session::render_and_submit`, set up by `Context::new` once the device is
+/// created.
+pub(crate) struct FrameRecorder {
+    device: ash::Device,
+    graphics_queue: ash::vk::Queue,
+    /// Command buffers and fences of the frames in flight
+    in_flight: InFlightFrames,
+    render_order: RenderOrder,
+    /// `Config::near_plane` and `Config::far_plane`
+    clip_planes: (f32, f32),
+    reverse_z: bool,
+}
+
+impl FrameRecorder {
+    pub(crate) fn new(
+        device: &ash::Device,
+        graphics_queue: crate::DeviceQueue,
+        in_flight: InFlightFrames,
+        config: &crate::Config,
+    ) -> Self {
+        FrameRecorder {
+            device: device.clone(),
+            graphics_queue: graphics_queue.queue,
+            in_flight,
+            render_order: config.render_order,
+            clip_planes: (config.near_plane, config.far_plane),
+            reverse_z: config.reverse_z,
+        }
+    }
+
+    /// Waits until the GPU is done with the next frame slot, then starts recording its command
+    /// buffer, see `InFlightFrames::begin`.
+    pub(crate) fn begin(&mut self) -> Result<(usize, ash::vk::CommandBuffer), Error> {
+        self.in_flight.begin(&self.device)
+    }
+
+    /// Submits the command buffer recorded since `begin` to the graphics queue.
+    pub(crate) fn submit(&self) -> Result<(), Error> {
+        self.in_flight.submit(&self.device, self.graphics_queue)
+    }
+
+    /// The device must be idle, or at least done with every frame slot.
+    pub(crate) fn destroy(self) {
+        self.in_flight.destroy(&self.device);
+    }
+}
+
+/// Wraps the OpenXR session along with the state reported by the runtime.
+pub struct Session {
+    pub(crate) handle: openxr_sys::Session,
+    pub(crate) instance: openxr_sys::Instance,
+    pub(crate) fp: Arc<XrInstanceFp>,
+    view_configuration_type: ViewConfigurationType,
+    environment_blend_modes: Vec<EnvironmentBlendMode>,
+    state: SessionState,
+    /// Whether xrBeginSession was called without a matching xrEndSession yet
+    running: bool,
+    /// Whether the session was begun at least once
+    pub(crate) begun: bool,
+    /// Set when the session begins again after having been stopped, the recommended view
+    /// configuration may have changed in between
+    pub(crate) restarted: bool,
+    eye_gaze: Option<EyeGaze>,
+    passthrough: Option<Passthrough>,
+    /// Blend mode the frames are submitted with
+    environment_blend_mode: EnvironmentBlendMode,
+    /// Handles of the action sets, once attached
+    attached_action_sets: Option<Vec<openxr_sys::ActionSet>>,
+    /// Filled by `App::run`
+    pub(crate) frame_stats: FrameStats,
+    /// State flags of the last views located with a valid orientation
+    last_view_state_flags: Cell<Option<openxr_sys::ViewStateFlags>>,
+    /// Set by `recenter`, for `App::run` to act on
+    recenter_requested: Cell<bool>,
+    /// Swapchains created for `Config::foveation`, the ones `set_foveation_level` updates
+    foveated_swapchains: Vec<openxr_sys::Swapchain>,
+    /// Level last set, applied again to the swapchains when they're recreated
+    foveation_level: Cell<Option<FoveationLevel>>,
+    frame_sequence: Arc<FrameSequence>,
+    /// Swapchains the views are rendered to, which the context creates and destroys
+    pub(crate) swapchains: Vec<Swapchain>,
+    pub(crate) swapchain_layout: SwapchainLayout,
+    /// Set between `Context::suspend` and `Context::resume`, while there are no swapchains
+    pub(crate) suspended: bool,
+    /// Only missing from the sessions of the tests, which have no device
+    pub(crate) recorder: Option<FrameRecorder>,
+}
+
+impl Session {
+    pub(crate) fn new(
+        handle: openxr_sys::Session,
+        instance: openxr_sys::Instance,
+        fp: Arc<XrInstanceFp>,
+        view_configuration_type: ViewConfigurationType,
+        environment_blend_modes: Vec<EnvironmentBlendMode>,
+    ) -> Self {
+        Session {
+            handle,
+            instance,
+            fp,
+            view_configuration_type,
+            environment_blend_modes,
+            state: SessionState::UNKNOWN,
+            running: false,
+            begun: false,
+            restarted: false,
+            eye_gaze: None,
+            passthrough: None,
+            environment_blend_mode: EnvironmentBlendMode::OPAQUE,
+            attached_action_sets: None,
+            frame_stats: FrameStats::new(None),
+            last_view_state_flags: Cell::new(None),
+            recenter_requested: Cell::new(false),
+            foveated_swapchains: Vec::new(),
+            foveation_level: Cell::new(None),
+            frame_sequence: Arc::default(),
+            swapchains: Vec::new(),
+            swapchain_layout: SwapchainLayout::Separate,
+            suspended: false,
+            recorder: None,
+        }
+    }
+
+    /// Sets up the eye gaze action, the gaze being then located relative to `base_space`. Its
+    /// action set gets attached along with the application's ones.
+    pub(crate) fn create_eye_gaze(&mut self, base_space: openxr_sys::Space) -> Result<(), Error> {
+        self.eye_gaze = Some(EyeGaze::new(
+            &self.fp,
+            self.instance,
+            self.handle,
+            base_space,
+        )?);
+        Ok(())
+    }
+
+    /// Locates the eye gaze relative to `base_space` from now on, when it's enabled. The
+    /// previous base space must not be destroyed before this is called.
+    pub(crate) fn set_eye_gaze_base_space(&mut self, base_space: openxr_sys::Space) {
+        if let Some(eye_gaze) = &mut self.eye_gaze {
+            eye_gaze.set_base_space(base_space);
+        }
+    }
+
+    /// Starts XR_FB_passthrough, switching the frames to the `ALPHA_BLEND` blend mode if the view
+    /// configuration supports it.
+    pub(crate) fn create_passthrough(&mut self) -> Result<(), Error> {
+        self.passthrough = Some(Passthrough::new(&self.fp, self.handle)?);
+        if self
+            .environment_blend_modes
+            .contains(&EnvironmentBlendMode::ALPHA_BLEND)
+        {
+            self.environment_blend_mode = EnvironmentBlendMode::ALPHA_BLEND;
+        }
+        Ok(())
+    }
+
+    /// Layer showing the surroundings, to submit below the others, when passthrough is enabled.
+    pub(crate) fn passthrough_layer(&self) -> Option<&dyn CompositionLayer> {
+        self.passthrough.as_ref().map(Passthrough::layer)
+    }
+
+    pub(crate) fn destroy_passthrough(&mut self) -> Result<(), Error> {
+        if let Some(passthrough) = self.passthrough.take() {
+            passthrough.destroy(&self.fp)?;
+        }
+        Ok(())
+    }
+
+    /// Creates an action set, to fill with `ActionSet::create_action` before attaching it.
+    /// Sets with a higher `priority` take precedence when their actions share inputs. Names too
+    /// long for OpenXR's fields fail with `Error::InvalidName`.
+    pub fn create_action_set(
+        &self,
+        name: &str,
+        localized_name: &str,
+        priority: u32,
+    ) -> Result<ActionSet, Error> {
+        ActionSet::new(&self.fp, self.instance, name, localized_name, priority)
+    }
+
+    /// Converts a path string, such as `/user/hand/right`, to the atom the runtime knows it as.
+    pub fn string_to_path(&self, path: &str) -> Result<openxr_sys::Path, Error> {
+        crate::string_to_path(&self.fp, self.instance, path)
+    }
+
+    /// Suggests which inputs of `interaction_profile` the actions should be bound to, for
+    /// instance:
+    ///
+    /// ```ignore
+    /// session.suggest_bindings(
+    ///     "/interaction_profiles/oculus/touch_controller",
+    ///     &[
+    ///         (&trigger, "/user/hand/right/input/trigger/value"),
+    ///         (&thumbstick, "/user/hand/right/input/thumbstick"),
+    ///     ],
+    /// )?;
+    /// ```
+    ///
+    /// Each call replaces the previous suggestions for that profile, and they all have to be
+    /// made before the action sets are attached.
+    pub fn suggest_bindings(
+        &self,
+        interaction_profile: &str,
+        bindings: &[(&Action, &str)],
+    ) -> Result<(), Error> {
+        let suggested_bindings = bindings
+            .iter()
+            .map(|(action, path)| {
+                Ok(openxr_sys::ActionSuggestedBinding {
+                    action: action.handle,
+                    binding: self.string_to_path(path)?,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        self.suggest_binding_paths(
+            self.string_to_path(interaction_profile)?,
+            &suggested_bindings,
+        )
+    }
+
+    /// `suggest_bindings` with the paths already converted.
+    pub(crate) fn suggest_binding_paths(
+        &self,
+        interaction_profile: openxr_sys::Path,
+        suggested_bindings: &[openxr_sys::ActionSuggestedBinding],
+    ) -> Result<(), Error> {
+        let suggested_info = openxr_sys::InteractionProfileSuggestedBinding {
+            ty: openxr_sys::InteractionProfileSuggestedBinding::TYPE,
+            next: std::ptr::null(),
+            interaction_profile,
+            count_suggested_bindings: suggested_bindings.len() as u32,
+            suggested_bindings: suggested_bindings.as_ptr(),
+        };
+
+        info!("xrSuggestInteractionProfileBindings()");
+        let result = unsafe {
+            (self.fp.suggest_interaction_profile_bindings)(self.instance, &suggested_info)
+        };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        Ok(())
+    }
+
+    /// Attaches `action_sets`, along with the eye gaze one when enabled, to the session. This can
+    /// only be done once per session, and `App::run` does it with no application set if it hasn't
+    /// been done before the frame loop starts.
+    pub fn attach_action_sets(&mut self, action_sets: &[&ActionSet]) -> Result<(), Error> {
+        let mut handles: Vec<_> = action_sets.iter().map(|set| set.handle).collect();
+        if let Some(eye_gaze) = &self.eye_gaze {
+            handles.push(eye_gaze.action_set);
+        }
+
+        let attach_info = openxr_sys::SessionActionSetsAttachInfo {
+            ty: openxr_sys::SessionActionSetsAttachInfo::TYPE,
+            next: std::ptr::null(),
+            count_action_sets: handles.len() as u32,
+            action_sets: handles.as_ptr(),
+        };
+
+        info!("xrAttachSessionActionSets()");
+        let result = unsafe { (self.fp.attach_session_action_sets)(self.handle, &attach_info) };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        self.attached_action_sets = Some(handles);
+        Ok(())
+    }
+
+    pub fn action_sets_attached(&self) -> bool {
+        self.attached_action_sets.is_some()
+    }
+
+    /// Interaction profile the runtime currently binds the actions to for `top_level_path`,
+    /// `None` when there's none, for instance before the action sets are attached or while
+    /// nothing is bound there.
+    pub fn current_interaction_profile(
+        &self,
+        top_level_path: TopLevelPath,
+    ) -> Result<Option<openxr_sys::Path>, Error> {
+        if !self.action_sets_attached() {
+            return Ok(None);
+        }
+
+        let top_level_user_path = self.string_to_path(top_level_path.path())?;
+        let mut profile = openxr_sys::InteractionProfileState {
+            ty: openxr_sys::InteractionProfileState::TYPE,
+            next: std::ptr::null_mut(),
+            interaction_profile: openxr_sys::Path::NULL,
+        };
+
+        let result = unsafe {
+            (self.fp.get_current_interaction_profile)(
+                self.handle,
+                top_level_user_path,
+                &mut profile,
+            )
+        };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        if profile.interaction_profile == openxr_sys::Path::NULL {
+            return Ok(None);
+        }
+        Ok(Some(profile.interaction_profile))
+    }
+
+    /// Top level user paths with an interaction profile currently bound, that is the devices
+    /// the actions get input from.
+    ///
+    /// This reflects binding rather than physical presence: runtimes may keep a profile bound
+    /// to a controller that was switched off or put down, and only pick one once it's used or
+    /// the session is focused. None is bound before the action sets are attached.
+    pub fn connected_devices(&self) -> Result<Vec<TopLevelPath>, Error> {
+        let mut connected = Vec::new();
+        for &top_level_path in &TopLevelPath::ALL {
+            if self.current_interaction_profile(top_level_path)?.is_some() {
+                connected.push(top_level_path);
+            }
+        }
+        Ok(connected)
+    }
+
+    /// Updates the state of the attached actions, to be called once per frame.
+    /// Does nothing when no action is in use.
+    pub(crate) fn sync_actions(&self) -> Result<(), Error> {
+        let action_sets = match &self.attached_action_sets {
+            Some(action_sets) if !action_sets.is_empty() => action_sets,
+            _ => return Ok(()),
+        };
+
+        let active_action_sets: Vec<_> = action_sets
+            .iter()
+            .map(|&action_set| openxr_sys::ActiveActionSet {
+                action_set,
+                subaction_path: openxr_sys::Path::NULL,
+            })
+            .collect();
+        let sync_info = openxr_sys::ActionsSyncInfo {
+            ty: openxr_sys::ActionsSyncInfo::TYPE,
+            next: std::ptr::null(),
+            count_active_action_sets: active_action_sets.len() as u32,
+            active_action_sets: active_action_sets.as_ptr(),
+        };
+
+        // Inputs are only available while focused, the actions otherwise just report as
+        // inactive, with SESSION_NOT_FOCUSED
+        let result = unsafe { (self.fp.sync_actions)(self.handle, &sync_info) };
+        result.into_result()?;
+        Ok(())
+    }
+
+    /// Where the user is looking at `time`, from XR_EXT_eye_gaze_interaction: the pose's -Z axis
+    /// is the gaze direction, relative to the context's reference space, the current one after
+    /// `Context::recenter`. `None` when the eyes aren't tracked at the moment. Fails if eye gaze
+    /// wasn't enabled through `Config::eye_gaze`.
+    pub fn eye_gaze_pose(&self, time: XrTime) -> Result<Option<openxr_sys::Posef>, Error> {
+        let eye_gaze = self
+            .eye_gaze
+            .as_ref()
+            .ok_or(Error::MissingExtension(eye_gaze::EXTENSION))?;
+
+        eye_gaze.locate(&self.fp, self.handle, time)
+    }
+
+    pub(crate) fn destroy_actions(&mut self) -> Result<(), Error> {
+        if let Some(eye_gaze) = self.eye_gaze.take() {
+            eye_gaze.destroy(&self.fp)?;
+        }
+        Ok(())
+    }
+
+    /// Drains the event queue, beginning and ending the session as the runtime asks, and returns
+    /// the events left for the application to handle.
+    pub fn poll_events(&mut self) -> Result<Vec<SessionEvent>, Error> {
+        let mut events = Vec::new();
+        loop {
+            let mut buffer: openxr_sys::EventDataBuffer = unsafe { std::mem::zeroed() };
+            buffer.ty = openxr_sys::EventDataBuffer::TYPE;
+
+            // The queue being empty is a success too, ending the loop
+            let result = unsafe { (self.fp.poll_event)(self.instance, &mut buffer) };
+            if result.into_result()? == XrResult::EVENT_UNAVAILABLE {
+                return Ok(events);
+            }
+
+            if buffer.ty == openxr_sys::StructureType::EVENT_DATA_SESSION_STATE_CHANGED {
+                let event = unsafe {
+                    &*(&buffer as *const _ as *const openxr_sys::EventDataSessionStateChanged)
+                };
+                self.on_state_changed(event.state)?;
+            } else if buffer.ty
+                == openxr_sys::StructureType::EVENT_DATA_REFERENCE_SPACE_CHANGE_PENDING
+            {
+                let event = unsafe {
+                    &*(&buffer as *const _
+                        as *const openxr_sys::EventDataReferenceSpaceChangePending)
+                };
+                info!(
+                    "Reference space {:?} changing at {:?}",
+                    event.reference_space_type, event.change_time
+                );
+                events.push(SessionEvent::ReferenceSpaceChangePending {
+                    reference_space_type: event.reference_space_type,
+                    change_time: event.change_time.into(),
+                    pose_valid: event.pose_valid.into(),
+                    pose_in_previous_space: event.pose_in_previous_space,
+                });
+            } else if buffer.ty == openxr_sys::StructureType::EVENT_DATA_PERF_SETTINGS_EXT {
+                let event = unsafe {
+                    &*(&buffer as *const _ as *const openxr_sys::EventDataPerfSettingsEXT)
+                };
+                info!(
+                    "Performance of {:?} ({:?}): {:?} -> {:?}",
+                    event.domain, event.sub_domain, event.from_level, event.to_level
+                );
+                events.push(SessionEvent::PerformanceChanged {
+                    domain: event.domain,
+                    sub_domain: event.sub_domain,
+                    from_level: event.from_level,
+                    to_level: event.to_level,
+                });
+            }
+        }
+    }
+
+    fn on_state_changed(&mut self, state: SessionState) -> Result<(), Error> {
+        info!("Session state: {:?} -> {:?}", self.state, state);
+        let lost_focus = self.state == SessionState::FOCUSED && state != SessionState::FOCUSED;
+        self.state = state;
+
+        // `App::run` stops syncing once unfocused, a last sync turning the actions inactive
+        // instead of leaving them stuck in their last state
+        if lost_focus {
+            self.sync_actions()?;
+        }
+
+        match state {
+            SessionState::READY => self.begin(),
+            SessionState::STOPPING => self.end(),
+            _ => Ok(()),
+        }
+    }
+
+    fn begin(&mut self) -> Result<(), Error> {
+        let begin_info = openxr_sys::SessionBeginInfo {
+            ty: openxr_sys::SessionBeginInfo::TYPE,
+            next: std::ptr::null(),
+            primary_view_configuration_type: self.view_configuration_type,
+        };
+
+        info!("xrBeginSession()");
+        let result = unsafe { (self.fp.begin_session)(self.handle, &begin_info) };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        self.running = true;
+        self.restarted = self.begun;
+        self.begun = true;
+        Ok(())
+    }
+
+    fn end(&mut self) -> Result<(), Error> {
+        info!("xrEndSession()");
+        let result = unsafe { (self.fp.end_session)(self.handle) };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        self.running = false;
+        Ok(())
+    }
+
+    /// Blend modes supported for the session's view configuration, preferred ones first.
+    pub fn environment_blend_modes(&self) -> &[EnvironmentBlendMode] {
+        &self.environment_blend_modes
+    }
+
+    /// Whether the system can show the real world behind the rendered content, meaning it
+    /// supports the `ALPHA_BLEND` or `ADDITIVE` blend mode. This is the standard way to tell AR
+    /// and VR systems apart at startup, but actual passthrough on VR headsets with cameras often
+    /// needs a vendor extension on top of it.
+    pub fn supports_passthrough(&self) -> bool {
+        self.environment_blend_modes.iter().any(|&mode| {
+            mode == EnvironmentBlendMode::ALPHA_BLEND || mode == EnvironmentBlendMode::ADDITIVE
+        })
+    }
+
+    /// Sets the fixed foveation level of the context's swapchains, through XR_FB_foveation and
+    /// XR_FB_swapchain_update_state, from the next image acquired on. The swapchains must have
+    /// been created with `XR_SWAPCHAIN_CREATE_FOVEATION_FRAGMENT_DENSITY_MAP_BIT_FB`, in an
+    /// `XrSwapchainCreateInfoFoveationFB` chained to their create info, which the context does
+    /// with `Config::foveation`. Fails with `Error::FoveationNotEnabled` without it, or while the
+    /// swapchains are destroyed, the level then set being kept for the next ones.
+    ///
+    /// With Vulkan, the runtime writes the level into the fragment density maps it attaches to
+    /// the swapchain images, and it's up to the render passes to use them: the ones built from
+    /// `ViewRenderTarget` don't, so the level only changes what runtimes foveating the layers
+    /// themselves do.
+    pub fn set_foveation_level(&self, level: FoveationLevel) -> Result<(), Error> {
+        let (create_profile, destroy_profile, update_swapchain) = match (
+            self.fp.create_foveation_profile_FB,
+            self.fp.destroy_foveation_profile_FB,
+            self.fp.update_swapchain_FB,
+        ) {
+            (Some(create), Some(destroy), Some(update)) => (create, destroy, update),
+            _ => return Err(Error::FoveationNotEnabled),
+        };
+        if self.foveated_swapchains.is_empty() {
+            return Err(Error::FoveationNotEnabled);
+        }
+
+        let mut level_info = FoveationLevelProfileCreateInfoFB::new(level);
+        let create_info = FoveationProfileCreateInfoFB::new(&mut level_info);
+        info!("xrCreateFoveationProfileFB()");
+        let mut profile = 0;
+        let result = unsafe { create_profile(self.handle, &create_info, &mut profile) };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        let state = SwapchainStateFoveationFB::new(profile);
+        let mut update_result = XrResult::SUCCESS;
+        for &swapchain in &self.foveated_swapchains {
+            info!("xrUpdateSwapchainFB()");
+            update_result = unsafe { update_swapchain(swapchain, &state) };
+            if !update_result.is_success() {
+                break;
+            }
+        }
+
+        // The swapchains keep the state, the profile isn't needed past the updates
+        info!("xrDestroyFoveationProfileFB()");
+        let destroy_result = unsafe { destroy_profile(profile) };
+        if !update_result.is_success() {
+            return Err(Error::Xr(update_result));
+        }
+        if !destroy_result.is_success() {
+            return Err(Error::Xr(destroy_result));
+        }
+
+        self.foveation_level.set(Some(level));
+        Ok(())
+    }
+
+    /// Hands the newly created swapchains over to `set_foveation_level`, setting the level last
+    /// set on them, `level` if none was.
+    pub(crate) fn foveate_swapchains(
+        &mut self,
+        swapchains: Vec<openxr_sys::Swapchain>,
+        level: FoveationLevel,
+    ) -> Result<(), Error> {
+        self.foveated_swapchains = swapchains;
+        self.set_foveation_level(self.foveation_level.get().unwrap_or(level))
+    }
+
+    /// Forgets the swapchains about to be destroyed.
+    pub(crate) fn forget_foveated_swapchains(&mut self) {
+        self.foveated_swapchains.clear();
+    }
+
+    /// Asks the runtime to end the session. It then goes through `STOPPING` and `EXITING` as
+    /// if the user had quit from the runtime's UI.
+    pub fn request_exit(&self) -> Result<(), Error> {
+        info!("xrRequestExitSession()");
+        let result = unsafe { (self.fp.request_exit_session)(self.handle) };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        Ok(())
+    }
+
+    /// Tells the runtime the calling thread is the main thread of the given type, so it can
+    /// schedule it accordingly.
+    /// Fails if XR_KHR_android_thread_settings isn't enabled on the instance.
+    #[cfg(target_os = "android")]
+    pub fn set_android_thread(
+        &self,
+        thread_type: openxr_sys::AndroidThreadTypeKHR,
+    ) -> Result<(), Error> {
+        extern "C" {
+            fn gettid() -> i32;
+        }
+
+        let set_android_application_thread = self
+            .fp
+            .set_android_application_thread_KHR
+            .ok_or(Error::MissingExtension(ANDROID_THREAD_SETTINGS_EXTENSION))?;
+
+        info!("xrSetAndroidApplicationThreadKHR({:?})", thread_type);
+        let thread_id = unsafe { gettid() } as u32;
+        let result = unsafe { set_android_application_thread(self.handle, thread_type, thread_id) };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        Ok(())
+    }
+
+    /// Hints the runtime at the performance the app needs from the CPU or GPU `domain`, so it
+    /// can adjust the clocks: `POWER_SAVINGS` or `SUSTAINED_LOW` for light scenes, sparing the
+    /// battery and heat, `SUSTAINED_HIGH` for the rest, and `BOOST` only for short bursts such as
+    /// loading, since it leads to thermal throttling if kept.
+    /// Fails if XR_EXT_performance_settings isn't enabled on the instance, which is done when
+    /// the runtime supports it.
+    pub fn set_performance_level(
+        &self,
+        domain: openxr_sys::PerfSettingsDomainEXT,
+        level: openxr_sys::PerfSettingsLevelEXT,
+    ) -> Result<(), Error> {
+        let set_performance_level = self
+            .fp
+            .perf_settings_set_performance_level_EXT
+            .ok_or(Error::MissingExtension(PERFORMANCE_SETTINGS_EXTENSION))?;
+
+        info!(
+            "xrPerfSettingsSetPerformanceLevelEXT({:?}, {:?})",
+            domain, level
+        );
+        let result = unsafe { set_performance_level(self.handle, domain, level) };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        Ok(())
+    }
+
+    /// Last state reported by the runtime.
+    ///
+    /// Like the predicates below, this only changes when `poll_events` processes a state change,
+    /// so it's as fresh as the last poll. `App::run` polls before every frame.
+    pub fn state(&self) -> SessionState {
+        self.state
+    }
+
+    /// Whether the session has been begun and not ended yet, meaning frames can be submitted.
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Whether what's rendered can be seen by the user. Frames still have to be submitted while
+    /// `is_running` otherwise, but the app can skip rendering them.
+    pub fn should_render(&self) -> bool {
+        self.is_visible()
+    }
+
+    /// Whether the session is `VISIBLE` or `FOCUSED`, its frames being shown to the user.
+    ///
+    /// The running states go `SYNCHRONIZED`, where frames are submitted but not shown, then
+    /// `VISIBLE`, where they're shown but something else, such as a system menu, has the input,
+    /// then `FOCUSED`, where the session gets the input too. A visible session keeps rendering
+    /// as usual, its content possibly dimmed or partly covered by the runtime.
+    pub fn is_visible(&self) -> bool {
+        self.state == SessionState::VISIBLE || self.state == SessionState::FOCUSED
+    }
+
+    /// Whether the session is `FOCUSED`, receiving input. Actions only report input then,
+    /// `App::run` not syncing them otherwise, so they stay inactive until focus comes back.
+    pub fn is_focused(&self) -> bool {
+        self.state == SessionState::FOCUSED
+    }
+
+    /// Asks for the reference space the views and layers are expressed in to be moved under
+    /// the head, facing where it faces but kept level, which `App::run` does at the start of the
+    /// next frame with `Context::recenter`. Content placed in that space then ends up in front
+    /// of the user again, as for a "recenter view" button.
+    ///
+    /// This is an app-level recenter, unlike the one from the system menu, which moves the
+    /// runtime's LOCAL space itself and gets reported with a
+    /// `SessionEvent::ReferenceSpaceChangePending`. The stage space and the boundary stay where
+    /// they are, as does the debug grid.
+    pub fn recenter(&self) {
+        self.recenter_requested.set(true);
+    }
+
+    /// Whether `recenter` was called since the last call, the request being cleared.
+    pub(crate) fn take_recenter_request(&self) -> bool {
+        self.recenter_requested.replace(false)
+    }
+
+    /// Whether the runtime wants the application to stop using this session.
+    pub fn is_exiting(&self) -> bool {
+        self.state == SessionState::EXITING || self.state == SessionState::LOSS_PENDING
+    }
+
+    /// Waits for the runtime to be ready for the next frame, which `begin_frame` then begins.
+    /// Fails with `Error::FrameOutOfOrder` if the previous wait wasn't followed by a
+    /// `begin_frame`, as the call would block until one is.
+    pub fn wait_frame(&self) -> Result<openxr_sys::FrameState, Error> {
+        if self.frame_sequence.waited.load(Ordering::Relaxed) {
+            return Err(Error::FrameOutOfOrder(
+                "xrWaitFrame called again before xrBeginFrame",
+            ));
+        }
+
+        let wait_info = openxr_sys::FrameWaitInfo {
+            ty: openxr_sys::FrameWaitInfo::TYPE,
+            next: std::ptr::null(),
+        };
+        let mut frame_state = openxr_sys::FrameState::out(std::ptr::null_mut());
+
+        let result =
+            unsafe { (self.fp.wait_frame)(self.handle, &wait_info, frame_state.as_mut_ptr()) };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        self.frame_sequence.waited.store(true, Ordering::Relaxed);
+        Ok(unsafe { frame_state.assume_init() })
+    }
+
+    /// Begins the frame `wait_frame` waited for. Fails with `Error::FrameOutOfOrder` without a
+    /// wait since the last begin, or while the previous frame hasn't been ended, which would
+    /// make the runtime discard it. The wait is used up even if xrBeginFrame fails, the next
+    /// frame starting with `wait_frame` again.
+    pub fn begin_frame(&self) -> Result<(), Error> {
+        if !self.frame_sequence.waited.load(Ordering::Relaxed) {
+            return Err(Error::FrameOutOfOrder(
+                "xrBeginFrame called without xrWaitFrame",
+            ));
+        }
+        if self.frame_sequence.begun.load(Ordering::Relaxed) {
+            return Err(Error::FrameOutOfOrder(
+                "xrBeginFrame called before the previous frame's xrEndFrame",
+            ));
+        }
+
+        let begin_info = openxr_sys::FrameBeginInfo {
+            ty: openxr_sys::FrameBeginInfo::TYPE,
+            next: std::ptr::null(),
+        };
+
+        let result = unsafe { (self.fp.begin_frame)(self.handle, &begin_info) };
+        self.frame_sequence.waited.store(false, Ordering::Relaxed);
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        self.frame_sequence.begun.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// `begin_frame`, returning a guard making sure the frame gets ended, with `display_time`
+    /// as returned by `wait_frame`.
+    pub(crate) fn begin_guarded_frame(&self, display_time: XrTime) -> Result<FrameGuard, Error> {
+        self.begin_frame()?;
+        Ok(FrameGuard {
+            fp: Arc::clone(&self.fp),
+            session: self.handle,
+            display_time,
+            environment_blend_mode: self.environment_blend_mode,
+            ended: false,
+            sequence: Arc::clone(&self.frame_sequence),
+        })
+    }
+
+    /// Submits `layers` to the compositor, see `FrameLayers` for how they get blended.
+    ///
+    /// Must follow every successful `begin_frame`, with no layers if nothing could be rendered:
+    /// skipping it breaks the frame cadence the runtime paces `wait_frame` with. Fails with
+    /// `Error::FrameOutOfOrder` if no frame is begun. The frame counts as ended even if the call
+    /// fails.
+    pub fn end_frame(&self, display_time: XrTime, layers: FrameLayers) -> Result<(), Error> {
+        if !self.frame_sequence.begun.swap(false, Ordering::Relaxed) {
+            return Err(Error::FrameOutOfOrder(
+                "xrEndFrame called without xrBeginFrame",
+            ));
+        }
+
+        let layers = layers.headers();
+        let end_info = openxr_sys::FrameEndInfo {
+            ty: openxr_sys::FrameEndInfo::TYPE,
+            next: std::ptr::null(),
+            display_time: display_time.into(),
+            environment_blend_mode: self.environment_blend_mode,
+            layer_count: layers.len() as u32,
+            layers: layers.as_ptr(),
+        };
+
+        let result = unsafe { (self.fp.end_frame)(self.handle, &end_info) };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        Ok(())
+    }
+
+    /// Runs a whole frame, `render` only having to record the draws of each view, for the apps
+    /// that drive the session themselves rather than through `App::run`. Returns whether the
+    /// frame was rendered, `false` right away, without waiting for a frame, while the context is
+    /// suspended, see `Context::suspend`. In order, it:
+    ///
+    /// 1. waits for the frame with xrWaitFrame, then begins it with xrBeginFrame,
+    /// 2. ends it without layers and returns `false` if the runtime doesn't want it rendered,
+    /// 3. locates the views in `space` at the predicted display time,
+    /// 4. syncs the actions, while the session is focused,
+    /// 5. acquires an image from each swapchain and waits for it, in the order their first view
+    ///    gets rendered in, see `Config::render_order`. If one is unavailable, the images
+    ///    acquired so far are released and the frame is ended without layers, returning `false`,
+    /// 6. waits for the frame slot, see `Config::max_frames_in_flight`, and starts recording its
+    ///    command buffer,
+    /// 7. calls `render(eye, image_index, view_projection, command_buffer)` for each view, in
+    ///    the order of `Config::render_order`, with the index of the image acquired from its
+    ///    swapchain and the matrix `FrameContext::view_projection` would give,
+    /// 8. submits the command buffer to the graphics queue, then releases the images,
+    /// 9. ends the frame with a projection layer in `space`, over the passthrough layer if any.
+    ///
+    /// Each view is rendered to its whole `Swapchain::sub_image`, as laid out by
+    /// `Context::swapchain_layout`, the images being in the layout `Swapchain::acquire_image`
+    /// leaves them in. An error from `render` stops the other views from being rendered.
+    /// Whatever fails after the images are acquired, the command buffer is submitted if it was
+    /// begun, the images are released and the frame is ended without layers before the error is
+    /// returned. The session events have to be polled, and the swapchains recreated when needed,
+    /// in between calls.
+    pub fn render_and_submit<F>(
+        &mut self,
+        space: openxr_sys::Space,
+        render: &mut F,
+    ) -> Result<bool, Error>
+    where
+        F: FnMut(usize, u32, [[f32; 4]; 4], ash::vk::CommandBuffer) -> Result<(), Error>,
+    {
+        let (render_order, (near, far), reverse_z) = match &self.recorder {
+            Some(recorder) if !self.suspended => (
+                recorder.render_order,
+                recorder.clip_planes,
+                recorder.reverse_z,
+            ),
+            // There are no swapchains to render to
+            _ => return Ok(false),
+        };
+
+        let wait_start = Instant::now();
+        let frame_state = self.wait_frame()?;
+        let wait_time = wait_start.elapsed();
+        let work_start = Instant::now();
+        let display_time = XrTime::from(frame_state.predicted_display_time);
+
+        let timing = || FrameTiming {
+            cpu_time: work_start.elapsed(),
+            wait_time,
+            gpu_time: None,
+            gpu_view_times: Vec::new(),
+        };
+
+        // From here on, returning early still ends the frame
+        let frame_guard = self.begin_guarded_frame(display_time)?;
+
+        if !bool::from(frame_state.should_render) {
+            frame_guard.end(self, FrameLayers::new())?;
+            self.frame_stats.record(timing());
+            return Ok(false);
+        }
+
+        let (_, views) =
+            self.locate_views_at(space, display_time, self.view_configuration_type())?;
+        if self.is_focused() {
+            self.sync_actions()?;
+        }
+
+        let layout = self.swapchain_layout;
+        let view_order: Vec<u32> = render_order
+            .passes(layout, views.len() as u32)
+            .into_iter()
+            .flatten()
+            .collect();
+        let mut swapchain_order: Vec<usize> = Vec::with_capacity(self.swapchains.len());
+        for &view in &view_order {
+            let swapchain_index = layout.swapchain_index(view);
+            if !swapchain_order.contains(&swapchain_index) {
+                swapchain_order.push(swapchain_index);
+            }
+        }
+
+        // On errors from here on, the images acquired go back before the guard ends the frame
+        let mut image_indices = vec![0; self.swapchains.len()];
+        for (acquired, &swapchain_index) in swapchain_order.iter().enumerate() {
+            let error = match self.swapchains[swapchain_index].acquire_image() {
+                Ok(index) => {
+                    image_indices[swapchain_index] = index;
+                    continue;
+                }
+                Err(error) => error,
+            };
+            let released = self.release_images(&swapchain_order[..acquired]);
+            if let Error::ImageUnavailable(result) = error {
+                debug!(
+                    "Skipping the frame, swapchain image unavailable: {:?}",
+                    result
+                );
+                released?;
+                frame_guard.end(self, FrameLayers::new())?;
+                self.frame_stats.record(timing());
+                return Ok(false);
+            }
+            return Err(error);
+        }
+
+        let recorder = self.recorder();
+        let recorded = match recorder.begin() {
+            Ok((_, command_buffer)) => {
+                let rendered = view_order.iter().try_for_each(|&view| {
+                    let located = &views[view as usize];
+                    let view_projection = math::to_columns(&math::view_projection(
+                        &located.pose,
+                        &located.fov,
+                        near,
+                        far,
+                        reverse_z,
+                    ));
+                    let image_index = image_indices[layout.swapchain_index(view)];
+                    render(view as usize, image_index, view_projection, command_buffer)
+                });
+                // Whatever `render` returned, the recording has to be submitted before the
+                // images are released
+                rendered.and(recorder.submit())
+            }
+            Err(error) => Err(error),
+        };
+        let released = self.release_images(&swapchain_order);
+        recorded?;
+        released?;
+
+        let projection_views: Vec<_> = views
+            .iter()
+            .enumerate()
+            .map(|(index, view)| openxr_sys::CompositionLayerProjectionView {
+                ty: openxr_sys::CompositionLayerProjectionView::TYPE,
+                next: std::ptr::null(),
+                pose: view.pose,
+                fov: view.fov,
+                sub_image: self.swapchains[layout.swapchain_index(index as u32)]
+                    .sub_image(layout, index as u32),
+            })
+            .collect();
+        // Lets the passthrough layer show through where the app renders a transparent color
+        let layer_flags = if self.passthrough_layer().is_some() {
+            openxr_sys::CompositionLayerFlags::BLEND_TEXTURE_SOURCE_ALPHA
+        } else {
+            openxr_sys::CompositionLayerFlags::EMPTY
+        };
+        let projection_layer = openxr_sys::CompositionLayerProjection {
+            ty: openxr_sys::CompositionLayerProjection::TYPE,
+            next: std::ptr::null(),
+            layer_flags,
+            space,
+            view_count: projection_views.len() as u32,
+            views: projection_views.as_ptr(),
+        };
+
+        let mut frame_layers = FrameLayers::new();
+        if let Some(passthrough_layer) = self.passthrough_layer() {
+            frame_layers.push(passthrough_layer);
+        }
+        frame_layers.push(&projection_layer);
+        frame_guard.end(self, frame_layers)?;
+
+        self.frame_stats.record(timing());
+        Ok(true)
+    }
+
+    /// Set up by `Context::new`, only the sessions of the tests lacking it.
+    pub(crate) fn recorder(&mut self) -> &mut FrameRecorder {
+        self.recorder
+            .as_mut()
+            .expect("The session has no frame recorder")
+    }
+
+    /// Releases the images acquired from the swapchains at `swapchain_indices`, going through
+    /// all of them even if one fails, the first failure being returned and the others logged.
+    pub(crate) fn release_images(&self, swapchain_indices: &[usize]) -> Result<(), Error> {
+        let mut released = Ok(());
+        for &swapchain_index in swapchain_indices {
+            if let Err(error) = self.swapchains[swapchain_index].release_image() {
+                if released.is_ok() {
+                    released = Err(error);
+                } else {
+                    warn!("Failed to release a swapchain image: {}", error);
+                }
+            }
+        }
+        released
+    }
+
+    /// Color spaces the compositor can convert the images from, from XR_FB_color_space.
+    /// Fails if the extension isn't enabled, see `Config::color_space`.
+    pub fn color_spaces(&self) -> Result<Vec<ColorSpace>, Error> {
+        let enumerate_color_spaces = self
+            .fp
+            .enumerate_color_spaces_FB
+            .ok_or(Error::MissingExtension(color_space::EXTENSION))?;
+
+        info!("xrEnumerateColorSpacesFB()");
+        let color_spaces = crate::enumerate(
+            self.fp.max_enumerate_count,
+            0,
+            |capacity, count, buffer| unsafe {
+                enumerate_color_spaces(self.handle, capacity, count, buffer)
+            },
+        )?;
+        Ok(color_spaces
+            .into_iter()
+            .filter_map(ColorSpace::from_raw)
+            .collect())
+    }
+
+    /// Tells the compositor which color space the images are rendered in, one of
+    /// `color_spaces`. Fails if XR_FB_color_space isn't enabled, see `Config::color_space`.
+    pub fn set_color_space(&self, color_space: ColorSpace) -> Result<(), Error> {
+        let set_color_space = self
+            .fp
+            .set_color_space_FB
+            .ok_or(Error::MissingExtension(color_space::EXTENSION))?;
+
+        info!("xrSetColorSpaceFB()");
+        let result = unsafe { set_color_space(self.handle, color_space.to_raw()) };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        Ok(())
+    }
+
+    /// Key of the model of the controller held in `hand`, from XR_MSFT_controller_model, to load
+    /// with `load_controller_model`. `None` while the runtime has no model for it, such as when
+    /// the controller isn't connected, or before the action sets are attached and the
+    /// interaction profile is known. Fails if `Config::controller_models` didn't enable the
+    /// extension.
+    pub fn controller_model_key(
+        &self,
+        hand: Hand,
+    ) -> Result<Option<openxr_sys::ControllerModelKeyMSFT>, Error> {
+        let user_path = self.string_to_path(hand.path())?;
+        controller_model::model_key(&self.fp, self.handle, user_path)
+    }
+
+    /// Loads the glTF model of `key` along with its animated nodes. The key stays the same
+    /// while the same controller is used, so this only has to be done again when it changes.
+    pub fn load_controller_model(
+        &self,
+        key: openxr_sys::ControllerModelKeyMSFT,
+    ) -> Result<ControllerModel, Error> {
+        controller_model::load(&self.fp, self.handle, key)
+    }
+
+    /// Creates an anchor at `pose` in `base_space`, as located at `time`, which the runtime then
+    /// keeps in place in the real world, see `SpatialAnchor`. `time` is typically the
+    /// `FrameContext::display_time` the pose was computed for. Fails with
+    /// `Error::MissingExtension` if the runtime lacks XR_MSFT_spatial_anchor.
+    pub fn create_spatial_anchor(
+        &self,
+        base_space: openxr_sys::Space,
+        pose: openxr_sys::Posef,
+        time: XrTime,
+    ) -> Result<SpatialAnchor, Error> {
+        SpatialAnchor::new(&self.fp, self.handle, base_space, pose, time)
+    }
+
+    /// Poses of the nodes of the model of `key`, in the order of `ControllerModel::nodes`, as of
+    /// the last `xrSyncActions`. Each is relative to the node's parent, and replaces its
+    /// transform in the glTF.
+    pub fn controller_model_node_poses(
+        &self,
+        key: openxr_sys::ControllerModelKeyMSFT,
+    ) -> Result<Vec<openxr_sys::Posef>, Error> {
+        controller_model::node_poses(&self.fp, self.handle, key)
+    }
+
+    /// Timings of the last frames submitted by `App::run`.
+    pub fn frame_stats(&self) -> &FrameStats {
+        &self.frame_stats
+    }
+
+    /// View configuration the session was begun with, `PRIMARY_QUAD_VARJO` with
+    /// `Config::quad_views` when supported, `PRIMARY_STEREO` otherwise.
+    pub fn view_configuration_type(&self) -> ViewConfigurationType {
+        self.view_configuration_type
+    }
+
+    /// Returns the pose and field of view of each view at `display_time`, relative to `space`.
+    pub fn locate_views(
+        &self,
+        space: openxr_sys::Space,
+        display_time: XrTime,
+    ) -> Result<Vec<openxr_sys::View>, Error> {
+        self.locate_views_at(space, display_time, self.view_configuration_type)
+            .map(|(_, views)| views)
+    }
+
+    /// Returns the views of `view_configuration_type` at `time`, relative to `space`, along with
+    /// the flags telling which parts of their poses are valid and tracked.
+    ///
+    /// Runtimes only predict reliably within a short window around the upcoming display times,
+    /// and only keep a short history, so poses far in the future or the past are extrapolated
+    /// or not valid at all.
+    pub fn locate_views_at(
+        &self,
+        space: openxr_sys::Space,
+        time: XrTime,
+        view_configuration_type: ViewConfigurationType,
+    ) -> Result<(openxr_sys::ViewStateFlags, Vec<openxr_sys::View>), Error> {
+        let locate_info = openxr_sys::ViewLocateInfo {
+            ty: openxr_sys::ViewLocateInfo::TYPE,
+            next: std::ptr::null(),
+            view_configuration_type,
+            display_time: time.into(),
+            space,
+        };
+        let mut view_state = openxr_sys::ViewState::out(std::ptr::null_mut());
+
+        let empty_view = openxr_sys::View {
+            ty: openxr_sys::View::TYPE,
+            next: std::ptr::null_mut(),
+            pose: crate::identity_pose(),
+            fov: openxr_sys::Fovf {
+                angle_left: 0.0,
+                angle_right: 0.0,
+                angle_up: 0.0,
+                angle_down: 0.0,
+            },
+        };
+        let views = crate::enumerate(
+            self.fp.max_enumerate_count,
+            empty_view,
+            |capacity, count, buffer| unsafe {
+                (self.fp.locate_views)(
+                    self.handle,
+                    &locate_info,
+                    view_state.as_mut_ptr(),
+                    capacity,
+                    count,
+                    buffer,
+                )
+            },
+        )?;
+
+        let view_state = unsafe { view_state.assume_init() };
+        let flags = view_state.view_state_flags;
+        if flags.contains(openxr_sys::ViewStateFlags::ORIENTATION_VALID) {
+            self.last_view_state_flags.set(Some(flags));
+        }
+        Ok((flags, views))
+    }
+
+    /// Whether the headset tracks the orientation and position of the views, according to the
+    /// last views located with a valid orientation. `None` until then.
+    ///
+    /// This isn't fixed: the position may only get tracked once tracking has warmed up, and
+    /// either can be lost at any time, when the headset's cameras are covered for instance, so
+    /// apps falling back to a neck model or warning the user should keep checking.
+    pub fn tracking_capabilities(&self) -> Option<TrackingCapabilities> {
+        self.last_view_state_flags
+            .get()
+            .map(TrackingCapabilities::from_flags)
+    }
+
+    /// Returns the size of the play area in the reference space of type `space_type`, `width`
+    /// being along its X axis and `height` the depth along its Z axis, in meters.
+    /// `None` means the runtime doesn't know the bounds, which may change later on.
+    pub fn reference_space_bounds(
+        &self,
+        space_type: openxr_sys::ReferenceSpaceType,
+    ) -> Result<Option<openxr_sys::Extent2Df>, Error> {
+        let mut bounds = openxr_sys::Extent2Df {
+            width: 0.0,
+            height: 0.0,
+        };
+
+        info!("xrGetReferenceSpaceBoundsRect()");
+        let result = unsafe {
+            (self.fp.get_reference_space_bounds_rect)(self.handle, space_type, &mut bounds)
+        };
+        if result.into_result()? == XrResult::SPACE_BOUNDS_UNAVAILABLE {
+            info!("  {:?} bounds unavailable", space_type);
+            return Ok(None);
+        }
+
+        info!(
+            "  {:?} bounds: {} x {} m",
+            space_type, bounds.width, bounds.height
+        );
+        Ok(Some(bounds))
+    }
+
+    /// Returns the mask of the view at `view_index`, for instance to fill the stencil buffer
+    /// before drawing so the pixels hidden by the lenses get skipped.
+    ///
+    /// The vertices are expressed in the view space, on the `z = -1` plane. The runtime may change
+    /// the mask over time (XrEventDataVisibilityMaskChangedKHR), it should be fetched again then.
+    pub fn visibility_mask(
+        &self,
+        view_configuration_type: ViewConfigurationType,
+        view_index: u32,
+        mask_type: openxr_sys::VisibilityMaskTypeKHR,
+    ) -> Result<VisibilityMask, Error> {
+        let get_visibility_mask = self
+            .fp
+            .get_visibility_mask_KHR
+            .ok_or(Error::MissingExtension(VISIBILITY_MASK_EXTENSION))?;
+
+        let mut mask = openxr_sys::VisibilityMaskKHR {
+            ty: openxr_sys::VisibilityMaskKHR::TYPE,
+            next: std::ptr::null_mut(),
+            vertex_capacity_input: 0,
+            vertex_count_output: 0,
+            vertices: std::ptr::null_mut(),
+            index_capacity_input: 0,
+            index_count_output: 0,
+            indices: std::ptr::null_mut(),
+        };
+
+        info!("xrGetVisibilityMaskKHR()");
+        let result = unsafe {
+            get_visibility_mask(
+                self.handle,
+                view_configuration_type,
+                view_index,
+                mask_type,
+                &mut mask,
+            )
+        };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        let mut vertices =
+            vec![openxr_sys::Vector2f { x: 0.0, y: 0.0 }; mask.vertex_count_output as usize];
+        let mut indices = vec![0; mask.index_count_output as usize];
+        mask.vertex_capacity_input = vertices.len() as u32;
+        mask.vertices = vertices.as_mut_ptr();
+        mask.index_capacity_input = indices.len() as u32;
+        mask.indices = indices.as_mut_ptr();
+
+        let result = unsafe {
+            get_visibility_mask(
+                self.handle,
+                view_configuration_type,
+                view_index,
+                mask_type,
+                &mut mask,
+            )
+        };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        vertices.truncate(mask.vertex_count_output as usize);
+        indices.truncate(mask.index_count_output as usize);
+
+        Ok(VisibilityMask { vertices, indices })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::{Cell, RefCell},
+        collections::VecDeque,
+    };
+
+    use openxr_sys::pfn;
+
+    use super::*;
+    use crate::mock;
+
+    thread_local! {
+        /// What the mock xrBeginFrame returns
+        static BEGIN_RESULT: Cell<XrResult> = Cell::new(XrResult::SUCCESS);
+    }
+
+    unsafe extern "system" fn wait_frame(
+        _session: openxr_sys::Session,
+        _info: *const openxr_sys::FrameWaitInfo,
+        state: *mut openxr_sys::FrameState,
+    ) -> XrResult {
+        state.write(openxr_sys::FrameState {
+            ty: openxr_sys::FrameState::TYPE,
+            next: std::ptr::null_mut(),
+            predicted_display_time: openxr_sys::Time::from_nanos(1),
+            predicted_display_period: openxr_sys::Duration::from_nanos(1),
+            should_render: true.into(),
+        });
+        XrResult::SUCCESS
+    }
+
+    unsafe extern "system" fn begin_frame(
+        _session: openxr_sys::Session,
+        _info: *const openxr_sys::FrameBeginInfo,
+    ) -> XrResult {
+        BEGIN_RESULT.with(Cell::get)
+    }
+
+    unsafe extern "system" fn end_frame(
+        _session: openxr_sys::Session,
+        _info: *const openxr_sys::FrameEndInfo,
+    ) -> XrResult {
+        XrResult::SUCCESS
+    }
+
+    thread_local! {
+        /// Events the mock xrPollEvent returns, oldest first, then the result it returns once
+        /// they're all out
+        static EVENTS: RefCell<(VecDeque<openxr_sys::EventDataBuffer>, XrResult)> =
+            RefCell::new((VecDeque::new(), XrResult::EVENT_UNAVAILABLE));
+        /// Number of calls to the mock xrPollEvent
+        static POLLS: Cell<u32> = Cell::new(0);
+    }
+
+    unsafe extern "system" fn poll_event(
+        _instance: openxr_sys::Instance,
+        buffer: *mut openxr_sys::EventDataBuffer,
+    ) -> XrResult {
+        POLLS.with(|polls| polls.set(polls.get() + 1));
+        EVENTS.with(|events| {
+            let (events, last_result) = &mut *events.borrow_mut();
+            match events.pop_front() {
+                Some(event) => {
+                    *buffer = event;
+                    XrResult::SUCCESS
+                }
+                None => *last_result,
+            }
+        })
+    }
+
+    /// Queues `event` for the mock xrPollEvent, `T` being one of the event structures.
+    fn push_event<T>(event: T) {
+        assert!(std::mem::size_of::<T>() <= std::mem::size_of::<openxr_sys::EventDataBuffer>());
+        let mut buffer: openxr_sys::EventDataBuffer = unsafe { std::mem::zeroed() };
+        unsafe { (&mut buffer as *mut _ as *mut T).write(event) };
+        EVENTS.with(|events| events.borrow_mut().0.push_back(buffer));
+    }
+
+    fn event_session() -> Session {
+        mock::session(mock::instance_fp(
+            &[("xrPollEvent", mock::function(poll_event as pfn::PollEvent))],
+            &[],
+        ))
+    }
+
+    fn perf_event(
+        from_level: openxr_sys::PerfSettingsNotificationLevelEXT,
+        to_level: openxr_sys::PerfSettingsNotificationLevelEXT,
+    ) -> openxr_sys::EventDataPerfSettingsEXT {
+        openxr_sys::EventDataPerfSettingsEXT {
+            ty: openxr_sys::EventDataPerfSettingsEXT::TYPE,
+            next: std::ptr::null(),
+            domain: openxr_sys::PerfSettingsDomainEXT::GPU,
+            sub_domain: openxr_sys::PerfSettingsSubDomainEXT::RENDERING,
+            from_level,
+            to_level,
+        }
+    }
+
+    #[test]
+    fn poll_events_until_unavailable() {
+        use openxr_sys::PerfSettingsNotificationLevelEXT as Level;
+
+        let mut session = event_session();
+        push_event(perf_event(Level::NORMAL, Level::WARNING));
+        push_event(perf_event(Level::WARNING, Level::IMPAIRED));
+
+        let events = session.poll_events().unwrap();
+        let levels: Vec<_> = events
+            .iter()
+            .map(|event| match *event {
+                SessionEvent::PerformanceChanged {
+                    from_level,
+                    to_level,
+                    ..
+                } => (from_level, to_level),
+                _ => panic!("Unexpected event {:?}", event),
+            })
+            .collect();
+        assert_eq!(
+            levels,
+            [
+                (Level::NORMAL, Level::WARNING),
+                (Level::WARNING, Level::IMPAIRED)
+            ]
+        );
+        // EVENT_UNAVAILABLE ends the loop right away
+        assert_eq!(POLLS.with(Cell::get), 3);
+
+        assert!(session.poll_events().unwrap().is_empty());
+        assert_eq!(POLLS.with(Cell::get), 4);
+    }
+
+    #[test]
+    fn reference_space_change_pending() {
+        let pose = openxr_sys::Posef {
+            orientation: openxr_sys::Quaternionf {
+                x: 0.0,
+                y: 0.6,
+                z: 0.0,
+                w: 0.8,
+            },
+            position: openxr_sys::Vector3f {
+                x: 1.0,
+                y: 0.0,
+                z: -2.0,
+            },
+        };
+        let mut session = event_session();
+        push_event(openxr_sys::EventDataReferenceSpaceChangePending {
+            ty: openxr_sys::EventDataReferenceSpaceChangePending::TYPE,
+            next: std::ptr::null(),
+            session: session.handle,
+            reference_space_type: openxr_sys::ReferenceSpaceType::STAGE,
+            change_time: openxr_sys::Time::from_nanos(123_456_789),
+            pose_valid: true.into(),
+            pose_in_previous_space: pose,
+        });
+
+        let events = session.poll_events().unwrap();
+        assert_eq!(events.len(), 1);
+        match events[0] {
+            SessionEvent::ReferenceSpaceChangePending {
+                reference_space_type,
+                change_time,
+                pose_valid,
+                pose_in_previous_space,
+            } => {
+                assert_eq!(reference_space_type, openxr_sys::ReferenceSpaceType::STAGE);
+                assert_eq!(change_time, XrTime::from_nanos(123_456_789));
+                assert!(pose_valid);
+                let orientation = pose_in_previous_space.orientation;
+                let position = pose_in_previous_space.position;
+                assert_eq!(
+                    [orientation.x, orientation.y, orientation.z, orientation.w],
+                    [0.0, 0.6, 0.0, 0.8]
+                );
+                assert_eq!([position.x, position.y, position.z], [1.0, 0.0, -2.0]);
+            }
+            event => panic!("Unexpected event {:?}", event),
+        }
+    }
+
+    thread_local! {
+        /// Base space the mock xrLocateSpace was last called with
+        static LOCATED_IN: Cell<openxr_sys::Space> = Cell::new(openxr_sys::Space::NULL);
+    }
+
+    unsafe extern "system" fn create_action_set(
+        _instance: openxr_sys::Instance,
+        _info: *const openxr_sys::ActionSetCreateInfo,
+        action_set: *mut openxr_sys::ActionSet,
+    ) -> XrResult {
+        *action_set = openxr_sys::ActionSet::from_raw(1);
+        XrResult::SUCCESS
+    }
+
+    unsafe extern "system" fn create_action(
+        _action_set: openxr_sys::ActionSet,
+        _info: *const openxr_sys::ActionCreateInfo,
+        action: *mut openxr_sys::Action,
+    ) -> XrResult {
+        *action = openxr_sys::Action::from_raw(1);
+        XrResult::SUCCESS
+    }
+
+    unsafe extern "system" fn string_to_path(
+        _instance: openxr_sys::Instance,
+        _path_string: *const std::os::raw::c_char,
+        path: *mut openxr_sys::Path,
+    ) -> XrResult {
+        *path = openxr_sys::Path::from_raw(1);
+        XrResult::SUCCESS
+    }
+
+    unsafe extern "system" fn suggest_bindings(
+        _instance: openxr_sys::Instance,
+        _suggested_bindings: *const openxr_sys::InteractionProfileSuggestedBinding,
+    ) -> XrResult {
+        XrResult::SUCCESS
+    }
+
+    unsafe extern "system" fn create_action_space(
+        _session: openxr_sys::Session,
+        _info: *const openxr_sys::ActionSpaceCreateInfo,
+        space: *mut openxr_sys::Space,
+    ) -> XrResult {
+        *space = openxr_sys::Space::from_raw(1);
+        XrResult::SUCCESS
+    }
+
+    unsafe extern "system" fn get_action_state_pose(
+        _session: openxr_sys::Session,
+        _info: *const openxr_sys::ActionStateGetInfo,
+        state: *mut openxr_sys::ActionStatePose,
+    ) -> XrResult {
+        (*state).is_active = true.into();
+        XrResult::SUCCESS
+    }
+
+    unsafe extern "system" fn locate_space(
+        _space: openxr_sys::Space,
+        base_space: openxr_sys::Space,
+        _time: openxr_sys::Time,
+        location: *mut openxr_sys::SpaceLocation,
+    ) -> XrResult {
+        LOCATED_IN.with(|located_in| located_in.set(base_space));
+        (*location).location_flags = openxr_sys::SpaceLocationFlags::ORIENTATION_VALID
+            | openxr_sys::SpaceLocationFlags::POSITION_VALID;
+        (*location).pose = crate::identity_pose();
+        XrResult::SUCCESS
+    }
+
+    #[test]
+    fn eye_gaze_follows_the_base_space() {
+        let functions = [
+            (
+                "xrCreateActionSet",
+                mock::function(create_action_set as pfn::CreateActionSet),
+            ),
+            (
+                "xrCreateAction",
+                mock::function(create_action as pfn::CreateAction),
+            ),
+            (
+                "xrStringToPath",
+                mock::function(string_to_path as pfn::StringToPath),
+            ),
+            (
+                "xrSuggestInteractionProfileBindings",
+                mock::function(suggest_bindings as pfn::SuggestInteractionProfileBindings),
+            ),
+            (
+                "xrCreateActionSpace",
+                mock::function(create_action_space as pfn::CreateActionSpace),
+            ),
+            (
+                "xrGetActionStatePose",
+                mock::function(get_action_state_pose as pfn::GetActionStatePose),
+            ),
+            (
+                "xrLocateSpace",
+                mock::function(locate_space as pfn::LocateSpace),
+            ),
+        ];
+        let mut session = mock::session(mock::instance_fp(&functions, &[eye_gaze::EXTENSION]));
+        let time = XrTime::from_nanos(1);
+
+        session
+            .create_eye_gaze(openxr_sys::Space::from_raw(10))
+            .unwrap();
+        assert!(session.eye_gaze_pose(time).unwrap().is_some());
+        assert_eq!(LOCATED_IN.with(Cell::get), openxr_sys::Space::from_raw(10));
+
+        // As `Context::recenter` does before destroying the old space
+        session.set_eye_gaze_base_space(openxr_sys::Space::from_raw(20));
+        assert!(session.eye_gaze_pose(time).unwrap().is_some());
+        assert_eq!(LOCATED_IN.with(Cell::get), openxr_sys::Space::from_raw(20));
+    }
+
+    thread_local! {
+        /// Foveation profiles alive, and the levels set on the swapchains, in order
+        static FOVEATION: RefCell<(u32, Vec<(openxr_sys::Swapchain, i32)>)> =
+            RefCell::new((0, Vec::new()));
+    }
+
+    /// Mock profiles are their level, offset so that none is null
+    const PROFILE_OFFSET: u64 = 100;
+
+    unsafe extern "system" fn create_foveation_profile(
+        _session: openxr_sys::Session,
+        info: *const FoveationProfileCreateInfoFB,
+        profile: *mut crate::foveation::FoveationProfileFB,
+    ) -> XrResult {
+        let level_info = (*info).next as *const FoveationLevelProfileCreateInfoFB;
+        *profile = PROFILE_OFFSET + (*level_info).level as u64;
+        FOVEATION.with(|foveation| foveation.borrow_mut().0 += 1);
+        XrResult::SUCCESS
+    }
+
+    unsafe extern "system" fn destroy_foveation_profile(
+        _profile: crate::foveation::FoveationProfileFB,
+    ) -> XrResult {
+        FOVEATION.with(|foveation| foveation.borrow_mut().0 -= 1);
+        XrResult::SUCCESS
+    }
+
+    unsafe extern "system" fn update_swapchain(
+        swapchain: openxr_sys::Swapchain,
+        state: *const SwapchainStateFoveationFB,
+    ) -> XrResult {
+        let level = ((*state).profile - PROFILE_OFFSET) as i32;
+        FOVEATION.with(|foveation| foveation.borrow_mut().1.push((swapchain, level)));
+        XrResult::SUCCESS
+    }
+
+    #[test]
+    fn set_foveation_level() {
+        let functions = [
+            (
+                "xrCreateFoveationProfileFB",
+                mock::function(
+                    create_foveation_profile as crate::foveation::CreateFoveationProfileFB,
+                ),
+            ),
+            (
+                "xrDestroyFoveationProfileFB",
+                mock::function(
+                    destroy_foveation_profile as crate::foveation::DestroyFoveationProfileFB,
+                ),
+            ),
+            (
+                "xrUpdateSwapchainFB",
+                mock::function(update_swapchain as crate::foveation::UpdateSwapchainFB),
+            ),
+        ];
+        let updates = || FOVEATION.with(|foveation| foveation.replace((0, Vec::new())));
+
+        // Without the extensions
+        let mut session = mock::session(mock::instance_fp(&functions, &[]));
+        let swapchains = vec![
+            openxr_sys::Swapchain::from_raw(1),
+            openxr_sys::Swapchain::from_raw(2),
+        ];
+        assert!(matches!(
+            session.foveate_swapchains(swapchains.clone(), FoveationLevel::High),
+            Err(Error::FoveationNotEnabled)
+        ));
+        assert_eq!(updates(), (0, vec![]));
+
+        let fp = mock::instance_fp(&functions, &crate::foveation::EXTENSIONS);
+        let mut session = mock::session(fp);
+        // Before the swapchains are created
+        assert!(matches!(
+            session.set_foveation_level(FoveationLevel::Low),
+            Err(Error::FoveationNotEnabled)
+        ));
+
+        session
+            .foveate_swapchains(swapchains.clone(), FoveationLevel::High)
+            .unwrap();
+        assert_eq!(updates(), (0, vec![(swapchains[0], 3), (swapchains[1], 3)]));
+
+        session.set_foveation_level(FoveationLevel::Low).unwrap();
+        assert_eq!(updates(), (0, vec![(swapchains[0], 1), (swapchains[1], 1)]));
+
+        // Recreated swapchains get the level last set rather than the configured one
+        session.forget_foveated_swapchains();
+        assert!(matches!(
+            session.set_foveation_level(FoveationLevel::Medium),
+            Err(Error::FoveationNotEnabled)
+        ));
+        let recreated = vec![openxr_sys::Swapchain::from_raw(3)];
+        session
+            .foveate_swapchains(recreated.clone(), FoveationLevel::High)
+            .unwrap();
+        assert_eq!(updates(), (0, vec![(recreated[0], 1)]));
+    }
+
+    #[test]
+    fn poll_events_failure() {
+        let mut session = event_session();
+        EVENTS.with(|events| events.borrow_mut().1 = XrResult::ERROR_INSTANCE_LOST);
+        assert!(matches!(
+            session.poll_events(),
+            Err(Error::Xr(XrResult::ERROR_INSTANCE_LOST))
+        ));
+    }
+
+    fn frame_session() -> Session {
+        mock::session(mock::instance_fp(
+            &[
+                ("xrWaitFrame", mock::function(wait_frame as pfn::WaitFrame)),
+                (
+                    "xrBeginFrame",
+                    mock::function(begin_frame as pfn::BeginFrame),
+                ),
+                ("xrEndFrame", mock::function(end_frame as pfn::EndFrame)),
+            ],
+            &[],
+        ))
+    }
+
+    fn end(session: &Session) -> Result<(), Error> {
+        session.end_frame(XrTime::from_nanos(1), FrameLayers::new())
+    }
+
+    #[test]
+    fn frame_sequence() {
+        let session = frame_session();
+        for _ in 0..3 {
+            session.wait_frame().unwrap();
+            session.begin_frame().unwrap();
+            end(&session).unwrap();
+        }
+    }
+
+    #[test]
+    fn begin_without_wait() {
+        let session = frame_session();
+        assert!(matches!(
+            session.begin_frame(),
+            Err(Error::FrameOutOfOrder(_))
+        ));
+
+        // Nor can a wait be used twice
+        session.wait_frame().unwrap();
+        session.begin_frame().unwrap();
+        end(&session).unwrap();
+        assert!(matches!(
+            session.begin_frame(),
+            Err(Error::FrameOutOfOrder(_))
+        ));
+    }
+
+    #[test]
+    fn end_without_begin() {
+        let session = frame_session();
+        assert!(matches!(end(&session), Err(Error::FrameOutOfOrder(_))));
+
+        session.wait_frame().unwrap();
+        assert!(matches!(end(&session), Err(Error::FrameOutOfOrder(_))));
+
+        session.begin_frame().unwrap();
+        end(&session).unwrap();
+        assert!(matches!(end(&session), Err(Error::FrameOutOfOrder(_))));
+    }
+
+    #[test]
+    fn double_wait() {
+        let session = frame_session();
+        session.wait_frame().unwrap();
+        assert!(matches!(
+            session.wait_frame(),
+            Err(Error::FrameOutOfOrder(_))
+        ));
+
+        // The next frame may be waited for while one is begun
+        session.begin_frame().unwrap();
+        session.wait_frame().unwrap();
+        end(&session).unwrap();
+        session.begin_frame().unwrap();
+        end(&session).unwrap();
+    }
+
+    #[test]
+    fn begin_before_end() {
+        let session = frame_session();
+        session.wait_frame().unwrap();
+        session.begin_frame().unwrap();
+        session.wait_frame().unwrap();
+        assert!(matches!(
+            session.begin_frame(),
+            Err(Error::FrameOutOfOrder(_))
+        ));
+    }
+
+    #[test]
+    fn wait_after_failed_begin() {
+        let session = frame_session();
+        session.wait_frame().unwrap();
+        BEGIN_RESULT.with(|result| result.set(XrResult::ERROR_SESSION_NOT_RUNNING));
+        assert!(matches!(
+            session.begin_frame(),
+            Err(Error::Xr(XrResult::ERROR_SESSION_NOT_RUNNING))
+        ));
+        // Nothing is begun, so there's nothing to end
+        assert!(matches!(end(&session), Err(Error::FrameOutOfOrder(_))));
+
+        BEGIN_RESULT.with(|result| result.set(XrResult::SUCCESS));
+        session.wait_frame().unwrap();
+        session.begin_frame().unwrap();
+        end(&session).unwrap();
+    }
+
+    #[test]
+    fn abandoned_frame_counts_as_ended() {
+        let session = frame_session();
+        session.wait_frame().unwrap();
+        drop(session.begin_guarded_frame(XrTime::from_nanos(1)).unwrap());
+
+        session.wait_frame().unwrap();
+        session.begin_frame().unwrap();
+        end(&session).unwrap();
+    }
+}
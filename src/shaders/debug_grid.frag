@@ -0,0 +1,12 @@
+// Built into debug_grid.frag.spv, regenerate with: glslangValidator -V debug_grid.frag -o debug_grid.frag.spv
+#version 450
+
+// Linear, unless the swapchain wants sRGB output from the shaders, in which case the vertex
+// colors come encoded already
+layout(location = 0) in vec3 color;
+
+layout(location = 0) out vec4 out_color;
+
+void main() {
+    out_color = vec4(color, 1.0);
+}
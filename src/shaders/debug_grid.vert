@@ -0,0 +1,16 @@
+// Built into debug_grid.vert.spv, regenerate with: glslangValidator -V debug_grid.vert -o debug_grid.vert.spv
+#version 450
+
+layout(push_constant) uniform PushConstants {
+    mat4 view_projection;
+} push_constants;
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 color;
+
+layout(location = 0) out vec3 out_color;
+
+void main() {
+    gl_Position = push_constants.view_projection * vec4(position, 1.0);
+    out_color = color;
+}
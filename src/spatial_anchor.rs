@@ -0,0 +1,168 @@
+use std::sync::Arc;
+
+use log::{info, warn};
+use openxr_sys::SpaceLocationFlags;
+
+use crate::{
+    error::{Error, XrResultExt},
+    time::XrTime,
+    XrInstanceFp,
+};
+
+pub(crate) const EXTENSION: &str = "XR_MSFT_spatial_anchor";
+
+/// Point the runtime keeps fixed in the real world, from XR_MSFT_spatial_anchor, created with
+/// `Session::create_spatial_anchor`. Unlike a reference space, which can shift as a whole when
+/// tracking improves, each anchor gets adjusted on its own to stay where it was put, so content
+/// placed relative to it stays put too.
+///
+/// The anchor and its space are destroyed on drop, or with `destroy` to get the error.
+pub struct SpatialAnchor {
+    handle: openxr_sys::SpatialAnchorMSFT,
+    space: openxr_sys::Space,
+    fp: Arc<XrInstanceFp>,
+}
+
+impl SpatialAnchor {
+    /// Creates an anchor at `pose` in `base_space`, as located at `time`, along with a space
+    /// whose origin is the anchor.
+    pub(crate) fn new(
+        fp: &Arc<XrInstanceFp>,
+        session: openxr_sys::Session,
+        base_space: openxr_sys::Space,
+        pose: openxr_sys::Posef,
+        time: XrTime,
+    ) -> Result<Self, Error> {
+        let (create_anchor, create_anchor_space) = match (
+            fp.create_spatial_anchor_MSFT,
+            fp.create_spatial_anchor_space_MSFT,
+        ) {
+            (Some(create_anchor), Some(create_anchor_space)) => {
+                (create_anchor, create_anchor_space)
+            }
+            _ => return Err(Error::MissingExtension(EXTENSION)),
+        };
+
+        let anchor_info = openxr_sys::SpatialAnchorCreateInfoMSFT {
+            ty: openxr_sys::SpatialAnchorCreateInfoMSFT::TYPE,
+            next: std::ptr::null(),
+            space: base_space,
+            pose,
+            time: time.into(),
+        };
+
+        info!("xrCreateSpatialAnchorMSFT()");
+        let mut handle = openxr_sys::SpatialAnchorMSFT::NULL;
+        let result = unsafe { create_anchor(session, &anchor_info, &mut handle) };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        // Owning the handle from here on, a failure below still destroys it
+        let mut anchor = SpatialAnchor {
+            handle,
+            space: openxr_sys::Space::NULL,
+            fp: Arc::clone(fp),
+        };
+
+        let space_info = openxr_sys::SpatialAnchorSpaceCreateInfoMSFT {
+            ty: openxr_sys::SpatialAnchorSpaceCreateInfoMSFT::TYPE,
+            next: std::ptr::null(),
+            anchor: handle,
+            pose_in_anchor_space: crate::identity_pose(),
+        };
+
+        info!("xrCreateSpatialAnchorSpaceMSFT()");
+        let result = unsafe { create_anchor_space(session, &space_info, &mut anchor.space) };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        Ok(anchor)
+    }
+
+    /// Space whose origin is the anchor, to locate it or to locate other spaces relative to it
+    /// with xrLocateSpace.
+    pub fn space(&self) -> openxr_sys::Space {
+        self.space
+    }
+
+    /// Pose of the anchor in `base_space` at `time`, or `None` while the runtime can't locate
+    /// it, for instance when tracking is lost or the anchor's surroundings haven't been seen yet.
+    pub fn locate(
+        &self,
+        base_space: openxr_sys::Space,
+        time: XrTime,
+    ) -> Result<Option<openxr_sys::Posef>, Error> {
+        let mut location = openxr_sys::SpaceLocation::out(std::ptr::null_mut());
+        let result = unsafe {
+            (self.fp.locate_space)(self.space, base_space, time.into(), location.as_mut_ptr())
+        };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        let location = unsafe { location.assume_init() };
+        let valid = SpaceLocationFlags::ORIENTATION_VALID | SpaceLocationFlags::POSITION_VALID;
+        if !location.location_flags.contains(valid) {
+            return Ok(None);
+        }
+
+        Ok(Some(location.pose))
+    }
+
+    /// Destroys the space then the anchor, reporting the failure that dropping them would only
+    /// log.
+    pub fn destroy(mut self) -> Result<(), Error> {
+        let space = std::mem::replace(&mut self.space, openxr_sys::Space::NULL);
+        if space != openxr_sys::Space::NULL {
+            info!("xrDestroySpace()");
+            let result = unsafe { (self.fp.destroy_space)(space) };
+            if !result.is_success() {
+                return Err(Error::Xr(result));
+            }
+        }
+
+        let handle = std::mem::replace(&mut self.handle, openxr_sys::SpatialAnchorMSFT::NULL);
+        // Loaded along with the creation function, which gave us the anchor
+        let destroy_anchor = self.fp.destroy_spatial_anchor_MSFT.unwrap();
+
+        info!("xrDestroySpatialAnchorMSFT()");
+        let result = unsafe { destroy_anchor(handle) };
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for SpatialAnchor {
+    fn drop(&mut self) {
+        if self.space != openxr_sys::Space::NULL {
+            info!("xrDestroySpace()");
+            let result = unsafe { (self.fp.destroy_space)(self.space) };
+            if !result.is_success() {
+                warn!(
+                    "Failed to destroy anchor space {:?}: {:?}",
+                    self.space, result
+                );
+            }
+        }
+
+        if self.handle == openxr_sys::SpatialAnchorMSFT::NULL {
+            return;
+        }
+
+        if let Some(destroy_anchor) = self.fp.destroy_spatial_anchor_MSFT {
+            info!("xrDestroySpatialAnchorMSFT()");
+            let result = unsafe { destroy_anchor(self.handle) };
+            if !result.is_success() {
+                warn!(
+                    "Failed to destroy spatial anchor {:?}: {:?}",
+                    self.handle, result
+                );
+            }
+        }
+    }
+}
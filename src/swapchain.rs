@@ -0,0 +1,794 @@
+use std::{os::raw::c_void, sync::Arc};
+
+use ash::{
+    version::{DeviceV1_0, InstanceV1_0},
+    vk::{self, Handle},
+};
+use log::{info, warn};
+use openxr_sys::{Result as XrResult, SwapchainUsageFlags};
+
+use crate::{
+    error::{Error, XrResultExt},
+    foveation::SwapchainCreateInfoFoveationFB,
+    XrInstanceFp,
+};
+
+/// Formats we'd rather use when the runtime offers them, in order of preference, the default of
+/// `Config::preferred_color_formats`.
+pub(crate) const PREFERRED_FORMATS: [vk::Format; 2] =
+    [vk::Format::R8G8B8A8_SRGB, vk::Format::B8G8R8A8_SRGB];
+
+/// What the fragment shaders rendering to the swapchains should output, following the format
+/// picked among `Config::preferred_color_formats`, see `Context::shader_output`.
+///
+/// Colors are meant to be computed in linear space, where lighting and blending are right. The
+/// runtime treats whatever the images hold as linear unless their format is sRGB, in which case
+/// the hardware does the encoding on write, and the decoding on blending and sampling, by itself.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShaderOutput {
+    /// Linear colors, encoded by the hardware with the sRGB formats, stored as is otherwise. With
+    /// 8-bit UNORM formats, dark gradients band, there being few values for them.
+    Linear,
+    /// Colors encoded to sRGB by the shaders, see `ShaderOutput::encode`. Only used with 8-bit
+    /// UNORM formats and `Config::manual_srgb_encode`. Blending then happens on the encoded
+    /// values, which is slightly off.
+    Srgb,
+}
+
+impl ShaderOutput {
+    /// Converts a linear color to what the shaders should output, applying the sRGB transfer
+    /// function for `Srgb`. Constant colors such as clear values need it too, the hardware only
+    /// encoding them with the sRGB formats.
+    pub fn encode(self, color: [f32; 3]) -> [f32; 3] {
+        match self {
+            ShaderOutput::Linear => color,
+            ShaderOutput::Srgb => {
+                let encode = |c: f32| {
+                    if c <= 0.003_130_8 {
+                        c * 12.92
+                    } else {
+                        1.055 * c.powf(1.0 / 2.4) - 0.055
+                    }
+                };
+                [encode(color[0]), encode(color[1]), encode(color[2])]
+            }
+        }
+    }
+}
+
+/// Whether the hardware converts between linear and sRGB when accessing images of `format`.
+pub fn is_srgb(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::R8_SRGB
+            | vk::Format::R8G8_SRGB
+            | vk::Format::R8G8B8_SRGB
+            | vk::Format::B8G8R8_SRGB
+            | vk::Format::R8G8B8A8_SRGB
+            | vk::Format::B8G8R8A8_SRGB
+            | vk::Format::A8B8G8R8_SRGB_PACK32
+    )
+}
+
+/// What the shaders rendering to `format` should output, `manual_encode` asking for sRGB output
+/// to the 8-bit UNORM formats.
+pub(crate) fn shader_output(format: vk::Format, manual_encode: bool) -> ShaderOutput {
+    let eight_bit_unorm = matches!(
+        format,
+        vk::Format::R8G8B8A8_UNORM | vk::Format::B8G8R8A8_UNORM | vk::Format::A8B8G8R8_UNORM_PACK32
+    );
+    if manual_encode && !eight_bit_unorm {
+        info!(
+            "Swapchain format {:?} isn't 8-bit UNORM, not encoding to sRGB in the shaders",
+            format
+        );
+    }
+    if manual_encode && eight_bit_unorm {
+        ShaderOutput::Srgb
+    } else {
+        ShaderOutput::Linear
+    }
+}
+
+/// Outcome of `Swapchain::wait_image`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageWait {
+    Ready,
+    /// The timeout expired before the image was ready
+    TimedOut,
+}
+
+/// How the views of a projection layer are laid out in their swapchains.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SwapchainLayout {
+    /// One swapchain per view, each view using all of its swapchain.
+    Separate,
+    /// A single swapchain with one array layer per view.
+    Array,
+    /// A single swapchain split into `view_count` columns of the same width, the first view on
+    /// the left.
+    SideBySide { view_count: u32 },
+}
+
+impl SwapchainLayout {
+    /// Index of the swapchain the view at `view_index` is rendered to.
+    pub fn swapchain_index(self, view_index: u32) -> usize {
+        match self {
+            SwapchainLayout::Separate => view_index as usize,
+            SwapchainLayout::Array | SwapchainLayout::SideBySide { .. } => 0,
+        }
+    }
+}
+
+pub struct Swapchain {
+    pub handle: openxr_sys::Swapchain,
+    pub format: vk::Format,
+    pub width: u32,
+    pub height: u32,
+    /// Number of layers of the images, one per view with `SwapchainLayout::Array`
+    pub array_size: u32,
+    /// Sample count the swapchain was created with, which anything rendering to it (render pass,
+    /// depth buffer...) must match.
+    pub sample_count: u32,
+    pub usage: SwapchainUsageFlags,
+    /// Vulkan usage matching `usage`, to use for anything created on top of the runtime's images
+    /// (image views, framebuffers...).
+    pub image_usage: vk::ImageUsageFlags,
+    /// Images owned by the runtime, `acquire_image` returning an index into them.
+    pub images: Vec<vk::Image>,
+    fp: Arc<XrInstanceFp>,
+}
+
+impl Swapchain {
+    /// Sub-image covering the whole swapchain, for layers that display all of it.
+    pub fn full_sub_image(&self) -> openxr_sys::SwapchainSubImage {
+        openxr_sys::SwapchainSubImage {
+            swapchain: self.handle,
+            image_rect: openxr_sys::Rect2Di {
+                offset: openxr_sys::Offset2Di { x: 0, y: 0 },
+                extent: openxr_sys::Extent2Di {
+                    width: self.width as i32,
+                    height: self.height as i32,
+                },
+            },
+            image_array_index: 0,
+        }
+    }
+
+    /// Sub-image the view at `view_index` is rendered to, for its `CompositionLayerProjectionView`.
+    /// With `SwapchainLayout::Separate`, `self` is expected to be the swapchain of that view.
+    ///
+    /// With `SwapchainLayout::SideBySide`, the columns are `width / view_count` wide, rounded
+    /// down. The swapchains the context creates are always a multiple of that, but with any other
+    /// width the `width % view_count` rightmost pixel columns belong to no view.
+    pub fn sub_image(
+        &self,
+        layout: SwapchainLayout,
+        view_index: u32,
+    ) -> openxr_sys::SwapchainSubImage {
+        let mut sub_image = self.full_sub_image();
+
+        match layout {
+            SwapchainLayout::Separate => {}
+            SwapchainLayout::Array => sub_image.image_array_index = view_index,
+            SwapchainLayout::SideBySide { view_count } => {
+                debug_assert!(view_index < view_count);
+                let view_width = self.width / view_count;
+                sub_image.image_rect.offset.x = (view_index * view_width) as i32;
+                sub_image.image_rect.extent.width = view_width as i32;
+            }
+        }
+
+        sub_image
+    }
+
+    /// Acquires the next image of the swapchain and waits until it can be rendered to.
+    ///
+    /// Fails with `Error::ImageUnavailable` when the runtime reports `SESSION_LOSS_PENDING`,
+    /// which happens while the session winds down and only means this frame shouldn't be
+    /// rendered. The image is released again in that case. Any other failure, such as
+    /// `SESSION_LOST` or `CALL_ORDER_INVALID` (an image acquired twice without being released),
+    /// is fatal and returned as `Error::Xr`.
+    pub fn acquire_image(&self) -> Result<u32, Error> {
+        let acquire_info = openxr_sys::SwapchainImageAcquireInfo {
+            ty: openxr_sys::SwapchainImageAcquireInfo::TYPE,
+            next: std::ptr::null(),
+        };
+        let mut index = 0;
+        let result =
+            unsafe { (self.fp.acquire_swapchain_image)(self.handle, &acquire_info, &mut index) };
+
+        // A qualified success, the image being acquired nonetheless
+        let loss_pending = result.into_result()? == XrResult::SESSION_LOSS_PENDING;
+
+        // An infinite wait shouldn't time out, but the image would have to be waited on again
+        while self.wait_image(openxr_sys::Duration::INFINITE)? == ImageWait::TimedOut {}
+
+        if loss_pending {
+            self.release_image()?;
+            return Err(Error::ImageUnavailable(XrResult::SESSION_LOSS_PENDING));
+        }
+
+        Ok(index)
+    }
+
+    /// Waits for the last acquired image to be ready to be rendered to, up to `timeout`.
+    ///
+    /// `acquire_image` already does so, this is for callers acquiring the images themselves.
+    /// `ImageWait::TimedOut` isn't an error: the image stays acquired and must be waited on
+    /// again before being rendered to.
+    pub fn wait_image(&self, timeout: openxr_sys::Duration) -> Result<ImageWait, Error> {
+        let wait_info = openxr_sys::SwapchainImageWaitInfo {
+            ty: openxr_sys::SwapchainImageWaitInfo::TYPE,
+            next: std::ptr::null(),
+            timeout,
+        };
+        let result = unsafe { (self.fp.wait_swapchain_image)(self.handle, &wait_info) };
+
+        match result.into_result()? {
+            XrResult::TIMEOUT_EXPIRED => Ok(ImageWait::TimedOut),
+            // SESSION_LOSS_PENDING included, the image is ready all the same
+            _ => Ok(ImageWait::Ready),
+        }
+    }
+
+    /// Hands the last acquired image back to the runtime.
+    pub fn release_image(&self) -> Result<(), Error> {
+        let release_info = openxr_sys::SwapchainImageReleaseInfo {
+            ty: openxr_sys::SwapchainImageReleaseInfo::TYPE,
+            next: std::ptr::null(),
+        };
+        let result = unsafe { (self.fp.release_swapchain_image)(self.handle, &release_info) };
+
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        Ok(())
+    }
+
+    /// Destroys the swapchain, reporting the failure that dropping it would only log.
+    pub fn destroy(mut self) -> Result<(), Error> {
+        let handle = std::mem::replace(&mut self.handle, openxr_sys::Swapchain::NULL);
+
+        info!("xrDestroySwapchain()");
+        let result = unsafe { (self.fp.destroy_swapchain)(handle) };
+
+        if !result.is_success() {
+            return Err(Error::Xr(result));
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Swapchain {
+    fn drop(&mut self) {
+        if self.handle == openxr_sys::Swapchain::NULL {
+            return;
+        }
+
+        info!("xrDestroySwapchain()");
+        let result = unsafe { (self.fp.destroy_swapchain)(self.handle) };
+        if !result.is_success() {
+            warn!(
+                "Failed to destroy swapchain {:?}: {:?}",
+                self.handle, result
+            );
+        }
+    }
+}
+
+/// `sub_image` shrunk by `scale`, from its top left corner, keeping at least a pixel each way.
+pub(crate) fn scale_sub_image(
+    mut sub_image: openxr_sys::SwapchainSubImage,
+    scale: f32,
+) -> openxr_sys::SwapchainSubImage {
+    let extent = &mut sub_image.image_rect.extent;
+    extent.width = ((extent.width as f32 * scale) as i32).max(1);
+    extent.height = ((extent.height as f32 * scale) as i32).max(1);
+    sub_image
+}
+
+/// Records the viewport and scissor covering `sub_image`, for pipelines with both dynamic.
+///
+/// The viewport keeps Vulkan's orientation, Y pointing down: the projection matrices of
+/// `FrameContext` already account for it, so a flipped viewport (negative height) would render
+/// upside down.
+pub fn set_viewport_and_scissor(
+    device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+    sub_image: &openxr_sys::SwapchainSubImage,
+) {
+    let rect = sub_image.image_rect;
+    let viewport = vk::Viewport {
+        x: rect.offset.x as f32,
+        y: rect.offset.y as f32,
+        width: rect.extent.width as f32,
+        height: rect.extent.height as f32,
+        min_depth: 0.0,
+        max_depth: 1.0,
+    };
+    let scissor = vk::Rect2D {
+        offset: vk::Offset2D {
+            x: rect.offset.x,
+            y: rect.offset.y,
+        },
+        extent: vk::Extent2D {
+            width: rect.extent.width as u32,
+            height: rect.extent.height as u32,
+        },
+    };
+
+    unsafe {
+        device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+        device.cmd_set_scissor(command_buffer, 0, &[scissor]);
+    }
+}
+
+/// Picks a format among the ones returned by xrEnumerateSwapchainFormats, the first of
+/// `preferred` the runtime supports, or the runtime's favorite if it supports none of them.
+/// Fails with `Error::NoSwapchainFormats` when `formats` is empty.
+pub fn select_format(formats: &[i64], preferred: &[vk::Format]) -> Result<vk::Format, Error> {
+    let formats: Vec<_> = formats
+        .iter()
+        .map(|&format| vk::Format::from_raw(format as i32))
+        .collect();
+
+    if let Some(format) = preferred
+        .iter()
+        .copied()
+        .find(|format| formats.contains(format))
+    {
+        return Ok(format);
+    }
+    let format = *formats.first().ok_or(Error::NoSwapchainFormats)?;
+    warn!(
+        "None of the preferred formats {:?} is supported, using {:?}",
+        preferred, format
+    );
+    Ok(format)
+}
+
+/// Formats to try creating the swapchains with, and whether to force a single sample, in order:
+/// `selected` as is, then single-sampled if it was `multisampled`, then the other `preferred`
+/// formats the runtime supports, then the rest of the runtime's `formats`, all single-sampled.
+/// Only the first attempt without `fallbacks`.
+pub(crate) fn creation_attempts(
+    selected: vk::Format,
+    formats: &[i64],
+    preferred: &[vk::Format],
+    multisampled: bool,
+    fallbacks: bool,
+) -> Vec<(vk::Format, bool)> {
+    let mut attempts = vec![(selected, false)];
+    if !fallbacks {
+        return attempts;
+    }
+    if multisampled {
+        attempts.push((selected, true));
+    }
+
+    let formats: Vec<_> = formats
+        .iter()
+        .map(|&format| vk::Format::from_raw(format as i32))
+        .collect();
+    let others = preferred
+        .iter()
+        .filter(|format| formats.contains(format))
+        .chain(&formats);
+    for &format in others {
+        if !attempts.iter().any(|&(tried, _)| tried == format) {
+            attempts.push((format, true));
+        }
+    }
+    attempts
+}
+
+/// Translates the XR swapchain usage into the equivalent Vulkan image usage.
+pub fn image_usage(usage: SwapchainUsageFlags) -> vk::ImageUsageFlags {
+    let mapping = [
+        (
+            SwapchainUsageFlags::COLOR_ATTACHMENT,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT,
+        ),
+        (
+            SwapchainUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+        ),
+        (
+            SwapchainUsageFlags::UNORDERED_ACCESS,
+            vk::ImageUsageFlags::STORAGE,
+        ),
+        (
+            SwapchainUsageFlags::TRANSFER_SRC,
+            vk::ImageUsageFlags::TRANSFER_SRC,
+        ),
+        (
+            SwapchainUsageFlags::TRANSFER_DST,
+            vk::ImageUsageFlags::TRANSFER_DST,
+        ),
+        (SwapchainUsageFlags::SAMPLED, vk::ImageUsageFlags::SAMPLED),
+    ];
+
+    mapping
+        .iter()
+        .filter(|(xr_usage, _)| usage.contains(*xr_usage))
+        .fold(vk::ImageUsageFlags::empty(), |acc, (_, vk_usage)| {
+            acc | *vk_usage
+        })
+}
+
+/// Checks that the device can create optimal-tiling 2D images of `format` with `usage`.
+pub fn supports_usage(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+    format: vk::Format,
+    usage: SwapchainUsageFlags,
+) -> bool {
+    let flags = if usage.contains(SwapchainUsageFlags::MUTABLE_FORMAT) {
+        vk::ImageCreateFlags::MUTABLE_FORMAT
+    } else {
+        vk::ImageCreateFlags::empty()
+    };
+
+    let result = unsafe {
+        instance.get_physical_device_image_format_properties(
+            physical_device,
+            format,
+            vk::ImageType::TYPE_2D,
+            vk::ImageTiling::OPTIMAL,
+            image_usage(usage),
+            flags,
+        )
+    };
+
+    result.is_ok()
+}
+
+/// Picks the highest sample count that's at most the runtime's `recommended` one and `max`, and
+/// that the device supports. Warns if that means going below the recommendation.
+pub fn clamp_sample_count(
+    recommended: u32,
+    supported: vk::SampleCountFlags,
+    max: Option<u32>,
+) -> u32 {
+    let limit = recommended.min(max.unwrap_or(u32::MAX));
+
+    // The flag for each count has the count as value
+    let sample_count = [64, 32, 16, 8, 4, 2, 1]
+        .iter()
+        .copied()
+        .find(|&count| count <= limit && supported.contains(vk::SampleCountFlags::from_raw(count)))
+        .unwrap_or(1);
+
+    if sample_count != recommended {
+        warn!(
+            "Using {} samples instead of the {} recommended by the runtime (device supports {:?}, max {:?})",
+            sample_count, recommended, supported, max
+        );
+    }
+
+    sample_count
+}
+
+/// Recommended size of the images for `view`, multiplied by `scale` and clamped to the maximum
+/// the runtime supports.
+pub fn scaled_size(view: &openxr_sys::ViewConfigurationView, scale: f32) -> (u32, u32) {
+    let scale_dimension =
+        |recommended: u32, max: u32| ((recommended as f32 * scale).round() as u32).clamp(1, max);
+    (
+        scale_dimension(view.recommended_image_rect_width, view.max_image_rect_width),
+        scale_dimension(
+            view.recommended_image_rect_height,
+            view.max_image_rect_height,
+        ),
+    )
+}
+
+/// Picks how to lay out the views in the swapchains: all of them in the layers of one swapchain
+/// if the device allows that many layers, side by side in one swapchain if the device allows
+/// that width, and in separate swapchains when they don't all have the same size, which is the
+/// case of the Varjo quad views.
+pub(crate) fn select_layout(
+    view_sizes: &[(u32, u32)],
+    limits: &vk::PhysicalDeviceLimits,
+) -> SwapchainLayout {
+    let view_count = view_sizes.len() as u32;
+    let same_size = view_sizes.windows(2).all(|pair| pair[0] == pair[1]);
+    if view_count < 2 || !same_size {
+        return SwapchainLayout::Separate;
+    }
+
+    let (width, _) = view_sizes[0];
+    if limits.max_image_array_layers >= view_count {
+        SwapchainLayout::Array
+    } else if width * view_count <= limits.max_image_dimension2_d {
+        SwapchainLayout::SideBySide { view_count }
+    } else {
+        SwapchainLayout::Separate
+    }
+}
+
+/// Creates a swapchain of `array_size` layers of `width`x`height` images. `width` covers all the
+/// views with `SwapchainLayout::SideBySide`. The runtime's error is returned as is, for instance
+/// `ERROR_FEATURE_UNSUPPORTED` when it can't honor `create_flags`. `foveation` chains
+/// `XrSwapchainCreateInfoFoveationFB` with the fragment density map flag, which
+/// `Session::set_foveation_level` needs.
+pub fn create_swapchain(
+    fp: &Arc<XrInstanceFp>,
+    session: openxr_sys::Session,
+    (width, height): (u32, u32),
+    array_size: u32,
+    format: vk::Format,
+    sample_count: u32,
+    usage: SwapchainUsageFlags,
+    create_flags: openxr_sys::SwapchainCreateFlags,
+    foveation: bool,
+) -> Result<Swapchain, Error> {
+    let foveation_info = SwapchainCreateInfoFoveationFB::new();
+    let create_info = openxr_sys::SwapchainCreateInfo {
+        ty: openxr_sys::SwapchainCreateInfo::TYPE,
+        next: if foveation {
+            &foveation_info as *const _ as *const c_void
+        } else {
+            std::ptr::null()
+        },
+        create_flags,
+        usage_flags: usage,
+        format: format.as_raw() as i64,
+        sample_count,
+        width,
+        height,
+        face_count: 1,
+        array_size,
+        mip_count: 1,
+    };
+
+    info!("xrCreateSwapchain()");
+    let mut handle = openxr_sys::Swapchain::NULL;
+    let result = unsafe { (fp.create_swapchain)(session, &create_info, &mut handle) };
+
+    if !result.is_success() {
+        return Err(Error::Xr(result));
+    }
+
+    // Owning the handle from here on, a failure below still destroys it
+    let mut swapchain = Swapchain {
+        handle,
+        format,
+        width: create_info.width,
+        height: create_info.height,
+        array_size,
+        sample_count: create_info.sample_count,
+        usage,
+        image_usage: image_usage(usage),
+        images: Vec::new(),
+        fp: Arc::clone(fp),
+    };
+    swapchain.images = enumerate_images(fp, handle)?;
+
+    Ok(swapchain)
+}
+
+/// Returns the Vulkan images backing `swapchain`.
+///
+/// The runtime checks the `ty` of the structures it fills, so they all get initialized here
+/// before being handed over.
+pub fn enumerate_images(
+    fp: &XrInstanceFp,
+    swapchain: openxr_sys::Swapchain,
+) -> Result<Vec<vk::Image>, Error> {
+    info!("xrEnumerateSwapchainImages()");
+    let empty_image = openxr_sys::SwapchainImageVulkanKHR {
+        ty: openxr_sys::SwapchainImageVulkanKHR::TYPE,
+        next: std::ptr::null_mut(),
+        image: 0,
+    };
+    let images = crate::enumerate(
+        fp.max_enumerate_count,
+        empty_image,
+        |capacity, count, buffer| unsafe {
+            (fp.enumerate_swapchain_images)(
+                swapchain,
+                capacity,
+                count,
+                buffer as *mut openxr_sys::SwapchainImageBaseHeader,
+            )
+        },
+    )?;
+    if images.is_empty() {
+        return Err(Error::NoSwapchainImages);
+    }
+
+    // A runtime getting these wrong would otherwise only show up much later, as a crash when
+    // creating the image views or framebuffers
+    for (index, image) in images.iter().enumerate() {
+        if image.ty != openxr_sys::SwapchainImageVulkanKHR::TYPE {
+            return Err(Error::InvalidSwapchainImage(index, "structure type"));
+        }
+        if image.image == 0 {
+            return Err(Error::InvalidSwapchainImage(index, "null image"));
+        }
+    }
+
+    Ok(images
+        .iter()
+        .map(|image| vk::Image::from_raw(image.image))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use openxr_sys::pfn;
+
+    use super::*;
+    use crate::mock;
+
+    thread_local! {
+        /// What the mock xrWaitSwapchainImage returns
+        static WAIT_RESULT: Cell<XrResult> = Cell::new(XrResult::SUCCESS);
+    }
+
+    unsafe extern "system" fn wait_swapchain_image(
+        _swapchain: openxr_sys::Swapchain,
+        _info: *const openxr_sys::SwapchainImageWaitInfo,
+    ) -> XrResult {
+        WAIT_RESULT.with(Cell::get)
+    }
+
+    unsafe extern "system" fn destroy_swapchain(_swapchain: openxr_sys::Swapchain) -> XrResult {
+        XrResult::SUCCESS
+    }
+
+    /// Swapchain of a mock runtime, without images.
+    fn mock_swapchain(width: u32, height: u32, array_size: u32) -> Swapchain {
+        let fp = mock::instance_fp(
+            &[
+                (
+                    "xrWaitSwapchainImage",
+                    mock::function(wait_swapchain_image as pfn::WaitSwapchainImage),
+                ),
+                (
+                    "xrDestroySwapchain",
+                    mock::function(destroy_swapchain as pfn::DestroySwapchain),
+                ),
+            ],
+            &[],
+        );
+        Swapchain {
+            handle: openxr_sys::Swapchain::from_raw(1),
+            format: vk::Format::R8G8B8A8_SRGB,
+            width,
+            height,
+            array_size,
+            sample_count: 1,
+            usage: SwapchainUsageFlags::COLOR_ATTACHMENT,
+            image_usage: vk::ImageUsageFlags::COLOR_ATTACHMENT,
+            images: Vec::new(),
+            fp,
+        }
+    }
+
+    fn rect(sub_image: openxr_sys::SwapchainSubImage) -> (i32, i32, i32, i32, u32) {
+        let rect = sub_image.image_rect;
+        (
+            rect.offset.x,
+            rect.offset.y,
+            rect.extent.width,
+            rect.extent.height,
+            sub_image.image_array_index,
+        )
+    }
+
+    #[test]
+    fn separate_sub_image() {
+        let swapchain = mock_swapchain(1000, 800, 1);
+        for view in 0..2 {
+            let sub_image = swapchain.sub_image(SwapchainLayout::Separate, view);
+            assert_eq!(rect(sub_image), (0, 0, 1000, 800, 0));
+        }
+    }
+
+    #[test]
+    fn array_sub_image() {
+        let swapchain = mock_swapchain(1000, 800, 2);
+        assert_eq!(
+            rect(swapchain.sub_image(SwapchainLayout::Array, 0)),
+            (0, 0, 1000, 800, 0)
+        );
+        assert_eq!(
+            rect(swapchain.sub_image(SwapchainLayout::Array, 1)),
+            (0, 0, 1000, 800, 1)
+        );
+    }
+
+    #[test]
+    fn side_by_side_sub_image() {
+        let layout = SwapchainLayout::SideBySide { view_count: 2 };
+        let swapchain = mock_swapchain(2000, 800, 1);
+        assert_eq!(rect(swapchain.sub_image(layout, 0)), (0, 0, 1000, 800, 0));
+        assert_eq!(
+            rect(swapchain.sub_image(layout, 1)),
+            (1000, 0, 1000, 800, 0)
+        );
+
+        let layout = SwapchainLayout::SideBySide { view_count: 4 };
+        let swapchain = mock_swapchain(4000, 800, 1);
+        for view in 0..4 {
+            let x = view as i32 * 1000;
+            assert_eq!(
+                rect(swapchain.sub_image(layout, view)),
+                (x, 0, 1000, 800, 0)
+            );
+        }
+    }
+
+    #[test]
+    fn side_by_side_sub_image_with_leftover_columns() {
+        let layout = SwapchainLayout::SideBySide { view_count: 4 };
+        let swapchain = mock_swapchain(1003, 800, 1);
+        let rects: Vec<_> = (0..4)
+            .map(|view| rect(swapchain.sub_image(layout, view)))
+            .collect();
+        assert_eq!(
+            rects,
+            [
+                (0, 0, 250, 800, 0),
+                (250, 0, 250, 800, 0),
+                (500, 0, 250, 800, 0),
+                (750, 0, 250, 800, 0),
+            ]
+        );
+        // The 3 rightmost columns are left out
+        let (x, _, width, _, _) = rects[3];
+        assert_eq!(swapchain.width as i32 - (x + width), 3);
+    }
+
+    fn wait_image_with(result: XrResult) -> Result<ImageWait, Error> {
+        WAIT_RESULT.with(|wait_result| wait_result.set(result));
+        mock_swapchain(1, 1, 1).wait_image(openxr_sys::Duration::from_nanos(1))
+    }
+
+    #[test]
+    fn wait_image_results() {
+        assert_eq!(
+            wait_image_with(XrResult::SUCCESS).unwrap(),
+            ImageWait::Ready
+        );
+        assert_eq!(
+            wait_image_with(XrResult::TIMEOUT_EXPIRED).unwrap(),
+            ImageWait::TimedOut
+        );
+        assert_eq!(
+            wait_image_with(XrResult::SESSION_LOSS_PENDING).unwrap(),
+            ImageWait::Ready
+        );
+        assert!(matches!(
+            wait_image_with(XrResult::ERROR_SESSION_LOST),
+            Err(Error::Xr(XrResult::ERROR_SESSION_LOST))
+        ));
+    }
+
+    #[test]
+    fn select_format_falls_back() {
+        let formats = [
+            vk::Format::R8G8B8A8_UNORM.as_raw() as i64,
+            vk::Format::B8G8R8A8_SRGB.as_raw() as i64,
+        ];
+        assert_eq!(
+            select_format(&formats, &PREFERRED_FORMATS).unwrap(),
+            vk::Format::B8G8R8A8_SRGB
+        );
+        assert_eq!(
+            select_format(&formats, &[vk::Format::R16G16B16A16_SFLOAT]).unwrap(),
+            vk::Format::R8G8B8A8_UNORM
+        );
+        assert!(matches!(
+            select_format(&[], &PREFERRED_FORMATS),
+            Err(Error::NoSwapchainFormats)
+        ));
+    }
+}
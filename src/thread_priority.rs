@@ -0,0 +1,71 @@
+//! Best-effort OS priority bump for the thread submitting the frames, see
+//! `Config::render_thread_priority`.
+
+use std::io;
+
+/// Nice value of the thread: the lowest an unprivileged process can usually go with a raised
+/// RLIMIT_NICE, while leaving room above for the compositor.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const NICE: i32 = -10;
+
+/// Raises the priority of the calling thread, failing with the OS error when it's not allowed
+/// to, or when the platform has no implementation.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn raise_current_thread_priority() -> io::Result<()> {
+    extern "C" {
+        fn gettid() -> i32;
+        fn setpriority(which: i32, who: u32, prio: i32) -> i32;
+    }
+    // PRIO_PROCESS with a thread ID only affects that thread on Linux
+    const PRIO_PROCESS: i32 = 0;
+
+    let result = unsafe { setpriority(PRIO_PROCESS, gettid() as u32, NICE) };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+pub(crate) fn raise_current_thread_priority() -> io::Result<()> {
+    extern "C" {
+        fn pthread_set_qos_class_self_np(qos_class: u32, relative_priority: i32) -> i32;
+    }
+    const QOS_CLASS_USER_INTERACTIVE: u32 = 0x21;
+
+    let result = unsafe { pthread_set_qos_class_self_np(QOS_CLASS_USER_INTERACTIVE, 0) };
+    if result != 0 {
+        return Err(io::Error::from_raw_os_error(result));
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "windows")]
+pub(crate) fn raise_current_thread_priority() -> io::Result<()> {
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetCurrentThread() -> *mut std::ffi::c_void;
+        fn SetThreadPriority(thread: *mut std::ffi::c_void, priority: i32) -> i32;
+    }
+    const THREAD_PRIORITY_HIGHEST: i32 = 2;
+
+    let result = unsafe { SetThreadPriority(GetCurrentThread(), THREAD_PRIORITY_HIGHEST) };
+    if result == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "windows"
+)))]
+pub(crate) fn raise_current_thread_priority() -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "thread priorities aren't supported on this platform",
+    ))
+}
@@ -0,0 +1,128 @@
+use std::{
+    convert::TryFrom,
+    ops::{Add, Sub},
+    time::Duration,
+};
+
+/// Point in time on the runtime clock, in nanoseconds.
+///
+/// The epoch is defined by the runtime, so an absolute `XrTime` means nothing on its own and
+/// can't be compared with values from another runtime or with the system clock. Only the
+/// difference between two `XrTime`s from the same instance is meaningful.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct XrTime(i64);
+
+impl XrTime {
+    pub fn from_nanos(nanos: i64) -> Self {
+        XrTime(nanos)
+    }
+
+    pub fn as_nanos(self) -> i64 {
+        self.0
+    }
+
+    /// Time elapsed from `earlier` to `self`, or `None` if `earlier` is the later of the two.
+    pub fn duration_since(self, earlier: XrTime) -> Option<Duration> {
+        let delta = self.0.checked_sub(earlier.0)?;
+        u64::try_from(delta).ok().map(Duration::from_nanos)
+    }
+
+    /// `self` moved forward by `duration`, or `None` on overflow.
+    pub fn checked_add(self, duration: Duration) -> Option<Self> {
+        let nanos = i64::try_from(duration.as_nanos()).ok()?;
+        self.0.checked_add(nanos).map(XrTime)
+    }
+
+    /// `self` moved backward by `duration`, or `None` on overflow.
+    pub fn checked_sub(self, duration: Duration) -> Option<Self> {
+        let nanos = i64::try_from(duration.as_nanos()).ok()?;
+        self.0.checked_sub(nanos).map(XrTime)
+    }
+}
+
+impl Add<Duration> for XrTime {
+    type Output = XrTime;
+
+    /// Panics on overflow, use `checked_add` to handle it.
+    fn add(self, duration: Duration) -> XrTime {
+        self.checked_add(duration)
+            .expect("overflow when adding a duration to an XrTime")
+    }
+}
+
+impl Sub<Duration> for XrTime {
+    type Output = XrTime;
+
+    /// Panics on overflow, use `checked_sub` to handle it.
+    fn sub(self, duration: Duration) -> XrTime {
+        self.checked_sub(duration)
+            .expect("overflow when subtracting a duration from an XrTime")
+    }
+}
+
+impl From<openxr_sys::Time> for XrTime {
+    fn from(time: openxr_sys::Time) -> Self {
+        XrTime(time.as_nanos())
+    }
+}
+
+impl From<XrTime> for openxr_sys::Time {
+    fn from(time: XrTime) -> Self {
+        openxr_sys::Time::from_nanos(time.0)
+    }
+}
+
+/// Converts an `XrDuration`, such as the predicted display period, into a `Duration`.
+/// Negative values, which the runtime shouldn't return there, become zero.
+pub(crate) fn to_duration(duration: openxr_sys::Duration) -> Duration {
+    Duration::from_nanos(u64::try_from(duration.as_nanos()).unwrap_or(0))
+}
+
+/// XR_KHR_convert_timespec_time, to relate `XrTime`s to `CLOCK_MONOTONIC` on the platforms other
+/// than Windows.
+#[cfg(not(windows))]
+pub(crate) const TIMESPEC_EXTENSION: &str = "XR_KHR_convert_timespec_time";
+/// XR_KHR_win32_convert_performance_counter_time, to relate `XrTime`s to
+/// `QueryPerformanceCounter` on Windows.
+#[cfg(windows)]
+pub(crate) const PERFORMANCE_COUNTER_EXTENSION: &str =
+    "XR_KHR_win32_convert_performance_counter_time";
+
+/// `struct timespec`, as filled by `clock_gettime`. Both fields are a C `long` on Linux and
+/// Android, `time_t` included.
+#[cfg(not(windows))]
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Timespec {
+    pub tv_sec: std::os::raw::c_long,
+    pub tv_nsec: std::os::raw::c_long,
+}
+
+#[cfg(not(windows))]
+pub(crate) type ConvertTimespecTimeToTimeKHR = unsafe extern "system" fn(
+    openxr_sys::Instance,
+    *const Timespec,
+    *mut openxr_sys::Time,
+) -> openxr_sys::Result;
+#[cfg(not(windows))]
+pub(crate) type ConvertTimeToTimespecTimeKHR = unsafe extern "system" fn(
+    openxr_sys::Instance,
+    openxr_sys::Time,
+    *mut Timespec,
+) -> openxr_sys::Result;
+
+/// `LARGE_INTEGER` being an `i64` union, counters are passed as one.
+#[cfg(windows)]
+pub(crate) type ConvertWin32PerformanceCounterToTimeKHR =
+    unsafe extern "system" fn(
+        openxr_sys::Instance,
+        *const i64,
+        *mut openxr_sys::Time,
+    ) -> openxr_sys::Result;
+#[cfg(windows)]
+pub(crate) type ConvertTimeToWin32PerformanceCounterKHR =
+    unsafe extern "system" fn(
+        openxr_sys::Instance,
+        openxr_sys::Time,
+        *mut i64,
+    ) -> openxr_sys::Result;
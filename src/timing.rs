@@ -0,0 +1,35 @@
+use std::time::Duration;
+
+use log::info;
+
+/// Wall-clock duration of each major initialization step, in the order they ran.
+#[derive(Clone, Debug, Default)]
+pub struct InitTimings {
+    pub steps: Vec<(&'static str, Duration)>,
+}
+
+impl InitTimings {
+    pub(crate) fn record(&mut self, step: &'static str, duration: Duration) {
+        self.steps.push((step, duration));
+    }
+
+    pub fn total(&self) -> Duration {
+        self.steps.iter().map(|(_, duration)| *duration).sum()
+    }
+
+    pub(crate) fn log_summary(&self) {
+        info!("Initialization timings:");
+        for (step, duration) in &self.steps {
+            info!(
+                "  {:<24} {:>10.3} ms",
+                step,
+                duration.as_secs_f64() * 1000.0
+            );
+        }
+        info!(
+            "  {:<24} {:>10.3} ms",
+            "total",
+            self.total().as_secs_f64() * 1000.0
+        );
+    }
+}
@@ -0,0 +1,119 @@
+//! Checks the whole initialization and a few frames against a software runtime, for CI machines
+//! without a headset: Monado with its simulated HMD, or the conformance tests' simulator.
+//!
+//! Unlike mocks, this goes through the real loader and runtime, so struct layouts or calling
+//! conventions not matching what the runtime expects show up as failures or garbage values.
+//!
+//! `XR_RUNTIME_JSON=/path/to/runtime.json cargo test --test software_runtime`. Without
+//! `XR_RUNTIME_JSON`, the test passes right away with a warning, so `cargo test` runs anywhere.
+
+use std::time::Duration;
+
+use openxr_test::{App, Config, ControlFlow, XrTime};
+
+const FRAME_COUNT: u32 = 60;
+/// Simulated runtimes start right away, this is only there to keep a stuck CI job from hanging
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+#[test]
+fn software_runtime() {
+    let _ = env_logger::builder()
+        .is_test(true)
+        .filter_level(log::LevelFilter::Info)
+        .try_init();
+
+    let runtime = match std::env::var_os("XR_RUNTIME_JSON") {
+        Some(runtime) => runtime,
+        None => {
+            log::warn!("Skipped: XR_RUNTIME_JSON isn't set");
+            return;
+        }
+    };
+    log::info!("Testing against {:?}", runtime);
+
+    if let Err(failure) = run() {
+        panic!("Failed against {:?}: {}", runtime, failure);
+    }
+}
+
+fn run() -> Result<(), String> {
+    let config = Config {
+        wait_for_system: Some(Duration::from_secs(10)),
+        enable_validation: true,
+        debug_grid: true,
+        ..Config::default()
+    };
+    let mut app = App::new(config).map_err(|error| format!("initialization: {}", error))?;
+
+    let stop_handle = app.stop_handle();
+    std::thread::spawn(move || {
+        std::thread::sleep(TIMEOUT);
+        stop_handle.stop();
+    });
+
+    let mut frames = 0;
+    let mut last_display_time: Option<XrTime> = None;
+    let mut failure = None;
+    app.run(|frame| {
+        // Garbage coming back from the runtime is the most likely sign of a layout mismatch
+        let check = || {
+            if frame.views.is_empty() {
+                return Err("no views located".to_string());
+            }
+            for eye in 0..frame.views.len() {
+                let sub_image = frame.sub_image(eye);
+                let extent = sub_image.image_rect.extent;
+                if sub_image.swapchain == openxr_sys::Swapchain::NULL
+                    || extent.width <= 0
+                    || extent.height <= 0
+                {
+                    return Err(format!(
+                        "invalid sub-image {:?} for view {}",
+                        sub_image, eye
+                    ));
+                }
+            }
+            for view in &frame.views {
+                if view.fov.angle_left >= view.fov.angle_right
+                    || view.fov.angle_down >= view.fov.angle_up
+                {
+                    return Err(format!("invalid field of view {:?}", view.fov));
+                }
+            }
+            if last_display_time.map_or(false, |last| frame.display_time <= last) {
+                return Err(format!(
+                    "display time {:?} not after {:?}",
+                    frame.display_time, last_display_time
+                ));
+            }
+            Ok(())
+        };
+        if let Err(error) = check() {
+            failure.get_or_insert(format!("frame {}: {}", frames, error));
+            return Ok(ControlFlow::Exit);
+        }
+
+        last_display_time = Some(frame.display_time);
+        frames += 1;
+        if frames < FRAME_COUNT {
+            Ok(ControlFlow::Continue)
+        } else {
+            Ok(ControlFlow::Exit)
+        }
+    })
+    .map_err(|error| format!("frame loop: {}", error))?;
+
+    if let Some(failure) = failure {
+        return Err(failure);
+    }
+    if frames < FRAME_COUNT {
+        return Err(format!(
+            "only {} frames out of {} rendered within {:?}",
+            frames, FRAME_COUNT, TIMEOUT
+        ));
+    }
+
+    app.destroy()
+        .map_err(|error| format!("teardown: {}", error))?;
+    Ok(())
+}